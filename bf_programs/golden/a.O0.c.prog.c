@@ -0,0 +1,19 @@
+#include <stdio.h>
+#include <string.h>
+int main(void)
+{
+	unsigned char m[30000] = {0};
+	unsigned int h = 0;
+	m[h] += 10;
+	while (m[h])
+	{
+		m[h]--;
+		h++;
+		m[h] += 10;
+		h--;
+	}
+	h++;
+	m[h] -= 3;
+	putchar(m[h]);
+	return 0;
+}
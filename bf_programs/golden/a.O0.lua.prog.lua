@@ -0,0 +1,10 @@
+local m = setmetatable({}, {__index = function() return 0 end})
+local h = 0
+m[h] = (m[h] + 10) % 256
+while m[h] ~= 0 do
+	m[h] = (m[h] + 255) % 256
+	m[h + 1] = (m[h + 1] + 10) % 256
+end
+m[h + 1] = (m[h + 1] + 253) % 256
+io.write(string.char(m[h + 1]))
+io.flush()
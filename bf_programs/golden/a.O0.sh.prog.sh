@@ -0,0 +1,12 @@
+#!/bin/sh
+bf_get() { eval "v=\${m$1:-0}"; }
+bf_add() { bf_get "$1"; eval "m$1=$(( (v + $2) & 255 ))"; }
+bf_out() { bf_get "$1"; printf "\\$(printf %o "$v")"; }
+h=0
+bf_add $h 10
+while bf_get $h; [ "$v" -ne 0 ]; do
+	bf_add $h 255
+	bf_add $((h + 1)) 10
+done
+bf_add $((h + 1)) 253
+bf_out $((h + 1))
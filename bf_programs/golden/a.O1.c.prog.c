@@ -0,0 +1,11 @@
+#include <stdio.h>
+#include <string.h>
+int main(void)
+{
+	int m[30000] = {0};
+	unsigned int h = 0;
+	m[h + 1] += 97;
+	h += 1;
+	putchar(m[h]);
+	return 0;
+}
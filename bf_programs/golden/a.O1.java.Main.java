@@ -0,0 +1,21 @@
+import java.io.BufferedOutputStream;
+import java.io.IOException;
+
+public class Main
+{
+	static byte[] m = new byte[30000];
+	static int h = 0;
+	static BufferedOutputStream out = new BufferedOutputStream(System.out);
+	
+	public static void main(String[] args) throws IOException
+	{
+		part0();
+	}
+	
+	static void part0() throws IOException
+	{
+		m[h + 1] += 97;
+		out.write(m[h + 1]);
+		out.flush();
+	}
+}
@@ -0,0 +1,5 @@
+local m = setmetatable({}, {__index = function() return 0 end})
+local h = 0
+m[h + 1] = (m[h + 1] + 97) % 256
+io.write(string.char(m[h + 1]))
+io.flush()
@@ -0,0 +1,7 @@
+#!/bin/sh
+bf_get() { eval "v=\${m$1:-0}"; }
+bf_add() { bf_get "$1"; eval "m$1=$(( (v + $2) & 255 ))"; }
+bf_out() { bf_get "$1"; printf "\\$(printf %o "$v")"; }
+h=0
+bf_add $((h + 1)) 97
+bf_out $((h + 1))
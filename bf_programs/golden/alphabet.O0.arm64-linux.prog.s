@@ -0,0 +1,86 @@
+	.text
+	.globl main
+	.p2align 2
+main:
+	stp x29, x30, [sp, #-32]!
+	mov x29, sp
+	str x19, [sp, #16]
+	adrp x19, xxbf_tape
+	add x19, x19, :lo12:xxbf_tape
+	ldurb w8, [x19, #0]
+	add w8, w8, #6
+	sturb w8, [x19, #0]
+.Lloop_0_start:
+	ldrb w8, [x19]
+	cbnz w8, 1f
+	b .Lloop_0_end
+1:
+	ldurb w8, [x19, #0]
+	add w8, w8, #255
+	sturb w8, [x19, #0]
+	ldurb w8, [x19, #1]
+	add w8, w8, #4
+	sturb w8, [x19, #1]
+	b .Lloop_0_start
+.Lloop_0_end:
+	add x19, x19, #1
+.Lloop_1_start:
+	ldrb w8, [x19]
+	cbnz w8, 1f
+	b .Lloop_1_end
+1:
+	ldurb w8, [x19, #-1]
+	add w8, w8, #4
+	sturb w8, [x19, #-1]
+	ldurb w8, [x19, #0]
+	add w8, w8, #255
+	sturb w8, [x19, #0]
+	b .Lloop_1_start
+.Lloop_1_end:
+	ldurb w8, [x19, #1]
+	add w8, w8, #5
+	sturb w8, [x19, #1]
+	add x19, x19, #1
+.Lloop_2_start:
+	ldrb w8, [x19]
+	cbnz w8, 1f
+	b .Lloop_2_end
+1:
+	ldurb w8, [x19, #-1]
+	add w8, w8, #5
+	sturb w8, [x19, #-1]
+	ldurb w8, [x19, #0]
+	add w8, w8, #255
+	sturb w8, [x19, #0]
+	b .Lloop_2_start
+.Lloop_2_end:
+	ldurb w8, [x19, #-1]
+	add w8, w8, #1
+	sturb w8, [x19, #-1]
+	sub x19, x19, #1
+.Lloop_3_start:
+	ldrb w8, [x19]
+	cbnz w8, 1f
+	b .Lloop_3_end
+1:
+	ldurb w8, [x19, #-1]
+	add w8, w8, #1
+	sturb w8, [x19, #-1]
+	ldurb w8, [x19, #0]
+	add w8, w8, #255
+	sturb w8, [x19, #0]
+	ldurb w0, [x19, #-1]
+	bl putchar
+	b .Lloop_3_start
+.Lloop_3_end:
+	mov x0, #0
+	bl fflush
+	mov w0, #0
+	ldr x19, [sp, #16]
+	ldp x29, x30, [sp], #32
+	ret
+	.bss
+	.p2align 4
+xxbf_tape:
+	.zero 30000
+	.section .note.GNU-stack,"",@progbits
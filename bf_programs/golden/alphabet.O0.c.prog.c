@@ -0,0 +1,43 @@
+#include <stdio.h>
+#include <string.h>
+int main(void)
+{
+	unsigned char m[30000] = {0};
+	unsigned int h = 0;
+	m[h] += 6;
+	while (m[h])
+	{
+		m[h]--;
+		h++;
+		m[h] += 4;
+		h--;
+	}
+	h++;
+	while (m[h])
+	{
+		m[h]--;
+		h--;
+		m[h] += 4;
+		h++;
+	}
+	h++;
+	m[h] += 5;
+	while (m[h])
+	{
+		m[h]--;
+		h--;
+		m[h] += 5;
+		h++;
+	}
+	h--;
+	m[h]++;
+	while (m[h])
+	{
+		m[h]--;
+		h--;
+		m[h]++;
+		putchar(m[h]);
+		h++;
+	}
+	return 0;
+}
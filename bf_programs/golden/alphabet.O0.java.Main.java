@@ -0,0 +1,46 @@
+import java.io.BufferedOutputStream;
+import java.io.IOException;
+
+public class Main
+{
+	static byte[] m = new byte[30000];
+	static int h = 0;
+	static BufferedOutputStream out = new BufferedOutputStream(System.out);
+	
+	public static void main(String[] args) throws IOException
+	{
+		part0();
+	}
+	
+	static void part0() throws IOException
+	{
+		m[h] += 6;
+		while (m[h] != 0)
+		{
+			m[h] += -1;
+			m[h + 1] += 4;
+		}
+		h += 1;
+		while (m[h] != 0)
+		{
+			m[h + -1] += 4;
+			m[h] += -1;
+		}
+		m[h + 1] += 5;
+		h += 1;
+		while (m[h] != 0)
+		{
+			m[h + -1] += 5;
+			m[h] += -1;
+		}
+		m[h + -1] += 1;
+		h += -1;
+		while (m[h] != 0)
+		{
+			m[h + -1] += 1;
+			m[h] += -1;
+			out.write(m[h + -1]);
+		}
+		out.flush();
+	}
+}
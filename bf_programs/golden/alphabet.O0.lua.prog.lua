@@ -0,0 +1,26 @@
+local m = setmetatable({}, {__index = function() return 0 end})
+local h = 0
+m[h] = (m[h] + 6) % 256
+while m[h] ~= 0 do
+	m[h] = (m[h] + 255) % 256
+	m[h + 1] = (m[h + 1] + 4) % 256
+end
+h = h + 1
+while m[h] ~= 0 do
+	m[h + -1] = (m[h + -1] + 4) % 256
+	m[h] = (m[h] + 255) % 256
+end
+m[h + 1] = (m[h + 1] + 5) % 256
+h = h + 1
+while m[h] ~= 0 do
+	m[h + -1] = (m[h + -1] + 5) % 256
+	m[h] = (m[h] + 255) % 256
+end
+m[h + -1] = (m[h + -1] + 1) % 256
+h = h - 1
+while m[h] ~= 0 do
+	m[h + -1] = (m[h + -1] + 1) % 256
+	m[h] = (m[h] + 255) % 256
+	io.write(string.char(m[h + -1]))
+end
+io.flush()
@@ -0,0 +1,51 @@
+(module
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  ;; Bytes 0 to 7 hold an I/O vector (address and length of the byte to read or write), bytes 8 to 11 the number of bytes read or written, byte 16 the byte, and the tape starts at byte 32.
+  (memory (export "memory") 1)
+  (data (i32.const 0) "\10\00\00\00\01\00\00\00")
+  
+  (func $putchar (param $byte i32)
+    (i32.store8 (i32.const 16) (local.get $byte))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8))))
+  
+  (func (export "_start")
+    ;; Address of the cell under the head.
+    (local $h i32)
+    (local.set $h (i32.const 32))
+    (i32.store8 (local.get $h) (i32.add (i32.load8_u (local.get $h)) (i32.const 6)))
+    ;; Loop from `[` at 1:7 to `]` at 1:15
+    (block $break_0
+      (loop $continue_0
+        (br_if $break_0 (i32.eqz (i32.load8_u (local.get $h))))
+        (i32.store8 (local.get $h) (i32.add (i32.load8_u (local.get $h)) (i32.const 255)))
+        (i32.store8 offset=1 (local.get $h) (i32.add (i32.load8_u offset=1 (local.get $h)) (i32.const 4)))
+        (br $continue_0)))
+    (local.set $h (i32.add (local.get $h) (i32.const 1)))
+    ;; Loop from `[` at 1:17 to `]` at 1:25
+    (block $break_1
+      (loop $continue_1
+        (br_if $break_1 (i32.eqz (i32.load8_u (local.get $h))))
+        (i32.store8 (i32.sub (local.get $h) (i32.const 1)) (i32.add (i32.load8_u (i32.sub (local.get $h) (i32.const 1))) (i32.const 4)))
+        (i32.store8 (local.get $h) (i32.add (i32.load8_u (local.get $h)) (i32.const 255)))
+        (br $continue_1)))
+    (i32.store8 offset=1 (local.get $h) (i32.add (i32.load8_u offset=1 (local.get $h)) (i32.const 5)))
+    (local.set $h (i32.add (local.get $h) (i32.const 1)))
+    ;; Loop from `[` at 2:7 to `]` at 2:16
+    (block $break_2
+      (loop $continue_2
+        (br_if $break_2 (i32.eqz (i32.load8_u (local.get $h))))
+        (i32.store8 (i32.sub (local.get $h) (i32.const 1)) (i32.add (i32.load8_u (i32.sub (local.get $h) (i32.const 1))) (i32.const 5)))
+        (i32.store8 (local.get $h) (i32.add (i32.load8_u (local.get $h)) (i32.const 255)))
+        (br $continue_2)))
+    (i32.store8 (i32.sub (local.get $h) (i32.const 1)) (i32.add (i32.load8_u (i32.sub (local.get $h) (i32.const 1))) (i32.const 1)))
+    (local.set $h (i32.add (local.get $h) (i32.const -1)))
+    ;; Loop from `[` at 3:1 to `]` at 3:7
+    (block $break_3
+      (loop $continue_3
+        (br_if $break_3 (i32.eqz (i32.load8_u (local.get $h))))
+        (i32.store8 (i32.sub (local.get $h) (i32.const 1)) (i32.add (i32.load8_u (i32.sub (local.get $h) (i32.const 1))) (i32.const 1)))
+        (i32.store8 (local.get $h) (i32.add (i32.load8_u (local.get $h)) (i32.const 255)))
+        (call $putchar (i32.load8_u (i32.sub (local.get $h) (i32.const 1))))
+        (br $continue_3)))
+  )
+)
@@ -0,0 +1,42 @@
+	.text
+	.globl main
+	.p2align 2
+main:
+	stp x29, x30, [sp, #-32]!
+	mov x29, sp
+	str x19, [sp, #16]
+	adrp x19, xxbf_tape
+	add x19, x19, :lo12:xxbf_tape
+	ldurb w8, [x19, #0]
+	add w8, w8, #96
+	sturb w8, [x19, #0]
+	ldurb w8, [x19, #1]
+	add w8, w8, #26
+	sturb w8, [x19, #1]
+	add x19, x19, #1
+.Lloop_0_start:
+	ldrb w8, [x19]
+	cbnz w8, 1f
+	b .Lloop_0_end
+1:
+	ldurb w8, [x19, #-1]
+	add w8, w8, #1
+	sturb w8, [x19, #-1]
+	ldurb w8, [x19, #0]
+	add w8, w8, #255
+	sturb w8, [x19, #0]
+	ldurb w0, [x19, #-1]
+	bl putchar
+	b .Lloop_0_start
+.Lloop_0_end:
+	mov x0, #0
+	bl fflush
+	mov w0, #0
+	ldr x19, [sp, #16]
+	ldp x29, x30, [sp], #32
+	ret
+	.bss
+	.p2align 4
+xxbf_tape:
+	.zero 30000
+	.section .note.GNU-stack,"",@progbits
@@ -0,0 +1,17 @@
+#include <stdio.h>
+#include <string.h>
+int main(void)
+{
+	unsigned char m[30000] = {0};
+	unsigned int h = 0;
+	m[h] += 96;
+	m[h + 1] += 26;
+	h += 1;
+	while (m[h])
+	{
+		m[h + -1] += 1;
+		m[h] += -1;
+		putchar(m[h + -1]);
+	}
+	return 0;
+}
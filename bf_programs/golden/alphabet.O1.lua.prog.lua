@@ -0,0 +1,11 @@
+local m = setmetatable({}, {__index = function() return 0 end})
+local h = 0
+m[h] = (m[h] + 96) % 256
+m[h + 1] = (m[h + 1] + 26) % 256
+h = h + 1
+while m[h] ~= 0 do
+	m[h + -1] = (m[h + -1] + 1) % 256
+	m[h] = (m[h] + 255) % 256
+	io.write(string.char(m[h + -1]))
+end
+io.flush()
@@ -0,0 +1,26 @@
+#include <stdio.h>
+#include <string.h>
+int main(void)
+{
+	unsigned char m[30000] = {0};
+	unsigned int h = 0;
+	h++;
+	m[h] = getchar();
+	while (m[h])
+	{
+		h++;
+		m[h] = getchar();
+	}
+	h--;
+	while (m[h])
+	{
+		h--;
+	}
+	h++;
+	while (m[h])
+	{
+		putchar(m[h]);
+		h++;
+	}
+	return 0;
+}
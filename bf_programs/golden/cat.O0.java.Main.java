@@ -0,0 +1,39 @@
+import java.io.BufferedOutputStream;
+import java.io.IOException;
+
+public class Main
+{
+	static byte[] m = new byte[30000];
+	static int h = 0;
+	static BufferedOutputStream out = new BufferedOutputStream(System.out);
+	
+	public static void main(String[] args) throws IOException
+	{
+		part0();
+	}
+	
+	static void part0() throws IOException
+	{
+		out.flush();
+		m[h + 1] = (byte) System.in.read();
+		h += 1;
+		while (m[h] != 0)
+		{
+			out.flush();
+			m[h + 1] = (byte) System.in.read();
+			h += 1;
+		}
+		h += -1;
+		while (m[h] != 0)
+		{
+			h += -1;
+		}
+		h += 1;
+		while (m[h] != 0)
+		{
+			out.write(m[h]);
+			h += 1;
+		}
+		out.flush();
+	}
+}
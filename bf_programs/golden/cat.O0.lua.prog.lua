@@ -0,0 +1,24 @@
+local m = setmetatable({}, {__index = function() return 0 end})
+local h = 0
+local function input()
+	local c = io.read(1)
+	return c and c:byte() or 255
+end
+io.flush()
+m[h + 1] = input()
+h = h + 1
+while m[h] ~= 0 do
+	io.flush()
+	m[h + 1] = input()
+	h = h + 1
+end
+h = h - 1
+while m[h] ~= 0 do
+	h = h - 1
+end
+h = h + 1
+while m[h] ~= 0 do
+	io.write(string.char(m[h]))
+	h = h + 1
+end
+io.flush()
@@ -0,0 +1,48 @@
+(module
+  (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  ;; Bytes 0 to 7 hold an I/O vector (address and length of the byte to read or write), bytes 8 to 11 the number of bytes read or written, byte 16 the byte, and the tape starts at byte 32.
+  (memory (export "memory") 1)
+  (data (i32.const 0) "\10\00\00\00\01\00\00\00")
+  
+  (func $putchar (param $byte i32)
+    (i32.store8 (i32.const 16) (local.get $byte))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8))))
+  
+  (func $getchar (result i32)
+    (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))
+    ;; Nothing read means the end of the input.
+    (if (result i32) (i32.load (i32.const 8))
+      (then (i32.load8_u (i32.const 16)))
+      (else (i32.const 255))))
+  
+  (func (export "_start")
+    ;; Address of the cell under the head.
+    (local $h i32)
+    (local.set $h (i32.const 32))
+    (i32.store8 offset=1 (local.get $h) (call $getchar))
+    (local.set $h (i32.add (local.get $h) (i32.const 1)))
+    ;; Loop from `[` at 1:3 to `]` at 1:6
+    (block $break_0
+      (loop $continue_0
+        (br_if $break_0 (i32.eqz (i32.load8_u (local.get $h))))
+        (i32.store8 offset=1 (local.get $h) (call $getchar))
+        (local.set $h (i32.add (local.get $h) (i32.const 1)))
+        (br $continue_0)))
+    (local.set $h (i32.add (local.get $h) (i32.const -1)))
+    ;; Loop from `[` at 1:9 to `]` at 1:11
+    (block $break_1
+      (loop $continue_1
+        (br_if $break_1 (i32.eqz (i32.load8_u (local.get $h))))
+        (local.set $h (i32.add (local.get $h) (i32.const -1)))
+        (br $continue_1)))
+    (local.set $h (i32.add (local.get $h) (i32.const 1)))
+    ;; Loop from `[` at 1:14 to `]` at 1:17
+    (block $break_2
+      (loop $continue_2
+        (br_if $break_2 (i32.eqz (i32.load8_u (local.get $h))))
+        (call $putchar (i32.load8_u (local.get $h)))
+        (local.set $h (i32.add (local.get $h) (i32.const 1)))
+        (br $continue_2)))
+  )
+)
@@ -0,0 +1,26 @@
+#include <stdio.h>
+#include <string.h>
+int main(void)
+{
+	int m[30000] = {0};
+	unsigned int h = 0;
+	m[h + 1] = (unsigned char)getchar();
+	h += 1;
+	while (m[h])
+	{
+		h += 1;
+		m[h] = (unsigned char)getchar();
+	}
+	h += -1;
+	while (m[h])
+	{
+		h += -1;
+	}
+	h += 1;
+	while (m[h])
+	{
+		putchar(m[h]);
+		h += 1;
+	}
+	return 0;
+}
@@ -0,0 +1,55 @@
+	.text
+	.globl _main
+	.p2align 2
+_main:
+	stp x29, x30, [sp, #-32]!
+	mov x29, sp
+	str x19, [sp, #16]
+	adrp x19, _xxbf_tape@PAGE
+	add x19, x19, _xxbf_tape@PAGEOFF
+	ldurb w8, [x19, #1]
+	add w8, w8, #1
+	sturb w8, [x19, #1]
+	add x19, x19, #1
+Lloop_0_start:
+	ldrb w8, [x19]
+	cbnz w8, 1f
+	b Lloop_0_end
+1:
+	mov x0, #0
+	bl _fflush
+	bl _getchar
+	sturb w0, [x19, #0]
+Lloop_1_start:
+	ldrb w8, [x19]
+	cbnz w8, 1f
+	b Lloop_1_end
+1:
+	ldurb w8, [x19, #0]
+	add w8, w8, #246
+	sturb w8, [x19, #0]
+Lloop_2_start:
+	ldrb w8, [x19]
+	cbnz w8, 1f
+	b Lloop_2_end
+1:
+	ldurb w8, [x19, #0]
+	add w8, w8, #10
+	sturb w8, [x19, #0]
+	ldurb w0, [x19, #0]
+	bl _putchar
+	add x19, x19, #1
+	b Lloop_2_start
+Lloop_2_end:
+	b Lloop_1_start
+Lloop_1_end:
+	sub x19, x19, #1
+	b Lloop_0_start
+Lloop_0_end:
+	mov x0, #0
+	bl _fflush
+	mov w0, #0
+	ldr x19, [sp, #16]
+	ldp x29, x30, [sp], #32
+	ret
+	.zerofill __DATA,__bss,_xxbf_tape,30000,4
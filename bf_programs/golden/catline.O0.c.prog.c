@@ -0,0 +1,25 @@
+#include <stdio.h>
+#include <string.h>
+int main(void)
+{
+	unsigned char m[30000] = {0};
+	unsigned int h = 0;
+	h++;
+	m[h]++;
+	while (m[h])
+	{
+		m[h] = getchar();
+		while (m[h])
+		{
+			m[h] -= 10;
+			while (m[h])
+			{
+				m[h] += 10;
+				putchar(m[h]);
+				h++;
+			}
+		}
+		h--;
+	}
+	return 0;
+}
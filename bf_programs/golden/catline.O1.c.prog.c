@@ -0,0 +1,25 @@
+#include <stdio.h>
+#include <string.h>
+int main(void)
+{
+	unsigned char m[30000] = {0};
+	unsigned int h = 0;
+	m[h + 1] += 1;
+	h += 1;
+	while (m[h])
+	{
+		m[h] = getchar();
+		while (m[h])
+		{
+			m[h] += -10;
+			while (m[h])
+			{
+				m[h] += 10;
+				h += 1;
+				putchar(m[h + -1]);
+			}
+		}
+		h += -1;
+	}
+	return 0;
+}
@@ -0,0 +1,22 @@
+local m = setmetatable({}, {__index = function() return 0 end})
+local h = 0
+local function input()
+	local c = io.read(1)
+	return c and c:byte() or 255
+end
+m[h + 1] = (m[h + 1] + 1) % 256
+h = h + 1
+while m[h] ~= 0 do
+	io.flush()
+	m[h] = input()
+	while m[h] ~= 0 do
+		m[h] = (m[h] + 246) % 256
+		while m[h] ~= 0 do
+			m[h] = (m[h] + 10) % 256
+			io.write(string.char(m[h]))
+			h = h + 1
+		end
+	end
+	h = h - 1
+end
+io.flush()
@@ -0,0 +1,23 @@
+#!/bin/sh
+bf_get() { eval "v=\${m$1:-0}"; }
+bf_add() { bf_get "$1"; eval "m$1=$(( (v + $2) & 255 ))"; }
+bf_out() { bf_get "$1"; printf "\\$(printf %o "$v")"; }
+bf_in() {
+	c=$(dd bs=1 count=1 2>/dev/null | od -An -tu1)
+	eval "m$1=$(( ${c:-255} ))"
+}
+h=0
+bf_add $((h + 1)) 1
+h=$((h + 1))
+while bf_get $h; [ "$v" -ne 0 ]; do
+	bf_in $h
+	while bf_get $h; [ "$v" -ne 0 ]; do
+		bf_add $h 246
+		while bf_get $h; [ "$v" -ne 0 ]; do
+			bf_add $h 10
+			bf_out $h
+			h=$((h + 1))
+		done
+	done
+	h=$((h + -1))
+done
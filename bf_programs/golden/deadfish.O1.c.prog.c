@@ -0,0 +1,8 @@
+#include <stdio.h>
+#include <string.h>
+int main(void)
+{
+	int m[30000] = {0};
+	unsigned int h = 0;
+	return 0;
+}
@@ -0,0 +1,2 @@
+Block moves: a loop that moves a run of non zero cells by one cell
++>+>+[[->+<]<]
@@ -0,0 +1,2 @@
+Clean up: soups that do nothing are removed
++-><,
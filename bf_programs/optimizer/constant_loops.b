@@ -0,0 +1,2 @@
+Constant loops: chains of multiplication loops on known cells become soups of constants
+++++++++[>++++++++<-]>[>+>++<<-]>>[<+>-]<<
@@ -0,0 +1,2 @@
+Fixed loops: a loop that does not move the head but steps its cell by more than one
+++++[-->+<]
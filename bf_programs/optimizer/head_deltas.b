@@ -0,0 +1,2 @@
+Head deltas: moves between outputs become offsets of the outputs
++[>.>.>.<<<-]
@@ -0,0 +1,2 @@
+Hello world: a version with loops in a row that build on each other
++++++[>+++++<-]>[>+++>++++>+<<<-]>---.>+.+++++++..+++.>---------------.
@@ -0,0 +1,2 @@
+Hello world: a version with a single multiplication loop
+++++++++++[>+++++++>++++++++++>+++>+<<<<-]>++.>+.+++++++..+++.>++.<<+++++++++++++++.>.+++.------.--------.>+.>.
@@ -0,0 +1,2 @@
+Loop fusion: a loop right after a loop on the same cell is never entered
+,[-][+][-]
@@ -0,0 +1,2 @@
+Moving loops: a loop that moves the head to the next zero cell
++>+>+<<[>]
@@ -0,0 +1,2 @@
+Multiplication loops: a loop that decrements its cell by one and adds to others
+,[->+++>++<<]
@@ -0,0 +1,2 @@
+Print loops: a loop that prints cells up to a zero cell
++>+>+<<[.>]
@@ -0,0 +1,3 @@
+Read loops: loops that read into cells until the end of the input or a zero byte
++[,>]
+>+[>,]
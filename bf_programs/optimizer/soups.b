@@ -0,0 +1,2 @@
+Soups: runs of moves and increments become a single soup of cell deltas
++++>++<->>--<
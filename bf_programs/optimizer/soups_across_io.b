@@ -0,0 +1,3 @@
+Soups across IO: arithmetic split by inputs and outputs on other cells becomes one soup
++>,>+++<<->.
++[>,>+++<<-.]
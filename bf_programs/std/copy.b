@@ -0,0 +1,8 @@
+Adds cell 0 (of value 5) to cell 1 (of value 2) then prints both
++++++>++<
+@include <std/copy>
+>
+@include <std/print_u8>
+[-]>++++++++++.[-]<<
+@include <std/print_u8>
+>++++++++++.
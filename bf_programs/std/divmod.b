@@ -0,0 +1,9 @@
+Divides 200 by 7 and prints the quotient then the remainder
+++++++++++[>++++++++++<-]>[<++>-]<>+++++++<
+@include <std/divmod>
+>>>
+@include <std/print_u8>
+[-]<
+>>>>>>>++++++++++.[-]<<<<<<<
+@include <std/print_u8>
+>>>>>>>++++++++++.
@@ -0,0 +1,9 @@
+Multiplies 12 by 11 then 7 by 40 (that wraps around)
+++++++++++++>+++++++++++<
+@include <std/multiply>
+@include <std/print_u8>
+>++++++++++.[-]<[-]
++++++++>++++++++++++++++++++++++++++++++++++++++<
+@include <std/multiply>
+@include <std/print_u8>
+>++++++++++.
@@ -0,0 +1,7 @@
+Reads two numbers and prints their sum
+@include <std/read_u8>
+>>>>
+@include <std/read_u8>
+[-<<<<+>>>>]<<<<
+@include <std/print_u8>
+>++++++++++.
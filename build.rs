@@ -0,0 +1,34 @@
+//! Gives the crate a build identifier (`XXBF_BUILD_ID`), a hash of its sources, so that the cache
+//! of optimized programs (see `cache`) does not use the entries of a build whose passes differ
+//! from the ones of this build, even if the version did not change.
+
+use std::path::Path;
+
+fn main() {
+	println!("cargo:rerun-if-changed=src");
+	let mut paths = Vec::new();
+	collect_source_paths(Path::new("src"), &mut paths);
+	paths.sort();
+	// FNV-1a, as in `hash`.
+	let mut state: u64 = 0xcbf29ce484222325;
+	for path in paths {
+		let content = std::fs::read(&path).expect("cannot read a source file");
+		let path = path.to_string_lossy().into_owned();
+		for &byte in path.as_bytes().iter().chain(&[0]).chain(&content) {
+			state ^= byte as u64;
+			state = state.wrapping_mul(0x100000001b3);
+		}
+	}
+	println!("cargo:rustc-env=XXBF_BUILD_ID={:016x}", state);
+}
+
+fn collect_source_paths(dir: &Path, paths: &mut Vec<std::path::PathBuf>) {
+	for entry in std::fs::read_dir(dir).expect("cannot read a source directory") {
+		let path = entry.expect("cannot read a source directory").path();
+		if path.is_dir() {
+			collect_source_paths(&path, paths);
+		} else if path.extension().is_some_and(|extension| extension == "rs") {
+			paths.push(path);
+		}
+	}
+}
@@ -0,0 +1,242 @@
+//! Static analyses of the raw program.
+//!
+//! The analyses here are forward abstract interpretations where the value of each cell is
+//! approximated by a `CellValue`, cells being identified by their offset from an origin that is
+//! reset whenever the head position is lost (after a loop that moves the head).
+
+use crate::astraw::{RawInstr, top_level};
+use crate::extended::ExtInstr;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// What is known about the value of a cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellValue {
+	Known(u8),
+	NonZero,
+	Unknown,
+}
+
+impl CellValue {
+	pub fn is_non_zero(self) -> bool {
+		match self {
+			CellValue::Known(value) => value != 0,
+			CellValue::NonZero => true,
+			CellValue::Unknown => false,
+		}
+	}
+
+	fn add(self, delta: u8) -> CellValue {
+		match self {
+			_ if delta == 0 => self,
+			CellValue::Known(value) => CellValue::Known(value.wrapping_add(delta)),
+			CellValue::NonZero | CellValue::Unknown => CellValue::Unknown,
+		}
+	}
+}
+
+/// What is known about the tape at some point of the program.
+#[derive(Debug, Clone)]
+pub struct TapeState {
+	/// Values of the cells, by offset from the origin.
+	cells: BTreeMap<isize, CellValue>,
+	/// Value of the cells that are not in `cells`.
+	other_cells: CellValue,
+	/// Offset of the head from the origin.
+	head: isize,
+}
+
+impl TapeState {
+	/// State at the start of the program, where all the cells are zero.
+	pub fn initial() -> TapeState {
+		TapeState {
+			cells: BTreeMap::new(),
+			other_cells: CellValue::Known(0),
+			head: 0,
+		}
+	}
+
+	/// State where nothing is known about the cells.
+	fn unknown() -> TapeState {
+		TapeState {
+			cells: BTreeMap::new(),
+			other_cells: CellValue::Unknown,
+			head: 0,
+		}
+	}
+
+	/// Value of the cell at the given offset from the head.
+	pub fn cell(&self, relative_head: isize) -> CellValue {
+		*self
+			.cells
+			.get(&(self.head + relative_head))
+			.unwrap_or(&self.other_cells)
+	}
+
+	fn set_cell(&mut self, relative_head: isize, value: CellValue) {
+		self.cells.insert(self.head + relative_head, value);
+	}
+}
+
+/// Offsets (relative to the head before it) of the cells that the sequence can modify, or
+/// `None` if the sequence can end with the head somewhere else than where it started (or if the
+/// head position inside a loop depends on the iteration).
+pub fn modified_cells(instr_seq: &[RawInstr]) -> Option<BTreeSet<isize>> {
+	let mut cells = BTreeSet::new();
+	let mut head: isize = 0;
+	for (instr, body) in top_level(instr_seq) {
+		match instr {
+			RawInstr::Plus(_)
+			| RawInstr::Minus(_)
+			| RawInstr::Comma
+			| RawInstr::Clock
+			| RawInstr::Random
+			| RawInstr::HostCall => {
+				cells.insert(head);
+			}
+			RawInstr::Extended(instr) => {
+				if instr.writes_cell() {
+					cells.insert(head);
+				}
+			}
+			RawInstr::Left(n) => head -= *n as isize,
+			RawInstr::Right(n) => head += *n as isize,
+			RawInstr::Dot => (),
+			RawInstr::BracketLoop { .. } => {
+				cells.extend(modified_cells(body)?.into_iter().map(|cell| head + cell));
+			}
+		}
+	}
+	(head == 0).then_some(cells)
+}
+
+/// A loop that never exits once entered, and is entered, followed by instructions that are thus
+/// never executed. Indices are in the raw program.
+#[derive(Debug, Clone)]
+pub struct UnreachableCode {
+	/// Index of the loop instruction.
+	pub loop_index: usize,
+	/// Indices of the unreachable instructions, the rest of the sequence that contains the loop.
+	pub unreachable: std::ops::Range<usize>,
+}
+
+/// A loop that is proven to never exit once entered.
+#[derive(Debug, Clone)]
+pub struct NeverExitingLoop {
+	/// Index of the loop instruction.
+	pub loop_index: usize,
+	/// The loop is proven to be entered whenever it is reached.
+	pub entered: bool,
+}
+
+/// Results of the analysis of the whole program.
+#[derive(Debug, Clone)]
+pub struct Analysis {
+	pub unreachable_code: Vec<UnreachableCode>,
+	pub never_exiting_loops: Vec<NeverExitingLoop>,
+	/// False if the program is proven to never finish.
+	pub finishes: bool,
+}
+
+/// Finds the loops that are proven to never exit and the code they make unreachable. A loop
+/// never exits when its decision cell is still non-zero after any iteration of its body (for
+/// example when the body does not modify it), or when its body itself never finishes.
+pub fn analyze(raw_prog: &[RawInstr]) -> Analysis {
+	let mut analysis = Analysis {
+		unreachable_code: Vec::new(),
+		never_exiting_loops: Vec::new(),
+		finishes: true,
+	};
+	analysis.finishes = analyze_seq(raw_prog, 0, TapeState::initial(), &mut analysis).is_some();
+	analysis
+}
+
+/// Returns the state after the sequence (which starts at `start_index` in the raw program), or
+/// `None` if the sequence never finishes.
+fn analyze_seq(
+	instr_seq: &[RawInstr],
+	start_index: usize,
+	mut state: TapeState,
+	analysis: &mut Analysis,
+) -> Option<TapeState> {
+	let mut index = start_index;
+	for (instr, body) in top_level(instr_seq) {
+		let instr_index = index;
+		index += 1 + body.len();
+		match instr {
+			RawInstr::Plus(n) => state.set_cell(0, state.cell(0).add(*n as u8)),
+			RawInstr::Minus(n) => state.set_cell(0, state.cell(0).add((*n as u8).wrapping_neg())),
+			RawInstr::Left(n) => state.head -= *n as isize,
+			RawInstr::Right(n) => state.head += *n as isize,
+			RawInstr::Dot => (),
+			RawInstr::Comma | RawInstr::Clock | RawInstr::Random | RawInstr::HostCall => {
+				state.set_cell(0, CellValue::Unknown)
+			}
+			RawInstr::Extended(instr) => {
+				if instr.writes_cell() {
+					state.set_cell(0, CellValue::Unknown)
+				}
+			}
+			RawInstr::BracketLoop { .. } => {
+				let decision = state.cell(0);
+				if decision == CellValue::Known(0) {
+					// Never entered.
+					continue;
+				}
+				let modified = modified_cells(body);
+
+				// State at the start of any iteration, which only keeps what the body cannot
+				// change (so that it holds for every iteration and not only for the first one).
+				let mut body_state = match &modified {
+					Some(modified) => {
+						let mut body_state = state.clone();
+						for cell in modified {
+							body_state.set_cell(*cell, CellValue::Unknown);
+						}
+						body_state
+					}
+					None => TapeState::unknown(),
+				};
+				if !body_state.cell(0).is_non_zero() {
+					body_state.set_cell(0, CellValue::NonZero);
+				}
+				// A body that can end the program does not loop forever.
+				let ends_program = body
+					.iter()
+					.any(|instr| matches!(instr, RawInstr::Extended(ExtInstr::End)));
+				let never_exits = match analyze_seq(body, instr_index + 1, body_state, analysis) {
+					Some(state_after_body) => state_after_body.cell(0).is_non_zero(),
+					None => true,
+				} && !ends_program;
+
+				if never_exits {
+					analysis.never_exiting_loops.push(NeverExitingLoop {
+						loop_index: instr_index,
+						entered: decision.is_non_zero(),
+					});
+				}
+				if never_exits && decision.is_non_zero() {
+					if index < start_index + instr_seq.len() {
+						analysis.unreachable_code.push(UnreachableCode {
+							loop_index: instr_index,
+							unreachable: index..start_index + instr_seq.len(),
+						});
+					}
+					return None;
+				}
+				if !never_exits {
+					// If the loop was entered then it could have modified some cells.
+					match modified {
+						Some(modified) => {
+							for cell in modified {
+								state.set_cell(cell, CellValue::Unknown);
+							}
+						}
+						None => state = TapeState::unknown(),
+					}
+				}
+				state.set_cell(0, CellValue::Known(0));
+			}
+		}
+	}
+	Some(state)
+}
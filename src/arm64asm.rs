@@ -0,0 +1,218 @@
+//! AArch64 (ARM64) assembly generation from the linear intermediate representation (see
+//! `lowering`), for Linux (GNU assembler syntax) and macOS (Apple assembler syntax). The generated
+//! `main` does its input and output with `getchar` and `putchar` from the C standard library, it
+//! can be assembled and linked with the system tools (for example `cc prog.s`, that does not
+//! involve compiling C code).
+//!
+//! The pointer to the cell under the head is kept in `x19`. As with the C backend, the tape has a
+//! fixed size and the head is not checked to stay within it, and reading at the end of the input
+//! gives 255.
+
+use crate::lowering::{LinearInstr, LinearProgram};
+
+/// Number of cells of the tape array of the generated program.
+pub const TAPE_SIZE: usize = 30000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arm64Os {
+	Linux,
+	MacOs,
+}
+
+impl Arm64Os {
+	/// Name of a C symbol in assembly (Mach-O prefixes them with an underscore).
+	fn symbol(self, name: &str) -> String {
+		match self {
+			Arm64Os::Linux => name.to_owned(),
+			Arm64Os::MacOs => format!("_{}", name),
+		}
+	}
+
+	/// Name of a label that does not appear in the symbol table of the object file.
+	fn local_label(self, name: &str) -> String {
+		match self {
+			Arm64Os::Linux => format!(".L{}", name),
+			Arm64Os::MacOs => format!("L{}", name),
+		}
+	}
+}
+
+struct Arm64Asm {
+	code: String,
+	os: Arm64Os,
+}
+
+impl Arm64Asm {
+	fn emit_line(&mut self, line: &str) {
+		self.code.push('\t');
+		self.code.push_str(line);
+		self.code.push('\n');
+	}
+
+	fn emit_label(&mut self, label: &str) {
+		self.code.push_str(label);
+		self.code.push_str(":\n");
+	}
+
+	/// Puts the value in the 64-bit register, whatever its size.
+	fn emit_mov_immediate(&mut self, register: &str, value: i64) {
+		let bits = value as u64;
+		self.emit_line(&format!("movz {}, #{}", register, bits & 0xffff));
+		for shift in [16, 32, 48] {
+			let chunk = (bits >> shift) & 0xffff;
+			if chunk != 0 {
+				self.emit_line(&format!("movk {}, #{}, lsl #{}", register, chunk, shift));
+			}
+		}
+	}
+
+	/// Memory operand of the cell at the offset from the head, computing its address in `x9` if
+	/// the offset does not fit in a load or store instruction.
+	fn cell(&mut self, offset: isize) -> String {
+		if (-256..=255).contains(&offset) {
+			format!("[x19, #{}]", offset)
+		} else {
+			self.emit_mov_immediate("x9", offset as i64);
+			self.emit_line("add x9, x19, x9");
+			"[x9]".to_owned()
+		}
+	}
+
+	fn emit_add(&mut self, offset: isize, value: u8) {
+		let cell = self.cell(offset);
+		self.emit_line(&format!("ldurb w8, {}", cell));
+		self.emit_line(&format!("add w8, w8, #{}", value));
+		self.emit_line(&format!("sturb w8, {}", cell));
+	}
+
+	fn emit_set(&mut self, offset: isize, value: u8) {
+		let cell = self.cell(offset);
+		self.emit_line(&format!("mov w8, #{}", value));
+		self.emit_line(&format!("sturb w8, {}", cell));
+	}
+
+	fn emit_mul_add(&mut self, source: isize, target: isize, factor: u8) {
+		let source_cell = self.cell(source);
+		self.emit_line(&format!("ldurb w10, {}", source_cell));
+		self.emit_line(&format!("mov w11, #{}", factor));
+		let cell = self.cell(target);
+		self.emit_line(&format!("ldurb w8, {}", cell));
+		self.emit_line("madd w8, w10, w11, w8");
+		self.emit_line(&format!("sturb w8, {}", cell));
+	}
+
+	fn emit_move(&mut self, delta: isize) {
+		if (0..4096).contains(&delta) {
+			self.emit_line(&format!("add x19, x19, #{}", delta));
+		} else if (-4095..0).contains(&delta) {
+			self.emit_line(&format!("sub x19, x19, #{}", -delta));
+		} else {
+			self.emit_mov_immediate("x9", delta as i64);
+			self.emit_line("add x19, x19, x9");
+		}
+	}
+
+	fn emit_output(&mut self, offset: isize) {
+		let cell = self.cell(offset);
+		self.emit_line(&format!("ldurb w0, {}", cell));
+		self.emit_line(&format!("bl {}", self.os.symbol("putchar")));
+	}
+
+	fn emit_input(&mut self, offset: isize) {
+		self.emit_line(&format!("bl {}", self.os.symbol("getchar")));
+		// The address is computed after the call, that does not preserve `x9`.
+		let cell = self.cell(offset);
+		// Keeps the low byte, EOF becoming 255.
+		self.emit_line(&format!("sturb w0, {}", cell));
+	}
+
+	/// Jumps to the label if the cell under the head is zero (or non-zero). The conditional
+	/// branch only skips an unconditional one, that can reach any distance.
+	fn emit_jump_if(&mut self, if_zero: bool, label: &str) {
+		self.emit_line("ldrb w8, [x19]");
+		self.emit_line(&format!("{} w8, 1f", if if_zero { "cbnz" } else { "cbz" }));
+		self.emit_line(&format!("b {}", label));
+		self.code.push_str("1:\n");
+	}
+
+	fn emit_tape_address(&mut self) {
+		let tape = self.os.symbol("xxbf_tape");
+		match self.os {
+			Arm64Os::Linux => {
+				self.emit_line(&format!("adrp x19, {}", tape));
+				self.emit_line(&format!("add x19, x19, :lo12:{}", tape));
+			}
+			Arm64Os::MacOs => {
+				self.emit_line(&format!("adrp x19, {}@PAGE", tape));
+				self.emit_line(&format!("add x19, x19, {}@PAGEOFF", tape));
+			}
+		}
+	}
+
+	fn emit_tape(&mut self) {
+		let tape = self.os.symbol("xxbf_tape");
+		match self.os {
+			Arm64Os::Linux => {
+				self.emit_line(".bss");
+				self.emit_line(".p2align 4");
+				self.emit_label(&tape);
+				self.emit_line(&format!(".zero {}", TAPE_SIZE));
+				self.emit_line(".section .note.GNU-stack,\"\",@progbits");
+			}
+			Arm64Os::MacOs => {
+				self.emit_line(&format!(".zerofill __DATA,__bss,{},{},4", tape, TAPE_SIZE))
+			}
+		}
+	}
+}
+
+pub fn transpile_linear_to_arm64_asm(program: &LinearProgram, os: Arm64Os) -> String {
+	let mut asm = Arm64Asm {
+		code: String::new(),
+		os,
+	};
+	let main = os.symbol("main");
+	asm.emit_line(".text");
+	asm.emit_line(&format!(".globl {}", main));
+	asm.emit_line(".p2align 2");
+	asm.emit_label(&main);
+	// Saves the frame pointer, the link register and `x19` (callee-saved).
+	asm.emit_line("stp x29, x30, [sp, #-32]!");
+	asm.emit_line("mov x29, sp");
+	asm.emit_line("str x19, [sp, #16]");
+	asm.emit_tape_address();
+	let loop_label = |id: usize, end: &str| os.local_label(&format!("loop_{}_{}", id, end));
+	for instr in &program.instrs {
+		match *instr {
+			LinearInstr::Add { offset, value } => asm.emit_add(offset, value),
+			LinearInstr::Set { offset, value } => asm.emit_set(offset, value),
+			LinearInstr::MulAdd {
+				source,
+				target,
+				factor,
+			} => asm.emit_mul_add(source, target, factor),
+			LinearInstr::Move(delta) => asm.emit_move(delta),
+			LinearInstr::Output { offset } => asm.emit_output(offset),
+			LinearInstr::Input { offset } => asm.emit_input(offset),
+			LinearInstr::Flush => {
+				// `fflush(NULL)` flushes all the output streams.
+				asm.emit_line("mov x0, #0");
+				asm.emit_line(&format!("bl {}", os.symbol("fflush")));
+			}
+			LinearInstr::LoopStart(id) => {
+				asm.emit_label(&loop_label(id, "start"));
+				asm.emit_jump_if(true, &loop_label(id, "end"));
+			}
+			LinearInstr::LoopEnd(id) => {
+				asm.emit_line(&format!("b {}", loop_label(id, "start")));
+				asm.emit_label(&loop_label(id, "end"));
+			}
+		}
+	}
+	asm.emit_line("mov w0, #0");
+	asm.emit_line("ldr x19, [sp, #16]");
+	asm.emit_line("ldp x29, x30, [sp], #32");
+	asm.emit_line("ret");
+	asm.emit_tape();
+	asm.code
+}
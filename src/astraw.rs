@@ -1,10 +1,96 @@
-#[derive(Debug, Clone)]
+use crate::extended::ExtInstr;
+
+/// The `+`, `-`, `<` and `>` instructions are counted, a run of identical such instructions
+/// in the source code being represented by only one `RawInstr` (see the tokenizer).
+///
+/// A program is a flat sequence of instructions, the body of a bracket loop being the
+/// `body_len` instructions that directly follow it in the sequence (so that loop bodies are
+/// sub-slices of the program). Use `top_level` to iterate over a sequence without going into
+/// loop bodies.
+#[derive(Debug, Clone, Hash)]
 pub enum RawInstr {
-	Plus,
-	Minus,
-	Left,
-	Right,
+	Plus(usize),
+	Minus(usize),
+	Left(usize),
+	Right(usize),
 	Dot,
 	Comma,
-	BracketLoop(Vec<RawInstr>),
+	/// `~` (see `Extensions::clock`).
+	Clock,
+	/// `?` (see `Extensions::random`).
+	Random,
+	/// See `Extensions::extended_type_1`.
+	Extended(ExtInstr),
+	/// See `Extensions::host_call`.
+	HostCall,
+	BracketLoop {
+		body_len: usize,
+		/// Given by the `[@name]` comments right before the loop (see
+		/// `Extensions::loop_attributes`).
+		attributes: LoopAttributes,
+	},
+}
+
+/// Hints given by the author of the program about a loop: `no-unroll` is followed by the
+/// optimizer, `hot` only by the C backend.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct LoopAttributes {
+	/// `[@no-unroll]`: the loop stays a loop that runs its iterations one by one, it is not
+	/// replaced by the effect of all its iterations at once (a fixed loop, that `constant-loops`
+	/// then unrolls into straight-line code when its number of iterations is known).
+	pub no_unroll: bool,
+	/// `[@hot]`: the loop runs a lot, so its code is emitted inline where it is run even when
+	/// compiling to C optimized for size (rather than as a call to a helper function shared by
+	/// identical loops, where the head is not a local variable). This is all it does: no pass
+	/// reads it, and no cell of the loop (such as the cell it tests) is kept in a local variable.
+	pub hot: bool,
+}
+
+impl LoopAttributes {
+	/// Sets the attribute of the given name, returns false if there is no such attribute.
+	pub fn set(&mut self, name: &str) -> bool {
+		match name {
+			"no-unroll" => self.no_unroll = true,
+			"hot" => self.hot = true,
+			_ => return false,
+		}
+		true
+	}
+
+	/// Names of the attributes that are set, as written in the source code and in the soup IR.
+	pub fn names(&self) -> Vec<&'static str> {
+		let mut names = Vec::new();
+		if self.no_unroll {
+			names.push("no-unroll");
+		}
+		if self.hot {
+			names.push("hot");
+		}
+		names
+	}
+}
+
+/// Iterates over the instructions of the sequence that are not in the body of a loop of the
+/// sequence, each with its loop body (which is empty if it is not a loop).
+pub fn top_level(instr_seq: &[RawInstr]) -> TopLevel<'_> {
+	TopLevel { instr_seq }
+}
+
+pub struct TopLevel<'a> {
+	instr_seq: &'a [RawInstr],
+}
+
+impl<'a> Iterator for TopLevel<'a> {
+	type Item = (&'a RawInstr, &'a [RawInstr]);
+
+	fn next(&mut self) -> Option<(&'a RawInstr, &'a [RawInstr])> {
+		let (instr, rest) = self.instr_seq.split_first()?;
+		let body_len = match instr {
+			RawInstr::BracketLoop { body_len, .. } => *body_len,
+			_ => 0,
+		};
+		let (body, rest) = rest.split_at(body_len);
+		self.instr_seq = rest;
+		Some((instr, body))
+	}
 }
@@ -1,5 +1,9 @@
-use crate::astraw::RawInstr;
-use std::collections::HashMap;
+use crate::astraw::{LoopAttributes, RawInstr, top_level};
+use crate::extended::ExtInstr;
+use crate::intervals::constant_loop_counts;
+use crate::passes::Fuel;
+use crate::tapeconfig::TapeConfig;
+use std::collections::BTreeMap;
 //use std::collections::HashSet;
 
 /*
@@ -30,42 +34,141 @@ struct TapeInfoForward {
 }
 */
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum SoupInstr {
 	Soup {
-		cell_deltas: HashMap<isize, isize>,
+		cell_deltas: BTreeMap<isize, isize>,
 		head_delta: isize,
 	},
-	Output,
-	Input,
+	/// Moves the head by the given delta, a soup that only moves the head (see `seek`), so that
+	/// a long run of `>` or `<` is a single head update (with a single bounds check).
+	Seek {
+		delta: isize,
+	},
+	/// Outputs the cell at the given offset from the head.
+	Output {
+		offset: isize,
+	},
+	/// Reads an input byte into the cell at the given offset from the head.
+	Input {
+		offset: isize,
+	},
+	/// Sets the cell at the given offset from the head to the tick counter (see
+	/// `Extensions::clock`).
+	Clock {
+		offset: isize,
+	},
+	/// Sets the cell at the given offset from the head to a pseudo-random byte (see
+	/// `Extensions::random`).
+	Random {
+		offset: isize,
+	},
+	/// Operates on the cell at the given offset from the head (see `extended`).
+	Extended {
+		instr: ExtInstr,
+		offset: isize,
+	},
+	/// Calls the host with the cell at the given offset from the head, and sets it to the value
+	/// returned (see `Extensions::host_call`).
+	HostCall {
+		offset: isize,
+	},
 	MultFixedLoop {
 		// Cell delta on head is -1 here.
-		cell_deltas: HashMap<isize, isize>,
+		cell_deltas: BTreeMap<isize, isize>,
 	},
 	SoupFixedLoop {
-		cell_deltas: HashMap<isize, isize>,
+		cell_deltas: BTreeMap<isize, isize>,
 	},
 	SoupMovingLoop {
-		cell_deltas: HashMap<isize, isize>,
+		cell_deltas: BTreeMap<isize, isize>,
 		head_delta: isize,
 	},
-	Loop(Vec<SoupInstr>),
+	/// The loop `[[-<offset>+<-offset>]<-offset>]` (with an offset of 1 or -1), which moves the
+	/// run of non-zero cells that ends at the head by one cell in the direction of the offset,
+	/// the head ending on the zero cell before the run. The cell under the head is added to its
+	/// destination, the other cells overwrite theirs.
+	///
+	/// This is the only block move that is recognized: there is no general move of `len` cells
+	/// from `src` to `dst`, scan-then-write loops (`[>]<[[-]...]`) are not recognized, and chains
+	/// of `[->+<]` over a known number of cells are already straight-line code once optimized.
+	BlockMove {
+		offset: isize,
+	},
+	/// The loop `[.>]` (with any non-zero stride instead of the `>`), which outputs the cells
+	/// from the head to the next zero cell.
+	PrintUntilZero {
+		stride: isize,
+	},
+	/// The loop `[,>]` (with any non-zero stride instead of the `>`), which overwrites the run of
+	/// non-zero cells that starts at the head with input bytes (each cell is tested before being
+	/// overwritten).
+	ReadBlock {
+		stride: isize,
+	},
+	/// The loop `[>,]` (with any non-zero stride instead of the `>`), which reads input bytes
+	/// into the cells after the head until it reads a zero.
+	ReadUntilZero {
+		stride: isize,
+	},
+	/// A generic loop, with the attributes given to it in the source code.
+	Loop(Vec<SoupInstr>, LoopAttributes),
+}
+
+/// Number of instructions in the program, including those in loop bodies.
+pub fn instr_count(instr_seq: &[SoupInstr]) -> usize {
+	instr_seq
+		.iter()
+		.map(|instr| match instr {
+			SoupInstr::Loop(body, _) => 1 + instr_count(body),
+			_ => 1,
+		})
+		.sum()
+}
+
+/// Estimation of the memory (in bytes) taken by the program, including loop bodies and cell
+/// delta maps (counting each map entry twice its size to account for the tree nodes).
+pub fn ir_memory(instr_seq: &[SoupInstr]) -> usize {
+	std::mem::size_of_val(instr_seq)
+		+ instr_seq
+			.iter()
+			.map(|instr| match instr {
+				SoupInstr::Soup { cell_deltas, .. }
+				| SoupInstr::MultFixedLoop { cell_deltas }
+				| SoupInstr::SoupFixedLoop { cell_deltas }
+				| SoupInstr::SoupMovingLoop { cell_deltas, .. } => {
+					cell_deltas.len() * 2 * std::mem::size_of::<(isize, isize)>()
+				}
+				SoupInstr::Loop(body, _) => ir_memory(body),
+				SoupInstr::Seek { .. }
+				| SoupInstr::Output { .. }
+				| SoupInstr::Input { .. }
+				| SoupInstr::Clock { .. }
+				| SoupInstr::Random { .. }
+				| SoupInstr::Extended { .. }
+				| SoupInstr::HostCall { .. }
+				| SoupInstr::BlockMove { .. }
+				| SoupInstr::PrintUntilZero { .. }
+				| SoupInstr::ReadBlock { .. }
+				| SoupInstr::ReadUntilZero { .. } => 0,
+			})
+			.sum::<usize>()
 }
 
-pub fn soupify(raw_prog: &Vec<RawInstr>) -> Vec<SoupInstr> {
+pub fn soupify(raw_prog: &[RawInstr]) -> Vec<SoupInstr> {
 	let mut soup_prog: Vec<SoupInstr> = Vec::new();
 	fn top_must_be_soup(soup_prog: &mut Vec<SoupInstr>) {
 		if !matches!(soup_prog.last(), Some(SoupInstr::Soup { .. })) {
 			soup_prog.push(SoupInstr::Soup {
-				cell_deltas: HashMap::new(),
+				cell_deltas: BTreeMap::new(),
 				head_delta: 0,
 			});
 		}
 	}
 
-	for raw_instr in raw_prog {
+	for (raw_instr, raw_body) in top_level(raw_prog) {
 		match raw_instr {
-			RawInstr::Plus | RawInstr::Minus | RawInstr::Left | RawInstr::Right => {
+			RawInstr::Plus(_) | RawInstr::Minus(_) | RawInstr::Left(_) | RawInstr::Right(_) => {
 				top_must_be_soup(&mut soup_prog);
 				if let Some(&mut SoupInstr::Soup {
 					ref mut cell_deltas,
@@ -73,48 +176,464 @@ pub fn soupify(raw_prog: &Vec<RawInstr>) -> Vec<SoupInstr> {
 				}) = soup_prog.last_mut()
 				{
 					match raw_instr {
-						RawInstr::Plus => *cell_deltas.entry(*head_delta).or_insert(0) += 1,
-						RawInstr::Minus => *cell_deltas.entry(*head_delta).or_insert(0) -= 1,
-						RawInstr::Left => *head_delta -= 1,
-						RawInstr::Right => *head_delta += 1,
+						RawInstr::Plus(n) => {
+							*cell_deltas.entry(*head_delta).or_insert(0) += *n as isize
+						}
+						RawInstr::Minus(n) => {
+							*cell_deltas.entry(*head_delta).or_insert(0) -= *n as isize
+						}
+						RawInstr::Left(n) => *head_delta -= *n as isize,
+						RawInstr::Right(n) => *head_delta += *n as isize,
 						_ => unreachable!(),
 					}
 				} else {
 					unreachable!()
 				}
 			}
-			RawInstr::Dot => soup_prog.push(SoupInstr::Output),
-			RawInstr::Comma => soup_prog.push(SoupInstr::Input),
-			RawInstr::BracketLoop(raw_instr_vec) => {
-				let body = soupify(raw_instr_vec);
-				if body.len() == 1 && matches!(body[0], SoupInstr::Soup { .. }) {
-					match &body[0] {
-						SoupInstr::Soup {
-							cell_deltas,
-							head_delta,
-						} => {
-							if *head_delta == 0 && *cell_deltas.get(&0).unwrap_or(&0) == -1 {
-								soup_prog.push(SoupInstr::MultFixedLoop {
-									cell_deltas: cell_deltas.clone(),
-								});
-							} else if *head_delta == 0 {
-								soup_prog.push(SoupInstr::SoupFixedLoop {
-									cell_deltas: cell_deltas.clone(),
-								});
-							} else {
-								soup_prog.push(SoupInstr::SoupMovingLoop {
-									cell_deltas: cell_deltas.clone(),
-									head_delta: *head_delta,
-								});
-							}
-						}
+			RawInstr::Dot => soup_prog.push(SoupInstr::Output { offset: 0 }),
+			RawInstr::Comma => soup_prog.push(SoupInstr::Input { offset: 0 }),
+			RawInstr::Clock => soup_prog.push(SoupInstr::Clock { offset: 0 }),
+			RawInstr::Random => soup_prog.push(SoupInstr::Random { offset: 0 }),
+			RawInstr::HostCall => soup_prog.push(SoupInstr::HostCall { offset: 0 }),
+			RawInstr::Extended(instr) => soup_prog.push(SoupInstr::Extended {
+				instr: *instr,
+				offset: 0,
+			}),
+			RawInstr::BracketLoop { attributes, .. } => {
+				soup_prog.push(SoupInstr::Loop(soupify(raw_body), *attributes))
+			}
+		}
+	}
+	soup_prog
+}
+
+/// Replaces the loops whose body is a single soup by the more specialized loop instructions, but
+/// for the fixed loops that have the `no-unroll` attribute (see `LoopAttributes`).
+pub fn classify_loops(soup_prog: &mut [SoupInstr], fuel: &mut Fuel) {
+	for instr in soup_prog.iter_mut() {
+		if let SoupInstr::Loop(body, attributes) = instr {
+			classify_loops(body, fuel);
+			if let [
+				SoupInstr::Soup {
+					cell_deltas,
+					head_delta,
+				},
+			] = body.as_slice()
+			{
+				if (attributes.no_unroll && *head_delta == 0) || !fuel.consume() {
+					continue;
+				}
+				let cell_deltas = cell_deltas.clone();
+				*instr = if *head_delta == 0 && *cell_deltas.get(&0).unwrap_or(&0) == -1 {
+					SoupInstr::MultFixedLoop { cell_deltas }
+				} else if *head_delta == 0 {
+					SoupInstr::SoupFixedLoop { cell_deltas }
+				} else {
+					SoupInstr::SoupMovingLoop {
+						cell_deltas,
+						head_delta: *head_delta,
+					}
+				};
+			}
+		}
+	}
+}
+
+/// Replaces the loops that move a run of cells by one cell (see `SoupInstr::BlockMove`), which
+/// are common in code that inserts or removes an element in an array of non-zero cells.
+pub fn recognize_block_moves(soup_prog: &mut [SoupInstr], fuel: &mut Fuel) {
+	for instr in soup_prog.iter_mut() {
+		if let SoupInstr::Loop(body, _) = instr {
+			recognize_block_moves(body, fuel);
+			if let [
+				SoupInstr::MultFixedLoop { cell_deltas },
+				SoupInstr::Soup {
+					cell_deltas: soup_cell_deltas,
+					head_delta,
+				},
+			] = body.as_slice()
+			{
+				let offset = -*head_delta;
+				let moves_by_one = (offset == 1 || offset == -1)
+					&& cell_deltas.len() == 2
+					&& cell_deltas.get(&offset) == Some(&1)
+					&& soup_cell_deltas.is_empty();
+				if moves_by_one && fuel.consume() {
+					*instr = SoupInstr::BlockMove { offset };
+				}
+			}
+		}
+	}
+}
+
+/// Replaces the loops that print strings (see `SoupInstr::PrintUntilZero`).
+pub fn recognize_print_loops(soup_prog: &mut [SoupInstr], fuel: &mut Fuel) {
+	for instr in soup_prog.iter_mut() {
+		if let SoupInstr::Loop(body, _) = instr {
+			recognize_print_loops(body, fuel);
+			if let [
+				SoupInstr::Output { offset: 0 },
+				SoupInstr::Soup {
+					cell_deltas,
+					head_delta,
+				},
+			] = body.as_slice()
+			{
+				if cell_deltas.is_empty() && *head_delta != 0 && fuel.consume() {
+					*instr = SoupInstr::PrintUntilZero {
+						stride: *head_delta,
+					};
+				}
+			}
+		}
+	}
+}
+
+/// Replaces the loops that read strings (see `SoupInstr::ReadBlock` and
+/// `SoupInstr::ReadUntilZero`).
+pub fn recognize_read_loops(soup_prog: &mut [SoupInstr], fuel: &mut Fuel) {
+	for instr in soup_prog.iter_mut() {
+		if let SoupInstr::Loop(body, _) = instr {
+			recognize_read_loops(body, fuel);
+			let new_instr = match body.as_slice() {
+				[
+					SoupInstr::Input { offset: 0 },
+					SoupInstr::Soup {
+						cell_deltas,
+						head_delta,
+					},
+				] if cell_deltas.is_empty() && *head_delta != 0 => SoupInstr::ReadBlock {
+					stride: *head_delta,
+				},
+				[
+					SoupInstr::Soup {
+						cell_deltas,
+						head_delta,
+					},
+					SoupInstr::Input { offset: 0 },
+				] if cell_deltas.is_empty() && *head_delta != 0 => SoupInstr::ReadUntilZero {
+					stride: *head_delta,
+				},
+				_ => continue,
+			};
+			if fuel.consume() {
+				*instr = new_instr;
+			}
+		}
+	}
+}
+
+/// Folds the head moves of the soups that are separated by inputs and outputs into the offsets
+/// of the following instructions, so that the head only moves once before the next loop (and
+/// not between each input or output).
+pub fn fold_head_deltas(soup_prog: &mut Vec<SoupInstr>, fuel: &mut Fuel) {
+	let mut folded_prog = Vec::with_capacity(soup_prog.len());
+	let mut instrs = std::mem::take(soup_prog).into_iter().peekable();
+	while instrs.peek().is_some() {
+		// Sequence of soups, inputs and outputs, that ends before the next loop.
+		let mut straight_seq = Vec::new();
+		while let Some(instr) = instrs.next_if(|instr| {
+			matches!(
+				instr,
+				SoupInstr::Soup { .. }
+					| SoupInstr::Output { .. }
+					| SoupInstr::Input { .. }
+					| SoupInstr::Clock { .. }
+					| SoupInstr::Random { .. }
+					| SoupInstr::Extended { .. }
+					| SoupInstr::HostCall { .. }
+			)
+		}) {
+			straight_seq.push(instr);
+		}
+		fold_straight_seq(&mut straight_seq, fuel);
+		folded_prog.extend(straight_seq);
+		if let Some(mut instr) = instrs.next() {
+			if let SoupInstr::Loop(body, _) = &mut instr {
+				fold_head_deltas(body, fuel);
+			}
+			folded_prog.push(instr);
+		}
+	}
+	*soup_prog = folded_prog;
+}
+
+fn fold_straight_seq(straight_seq: &mut Vec<SoupInstr>, fuel: &mut Fuel) {
+	// Only worth it if the head moves before an input or output.
+	let mut head_delta_sum = 0;
+	let mut moved_before_io = false;
+	for instr in straight_seq.iter() {
+		match instr {
+			SoupInstr::Soup { head_delta, .. } => head_delta_sum += head_delta,
+			_ => moved_before_io |= head_delta_sum != 0,
+		}
+	}
+	if !moved_before_io || !fuel.consume() {
+		return;
+	}
+
+	let mut head_delta_sum = 0;
+	for instr in straight_seq.iter_mut() {
+		match instr {
+			SoupInstr::Soup {
+				cell_deltas,
+				head_delta,
+			} => {
+				*cell_deltas = cell_deltas
+					.iter()
+					.map(|(relative_head, delta)| (relative_head + head_delta_sum, *delta))
+					.collect();
+				head_delta_sum += *head_delta;
+				*head_delta = 0;
+			}
+			SoupInstr::Output { offset }
+			| SoupInstr::Input { offset }
+			| SoupInstr::Clock { offset }
+			| SoupInstr::Random { offset }
+			| SoupInstr::Extended { offset, .. }
+			| SoupInstr::HostCall { offset } => {
+				*offset += head_delta_sum;
+			}
+			_ => unreachable!(),
+		}
+	}
+	straight_seq.retain(
+		|instr| !matches!(instr, SoupInstr::Soup { cell_deltas, .. } if cell_deltas.is_empty()),
+	);
+	if head_delta_sum != 0 {
+		match straight_seq.last_mut() {
+			Some(SoupInstr::Soup { head_delta, .. }) => *head_delta = head_delta_sum,
+			_ => straight_seq.push(SoupInstr::Soup {
+				cell_deltas: BTreeMap::new(),
+				head_delta: head_delta_sum,
+			}),
+		}
+	}
+}
+
+/// Merges each soup into the previous soup when only inputs and outputs (and the like) on other
+/// cells are in between, so that `,>+++<` and its neighbors make a single soup around the input.
+/// The instructions in between are then done after the merged soup, their offsets taking its
+/// head move into account (the order of the inputs and outputs is kept).
+pub fn merge_soups_across_io(soup_prog: &mut Vec<SoupInstr>, fuel: &mut Fuel) {
+	let mut merged_prog: Vec<SoupInstr> = Vec::with_capacity(soup_prog.len());
+	for mut instr in std::mem::take(soup_prog) {
+		if let SoupInstr::Loop(body, _) = &mut instr {
+			merge_soups_across_io(body, fuel);
+		}
+		if let SoupInstr::Soup {
+			cell_deltas,
+			head_delta,
+		} = &instr
+		{
+			// Instructions that the soup could be moved before, up to the previous soup.
+			let crossed = merged_prog
+				.iter()
+				.rev()
+				.take_while(|previous| match previous {
+					SoupInstr::Output { offset }
+					| SoupInstr::Input { offset }
+					| SoupInstr::Clock { offset }
+					| SoupInstr::Random { offset }
+					| SoupInstr::HostCall { offset } => !cell_deltas.contains_key(offset),
+					SoupInstr::Extended { instr, offset } => {
+						*instr != ExtInstr::End && !cell_deltas.contains_key(offset)
+					}
+					_ => false,
+				})
+				.count();
+			let previous_index = merged_prog.len().checked_sub(crossed + 1).filter(|&index| {
+				crossed > 0 && matches!(merged_prog[index], SoupInstr::Soup { .. })
+			});
+			if let Some(previous_index) = previous_index.filter(|_| fuel.consume()) {
+				for crossed_instr in &mut merged_prog[previous_index + 1..] {
+					match crossed_instr {
+						SoupInstr::Output { offset }
+						| SoupInstr::Input { offset }
+						| SoupInstr::Clock { offset }
+						| SoupInstr::Random { offset }
+						| SoupInstr::Extended { offset, .. }
+						| SoupInstr::HostCall { offset } => *offset -= head_delta,
 						_ => unreachable!(),
 					}
-				} else {
-					soup_prog.push(SoupInstr::Loop(body));
 				}
+				if let SoupInstr::Soup {
+					cell_deltas: previous_cell_deltas,
+					head_delta: previous_head_delta,
+				} = &mut merged_prog[previous_index]
+				{
+					for (relative_head, delta) in cell_deltas {
+						let offset = *previous_head_delta + relative_head;
+						*previous_cell_deltas.entry(offset).or_insert(0) += delta;
+					}
+					previous_cell_deltas.retain(|_, delta| *delta != 0);
+					*previous_head_delta += head_delta;
+				}
+				continue;
 			}
 		}
+		merged_prog.push(instr);
+	}
+	*soup_prog = merged_prog;
+}
+
+/// Removes the loops that can only be reached with a zero cell under the head because they come
+/// after another loop (that exited on that zero cell) with only instructions that leave that
+/// cell untouched in between. Such a loop is never entered, so adjacent loops on the same
+/// decision cell are fused into the first one.
+pub fn fuse_loops(soup_prog: &mut Vec<SoupInstr>, fuel: &mut Fuel) {
+	let mut zero_under_head = false;
+	soup_prog.retain_mut(|instr| match instr {
+		SoupInstr::Soup {
+			cell_deltas,
+			head_delta,
+		} => {
+			zero_under_head &= *head_delta == 0 && *cell_deltas.get(&0).unwrap_or(&0) == 0;
+			true
+		}
+		SoupInstr::Seek { .. } => {
+			zero_under_head = false;
+			true
+		}
+		SoupInstr::Output { .. } => true,
+		SoupInstr::Extended { instr, offset } => {
+			zero_under_head &= !instr.writes_cell() || *offset != 0;
+			true
+		}
+		SoupInstr::Input { offset }
+		| SoupInstr::Clock { offset }
+		| SoupInstr::Random { offset }
+		| SoupInstr::HostCall { offset } => {
+			zero_under_head &= *offset != 0;
+			true
+		}
+		SoupInstr::MultFixedLoop { .. }
+		| SoupInstr::SoupFixedLoop { .. }
+		| SoupInstr::SoupMovingLoop { .. }
+		| SoupInstr::BlockMove { .. }
+		| SoupInstr::PrintUntilZero { .. }
+		| SoupInstr::ReadBlock { .. }
+		| SoupInstr::ReadUntilZero { .. }
+		| SoupInstr::Loop(..) => {
+			if zero_under_head && fuel.consume() {
+				return false;
+			}
+			if let SoupInstr::Loop(body, _) = instr {
+				fuse_loops(body, fuel);
+			}
+			zero_under_head = true;
+			true
+		}
+	});
+}
+
+/// Replaces the multiplication loops that always run the same number of times (see
+/// `intervals::constant_loop_counts`) by soups, so that the chains of such loops that initialize
+/// cells (such as `++++++++[>++++++++<-]>`) become soups of constants, even with head moves in
+/// between. The cells and the head start as configured (see `tapeconfig`).
+pub fn fold_constant_loops(
+	soup_prog: &mut [SoupInstr],
+	fuel: &mut Fuel,
+	tape_config: &TapeConfig,
+) {
+	let counts = constant_loop_counts(soup_prog, tape_config);
+	replace_constant_loops(soup_prog, &counts, &mut 0, fuel);
+}
+
+/// Does `fold_constant_loops` on the instructions, the first one being at the given index in the
+/// program (in pre-order), which is advanced past them.
+fn replace_constant_loops(
+	instr_seq: &mut [SoupInstr],
+	counts: &[Option<u8>],
+	soup_index: &mut usize,
+	fuel: &mut Fuel,
+) {
+	for instr in instr_seq {
+		let index = *soup_index;
+		*soup_index += 1;
+		match instr {
+			SoupInstr::Loop(body, _) => replace_constant_loops(body, counts, soup_index, fuel),
+			SoupInstr::MultFixedLoop { cell_deltas } => {
+				if let Some(count) = counts[index] {
+					if fuel.consume() {
+						// The cell under the head ends at zero, its delta being -1.
+						let count = count as isize;
+						*instr = SoupInstr::Soup {
+							cell_deltas: cell_deltas
+								.iter()
+								.map(|(&relative_head, &delta)| (relative_head, delta * count))
+								.collect(),
+							head_delta: 0,
+						};
+					}
+				}
+			}
+			_ => (),
+		}
+	}
+}
+
+/// Removes the cell deltas of zero, the soups that do nothing, and merges adjacent soups.
+pub fn clean_up(soup_prog: &mut Vec<SoupInstr>, fuel: &mut Fuel) {
+	let mut cleaned_prog: Vec<SoupInstr> = Vec::with_capacity(soup_prog.len());
+	for mut instr in std::mem::take(soup_prog) {
+		match &mut instr {
+			SoupInstr::Soup { cell_deltas, .. }
+			| SoupInstr::MultFixedLoop { cell_deltas }
+			| SoupInstr::SoupFixedLoop { cell_deltas }
+			| SoupInstr::SoupMovingLoop { cell_deltas, .. }
+				if cell_deltas.values().any(|&delta| delta == 0) && fuel.consume() =>
+			{
+				cell_deltas.retain(|_, delta| *delta != 0);
+			}
+			SoupInstr::Loop(body, _) => clean_up(body, fuel),
+			_ => (),
+		}
+		if let SoupInstr::Soup {
+			cell_deltas,
+			head_delta,
+		} = &instr
+		{
+			if cell_deltas.is_empty() && *head_delta == 0 && fuel.consume() {
+				continue;
+			}
+			if let Some(SoupInstr::Soup {
+				cell_deltas: previous_cell_deltas,
+				head_delta: previous_head_delta,
+			}) = cleaned_prog.last_mut()
+			{
+				if fuel.consume() {
+					for (relative_head, delta) in cell_deltas {
+						let offset = *previous_head_delta + relative_head;
+						*previous_cell_deltas.entry(offset).or_insert(0) += delta;
+					}
+					previous_cell_deltas.retain(|_, delta| *delta != 0);
+					*previous_head_delta += head_delta;
+					// The merged soup may do nothing.
+					if previous_cell_deltas.is_empty() && *previous_head_delta == 0 {
+						cleaned_prog.pop();
+					}
+					continue;
+				}
+			}
+		}
+		cleaned_prog.push(instr);
+	}
+	*soup_prog = cleaned_prog;
+}
+
+/// Replaces the soups that only move the head by seeks.
+pub fn seek(soup_prog: &mut [SoupInstr], fuel: &mut Fuel) {
+	for instr in soup_prog.iter_mut() {
+		match instr {
+			SoupInstr::Soup {
+				cell_deltas,
+				head_delta,
+			} if cell_deltas.is_empty() && *head_delta != 0 && fuel.consume() => {
+				*instr = SoupInstr::Seek { delta: *head_delta };
+			}
+			SoupInstr::Loop(body, _) => seek(body, fuel),
+			_ => (),
+		}
 	}
-	soup_prog
 }
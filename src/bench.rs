@@ -0,0 +1,186 @@
+//! Benchmarks of the optimizer and of the interpreter on the programs of a corpus (see
+//! `conformance`), and their comparison with a baseline recorded before, so that a change that
+//! makes xxbf slower can be caught.
+//!
+//! Each program of the corpus gives two benchmarks, `prog/optimize` (optimizing the parsed
+//! program) and `prog/run` (running the optimized program on its input), each timed as the median
+//! of several runs. Baselines are JSON files that map benchmark names to times in nanoseconds.
+
+use crate::conformance::corpus_programs;
+use crate::json::{Json, json_string, parse_json};
+use crate::program::Program;
+use crate::tokenizer::Extensions;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Version of the format of the baseline files.
+const FORMAT_VERSION: u64 = 1;
+
+#[derive(Debug)]
+pub struct BenchOptions {
+	pub corpus_dir: PathBuf,
+	/// Number of times each benchmark runs, its time being the median.
+	pub runs: usize,
+	/// Baseline file the times are compared to.
+	pub baseline: Option<PathBuf>,
+	/// Baseline file the times are written to.
+	pub save: Option<PathBuf>,
+	/// A benchmark regresses when it is slower than in the baseline by more than this
+	/// percentage, the comparison failing if any does.
+	pub fail_on_regression: Option<f64>,
+}
+
+impl Default for BenchOptions {
+	fn default() -> BenchOptions {
+		BenchOptions {
+			corpus_dir: PathBuf::from("bf_programs"),
+			runs: 5,
+			baseline: None,
+			save: None,
+			fail_on_regression: None,
+		}
+	}
+}
+
+#[derive(Debug)]
+pub struct BenchResult {
+	pub name: String,
+	pub time: Duration,
+	/// Time in the baseline, if there is a baseline with this benchmark.
+	pub baseline_time: Option<Duration>,
+	/// The benchmark is slower than in the baseline by more than the threshold.
+	pub regressed: bool,
+}
+
+impl BenchResult {
+	/// Relative change from the baseline time in percents (positive when slower).
+	pub fn change(&self) -> Option<f64> {
+		self.baseline_time.map(|baseline_time| {
+			(self.time.as_secs_f64() / baseline_time.as_secs_f64().max(1e-9) - 1.0) * 100.0
+		})
+	}
+}
+
+/// Runs the benchmarks of the corpus (and compares them to the baseline if any, and writes the
+/// new baseline if asked), the given callback being called with the result of each benchmark as
+/// soon as it is known.
+pub fn run_benchmarks(
+	options: &BenchOptions,
+	mut on_result: impl FnMut(&BenchResult),
+) -> Result<Vec<BenchResult>, String> {
+	let baseline = match &options.baseline {
+		Some(path) => Some(load_baseline(path)?),
+		None => None,
+	};
+	let runs = options.runs.max(1);
+	let mut results = Vec::new();
+	for src_path in corpus_programs(&options.corpus_dir)? {
+		let program_name = src_path.file_stem().unwrap().to_string_lossy().into_owned();
+		let src_code = std::fs::read_to_string(&src_path)
+			.map_err(|error| format!("cannot read `{}`: {}", src_path.display(), error))?;
+		let input = std::fs::read(src_path.with_extension("in")).unwrap_or_default();
+		let parse = || {
+			Program::parse(&src_code, &Extensions::default())
+				.map_err(|_| format!("cannot parse `{}`", src_path.display()))
+		};
+
+		let mut optimize_times = Vec::with_capacity(runs);
+		let mut program = parse()?;
+		for run_index in 0..runs {
+			if run_index != 0 {
+				program = parse()?;
+			}
+			let start = Instant::now();
+			program.optimize(1);
+			optimize_times.push(start.elapsed());
+		}
+		let run_times = (0..runs)
+			.map(|_| {
+				let start = Instant::now();
+				program.run(Some(input.clone()));
+				start.elapsed()
+			})
+			.collect();
+
+		for (kind, times) in [("optimize", optimize_times), ("run", run_times)] {
+			let name = format!("{}/{}", program_name, kind);
+			let time = median(times);
+			let baseline_time = baseline.as_ref().and_then(|baseline| {
+				baseline
+					.iter()
+					.find(|(baseline_name, _)| *baseline_name == name)
+					.map(|&(_, time)| time)
+			});
+			let mut result = BenchResult {
+				name,
+				time,
+				baseline_time,
+				regressed: false,
+			};
+			result.regressed = options
+				.fail_on_regression
+				.zip(result.change())
+				.is_some_and(|(threshold, change)| change > threshold);
+			on_result(&result);
+			results.push(result);
+		}
+	}
+	if let Some(path) = &options.save {
+		save_baseline(path, &results)?;
+	}
+	Ok(results)
+}
+
+fn median(mut times: Vec<Duration>) -> Duration {
+	times.sort();
+	times[times.len() / 2]
+}
+
+fn load_baseline(path: &Path) -> Result<Vec<(String, Duration)>, String> {
+	let text = std::fs::read_to_string(path)
+		.map_err(|error| format!("cannot read `{}`: {}", path.display(), error))?;
+	let invalid = |message: &str| format!("invalid baseline `{}`: {}", path.display(), message);
+	let Json::Object(fields) = parse_json(&text).map_err(|error| invalid(&error))? else {
+		return Err(invalid("expected an object"));
+	};
+	let field = |name: &str| {
+		fields
+			.iter()
+			.find(|(field_name, _)| field_name == name)
+			.map(|(_, value)| value)
+			.ok_or_else(|| invalid(&format!("missing field `{}`", name)))
+	};
+	if !matches!(field("version")?, Json::Number(FORMAT_VERSION)) {
+		return Err(invalid("unsupported version"));
+	}
+	let Json::Object(benchmarks) = field("benchmarks")? else {
+		return Err(invalid("field `benchmarks` is not an object"));
+	};
+	benchmarks
+		.iter()
+		.map(|(name, time)| match time {
+			Json::Number(nanos) => Ok((name.clone(), Duration::from_nanos(*nanos))),
+			_ => Err(invalid(&format!("the time of `{}` is not a number", name))),
+		})
+		.collect()
+}
+
+fn save_baseline(path: &Path, results: &[BenchResult]) -> Result<(), String> {
+	let benchmarks: Vec<String> = results
+		.iter()
+		.map(|result| {
+			format!(
+				"\t\t{}: {}",
+				json_string(&result.name),
+				result.time.as_nanos()
+			)
+		})
+		.collect();
+	let text = format!(
+		"{{\n\t\"version\": {},\n\t\"benchmarks\": {{\n{}\n\t}}\n}}\n",
+		FORMAT_VERSION,
+		benchmarks.join(",\n")
+	);
+	std::fs::write(path, text)
+		.map_err(|error| format!("cannot write `{}`: {}", path.display(), error))
+}
@@ -0,0 +1,64 @@
+use crate::astraw::RawInstr;
+use crate::differential::{DifferentialOutputs, run_raw_and_soup};
+use crate::passes::PassManager;
+use crate::tapeconfig::TapeConfig;
+
+#[derive(Debug)]
+pub enum BisectResult {
+	/// The optimized program behaves like the raw program with all the transformations applied.
+	NoDifference { transformations: u64 },
+	/// The optimized program already behaves differently before any transformation, the bug is
+	/// in soupification.
+	BadSoupify { outputs: DifferentialOutputs },
+	/// Applying this transformation (counting from 1) makes the optimized program behave
+	/// differently, when all the transformations before it are applied.
+	BadTransformation {
+		index: u64,
+		pass_name: &'static str,
+		outputs: DifferentialOutputs,
+	},
+}
+
+/// Finds the first transformation of the pass pipeline that changes the output of the program
+/// on the given input, by binary searching the fuel given to the passes (the fuel limit of the
+/// given pass manager is ignored, as is its memory limit). The programs run on the default tape.
+pub fn bisect(raw_prog: &[RawInstr], pass_manager: &PassManager, input: &[u8]) -> BisectResult {
+	let run_with_fuel = |fuel: Option<u64>| {
+		let pass_manager = PassManager {
+			fuel,
+			mem_limit: None,
+			tape_config: Some(TapeConfig::default()),
+			..pass_manager.clone()
+		};
+		let (soup_prog, fuel) = pass_manager.optimize_and_get_fuel(raw_prog).unwrap();
+		(run_raw_and_soup(raw_prog, &soup_prog, input), fuel)
+	};
+
+	let (outputs, fuel) = run_with_fuel(None);
+	let transformations = fuel.consumed();
+	if !outputs.differ() {
+		return BisectResult::NoDifference { transformations };
+	}
+	let (outputs, _) = run_with_fuel(Some(0));
+	if outputs.differ() {
+		return BisectResult::BadSoupify { outputs };
+	}
+
+	// With `good` transformations the outputs are the same, with `bad` they differ.
+	let mut good = 0;
+	let mut bad = transformations;
+	while bad - good > 1 {
+		let middle = good + (bad - good) / 2;
+		if run_with_fuel(Some(middle)).0.differ() {
+			bad = middle;
+		} else {
+			good = middle;
+		}
+	}
+	let (outputs, fuel) = run_with_fuel(Some(bad));
+	BisectResult::BadTransformation {
+		index: bad,
+		pass_name: fuel.last_pass().unwrap(),
+		outputs,
+	}
+}
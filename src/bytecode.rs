@@ -0,0 +1,507 @@
+//! Linear form of the soup intermediate representation, where loops are replaced by conditional
+//! jumps, used to run optimized programs. A peephole stage simplifies the sequence of operations
+//! produced by the flattening of the soup tree.
+
+use crate::astsoup::SoupInstr;
+use crate::extended::ExtInstr;
+use std::convert::TryFrom;
+
+/// An op of the bytecode. The variants are ordered from the most run to the least run (as
+/// measured on the programs of `bf_programs`), so that the hottest ops have the smallest opcodes,
+/// and the operands are stored inline on 32 bits so that an op fits in 8 bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Op {
+	/// Jumps to the op of the given index if the cell under the head is not zero.
+	JumpIfNonZero(u32) = 0,
+	/// Adds the cell under the head times the factor (wrapping) to the cell at the offset.
+	MulAdd {
+		factor: u8,
+		offset: i32,
+	} = 1,
+	/// Adds the value (wrapping) to the cell at the offset from the head.
+	Add {
+		value: u8,
+		offset: i32,
+	} = 2,
+	Move(i32) = 3,
+	Set {
+		value: u8,
+		offset: i32,
+	} = 4,
+	Output {
+		offset: i32,
+	} = 5,
+	/// Jumps to the op of the given index if the cell under the head is zero.
+	JumpIfZero(u32) = 6,
+	Input {
+		offset: i32,
+	} = 7,
+	/// See `SoupInstr::BlockMove`.
+	BlockMove {
+		offset: i32,
+	} = 8,
+	PrintUntilZero {
+		stride: i32,
+	} = 9,
+	ReadUntilZero {
+		stride: i32,
+	} = 10,
+	ReadBlock {
+		stride: i32,
+	} = 11,
+	/// See `SoupInstr::Extended`.
+	Extended {
+		instr: ExtInstr,
+		offset: i32,
+	} = 12,
+	/// See `SoupInstr::Clock`.
+	Clock {
+		offset: i32,
+	} = 13,
+	/// See `SoupInstr::Random`.
+	Random {
+		offset: i32,
+	} = 14,
+	/// See `SoupInstr::HostCall`.
+	HostCall {
+		offset: i32,
+	} = 15,
+}
+
+const _: () = assert!(std::mem::size_of::<Op>() == 8);
+
+impl Op {
+	/// The discriminant of the op, that is its first byte.
+	pub fn opcode(&self) -> u8 {
+		match self {
+			Op::JumpIfNonZero(_) => 0,
+			Op::MulAdd { .. } => 1,
+			Op::Add { .. } => 2,
+			Op::Move(_) => 3,
+			Op::Set { .. } => 4,
+			Op::Output { .. } => 5,
+			Op::JumpIfZero(_) => 6,
+			Op::Input { .. } => 7,
+			Op::BlockMove { .. } => 8,
+			Op::PrintUntilZero { .. } => 9,
+			Op::ReadUntilZero { .. } => 10,
+			Op::ReadBlock { .. } => 11,
+			Op::Extended { .. } => 12,
+			Op::Clock { .. } => 13,
+			Op::Random { .. } => 14,
+			Op::HostCall { .. } => 15,
+		}
+	}
+
+	/// Name of the op in the textual form of the bytecode (see `print_bytecode`).
+	pub fn name(&self) -> &'static str {
+		match self {
+			Op::JumpIfNonZero(_) => "jump_if_non_zero",
+			Op::MulAdd { .. } => "mul_add",
+			Op::Add { .. } => "add",
+			Op::Move(_) => "move",
+			Op::Set { .. } => "set",
+			Op::Output { .. } => "output",
+			Op::JumpIfZero(_) => "jump_if_zero",
+			Op::Input { .. } => "input",
+			Op::BlockMove { .. } => "block_move",
+			Op::PrintUntilZero { .. } => "print_until_zero",
+			Op::ReadUntilZero { .. } => "read_until_zero",
+			Op::ReadBlock { .. } => "read_block",
+			Op::Extended { .. } => "ext",
+			Op::Clock { .. } => "clock",
+			Op::Random { .. } => "random",
+			Op::HostCall { .. } => "host_call",
+		}
+	}
+
+	fn jump_target(&self) -> Option<usize> {
+		match self {
+			Op::JumpIfZero(target) | Op::JumpIfNonZero(target) => Some(*target as usize),
+			_ => None,
+		}
+	}
+}
+
+/// Operand of an op (an offset, a stride or a jump target), that must fit on 32 bits.
+fn operand<T: TryFrom<I>, I: Copy + std::fmt::Display>(value: I) -> T {
+	T::try_from(value)
+		.unwrap_or_else(|_| panic!("operand `{}` does not fit in the bytecode", value))
+}
+
+pub fn flatten(soup_prog: &[SoupInstr]) -> Vec<Op> {
+	let mut ops = Vec::new();
+	flatten_instr_seq(&mut ops, soup_prog);
+	ops
+}
+
+fn flatten_instr_seq(ops: &mut Vec<Op>, instr_seq: &[SoupInstr]) {
+	for instr in instr_seq {
+		match instr {
+			SoupInstr::Soup {
+				cell_deltas,
+				head_delta,
+			} => {
+				flatten_cell_deltas(ops, cell_deltas);
+				if *head_delta != 0 {
+					ops.push(Op::Move(operand(*head_delta)));
+				}
+			}
+			SoupInstr::Seek { delta } => ops.push(Op::Move(operand(*delta))),
+			SoupInstr::Output { offset } => ops.push(Op::Output {
+				offset: operand(*offset),
+			}),
+			SoupInstr::Input { offset } => ops.push(Op::Input {
+				offset: operand(*offset),
+			}),
+			SoupInstr::Clock { offset } => ops.push(Op::Clock {
+				offset: operand(*offset),
+			}),
+			SoupInstr::Random { offset } => ops.push(Op::Random {
+				offset: operand(*offset),
+			}),
+			SoupInstr::HostCall { offset } => ops.push(Op::HostCall {
+				offset: operand(*offset),
+			}),
+			SoupInstr::Extended { instr, offset } => ops.push(Op::Extended {
+				instr: *instr,
+				offset: operand(*offset),
+			}),
+			SoupInstr::MultFixedLoop { cell_deltas } => {
+				for (&offset, &delta) in cell_deltas {
+					if offset != 0 {
+						let factor = delta.rem_euclid(256) as u8;
+						ops.push(Op::MulAdd {
+							factor,
+							offset: operand(offset),
+						});
+					}
+				}
+				ops.push(Op::Set {
+					offset: 0,
+					value: 0,
+				});
+			}
+			SoupInstr::SoupFixedLoop { cell_deltas } => {
+				flatten_loop(ops, |ops| flatten_cell_deltas(ops, cell_deltas))
+			}
+			SoupInstr::SoupMovingLoop {
+				cell_deltas,
+				head_delta,
+			} => flatten_loop(ops, |ops| {
+				flatten_cell_deltas(ops, cell_deltas);
+				ops.push(Op::Move(operand(*head_delta)));
+			}),
+			SoupInstr::BlockMove { offset } => ops.push(Op::BlockMove {
+				offset: operand(*offset),
+			}),
+			SoupInstr::PrintUntilZero { stride } => ops.push(Op::PrintUntilZero {
+				stride: operand(*stride),
+			}),
+			SoupInstr::ReadBlock { stride } => ops.push(Op::ReadBlock {
+				stride: operand(*stride),
+			}),
+			SoupInstr::ReadUntilZero { stride } => ops.push(Op::ReadUntilZero {
+				stride: operand(*stride),
+			}),
+			SoupInstr::Loop(body, _) => flatten_loop(ops, |ops| flatten_instr_seq(ops, body)),
+		}
+	}
+}
+
+fn flatten_cell_deltas(ops: &mut Vec<Op>, cell_deltas: &std::collections::BTreeMap<isize, isize>) {
+	for (&offset, &delta) in cell_deltas {
+		let value = delta.rem_euclid(256) as u8;
+		if value != 0 {
+			ops.push(Op::Add {
+				value,
+				offset: operand(offset),
+			});
+		}
+	}
+}
+
+/// Flattens a loop with its test at the bottom: a `JumpIfZero` past the loop that is only run
+/// when entering it, the body, and a `JumpIfNonZero` back to the start of the body.
+fn flatten_loop(ops: &mut Vec<Op>, flatten_body: impl FnOnce(&mut Vec<Op>)) {
+	let loop_start = ops.len();
+	// The target is set once the end of the loop is known.
+	ops.push(Op::JumpIfZero(0));
+	flatten_body(ops);
+	ops.push(Op::JumpIfNonZero(operand(loop_start + 1)));
+	ops[loop_start] = Op::JumpIfZero(operand(ops.len()));
+}
+
+/// Applies local simplifications until there are none left:
+/// - jumps to conditional jumps on the same cell are redirected to where they lead,
+/// - a `Set` followed by an `Add` on the same cell become a single `Set` (and two `Add`s a
+///   single `Add`),
+/// - writes to a cell that are overwritten by an input right after are removed.
+pub fn peephole(ops: &mut Vec<Op>) {
+	loop {
+		let mut changed = thread_jumps(ops);
+		let mut is_target = vec![false; ops.len() + 1];
+		for op in ops.iter() {
+			if let Some(target) = op.jump_target() {
+				is_target[target] = true;
+			}
+		}
+		let mut removed = vec![false; ops.len()];
+		let mut index = 0;
+		while index + 1 < ops.len() {
+			let next = index + 1;
+			// Fusing two ops is only possible if the second one cannot be jumped to.
+			let fused = match (ops[index], ops[next]) {
+				(
+					Op::Set { offset, value },
+					Op::Add {
+						offset: o,
+						value: v,
+					},
+				) if offset == o && !is_target[next] => Some(Op::Set {
+					offset,
+					value: value.wrapping_add(v),
+				}),
+				(
+					Op::Add { offset, value },
+					Op::Add {
+						offset: o,
+						value: v,
+					},
+				) if offset == o && !is_target[next] => Some(Op::Add {
+					offset,
+					value: value.wrapping_add(v),
+				}),
+				_ => None,
+			};
+			if let Some(fused) = fused {
+				ops[next] = fused;
+				removed[index] = true;
+				changed = true;
+				index += 2;
+				continue;
+			}
+			if let (
+				Op::Set { offset, .. } | Op::Add { offset, .. },
+				Op::Input {
+					offset: input_offset,
+				}
+				| Op::Clock {
+					offset: input_offset,
+				}
+				| Op::Random {
+					offset: input_offset,
+				},
+			) = (ops[index], ops[next])
+			{
+				if offset == input_offset {
+					removed[index] = true;
+					changed = true;
+				}
+			}
+			index += 1;
+		}
+		remove_ops(ops, &removed);
+		if !changed {
+			break;
+		}
+	}
+}
+
+/// Maximum number of ops of the loop bodies that `unroll_tiny_loops` duplicates by default.
+pub const DEFAULT_MAX_UNROLLED_BODY_LEN: usize = 2;
+
+/// Duplicates the bodies of at most `max_body_len` ops without jumps (such as the `Move` of a
+/// scan) of the loops, with a `JumpIfZero` past the loop between the two copies, so that the
+/// backward jump is taken once every two iterations. The branches of the interpreter are then
+/// better predicted: scan and clear heavy programs run about 20% to 40% faster on the bytecode
+/// interpreter. Runs after `peephole`, a `max_body_len` of 0 disables it.
+pub fn unroll_tiny_loops(ops: &mut Vec<Op>, max_body_len: usize) {
+	let mut new_ops = Vec::with_capacity(ops.len());
+	// Jumps into a body go to its first copy, which then runs as the rest of the iteration.
+	let mut new_indices = Vec::with_capacity(ops.len() + 1);
+	for (index, &op) in ops.iter().enumerate() {
+		if let Op::JumpIfNonZero(body_start) = op {
+			let body = &ops[(body_start as usize).min(index)..index];
+			if !body.is_empty()
+				&& body.len() <= max_body_len
+				&& body.iter().all(|op| op.jump_target().is_none())
+			{
+				// The target is mapped to its new index with the other jumps.
+				new_ops.push(Op::JumpIfZero(operand(index + 1)));
+				new_ops.extend_from_slice(body);
+			}
+		}
+		new_indices.push(new_ops.len());
+		new_ops.push(op);
+	}
+	new_indices.push(new_ops.len());
+	for op in new_ops.iter_mut() {
+		match op {
+			Op::JumpIfZero(target) | Op::JumpIfNonZero(target) => {
+				*target = operand(new_indices[*target as usize])
+			}
+			_ => (),
+		}
+	}
+	*ops = new_ops;
+}
+
+/// Redirects the jumps that lead to conditional jumps (that test the same cell, since there is
+/// nothing in between), returns true if any jump changed.
+fn thread_jumps(ops: &mut [Op]) -> bool {
+	let mut changed = false;
+	for index in 0..ops.len() {
+		let (jumps_if_zero, mut target) = match ops[index] {
+			Op::JumpIfZero(target) => (true, target as usize),
+			Op::JumpIfNonZero(target) => (false, target as usize),
+			_ => continue,
+		};
+		// Bounded in case of a cycle of jumps.
+		for _ in 0..ops.len() {
+			target = match (jumps_if_zero, ops.get(target)) {
+				(true, Some(Op::JumpIfZero(next))) | (false, Some(Op::JumpIfNonZero(next))) => {
+					*next as usize
+				}
+				(true, Some(Op::JumpIfNonZero(_))) | (false, Some(Op::JumpIfZero(_))) => {
+					target + 1
+				}
+				_ => break,
+			};
+		}
+		let threaded = if jumps_if_zero {
+			Op::JumpIfZero(operand(target))
+		} else {
+			Op::JumpIfNonZero(operand(target))
+		};
+		if threaded != ops[index] {
+			ops[index] = threaded;
+			changed = true;
+		}
+	}
+	changed
+}
+
+/// Removes the ops marked as removed, jumps to a removed op going to the next op that is kept.
+fn remove_ops(ops: &mut Vec<Op>, removed: &[bool]) {
+	let mut new_indices = Vec::with_capacity(ops.len() + 1);
+	let mut kept_count = 0;
+	for &is_removed in removed {
+		new_indices.push(kept_count);
+		if !is_removed {
+			kept_count += 1;
+		}
+	}
+	new_indices.push(kept_count);
+	let mut index = 0;
+	ops.retain(|_| {
+		index += 1;
+		!removed[index - 1]
+	});
+	for op in ops.iter_mut() {
+		match op {
+			Op::JumpIfZero(target) | Op::JumpIfNonZero(target) => {
+				*target = operand(new_indices[*target as usize])
+			}
+			_ => (),
+		}
+	}
+}
+
+impl Op {
+	/// Operands of the op in the textual form of the bytecode.
+	fn operands_text(&self) -> String {
+		match self {
+			Op::Add { value, offset } | Op::Set { value, offset } => {
+				format!("{:+} {}", offset, value)
+			}
+			Op::MulAdd { factor, offset } => format!("{:+} {}", offset, factor),
+			Op::Move(delta) => format!("{:+}", delta),
+			Op::Extended { instr, offset } => format!("{} {:+}", instr.name(), offset),
+			Op::Output { offset }
+			| Op::Input { offset }
+			| Op::Clock { offset }
+			| Op::Random { offset }
+			| Op::HostCall { offset }
+			| Op::BlockMove { offset } => format!("{:+}", offset),
+			Op::PrintUntilZero { stride }
+			| Op::ReadBlock { stride }
+			| Op::ReadUntilZero { stride } => format!("{:+}", stride),
+			Op::JumpIfZero(target) | Op::JumpIfNonZero(target) => format!("{}", target),
+		}
+	}
+
+	/// The 8 bytes of the op as laid out in memory: the opcode, the 8-bit operand (if any),
+	/// two bytes of padding (zeros here) and the 32-bit operand (in little-endian order).
+	pub fn encode(&self) -> [u8; 8] {
+		let (small, large) = match *self {
+			Op::Add { value, offset } | Op::Set { value, offset } => (value, offset as u32),
+			Op::MulAdd { factor, offset } => (factor, offset as u32),
+			Op::Extended { instr, offset } => (instr as u8, offset as u32),
+			Op::Move(offset)
+			| Op::Output { offset }
+			| Op::Input { offset }
+			| Op::Clock { offset }
+			| Op::Random { offset }
+			| Op::HostCall { offset }
+			| Op::BlockMove { offset }
+			| Op::PrintUntilZero { stride: offset }
+			| Op::ReadBlock { stride: offset }
+			| Op::ReadUntilZero { stride: offset } => (0, offset as u32),
+			Op::JumpIfZero(target) | Op::JumpIfNonZero(target) => (0, target),
+		};
+		let mut bytes = [self.opcode(), small, 0, 0, 0, 0, 0, 0];
+		bytes[4..].copy_from_slice(&large.to_le_bytes());
+		bytes
+	}
+}
+
+/// Textual form of the bytecode, one op per line preceded by its index.
+pub fn print_bytecode(ops: &[Op]) -> String {
+	let mut text = String::new();
+	for (index, op) in ops.iter().enumerate() {
+		text.push_str(&format!(
+			"{:5} {} {}\n",
+			index,
+			op.name(),
+			op.operands_text()
+		));
+	}
+	text
+}
+
+/// Encoded form of the bytecode: the opcodes used with the number of ops of each, then the
+/// bytes of each op (see `Op::encode`) next to its textual form.
+pub fn dump_bytecode(ops: &[Op]) -> String {
+	let mut op_counts: Vec<(u8, &str, usize)> = Vec::new();
+	for op in ops {
+		match op_counts
+			.iter_mut()
+			.find(|(opcode, ..)| *opcode == op.opcode())
+		{
+			Some((_, _, count)) => *count += 1,
+			None => op_counts.push((op.opcode(), op.name(), 1)),
+		}
+	}
+	op_counts.sort();
+	let mut text = String::new();
+	for (opcode, name, count) in op_counts {
+		text.push_str(&format!("opcode {:02x} {}: {} ops\n", opcode, name, count));
+	}
+	for (index, op) in ops.iter().enumerate() {
+		let bytes: Vec<String> = op
+			.encode()
+			.iter()
+			.map(|byte| format!("{:02x}", byte))
+			.collect();
+		text.push_str(&format!(
+			"{:5} {} {} {}\n",
+			index,
+			bytes.join(" "),
+			op.name(),
+			op.operands_text()
+		));
+	}
+	text
+}
@@ -0,0 +1,162 @@
+//! On-disk cache of optimized programs, so that running or compiling the same (large) program
+//! again skips parsing and optimization. Entries are files named after a hash of the source code
+//! and of what the optimization depends on, that contain the optimized IR in its textual form
+//! (see `irtext`) preceded by the warnings about the program (as comments).
+//!
+//! The cache is in `$XDG_CACHE_HOME/xxbf` (or `$HOME/.cache/xxbf`).
+
+use crate::astsoup::SoupInstr;
+use crate::hash::stable_hash;
+use crate::irtext::{parse_soup, print_soup};
+use crate::passes::PassManager;
+use crate::tapeconfig::TapeConfig;
+use crate::tokenizer::Extensions;
+use crate::warnings::Warning;
+use std::path::PathBuf;
+
+/// To be changed when the format of the entries changes, so that older entries are not used.
+const FORMAT_VERSION: u32 = 1;
+
+const ENTRY_EXTENSION: &str = "ir";
+
+/// Prefix of the comment lines that hold the warnings.
+const WARNING_PREFIX: &str = "# warning ";
+
+/// The cache directory, `None` if neither `XDG_CACHE_HOME` nor `HOME` is set.
+pub fn cache_dir() -> Option<PathBuf> {
+	match std::env::var_os("XDG_CACHE_HOME").filter(|dir| !dir.is_empty()) {
+		Some(dir) => Some(PathBuf::from(dir).join("xxbf")),
+		None => {
+			let home = std::env::var_os("HOME").filter(|dir| !dir.is_empty())?;
+			Some(PathBuf::from(home).join(".cache").join("xxbf"))
+		}
+	}
+}
+
+/// Key of the entry of the given program optimized by the given pass manager, which depends on
+/// the build of xxbf (a hash of its sources, as the passes may change without the version
+/// changing).
+pub fn cache_key(src_code: &str, extensions: &Extensions, pass_manager: &PassManager) -> u64 {
+	stable_hash(&(
+		FORMAT_VERSION,
+		env!("CARGO_PKG_VERSION"),
+		env!("XXBF_BUILD_ID"),
+		src_code,
+		(
+			extensions.frontend.name(),
+			extensions.comments,
+			extensions.clock,
+			extensions.random,
+			extensions.extended_type_1,
+			extensions.host_call,
+			extensions.loop_attributes,
+		),
+		(
+			pass_manager.fuel,
+			pass_manager.mem_limit,
+			pass_manager.strict_io,
+			pass_manager
+				.tape_config
+				.as_ref()
+				.map(TapeConfig::is_default),
+			&pass_manager.disabled_passes,
+		),
+	))
+}
+
+fn entry_path(key: u64) -> Option<PathBuf> {
+	Some(cache_dir()?.join(format!("{:016x}.{}", key, ENTRY_EXTENSION)))
+}
+
+/// A cached optimized program, with the warnings about the program before it was optimized.
+pub struct CacheEntry {
+	pub soup_prog: Vec<SoupInstr>,
+	pub warnings: Vec<Warning>,
+}
+
+/// The entry of the given key, `None` if there is none or if it cannot be read.
+pub fn load(key: u64) -> Option<CacheEntry> {
+	let text = std::fs::read_to_string(entry_path(key)?).ok()?;
+	let warnings = text
+		.lines()
+		.filter_map(|line| line.strip_prefix(WARNING_PREFIX))
+		.map(parse_warning)
+		.collect::<Option<_>>()?;
+	Some(CacheEntry {
+		soup_prog: parse_soup(&text).ok()?,
+		warnings,
+	})
+}
+
+/// Writes the entry of the given key, failing silently (a cache that cannot be written only
+/// makes the next runs slower).
+pub fn store(key: u64, soup_prog: &[SoupInstr], warnings: &[Warning]) {
+	let Some(path) = entry_path(key) else {
+		return;
+	};
+	let mut text = String::new();
+	for warning in warnings {
+		let Warning::UnreachableCode {
+			loop_span,
+			unreachable_span,
+		} = warning;
+		text.push_str(&format!(
+			"{}{} {} {} {}\n",
+			WARNING_PREFIX,
+			loop_span.start,
+			loop_span.end,
+			unreachable_span.start,
+			unreachable_span.end
+		));
+	}
+	text.push_str(&print_soup(soup_prog));
+	// Written to a temporary file that is then renamed, so that other processes never read an
+	// entry that is partially written.
+	let temporary_path = path.with_extension(format!("tmp{}", std::process::id()));
+	let written = path
+		.parent()
+		.map_or(Ok(()), std::fs::create_dir_all)
+		.and_then(|()| std::fs::write(&temporary_path, text))
+		.and_then(|()| std::fs::rename(&temporary_path, &path));
+	if written.is_err() {
+		std::fs::remove_file(&temporary_path).ok();
+	}
+}
+
+fn parse_warning(text: &str) -> Option<Warning> {
+	let numbers: Vec<usize> = text
+		.split_whitespace()
+		.map(|number| number.parse().ok())
+		.collect::<Option<_>>()?;
+	let [loop_start, loop_end, unreachable_start, unreachable_end] = numbers[..] else {
+		return None;
+	};
+	Some(Warning::UnreachableCode {
+		loop_span: loop_start..loop_end,
+		unreachable_span: unreachable_start..unreachable_end,
+	})
+}
+
+/// Removes all the entries, returning how many there were.
+pub fn clear() -> std::io::Result<usize> {
+	let Some(dir) = cache_dir() else {
+		return Ok(0);
+	};
+	let entries = match std::fs::read_dir(&dir) {
+		Ok(entries) => entries,
+		Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+		Err(error) => return Err(error),
+	};
+	let mut removed = 0;
+	for entry in entries {
+		let path = entry?.path();
+		if path
+			.extension()
+			.is_some_and(|extension| extension == ENTRY_EXTENSION)
+		{
+			std::fs::remove_file(&path)?;
+			removed += 1;
+		}
+	}
+	Ok(removed)
+}
@@ -0,0 +1,44 @@
+//! Cancellation of programs run by the interpreter, for applications embedding xxbf to stop a
+//! program that runs for too long (from another thread, as the program runs in the thread that
+//! called `Program::run_cancellable`). The interpreter checks the token at each iteration of a
+//! loop, so a cancelled program stops soon after, with the output it produced so far.
+//!
+//! Loops compiled by the JIT could not be stopped, so the JIT is not used by cancellable runs.
+//! Cancelling does not interrupt reading input from the user.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// Shared flag that cancels the runs it is given to once it is set, clones sharing the flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+	cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+	pub fn new() -> CancelToken {
+		CancelToken::default()
+	}
+
+	pub fn cancel(&self) {
+		self.cancelled.store(true, Ordering::Relaxed);
+	}
+
+	pub fn is_cancelled(&self) -> bool {
+		self.cancelled.load(Ordering::Relaxed)
+	}
+}
+
+/// What a cancellable run did, whether it halted or it was cancelled.
+#[derive(Debug)]
+pub struct RunOutcome {
+	/// All the output if the program halted, what it output before being cancelled otherwise.
+	pub output: Vec<u8>,
+	pub cancelled: bool,
+	/// Index of the cell under the head when the program stopped.
+	pub head: usize,
+	/// Number of cells of the tape used by the program (see `tapedump::used_cells`).
+	pub used_cells: usize,
+	pub duration: Duration,
+}
@@ -0,0 +1,260 @@
+//! Configuration of the interpreter, of the optimization and of the code generation for
+//! programs embedding xxbf, with the same options as the command line (see `Program::run_with`,
+//! `Program::optimize_with_config` and `Program::compile`). Each configuration is built from its
+//! defaults by chaining calls, such as `VmConfig::new().initial_tape(vec![1, 2]).jit(1000)`.
+
+use crate::arm64asm::Arm64Os;
+use crate::ctranspiler::{CFormat, COptions, CodegenPreference};
+use crate::machine::Storage;
+use crate::passes::{Pass, PassManager};
+use crate::tapeconfig::{Protection, TapeConfig};
+use std::time::Duration;
+
+/// Configuration of the interpreter (the tape starts with zeros and the head on the first cell,
+/// with no JIT by default).
+#[derive(Debug, Clone, Default)]
+pub struct VmConfig {
+	pub tape_config: TapeConfig,
+	/// Number of iterations after which a loop is compiled (see `jit`).
+	pub jit_threshold: Option<u64>,
+}
+
+impl VmConfig {
+	pub fn new() -> VmConfig {
+		VmConfig::default()
+	}
+
+	/// Values of the first cells when the program starts (`--init-tape`).
+	pub fn initial_tape(mut self, initial_tape: Vec<u8>) -> VmConfig {
+		self.tape_config.initial_tape = initial_tape;
+		self
+	}
+
+	/// Index of the cell under the head when the program starts (`--head-start`).
+	pub fn head_start(mut self, head_start: usize) -> VmConfig {
+		self.tape_config.head_start = head_start;
+		self
+	}
+
+	/// Adds a region of cells after the last one (`--tape-region`).
+	pub fn region(mut self, name: &str, size: usize, protection: Protection) -> VmConfig {
+		self.tape_config
+			.add_region(name.to_owned(), size, protection);
+		self
+	}
+
+	/// Seed of the generator of the random extension (`--seed`).
+	pub fn seed(mut self, seed: u64) -> VmConfig {
+		self.tape_config.seed = Some(seed);
+		self
+	}
+
+	/// Storage of the extended-type-1 extension (`--storage`).
+	pub fn storage(mut self, storage: Storage) -> VmConfig {
+		self.tape_config.machine.storage = storage;
+		self
+	}
+
+	/// Runs the bytecode with the JIT (`--jit-threshold`).
+	pub fn jit(mut self, threshold: u64) -> VmConfig {
+		self.jit_threshold = Some(threshold);
+		self
+	}
+}
+
+/// Configuration of the optimization (level 1 with no limit by default).
+#[derive(Debug, Clone)]
+pub struct OptimizeConfig {
+	/// 0 meaning no optimization at all (`-O0`).
+	pub level: u32,
+	pub pass_manager: PassManager,
+}
+
+impl Default for OptimizeConfig {
+	fn default() -> OptimizeConfig {
+		OptimizeConfig {
+			level: 1,
+			pass_manager: PassManager::default(),
+		}
+	}
+}
+
+impl OptimizeConfig {
+	pub fn new() -> OptimizeConfig {
+		OptimizeConfig::default()
+	}
+
+	pub fn level(mut self, level: u32) -> OptimizeConfig {
+		self.level = level;
+		self
+	}
+
+	/// Maximum number of transformations applied (`--opt-fuel`).
+	pub fn fuel(mut self, fuel: u64) -> OptimizeConfig {
+		self.pass_manager.fuel = Some(fuel);
+		self
+	}
+
+	/// Time after which the optimization stops, keeping the transformations applied so far
+	/// (`--opt-time-budget`).
+	pub fn time_budget(mut self, time_budget: Duration) -> OptimizeConfig {
+		self.pass_manager.time_budget = Some(time_budget);
+		self
+	}
+
+	/// Maximum memory (in bytes) the IR can take (`--compiler-mem-limit`).
+	pub fn mem_limit(mut self, mem_limit: usize) -> OptimizeConfig {
+		self.pass_manager.mem_limit = Some(mem_limit);
+		self
+	}
+
+	/// Prints the IR after the given pass, or after `all` of them (`--print-ir-after`).
+	pub fn print_ir_after(mut self, pass_name: &str) -> OptimizeConfig {
+		self.pass_manager.print_ir_after.push(pass_name.to_owned());
+		self
+	}
+
+	/// Prints the diff of the IR for each pass that changed it (`--print-ir-diff`).
+	pub fn print_ir_diff(mut self) -> OptimizeConfig {
+		self.pass_manager.print_ir_diff = true;
+		self
+	}
+
+	/// Undoes the passes that may change the order of the inputs and outputs (`--strict-io`).
+	pub fn strict_io(mut self) -> OptimizeConfig {
+		self.pass_manager.strict_io = true;
+		self
+	}
+
+	/// Prints the time taken by each pass (`--time-passes`).
+	pub fn time_passes(mut self) -> OptimizeConfig {
+		self.pass_manager.time_passes = true;
+		self
+	}
+
+	/// Runs the pass right after the pass of the given name (see `PassManager::register_pass`,
+	/// `--plugin`).
+	pub fn pass(mut self, after: &str, pass: impl Pass + 'static) -> OptimizeConfig {
+		self.pass_manager.register_pass(after, pass);
+		self
+	}
+}
+
+/// What the code is generated for (`--target`).
+#[derive(Debug, Clone)]
+pub enum Target {
+	C,
+	Lua,
+	/// Java source of a public class of the given name.
+	Java(String),
+	/// POSIX shell script.
+	Sh,
+	/// WebAssembly text format.
+	Wat,
+	/// AArch64 assembly.
+	Arm64Asm(Arm64Os),
+	/// Native x86-64 Linux object file.
+	Object,
+}
+
+/// Configuration of the code generation (C with its default options by default). The options
+/// other than the target and the `tape_config` are only supported by the C backend.
+#[derive(Debug, Clone)]
+pub struct CodegenConfig {
+	pub target: Target,
+	pub c_options: COptions,
+	/// Only supported by the C backend, as for the interpreter (see `VmConfig`).
+	pub tape_config: TapeConfig,
+}
+
+impl Default for CodegenConfig {
+	fn default() -> CodegenConfig {
+		CodegenConfig {
+			target: Target::C,
+			c_options: COptions::default(),
+			tape_config: TapeConfig::default(),
+		}
+	}
+}
+
+impl CodegenConfig {
+	pub fn new(target: Target) -> CodegenConfig {
+		CodegenConfig {
+			target,
+			..CodegenConfig::default()
+		}
+	}
+
+	/// Number of cells of the tape of the generated program.
+	pub fn tape_size(mut self, tape_size: usize) -> CodegenConfig {
+		self.c_options.tape_size = tape_size;
+		self
+	}
+
+	/// Stops the generated program when a cell would wrap around (`--c-trap-on-overflow`).
+	pub fn trap_on_overflow(mut self) -> CodegenConfig {
+		self.c_options.trap_on_overflow = true;
+		self
+	}
+
+	/// Function called to output a byte instead of `putchar` (`--c-write-fn`).
+	pub fn write_fn(mut self, name: &str) -> CodegenConfig {
+		self.c_options.write_fn = Some(name.to_owned());
+		self
+	}
+
+	/// Function called to input a byte instead of `getchar` (`--c-read-fn`).
+	pub fn read_fn(mut self, name: &str) -> CodegenConfig {
+		self.c_options.read_fn = Some(name.to_owned());
+		self
+	}
+
+	/// Flushes the output before each input (`--strict-io`).
+	pub fn strict_io(mut self) -> CodegenConfig {
+		self.c_options.strict_io = true;
+		self
+	}
+
+	/// Moves the loops nested deeper than that to helper functions (`--c-max-nesting`).
+	pub fn max_nesting(mut self, depth: usize) -> CodegenConfig {
+		self.c_options.max_nesting = Some(depth);
+		self
+	}
+
+	/// Layout of the generated code (`--c-indent`, `--c-brace-style`, `--c-line-width` and
+	/// `--c-compact`).
+	pub fn format(mut self, format: CFormat) -> CodegenConfig {
+		self.c_options.format = format;
+		self
+	}
+
+	/// What the generated code is optimized for (`--codegen`).
+	pub fn codegen(mut self, preference: CodegenPreference) -> CodegenConfig {
+		self.c_options.codegen = preference;
+		self
+	}
+
+	/// Makes the generated program refer to the given source file (`--c-line-directives`).
+	pub fn line_directives(mut self, src_file_name: &str) -> CodegenConfig {
+		self.c_options.line_directives = Some(src_file_name.to_owned());
+		self
+	}
+
+	/// Seed of the generator of the random extension (`--seed`).
+	pub fn seed(mut self, seed: u64) -> CodegenConfig {
+		self.tape_config.seed = Some(seed);
+		self
+	}
+
+	/// Storage of the extended-type-1 extension (`--storage`).
+	pub fn storage(mut self, storage: Storage) -> CodegenConfig {
+		self.tape_config.machine.storage = storage;
+		self
+	}
+
+	/// Tape configuration of the generated program (see `VmConfig`).
+	pub fn tape_config(mut self, tape_config: TapeConfig) -> CodegenConfig {
+		self.tape_config = tape_config;
+		self
+	}
+}
@@ -0,0 +1,376 @@
+//! Conformance testing of the compilation backends: every registered backend compiles every
+//! program of a corpus (optimized and not), and the compiled program is built and run with the
+//! toolchain of the backend when it is available, its output having to match the output of the
+//! interpreter. Without the toolchain, the emitted code is compared to a golden file instead (if
+//! there is one, golden files being written when blessing).
+//!
+//! A corpus is a directory of programs (`.b` or `.bf` files), the input of a program being in a
+//! file of the same name with the `.in` extension (empty if there is none). The interpreter gives
+//! 0 at the end of the input and the compiled programs give 255, so the compiled programs get the
+//! input followed by a 0 byte (programs that read further are not portable across backends).
+//!
+//! A panic of the interpreter, of the optimization or of a backend only fails the cases it
+//! concerns, with its message as the error, and the other cases still run.
+
+use crate::arm64asm::Arm64Os;
+use crate::program::Program;
+use crate::sandbox::panic_message;
+use crate::tokenizer::Extensions;
+use std::io::{Read, Write};
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Time after which a compiled program is killed and considered to fail.
+const RUN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A compilation backend and the toolchain that builds and runs its output.
+pub struct Backend {
+	/// Name of the backend, as given to `--target`.
+	pub name: &'static str,
+	/// Name of the file the compiled code is written to.
+	pub file_name: &'static str,
+	pub emit: fn(&Program) -> Vec<u8>,
+	/// Commands that build the compiled code, run in the directory of the file.
+	pub build: &'static [&'static [&'static str]],
+	/// Command that runs the built program, in the directory of the file.
+	pub run: &'static [&'static str],
+	/// The built program can only run on some hosts.
+	pub runs_on_host: bool,
+}
+
+/// Every backend, a new backend has to be added here to be tested.
+pub fn backends() -> Vec<Backend> {
+	vec![
+		Backend {
+			name: "c",
+			file_name: "prog.c",
+			emit: |program| program.to_c(&Default::default()).into_bytes(),
+			build: &[&["cc", "-O1", "-o", "prog", "prog.c"]],
+			run: &["./prog"],
+			runs_on_host: true,
+		},
+		Backend {
+			name: "lua",
+			file_name: "prog.lua",
+			emit: |program| program.to_lua().into_bytes(),
+			build: &[],
+			run: &["lua", "prog.lua"],
+			runs_on_host: true,
+		},
+		Backend {
+			name: "java",
+			file_name: "Main.java",
+			emit: |program| program.to_java("Main").into_bytes(),
+			build: &[&["javac", "Main.java"]],
+			run: &["java", "-cp", ".", "Main"],
+			runs_on_host: true,
+		},
+		Backend {
+			name: "sh",
+			file_name: "prog.sh",
+			emit: |program| program.to_sh().into_bytes(),
+			build: &[],
+			run: &["sh", "prog.sh"],
+			runs_on_host: true,
+		},
+		Backend {
+			name: "wat",
+			file_name: "prog.wat",
+			emit: |program| program.to_wat().into_bytes(),
+			build: &[&["wat2wasm", "prog.wat", "-o", "prog.wasm"]],
+			run: &["wasmtime", "prog.wasm"],
+			runs_on_host: true,
+		},
+		Backend {
+			name: "arm64-linux",
+			file_name: "prog.s",
+			emit: |program| program.to_arm64_asm(Arm64Os::Linux).into_bytes(),
+			build: &[&["cc", "-o", "prog", "prog.s"]],
+			run: &["./prog"],
+			runs_on_host: cfg!(all(target_arch = "aarch64", target_os = "linux")),
+		},
+		Backend {
+			name: "arm64-macos",
+			file_name: "prog.s",
+			emit: |program| program.to_arm64_asm(Arm64Os::MacOs).into_bytes(),
+			build: &[&["cc", "-o", "prog", "prog.s"]],
+			run: &["./prog"],
+			runs_on_host: cfg!(all(target_arch = "aarch64", target_os = "macos")),
+		},
+		Backend {
+			name: "object",
+			file_name: "prog.o",
+			emit: |program| program.to_object(),
+			build: &[&["ld", "-o", "prog", "prog.o"]],
+			run: &["./prog"],
+			runs_on_host: cfg!(all(target_arch = "x86_64", target_os = "linux")),
+		},
+	]
+}
+
+impl Backend {
+	/// The compiled code can be built and run here.
+	pub(crate) fn is_available(&self) -> bool {
+		self.runs_on_host
+			&& self
+				.build
+				.iter()
+				.chain(std::iter::once(&self.run))
+				.all(|command| command[0].starts_with("./") || is_in_path(command[0]))
+	}
+}
+
+fn is_in_path(program_name: &str) -> bool {
+	std::env::var_os("PATH").is_some_and(|path| {
+		std::env::split_paths(&path).any(|dir| dir.join(program_name).is_file())
+	})
+}
+
+#[derive(Debug)]
+pub enum Verdict {
+	/// The compiled program gave the expected output.
+	Pass,
+	Fail {
+		expected: Vec<u8>,
+		actual: Vec<u8>,
+	},
+	/// Building or running the compiled program failed, or it timed out.
+	Error(String),
+	/// Without the toolchain, the emitted code is the same as in the golden file.
+	GoldenMatch,
+	GoldenMismatch,
+	GoldenWritten,
+	/// Without the toolchain nor a golden file, nothing can be checked.
+	Skipped,
+}
+
+impl Verdict {
+	pub fn is_failure(&self) -> bool {
+		matches!(
+			self,
+			Verdict::Fail { .. } | Verdict::Error(_) | Verdict::GoldenMismatch
+		)
+	}
+}
+
+#[derive(Debug)]
+pub struct CaseResult {
+	pub program_name: String,
+	pub backend_name: &'static str,
+	pub optimized: bool,
+	pub verdict: Verdict,
+}
+
+#[derive(Debug)]
+pub struct ConformanceOptions {
+	pub corpus_dir: PathBuf,
+	/// Names of the backends to test, all of them if empty.
+	pub backend_names: Vec<String>,
+	/// Writes the golden files instead of checking them (for backends without toolchain).
+	pub bless: bool,
+}
+
+impl Default for ConformanceOptions {
+	fn default() -> ConformanceOptions {
+		ConformanceOptions {
+			corpus_dir: PathBuf::from("bf_programs"),
+			backend_names: Vec::new(),
+			bless: false,
+		}
+	}
+}
+
+/// Tests the backends on the corpus, the given callback being called with the result of each
+/// case as soon as it is known.
+pub fn run_conformance(
+	options: &ConformanceOptions,
+	mut on_result: impl FnMut(&CaseResult),
+) -> Result<Vec<CaseResult>, String> {
+	let backends: Vec<Backend> = backends()
+		.into_iter()
+		.filter(|backend| {
+			options.backend_names.is_empty()
+				|| options
+					.backend_names
+					.iter()
+					.any(|name| name == backend.name)
+		})
+		.collect();
+	for name in &options.backend_names {
+		if !backends.iter().any(|backend| backend.name == name) {
+			return Err(format!("unknown backend `{}`", name));
+		}
+	}
+	let work_dir = std::env::temp_dir().join(format!("xxbf-conformance-{}", std::process::id()));
+	let golden_dir = options.corpus_dir.join("golden");
+
+	let mut results = Vec::new();
+	for src_path in corpus_programs(&options.corpus_dir)? {
+		let program_name = src_path.file_stem().unwrap().to_string_lossy().into_owned();
+		let src_code = std::fs::read_to_string(&src_path)
+			.map_err(|error| format!("cannot read `{}`: {}", src_path.display(), error))?;
+		let input = std::fs::read(src_path.with_extension("in")).unwrap_or_default();
+		let mut program = Program::parse(&src_code, &Extensions::default())
+			.map_err(|_| format!("cannot parse `{}`", src_path.display()))?;
+		let expected = catch_unwind(AssertUnwindSafe(|| program.run(Some(input.clone()))))
+			.map_err(|payload| format!("the interpreter panicked: {}", panic_message(&*payload)));
+		let mut compiled_input = input;
+		compiled_input.push(0);
+
+		for optimized in [false, true] {
+			let optimization = if optimized {
+				catch_unwind(AssertUnwindSafe(|| program.optimize(1))).map_err(|payload| {
+					format!("the optimization panicked: {}", panic_message(&*payload))
+				})
+			} else {
+				Ok(())
+			};
+			for backend in &backends {
+				let code = optimization.clone().and_then(|()| {
+					catch_unwind(AssertUnwindSafe(|| (backend.emit)(&program))).map_err(
+						|payload| format!("the backend panicked: {}", panic_message(&*payload)),
+					)
+				});
+				let verdict = if let Err(error) = code.as_ref().and(expected.as_ref()) {
+					Verdict::Error(error.clone())
+				} else if backend.is_available() && !options.bless {
+					let (code, expected) = (code.unwrap(), expected.as_ref().unwrap());
+					let dir = work_dir.join(format!(
+						"{}-{}-O{}",
+						program_name, backend.name, optimized as u8
+					));
+					match build_and_run(backend, &code, &dir, &compiled_input) {
+						Ok(actual) if actual == *expected => Verdict::Pass,
+						Ok(actual) => Verdict::Fail {
+							expected: expected.clone(),
+							actual,
+						},
+						Err(error) => Verdict::Error(error),
+					}
+				} else {
+					// Named after the backend too, as backends can have the same file name.
+					let golden_path = golden_dir.join(format!(
+						"{}.O{}.{}.{}",
+						program_name, optimized as u8, backend.name, backend.file_name
+					));
+					check_golden(&golden_path, &code.unwrap(), options.bless)?
+				};
+				let result = CaseResult {
+					program_name: program_name.clone(),
+					backend_name: backend.name,
+					optimized,
+					verdict,
+				};
+				on_result(&result);
+				results.push(result);
+			}
+		}
+	}
+	std::fs::remove_dir_all(&work_dir).ok();
+	Ok(results)
+}
+
+/// Paths of the programs of the corpus, in alphabetical order.
+pub(crate) fn corpus_programs(corpus_dir: &Path) -> Result<Vec<PathBuf>, String> {
+	let entries = std::fs::read_dir(corpus_dir)
+		.map_err(|error| format!("cannot read `{}`: {}", corpus_dir.display(), error))?;
+	let mut paths: Vec<PathBuf> = entries
+		.filter_map(|entry| Some(entry.ok()?.path()))
+		.filter(|path| {
+			path.extension()
+				.is_some_and(|extension| extension == "b" || extension == "bf")
+		})
+		.collect();
+	paths.sort();
+	Ok(paths)
+}
+
+fn check_golden(golden_path: &Path, code: &[u8], bless: bool) -> Result<Verdict, String> {
+	if bless {
+		std::fs::create_dir_all(golden_path.parent().unwrap())
+			.and_then(|()| std::fs::write(golden_path, code))
+			.map_err(|error| format!("cannot write `{}`: {}", golden_path.display(), error))?;
+		return Ok(Verdict::GoldenWritten);
+	}
+	Ok(match std::fs::read(golden_path) {
+		Ok(golden) if golden == code => Verdict::GoldenMatch,
+		Ok(_) => Verdict::GoldenMismatch,
+		Err(_) => Verdict::Skipped,
+	})
+}
+
+/// Writes the code in the directory, builds it and runs it on the input, returning its output.
+pub(crate) fn build_and_run(
+	backend: &Backend,
+	code: &[u8],
+	dir: &Path,
+	input: &[u8],
+) -> Result<Vec<u8>, String> {
+	build(backend, code, dir)?;
+	run_built(backend, dir, input)
+}
+
+/// Writes the code in the directory and builds it.
+pub(crate) fn build(backend: &Backend, code: &[u8], dir: &Path) -> Result<(), String> {
+	std::fs::create_dir_all(dir).map_err(|error| error.to_string())?;
+	std::fs::write(dir.join(backend.file_name), code).map_err(|error| error.to_string())?;
+	for command in backend.build {
+		let output = Command::new(command[0])
+			.args(&command[1..])
+			.current_dir(dir)
+			.stdin(Stdio::null())
+			.output()
+			.map_err(|error| format!("cannot run `{}`: {}", command[0], error))?;
+		if !output.status.success() {
+			return Err(format!(
+				"`{}` failed: {}",
+				command.join(" "),
+				String::from_utf8_lossy(&output.stderr).trim_end()
+			));
+		}
+	}
+	Ok(())
+}
+
+/// Runs the program built in the directory (see `build`) on the input, returning its output.
+pub(crate) fn run_built(backend: &Backend, dir: &Path, input: &[u8]) -> Result<Vec<u8>, String> {
+	let mut child = Command::new(backend.run[0])
+		.args(&backend.run[1..])
+		.current_dir(dir)
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::null())
+		.spawn()
+		.map_err(|error| format!("cannot run `{}`: {}", backend.run[0], error))?;
+	// Feeding the input and collecting the output are done in other threads so that a program
+	// that does not terminate can be killed.
+	let mut stdin = child.stdin.take().unwrap();
+	let input = input.to_vec();
+	let writer = std::thread::spawn(move || stdin.write_all(&input).ok());
+	let mut stdout = child.stdout.take().unwrap();
+	let reader = std::thread::spawn(move || {
+		let mut output = Vec::new();
+		stdout.read_to_end(&mut output).ok();
+		output
+	});
+	let start = Instant::now();
+	let status = loop {
+		if let Some(status) = child.try_wait().map_err(|error| error.to_string())? {
+			break status;
+		}
+		if start.elapsed() > RUN_TIMEOUT {
+			child.kill().ok();
+			child.wait().ok();
+			return Err(format!("timed out after {} seconds", RUN_TIMEOUT.as_secs()));
+		}
+		std::thread::sleep(Duration::from_millis(10));
+	};
+	writer.join().ok();
+	let output = reader.join().unwrap();
+	if !status.success() {
+		return Err(format!("the compiled program failed ({})", status));
+	}
+	Ok(output)
+}
@@ -0,0 +1,258 @@
+//! Post-mortem core dumps: when the interpreter stops on an error, the state of the program (the
+//! error, the tape and the head, the last instructions run, the input read and the output so far,
+//! and the source code) can be written to a JSON file, to be attached to bug reports and read back
+//! by the `debug` subcommand.
+//!
+//! Byte strings (the tape, the input and the output) are written in hexadecimal, and positions in
+//! the source code are pairs of a line and a column (starting at 1).
+
+use crate::json::{Json, json_string, parse_json};
+use crate::parser::{instr_spans, lines_and_columns};
+use crate::tapedump::{format_tape_dump, used_cells};
+use crate::tokenizer::Extensions;
+use crate::vm::Observer;
+use std::collections::VecDeque;
+
+/// Name of the core dump file when none is given.
+pub const DEFAULT_CORE_FILE: &str = "xxbf-core.json";
+
+/// Number of last instructions run kept in the trace.
+const TRACE_LEN: usize = 32;
+
+/// Version of the format of the core dump files.
+const FORMAT_VERSION: u64 = 1;
+
+#[derive(Debug)]
+pub struct CoreDump {
+	pub error: String,
+	/// Path of the source file, if the program was read from a file.
+	pub src_file: Option<String>,
+	pub src_code: String,
+	/// Positions of the last instructions run, the last one being the instruction that was
+	/// running when the error occurred.
+	pub trace: Vec<(usize, usize)>,
+	pub head: usize,
+	pub tape: Vec<u8>,
+	pub input: Vec<u8>,
+	pub output: Vec<u8>,
+}
+
+/// Observer of the VM that records what a core dump is made of, the instructions run being only
+/// known when running a program that is not optimized.
+#[derive(Default)]
+pub struct CoreRecorder {
+	/// Indices of the last raw instructions run.
+	trace: VecDeque<usize>,
+	tape: Vec<u8>,
+	head: usize,
+	input: Vec<u8>,
+	output: Vec<u8>,
+}
+
+impl Observer for CoreRecorder {
+	fn on_output(&mut self, char_values: &[u8]) {
+		self.output.extend_from_slice(char_values);
+	}
+
+	fn on_input(&mut self, char_values: &[u8]) {
+		self.input.extend_from_slice(char_values);
+	}
+
+	fn traces_instrs(&self) -> bool {
+		true
+	}
+
+	fn on_instr(&mut self, instr_index: usize, _head: usize, _cell_value: u8) {
+		if self.trace.len() == TRACE_LEN {
+			self.trace.pop_front();
+		}
+		self.trace.push_back(instr_index);
+	}
+
+	fn on_halt(&mut self, tape: &[u8], head: usize) {
+		self.tape = tape.to_vec();
+		self.head = head;
+	}
+}
+
+impl CoreRecorder {
+	/// Core dump of the program that stopped on the given error (after its run).
+	pub fn into_core_dump(
+		self,
+		error: String,
+		src_file: Option<String>,
+		src_code: String,
+		extensions: &Extensions,
+	) -> CoreDump {
+		let spans = instr_spans(&src_code, extensions);
+		// The positions must be computed in increasing order.
+		let mut sorted_indices: Vec<usize> = self.trace.iter().copied().collect();
+		sorted_indices.sort_unstable();
+		sorted_indices.dedup();
+		let positions = lines_and_columns(
+			&src_code,
+			sorted_indices.iter().map(|&index| spans[index].start),
+		);
+		let trace = self
+			.trace
+			.iter()
+			.map(|index| positions[sorted_indices.binary_search(index).unwrap()])
+			.collect();
+		CoreDump {
+			error,
+			src_file,
+			src_code,
+			trace,
+			head: self.head,
+			tape: self.tape,
+			input: self.input,
+			output: self.output,
+		}
+	}
+}
+
+impl CoreDump {
+	pub fn to_json(&self) -> String {
+		let trace: Vec<String> = self
+			.trace
+			.iter()
+			.map(|(line, column)| format!("[{}, {}]", line, column))
+			.collect();
+		let fields = [
+			("version", FORMAT_VERSION.to_string()),
+			("error", json_string(&self.error)),
+			(
+				"src_file",
+				self.src_file
+					.as_deref()
+					.map_or("null".to_owned(), json_string),
+			),
+			("src_code", json_string(&self.src_code)),
+			("trace", format!("[{}]", trace.join(", "))),
+			("head", self.head.to_string()),
+			("tape", json_string(&hex(&self.tape))),
+			("input", json_string(&hex(&self.input))),
+			("output", json_string(&hex(&self.output))),
+		];
+		let fields: Vec<String> = fields
+			.iter()
+			.map(|(name, value)| format!("\t\"{}\": {}", name, value))
+			.collect();
+		format!("{{\n{}\n}}\n", fields.join(",\n"))
+	}
+
+	pub fn from_json(text: &str) -> Result<CoreDump, String> {
+		let Json::Object(fields) = parse_json(text)? else {
+			return Err("expected an object".to_owned());
+		};
+		let field = |name: &str| {
+			fields
+				.iter()
+				.find(|(field_name, _)| field_name == name)
+				.map(|(_, value)| value)
+				.ok_or_else(|| format!("missing field `{}`", name))
+		};
+		let string = |name: &str| match field(name)? {
+			Json::String(string) => Ok(string.clone()),
+			_ => Err(format!("field `{}` is not a string", name)),
+		};
+		let number = |value: &Json, name: &str| match value {
+			Json::Number(number) => Ok(*number as usize),
+			_ => Err(format!("field `{}` is not a number", name)),
+		};
+		let bytes = |name: &str| {
+			unhex(&string(name)?).ok_or_else(|| format!("field `{}` is not hexadecimal", name))
+		};
+		if number(field("version")?, "version")? as u64 != FORMAT_VERSION {
+			return Err("unsupported core dump version".to_owned());
+		}
+		let trace = match field("trace")? {
+			Json::Array(positions) => positions
+				.iter()
+				.map(|position| match position {
+					Json::Array(pair) if pair.len() == 2 => {
+						Ok((number(&pair[0], "trace")?, number(&pair[1], "trace")?))
+					}
+					_ => Err("field `trace` is not a list of positions".to_owned()),
+				})
+				.collect::<Result<_, _>>()?,
+			_ => return Err("field `trace` is not a list".to_owned()),
+		};
+		Ok(CoreDump {
+			error: string("error")?,
+			src_file: match field("src_file")? {
+				Json::Null => None,
+				_ => Some(string("src_file")?),
+			},
+			src_code: string("src_code")?,
+			trace,
+			head: number(field("head")?, "head")?,
+			tape: bytes("tape")?,
+			input: bytes("input")?,
+			output: bytes("output")?,
+		})
+	}
+
+	/// Human-readable description of the core dump, as printed by the `debug` subcommand.
+	pub fn report(&self) -> String {
+		let mut report = format!("Error: {}\n", self.error);
+		let src_name = self.src_file.as_deref().unwrap_or("<src>");
+		match self.trace.last() {
+			Some(&(line, column)) => {
+				report.push_str(&format!(
+					"Stopped on line {} column {} of {}:\n",
+					line, column, src_name
+				));
+				let src_line = self.src_code.lines().nth(line - 1).unwrap_or("");
+				report.push_str(&format!("\t{}\n", src_line));
+				let indent: String = src_line
+					.chars()
+					.take(column - 1)
+					.map(|c| if c == '\t' { '\t' } else { ' ' })
+					.collect();
+				report.push_str(&format!("\t{}^\n", indent));
+			}
+			None => report.push_str("Stopped before running any instruction.\n"),
+		}
+		if self.trace.len() > 1 {
+			let positions: Vec<String> = self
+				.trace
+				.iter()
+				.map(|(line, column)| format!("{}:{}", line, column))
+				.collect();
+			report.push_str(&format!(
+				"Last instructions run (line:column, oldest first): {}\n",
+				positions.join(" ")
+			));
+		}
+		report.push_str("Tape:\n");
+		report.push_str(&format_tape_dump(
+			&self.tape,
+			self.head,
+			used_cells(&self.tape, self.head),
+		));
+		report.push_str(&format!(
+			"Input read: {:?}\n",
+			String::from_utf8_lossy(&self.input)
+		));
+		report.push_str(&format!(
+			"Output: {:?}\n",
+			String::from_utf8_lossy(&self.output)
+		));
+		report
+	}
+}
+
+fn hex(bytes: &[u8]) -> String {
+	bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+	if !text.len().is_multiple_of(2) || !text.is_ascii() {
+		return None;
+	}
+	(0..text.len())
+		.step_by(2)
+		.map(|index| u8::from_str_radix(&text[index..index + 2], 16).ok())
+		.collect()
+}
@@ -0,0 +1,44 @@
+//! Cost model of brainfuck code, the weights of the instructions being explicit so that the
+//! generators of code (see `gen`) choose between equivalent pieces of code by their cost rather
+//! than by fixed rules. The default model counts the instructions, which gives the shortest code.
+
+/// Weights of the instructions of a piece of code, each instruction counting once however many
+/// times it runs.
+#[derive(Debug, Clone)]
+pub struct CostModel {
+	/// Cost of a `+` or a `-`.
+	pub add: u32,
+	/// Cost of a `<` or a `>`.
+	pub head_move: u32,
+	/// Cost of a `.` or a `,`.
+	pub io: u32,
+	/// Cost of a loop besides its body (its two brackets).
+	pub loop_overhead: u32,
+}
+
+impl Default for CostModel {
+	fn default() -> CostModel {
+		CostModel {
+			add: 1,
+			head_move: 1,
+			io: 1,
+			loop_overhead: 2,
+		}
+	}
+}
+
+impl CostModel {
+	/// Cost of the code, the characters that are not instructions being free.
+	pub fn code_cost(&self, code: &str) -> u32 {
+		code.chars()
+			.map(|c| match c {
+				'+' | '-' => self.add,
+				'<' | '>' => self.head_move,
+				'.' | ',' => self.io,
+				// Both brackets of a loop are counted at the opening one.
+				'[' => self.loop_overhead,
+				_ => 0,
+			})
+			.sum()
+	}
+}
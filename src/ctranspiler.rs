@@ -1,25 +1,276 @@
-use crate::astraw::RawInstr;
-use crate::astsoup::SoupInstr;
-use std::collections::HashMap;
+use crate::astraw::{RawInstr, top_level};
+use crate::astsoup::{SoupInstr, instr_count};
+use crate::extended::ExtInstr;
+use crate::hostcall;
+use crate::intervals::{WrapFacts, wrap_facts};
+use crate::machine::Storage;
+use crate::tapeconfig::{Region, TapeConfig};
+use std::collections::{HashMap, HashSet};
+
+/// What the generated code is optimized for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodegenPreference {
+	/// Each cell update is a statement and each special loop is inlined.
+	Speed,
+	/// Long runs of cell updates are done by loops over a table of deltas, and the special loops
+	/// and the loops that appear several times are calls to helper functions.
+	Size,
+}
+
+/// Minimal number of updates of consecutive cells done by a loop over a table when optimizing
+/// for size.
+const TABLE_THRESHOLD: usize = 8;
+
+/// Minimal number of times a loop appears in the program for it to be a call to a helper function
+/// when optimizing for size.
+const SHARED_LOOP_THRESHOLD: usize = 2;
+
+/// Minimal number of instructions in the body of a loop for it to be a call to a helper function
+/// (the call would take about as much code as a smaller loop).
+const SHARED_LOOP_MIN_BODY: usize = 4;
+
+/// Placement of the opening braces in the generated code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BraceStyle {
+	/// On their own line (Allman style).
+	NextLine,
+	/// At the end of the line that comes before them (K&R style).
+	SameLine,
+}
+
+/// Width of the lines of the generated code in compact mode (see `CFormat::compact`).
+const COMPACT_LINE_WIDTH: usize = 200;
+
+/// Layout of the generated code, which does not change what it does.
+#[derive(Debug, Clone)]
+pub struct CFormat {
+	/// Text that indents each nesting level.
+	pub indent: String,
+	pub brace_style: BraceStyle,
+	/// Consecutive statements of the same nesting level (such as chains of cell updates) are
+	/// joined into lines of at most that many bytes instead of being one per line.
+	pub max_line_width: Option<usize>,
+	/// Every line is joined to the previous one when the width allows (not only statements),
+	/// and the spaces that C does not need are removed.
+	pub minimal_whitespace: bool,
+}
+
+impl Default for CFormat {
+	fn default() -> CFormat {
+		CFormat {
+			indent: "\t".to_owned(),
+			brace_style: BraceStyle::NextLine,
+			max_line_width: None,
+			minimal_whitespace: false,
+		}
+	}
+}
+
+impl CFormat {
+	/// As little whitespace as possible, for huge programs.
+	pub fn compact() -> CFormat {
+		CFormat {
+			indent: String::new(),
+			brace_style: BraceStyle::SameLine,
+			max_line_width: Some(COMPACT_LINE_WIDTH),
+			minimal_whitespace: true,
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct COptions {
+	/// Number of cells of the tape array of the generated program.
+	pub tape_size: usize,
+	/// The generated program stops with an error when a cell would wrap around (go above 255 or
+	/// below 0), instead of wrapping. Instructions that are proven to never make a cell wrap
+	/// around (see `intervals`) are not checked.
+	pub trap_on_overflow: bool,
+	/// Function called to output a byte instead of `putchar` (declared by the generated program
+	/// with the same signature as `putchar`, the user providing its definition).
+	pub write_fn: Option<String>,
+	/// Function called to input a byte instead of `getchar` (declared by the generated program
+	/// with the same signature as `getchar`, the user providing its definition).
+	pub read_fn: Option<String>,
+	pub codegen: CodegenPreference,
+	/// Name of the source file, given to make the generated program refer to it with `#line`
+	/// directives (for debuggers). Only the instructions of programs that are not optimized can
+	/// be mapped back to the source code.
+	pub line_directives: Option<String>,
+	/// The output is flushed before each input, as the interpreter does, so that the output
+	/// interleaves with the input as in the interpreter even when stdout is not a terminal (only
+	/// when the output goes through `putchar`).
+	pub strict_io: bool,
+	/// Maximal number of nested loops in a function of the generated program, the loops nested
+	/// deeper being moved to helper functions, as some C compilers cannot handle deep nesting.
+	/// Only for optimized programs, and the loops that need no nesting (such as those that clear
+	/// a cell) do not count.
+	pub max_nesting: Option<usize>,
+	pub format: CFormat,
+}
+
+/// Positions in the source code of the instructions of a raw program.
+pub struct SourcePositions {
+	pub file_name: String,
+	/// Line and column (starting at 1) of each instruction, in the order of the program.
+	pub instr_positions: Vec<(usize, usize)>,
+}
+
+impl Default for COptions {
+	fn default() -> COptions {
+		COptions {
+			tape_size: 30000,
+			trap_on_overflow: false,
+			write_fn: None,
+			read_fn: None,
+			codegen: CodegenPreference::Speed,
+			line_directives: None,
+			strict_io: false,
+			max_nesting: None,
+			format: CFormat::default(),
+		}
+	}
+}
 
 struct TranspiledC {
 	code: String,
 	indent_level: u32,
+	format: CFormat,
+	/// Index in `code` of the start of the last line, that a line may be joined to.
+	last_line_start: usize,
+	/// Nesting level of the last line, if it is a statement.
+	last_statement_level: Option<u32>,
+	/// Cells are `int`s rather than `unsigned char`s, which is possible when cells never wrap
+	/// around (or when wrapping is trapped).
+	int_cells: bool,
+	trap_on_overflow: bool,
+	/// The output goes through `putchar` (and thus `stdout`), so `fwrite` can be used.
+	stdio_write: bool,
+	/// The input goes through `getchar` (and thus `stdin`), so `fread` can be used.
+	stdio_read: bool,
+	write_fn: String,
+	read_fn: String,
+	/// See `COptions::strict_io`.
+	flush_before_input: bool,
+	codegen: CodegenPreference,
+	/// Values of the first cells of the tape, the others being zeros.
+	initial_tape: Vec<u8>,
+	head_start: usize,
+	/// Regions whose cells are checked after being written, to stop the program if they changed.
+	read_only_regions: Vec<Region>,
+	/// Positions of the raw instructions, to emit `#line` directives.
+	source_positions: Option<SourcePositions>,
+	/// Index of the next raw instruction to emit.
+	raw_index: usize,
+	/// Absent for raw programs, where every instruction is considered to possibly wrap.
+	wrap_facts: Option<WrapFacts>,
+	/// Index of the next soup instruction to emit (in pre-order, see `WrapFacts`).
+	soup_index: usize,
+	/// The program reads the clock (see `Extensions::clock`), so the helper that reads it is
+	/// emitted.
+	uses_clock: bool,
+	/// The program reads random bytes (see `Extensions::random`), so the generator is emitted.
+	uses_random: bool,
+	/// The program calls the host (see `hostcall`), so the host function is declared.
+	uses_host_call: bool,
+	/// Seed of the generator, seeded by the time when the program starts if absent.
+	seed: Option<u64>,
+	/// The program uses Extended Brainfuck Type I instructions (see `extended`), so the storage
+	/// is declared.
+	uses_extended: bool,
+	/// The storage is an array of as many cells as the tape rather than a single cell (see
+	/// `machine`).
+	storage_tape: bool,
+	/// Index of the helper function (see `helper_loop_fn`) of each body of the loops that are
+	/// calls to one.
+	helper_loops: HashMap<Vec<SoupInstr>, usize>,
 }
 
 impl TranspiledC {
-	fn new() -> TranspiledC {
+	fn new(
+		options: &COptions,
+		tape_config: &TapeConfig,
+		source_positions: Option<SourcePositions>,
+		wrap_facts: Option<WrapFacts>,
+	) -> TranspiledC {
+		let never_wraps = wrap_facts
+			.as_ref()
+			.is_some_and(|wrap_facts| wrap_facts.never_wraps());
 		TranspiledC {
 			code: String::new(),
 			indent_level: 0,
+			format: options.format.clone(),
+			last_line_start: 0,
+			last_statement_level: None,
+			int_cells: options.trap_on_overflow || never_wraps,
+			trap_on_overflow: options.trap_on_overflow,
+			stdio_write: options.write_fn.is_none(),
+			stdio_read: options.read_fn.is_none(),
+			write_fn: options.write_fn.as_deref().unwrap_or("putchar").to_owned(),
+			read_fn: options.read_fn.as_deref().unwrap_or("getchar").to_owned(),
+			flush_before_input: options.strict_io && options.write_fn.is_none(),
+			codegen: options.codegen,
+			initial_tape: tape_config.initial_tape.clone(),
+			head_start: tape_config.head_start,
+			read_only_regions: tape_config.read_only_regions().cloned().collect(),
+			source_positions,
+			raw_index: 0,
+			wrap_facts,
+			soup_index: 0,
+			uses_clock: false,
+			uses_random: false,
+			uses_host_call: false,
+			seed: tape_config.seed,
+			uses_extended: false,
+			storage_tape: tape_config.machine.storage == Storage::Tape,
+			helper_loops: HashMap::new(),
 		}
 	}
 
 	fn emit_line(&mut self, line_content: &str) {
-		self.code
-			.extend(std::iter::repeat("\t").take(self.indent_level as usize));
-		self.code.extend(line_content.chars());
-		self.code.extend("\n".chars());
+		let line_content = if self.format.minimal_whitespace {
+			without_spaces(line_content)
+		} else {
+			line_content.to_owned()
+		};
+		let separator = if self.format.minimal_whitespace {
+			""
+		} else {
+			" "
+		};
+		let statement_level = line_content.ends_with(';').then_some(self.indent_level);
+		let last_line = &self.code[self.last_line_start..];
+		let after_last_line = !last_line.is_empty() && !last_line.starts_with('#');
+		let joined = if !after_last_line {
+			false
+		} else if line_content == "{" && self.format.brace_style == BraceStyle::SameLine {
+			true
+		} else {
+			let fits = self.format.max_line_width.is_some_and(|width| {
+				last_line.len() - 1 + separator.len() + line_content.len() <= width
+			});
+			fits && (self.format.minimal_whitespace
+				|| (statement_level.is_some() && statement_level == self.last_statement_level))
+		};
+		if joined {
+			self.code.pop();
+			self.code.push_str(separator);
+		} else {
+			self.last_line_start = self.code.len();
+			for _ in 0..self.indent_level {
+				self.code.push_str(&self.format.indent);
+			}
+		}
+		self.code.push_str(&line_content);
+		self.code.push('\n');
+		self.last_statement_level = statement_level;
+	}
+
+	/// Emits a preprocessor directive, that is not indented and not joined to other lines.
+	fn emit_directive(&mut self, directive: &str) {
+		self.last_line_start = self.code.len();
+		self.code.push_str(directive);
+		self.code.push('\n');
 	}
 
 	fn emit_indent(&mut self) {
@@ -29,15 +280,375 @@ impl TranspiledC {
 		self.indent_level -= 1;
 	}
 
-	fn emit_header(&mut self) {
-		assert!(self.code.len() == 0);
+	fn cell_type(&self) -> &'static str {
+		if self.int_cells {
+			"int"
+		} else {
+			"unsigned char"
+		}
+	}
+
+	/// `block_move_offsets` are the offsets of the block moves that get a helper function.
+	fn emit_header(&mut self, options: &COptions, block_move_offsets: &[isize]) {
+		assert!(self.code.is_empty());
 		assert!(self.indent_level == 0);
-		self.emit_line("#include <stdio.h>");
+		if self.uses_clock {
+			// For `clock_gettime`.
+			self.emit_directive("#define _POSIX_C_SOURCE 199309L");
+		}
+		self.emit_directive("#include <stdio.h>");
+		self.emit_directive("#include <string.h>");
+		if !self.stdio_write {
+			self.emit_line(&format!("int {}(int c);", self.write_fn));
+		}
+		if !self.stdio_read {
+			self.emit_line(&format!("int {}(void);", self.read_fn));
+		}
+		if self.trap_on_overflow || !self.read_only_regions.is_empty() {
+			self.emit_directive("#include <stdlib.h>");
+		}
+		if self.trap_on_overflow {
+			self.emit_line("static void overflow(void)");
+			self.emit_line("{");
+			self.emit_indent();
+			self.emit_line("fputs(\"cell value out of range\\n\", stderr);");
+			self.emit_line("exit(1);");
+			self.emit_unindent();
+			self.emit_line("}");
+		}
+		if let Some(last_region) = self.read_only_regions.last() {
+			// The read-only cells keep the values they start with, copied in `ro`.
+			self.emit_line(&format!(
+				"static {} ro[{}];",
+				self.cell_type(),
+				last_region.cells.end
+			));
+			self.emit_line(&format!(
+				"static void check_writes(const {} *m, unsigned int first, unsigned int count)",
+				self.cell_type()
+			));
+			self.emit_line("{");
+			self.emit_indent();
+			self.emit_line("for (unsigned int i = first; i < first + count; i++)");
+			self.emit_line("{");
+			self.emit_indent();
+			for region in self.read_only_regions.clone() {
+				let lower_bound = if region.cells.start == 0 {
+					String::new()
+				} else {
+					format!("i >= {} && ", region.cells.start)
+				};
+				self.emit_line(&format!(
+					"if ({}i < {} && m[i] != ro[i])",
+					lower_bound, region.cells.end
+				));
+				self.emit_line("{");
+				self.emit_indent();
+				self.emit_line(&format!(
+					"fprintf(stderr, \"the program changed cell %u of the read-only region \
+					 `{}`\\n\", i);",
+					region
+						.name
+						.replace('\\', "\\\\")
+						.replace('"', "\\\"")
+						.replace('%', "%%")
+				));
+				self.emit_line("exit(1);");
+				self.emit_unindent();
+				self.emit_line("}");
+			}
+			self.emit_unindent();
+			self.emit_line("}");
+			self.emit_unindent();
+			self.emit_line("}");
+		}
+		if self.uses_clock || (self.uses_random && self.seed.is_none()) {
+			self.emit_directive("#include <time.h>");
+		}
+		if self.uses_clock {
+			// The clock ticks every 10 milliseconds since the program started.
+			self.emit_line("static struct timespec clock_start;");
+			self.emit_line("static unsigned char read_clock(void)");
+			self.emit_line("{");
+			self.emit_indent();
+			self.emit_line("struct timespec now;");
+			self.emit_line("clock_gettime(CLOCK_MONOTONIC, &now);");
+			self.emit_line(
+				"long long ms = (now.tv_sec - clock_start.tv_sec) * 1000LL \
+				+ (now.tv_nsec - clock_start.tv_nsec) / 1000000;",
+			);
+			self.emit_line("return (unsigned char)(ms / 10);");
+			self.emit_unindent();
+			self.emit_line("}");
+		}
+		if self.uses_random {
+			// SplitMix64, as in the VM.
+			self.emit_directive("#include <stdint.h>");
+			self.emit_line(&format!(
+				"static uint64_t random_state = {}u;",
+				self.seed.unwrap_or(0)
+			));
+			self.emit_line("static unsigned char random_byte(void)");
+			self.emit_line("{");
+			self.emit_indent();
+			self.emit_line("uint64_t z = random_state += 0x9e3779b97f4a7c15u;");
+			self.emit_line("z = (z ^ (z >> 30)) * 0xbf58476d1ce4e5b9u;");
+			self.emit_line("z = (z ^ (z >> 27)) * 0x94d049bb133111ebu;");
+			self.emit_line("return (unsigned char)((z ^ (z >> 31)) >> 56);");
+			self.emit_unindent();
+			self.emit_line("}");
+		}
+		if self.uses_host_call {
+			// Defined by the host, that links the program with it.
+			self.emit_line(&format!(
+				"extern unsigned char {}(unsigned char value);",
+				hostcall::C_FUNCTION
+			));
+		}
+		for &offset in block_move_offsets {
+			self.emit_line(&format!(
+				"static unsigned int {}({} *m, unsigned int h)",
+				block_move_fn(offset),
+				self.cell_type()
+			));
+			self.emit_line("{");
+			self.emit_indent();
+			self.emit_block_move_statements(offset, self.trap_on_overflow);
+			self.emit_line("return h;");
+			self.emit_unindent();
+			self.emit_line("}");
+		}
+		// The storage is shared with the helpers of the loops if there are some.
+		let static_storage = self.uses_extended && !self.helper_loops.is_empty();
+		if static_storage && self.storage_tape {
+			self.emit_line(&format!("static unsigned char s[{}];", options.tape_size));
+		} else if static_storage {
+			self.emit_line("static unsigned char s;");
+		}
+		// A helper can run for several loops of the program, so its updates are checked as if
+		// any of them could wrap around.
+		let wrap_facts = self.wrap_facts.take();
+		let mut helper_loops: Vec<(Vec<SoupInstr>, usize)> =
+			self.helper_loops.clone().into_iter().collect();
+		helper_loops.sort_by_key(|&(_, index)| index);
+		// Declared first, as helpers call each other.
+		for &(_, index) in &helper_loops {
+			self.emit_line(&format!(
+				"static unsigned int {}({} *m, unsigned int h);",
+				helper_loop_fn(index),
+				self.cell_type()
+			));
+		}
+		for (body, index) in helper_loops {
+			self.emit_line(&format!(
+				"static unsigned int {}({} *m, unsigned int h)",
+				helper_loop_fn(index),
+				self.cell_type()
+			));
+			self.emit_line("{");
+			self.emit_indent();
+			self.emit_line("while (m[h])");
+			self.emit_line("{");
+			self.emit_indent();
+			self.emit_soup_instr_seq(&body);
+			self.emit_unindent();
+			self.emit_line("}");
+			self.emit_line("return h;");
+			self.emit_unindent();
+			self.emit_line("}");
+		}
+		self.wrap_facts = wrap_facts;
+		self.soup_index = 0;
 		self.emit_line("int main(void)");
 		self.emit_line("{");
 		self.emit_indent();
-		self.emit_line("unsigned char m[30000] = {0};");
-		self.emit_line("unsigned int h = 0;");
+		assert!(self.initial_tape.len() <= options.tape_size);
+		let initializer = if self.initial_tape.is_empty() {
+			"0".to_owned()
+		} else {
+			let values: Vec<String> = self
+				.initial_tape
+				.iter()
+				.map(|value| format!("0x{:02x}", value))
+				.collect();
+			values.join(", ")
+		};
+		self.emit_line(&format!(
+			"{} m[{}] = {{{}}};",
+			self.cell_type(),
+			options.tape_size,
+			initializer
+		));
+		if !self.read_only_regions.is_empty() {
+			self.emit_line("memcpy(ro, m, sizeof ro);");
+		}
+		self.emit_line(&format!("unsigned int h = {};", self.head_start));
+		if self.uses_clock {
+			self.emit_line("clock_gettime(CLOCK_MONOTONIC, &clock_start);");
+		}
+		if self.uses_random && self.seed.is_none() {
+			self.emit_line("random_state = (uint64_t)time(NULL);");
+		}
+		if self.uses_extended && self.storage_tape && !static_storage {
+			self.emit_line(&format!("unsigned char s[{}] = {{0}};", options.tape_size));
+		} else if self.uses_extended && !static_storage {
+			self.emit_line("unsigned char s = 0;");
+		}
+	}
+
+	/// Emits a check that the written cells (from `first`, `count` of them) are not read-only
+	/// cells that changed, if there are read-only cells.
+	fn emit_write_check(&mut self, first: &str, count: &str) {
+		if !self.read_only_regions.is_empty() {
+			self.emit_line(&format!("check_writes(m, {}, {});", first, count));
+		}
+	}
+
+	/// Emits a cell update, followed by a check that the cell did not wrap around if needed.
+	fn emit_cell_update(&mut self, update: &str, relative_head: isize, checked: bool) {
+		self.emit_line(update);
+		if checked {
+			self.emit_line(&format!("if (m[{}] & ~255) overflow();", h(relative_head)));
+		}
+		self.emit_write_check(&h(relative_head), "1");
+	}
+
+	/// Adds the deltas (multiplied by the factor if any) to the cells at their offsets.
+	fn emit_cell_deltas(
+		&mut self,
+		cell_deltas: impl Iterator<Item = (isize, isize)>,
+		factor: Option<&str>,
+		checked: bool,
+	) {
+		let times_factor = factor.map_or(String::new(), |factor| format!("{} * ", factor));
+		let cell_deltas: Vec<(isize, isize)> = cell_deltas.collect();
+		// Runs of consecutive offsets.
+		let mut runs: Vec<&[(isize, isize)]> = Vec::new();
+		let mut run_start = 0;
+		for index in 1..=cell_deltas.len() {
+			if index == cell_deltas.len() || cell_deltas[index].0 != cell_deltas[index - 1].0 + 1 {
+				runs.push(&cell_deltas[run_start..index]);
+				run_start = index;
+			}
+		}
+		for run in runs {
+			if self.codegen == CodegenPreference::Size && run.len() >= TABLE_THRESHOLD {
+				let delta_type = if run.iter().all(|&(_, delta)| (-128..=127).contains(&delta)) {
+					"signed char"
+				} else {
+					"int"
+				};
+				let deltas: Vec<String> = run.iter().map(|(_, delta)| delta.to_string()).collect();
+				let cell = format!("m[{} + i]", h(run[0].0));
+				self.emit_line("{");
+				self.emit_indent();
+				self.emit_line(&format!(
+					"static const {} d[] = {{{}}};",
+					delta_type,
+					deltas.join(", ")
+				));
+				self.emit_line("for (unsigned int i = 0; i < sizeof d / sizeof d[0]; i++)");
+				self.emit_line("{");
+				self.emit_indent();
+				self.emit_line(&format!("{} += {}d[i];", cell, times_factor));
+				if checked {
+					self.emit_line(&format!("if ({} & ~255) overflow();", cell));
+				}
+				self.emit_unindent();
+				self.emit_line("}");
+				self.emit_write_check(&h(run[0].0), "sizeof d / sizeof d[0]");
+				self.emit_unindent();
+				self.emit_line("}");
+			} else {
+				for &(relative_head, delta) in run {
+					self.emit_cell_update(
+						&format!("m[{}] += {}{};", h(relative_head), times_factor, delta),
+						relative_head,
+						checked,
+					);
+				}
+			}
+		}
+	}
+
+	/// Statements of a block move (see `SoupInstr::BlockMove`) that use and update `h`.
+	fn emit_block_move_statements(&mut self, offset: isize, checked: bool) {
+		// `n` is the length of the run of non-zero cells, which goes from `h` to `first`
+		// (included) in the direction opposite to the offset.
+		let (scan, first, moved, shifted, direction) = if offset == 1 {
+			("h - n", "h - n + 1", "h - n + 2", "h - n + 1", "-")
+		} else {
+			("h + n", "h + n - 1", "h", "h + 1", "+")
+		};
+		self.emit_line("unsigned int n = 0;");
+		self.emit_line(&format!("while (m[{}]) n++;", scan));
+		self.emit_line("if (n)");
+		self.emit_line("{");
+		self.emit_indent();
+		self.emit_cell_update(&format!("m[{}] += m[h];", h(offset)), offset, checked);
+		self.emit_line(&format!(
+			"memmove(&m[{}], &m[{}], (n - 1) * sizeof m[0]);",
+			moved, shifted
+		));
+		self.emit_line(&format!("m[{}] = 0;", first));
+		// The run and the cell it moved into.
+		self.emit_write_check(if offset == 1 { first } else { "h - 1" }, "n + 1");
+		self.emit_unindent();
+		self.emit_line("}");
+		self.emit_line(&format!("h {}= n;", direction));
+	}
+
+	fn emit_flush_before_input(&mut self) {
+		if self.flush_before_input {
+			self.emit_line("fflush(stdout);");
+		}
+	}
+
+	fn emit_input(&mut self, relative_head: isize) {
+		self.emit_flush_before_input();
+		if self.int_cells {
+			// Keep the `unsigned char` behavior (EOF becoming 255).
+			self.emit_line(&format!(
+				"m[{}] = (unsigned char){}();",
+				h(relative_head),
+				self.read_fn
+			));
+		} else {
+			self.emit_line(&format!("m[{}] = {}();", h(relative_head), self.read_fn));
+		}
+		self.emit_write_check(&h(relative_head), "1");
+	}
+
+	fn emit_clock(&mut self, relative_head: isize) {
+		self.emit_line(&format!("m[{}] = read_clock();", h(relative_head)));
+		self.emit_write_check(&h(relative_head), "1");
+	}
+
+	fn emit_random(&mut self, relative_head: isize) {
+		self.emit_line(&format!("m[{}] = random_byte();", h(relative_head)));
+		self.emit_write_check(&h(relative_head), "1");
+	}
+
+	fn emit_host_call(&mut self, relative_head: isize) {
+		let cell = format!("m[{}]", h(relative_head));
+		self.emit_line(&format!("{} = {}({});", cell, hostcall::C_FUNCTION, cell));
+		self.emit_write_check(&h(relative_head), "1");
+	}
+
+	fn emit_extended(&mut self, instr: ExtInstr, relative_head: isize) {
+		let storage = if self.storage_tape {
+			format!("s[{}]", h(relative_head))
+		} else {
+			"s".to_owned()
+		};
+		self.emit_line(&instr.c_statement(&format!("m[{}]", h(relative_head)), &storage));
+		if instr.writes_cell() {
+			self.emit_write_check(&h(relative_head), "1");
+		}
+	}
+
+	fn emit_output(&mut self, relative_head: isize) {
+		self.emit_line(&format!("{}(m[{}]);", self.write_fn, h(relative_head)));
 	}
 
 	fn emit_footer(&mut self) {
@@ -47,16 +658,48 @@ impl TranspiledC {
 		assert!(self.indent_level == 0);
 	}
 
-	fn emit_raw_instr_seq(&mut self, instr_seq: Vec<RawInstr>) {
-		for instr in instr_seq {
+	/// Makes the next line of the generated code refer to the position of the next raw
+	/// instruction, the column being in a comment.
+	fn emit_line_directive(&mut self) {
+		if let Some(source_positions) = &self.source_positions {
+			let (line, column) = source_positions.instr_positions[self.raw_index];
+			let directive = format!(
+				"#line {} \"{}\" /* column {} */",
+				line,
+				source_positions
+					.file_name
+					.replace('\\', "\\\\")
+					.replace('"', "\\\""),
+				column
+			);
+			self.emit_directive(&directive);
+		}
+		self.raw_index += 1;
+	}
+
+	fn emit_raw_instr_seq(&mut self, instr_seq: &[RawInstr]) {
+		for (instr, body) in top_level(instr_seq) {
+			self.emit_line_directive();
 			match instr {
-				RawInstr::Plus => self.emit_line("m[h]++;"),
-				RawInstr::Minus => self.emit_line("m[h]--;"),
-				RawInstr::Left => self.emit_line("h--;"),
-				RawInstr::Right => self.emit_line("h++;"),
-				RawInstr::Dot => self.emit_line("putchar(m[h]);"),
-				RawInstr::Comma => self.emit_line("m[h] = getchar();"),
-				RawInstr::BracketLoop(body) => {
+				RawInstr::Plus(1) => self.emit_cell_update("m[h]++;", 0, self.trap_on_overflow),
+				RawInstr::Plus(n) => {
+					self.emit_cell_update(&format!("m[h] += {};", n), 0, self.trap_on_overflow)
+				}
+				RawInstr::Minus(1) => self.emit_cell_update("m[h]--;", 0, self.trap_on_overflow),
+				RawInstr::Minus(n) => {
+					self.emit_cell_update(&format!("m[h] -= {};", n), 0, self.trap_on_overflow)
+				}
+				RawInstr::Left(1) => self.emit_line("h--;"),
+				RawInstr::Left(n) => self.emit_line(&format!("h -= {};", n)),
+				RawInstr::Right(1) => self.emit_line("h++;"),
+				RawInstr::Right(n) => self.emit_line(&format!("h += {};", n)),
+				RawInstr::Dot => self.emit_output(0),
+				RawInstr::Comma => self.emit_input(0),
+				RawInstr::Clock => self.emit_clock(0),
+				RawInstr::Random => self.emit_random(0),
+				RawInstr::Extended(instr) => self.emit_extended(*instr, 0),
+				RawInstr::HostCall => self.emit_host_call(0),
+				RawInstr::BracketLoop { .. } => {
 					self.emit_line("while (m[h])");
 					self.emit_line("{");
 					self.emit_indent();
@@ -68,42 +711,57 @@ impl TranspiledC {
 		}
 	}
 
-	fn emit_soup_instr_seq(&mut self, instr_seq: Vec<SoupInstr>) {
+	fn emit_soup_instr_seq(&mut self, instr_seq: &[SoupInstr]) {
 		for instr in instr_seq {
+			let checked = self.trap_on_overflow
+				&& self
+					.wrap_facts
+					.as_ref()
+					.is_none_or(|wrap_facts| wrap_facts.may_wrap(self.soup_index));
+			self.soup_index += 1;
 			match instr {
 				SoupInstr::Soup {
 					cell_deltas,
 					head_delta,
 				} => {
-					let cell_deltas = sort_cell_deltas(cell_deltas);
-					for (relative_head, delta) in cell_deltas {
-						self.emit_line(&format!("m[{}] += {};", h(relative_head), delta));
-					}
-					if head_delta != 0 {
+					self.emit_cell_deltas(
+						cell_deltas.iter().map(|(&offset, &delta)| (offset, delta)),
+						None,
+						checked,
+					);
+					if *head_delta != 0 {
 						self.emit_line(&format!("h += {};", head_delta));
 					}
 				}
-				SoupInstr::Output => self.emit_line("putchar(m[h]);"),
-				SoupInstr::Input => self.emit_line("m[h] = getchar();"),
+				SoupInstr::Seek { delta } => self.emit_line(&format!("h += {};", delta)),
+				SoupInstr::Output { offset } => self.emit_output(*offset),
+				SoupInstr::Input { offset } => self.emit_input(*offset),
+				SoupInstr::Clock { offset } => self.emit_clock(*offset),
+				SoupInstr::Random { offset } => self.emit_random(*offset),
+				SoupInstr::Extended { instr, offset } => self.emit_extended(*instr, *offset),
+				SoupInstr::HostCall { offset } => self.emit_host_call(*offset),
 				SoupInstr::MultFixedLoop { cell_deltas } => {
 					assert!(matches!(cell_deltas.get(&0), Some(-1)));
-					let cell_deltas = sort_cell_deltas(cell_deltas);
-					for (relative_head, delta) in cell_deltas.iter() {
-						if *relative_head == 0 {
-							continue;
-						}
-						self.emit_line(&format!("m[{}] += m[h] * {};", h(*relative_head), delta));
-					}
-					self.emit_line(&format!("m[h] = 0;"));
+					self.emit_cell_deltas(
+						cell_deltas
+							.iter()
+							.filter(|(&offset, _)| offset != 0)
+							.map(|(&offset, &delta)| (offset, delta)),
+						Some("m[h]"),
+						checked,
+					);
+					self.emit_line("m[h] = 0;");
+					self.emit_write_check("h", "1");
 				}
 				SoupInstr::SoupFixedLoop { cell_deltas } => {
 					self.emit_line("while (m[h])");
 					self.emit_line("{");
 					self.emit_indent();
-					let cell_deltas = sort_cell_deltas(cell_deltas);
-					for (relative_head, delta) in cell_deltas {
-						self.emit_line(&format!("m[{}] += {};", h(relative_head), delta));
-					}
+					self.emit_cell_deltas(
+						cell_deltas.iter().map(|(&offset, &delta)| (offset, delta)),
+						None,
+						checked,
+					);
 					self.emit_unindent();
 					self.emit_line("}");
 				}
@@ -114,15 +772,81 @@ impl TranspiledC {
 					self.emit_line("while (m[h])");
 					self.emit_line("{");
 					self.emit_indent();
-					let cell_deltas = sort_cell_deltas(cell_deltas);
-					for (relative_head, delta) in cell_deltas {
-						self.emit_line(&format!("m[{}] += {};", h(relative_head), delta));
-					}
+					self.emit_cell_deltas(
+						cell_deltas.iter().map(|(&offset, &delta)| (offset, delta)),
+						None,
+						checked,
+					);
 					self.emit_line(&format!("h += {};", head_delta));
 					self.emit_unindent();
 					self.emit_line("}");
 				}
-				SoupInstr::Loop(body) => {
+				SoupInstr::BlockMove { offset } if self.codegen == CodegenPreference::Size => {
+					self.emit_line(&format!("h = {}(m, h);", block_move_fn(*offset)))
+				}
+				SoupInstr::BlockMove { offset } => {
+					self.emit_line("{");
+					self.emit_indent();
+					self.emit_block_move_statements(*offset, checked);
+					self.emit_unindent();
+					self.emit_line("}");
+				}
+				SoupInstr::PrintUntilZero { stride: 1 } if !self.int_cells && self.stdio_write => {
+					self.emit_line("{");
+					self.emit_indent();
+					self.emit_line("size_t n = strlen((char *)&m[h]);");
+					self.emit_line("fwrite(&m[h], 1, n, stdout);");
+					self.emit_line("h += n;");
+					self.emit_unindent();
+					self.emit_line("}");
+				}
+				SoupInstr::PrintUntilZero { stride } => {
+					self.emit_line("while (m[h])");
+					self.emit_line("{");
+					self.emit_indent();
+					self.emit_output(0);
+					self.emit_line(&format!("h += {};", stride));
+					self.emit_unindent();
+					self.emit_line("}");
+				}
+				SoupInstr::ReadBlock { stride: 1 } if !self.int_cells && self.stdio_read => {
+					// Missing bytes read as EOF, which is 255 in a cell (as with `getchar`).
+					self.emit_line("{");
+					self.emit_indent();
+					self.emit_line("size_t n = strlen((char *)&m[h]);");
+					self.emit_flush_before_input();
+					self.emit_line("size_t r = fread(&m[h], 1, n, stdin);");
+					self.emit_line("memset(&m[h + r], 255, n - r);");
+					self.emit_write_check("h", "n");
+					self.emit_line("h += n;");
+					self.emit_unindent();
+					self.emit_line("}");
+				}
+				SoupInstr::ReadBlock { stride } => {
+					self.emit_line("while (m[h])");
+					self.emit_line("{");
+					self.emit_indent();
+					self.emit_input(0);
+					self.emit_line(&format!("h += {};", stride));
+					self.emit_unindent();
+					self.emit_line("}");
+				}
+				SoupInstr::ReadUntilZero { stride } => {
+					// Not `fread`, which could read past the zero byte.
+					self.emit_line("while (m[h])");
+					self.emit_line("{");
+					self.emit_indent();
+					self.emit_line(&format!("h += {};", stride));
+					self.emit_input(0);
+					self.emit_unindent();
+					self.emit_line("}");
+				}
+				SoupInstr::Loop(body, _) if self.helper_loops.contains_key(body) => {
+					let index = self.helper_loops[body];
+					self.emit_line(&format!("h = {}(m, h);", helper_loop_fn(index)));
+					self.soup_index += instr_count(body);
+				}
+				SoupInstr::Loop(body, _) => {
 					self.emit_line("while (m[h])");
 					self.emit_line("{");
 					self.emit_indent();
@@ -135,30 +859,103 @@ impl TranspiledC {
 	}
 }
 
-pub fn transpile_raw_to_c(instr_seq: Vec<RawInstr>) -> String {
-	let mut transpiled = TranspiledC::new();
-	transpiled.emit_header();
+/// The tape starts as configured (see `tapeconfig`), and the source positions are given to emit
+/// `#line` directives.
+pub fn transpile_raw_to_c(
+	instr_seq: &[RawInstr],
+	options: &COptions,
+	tape_config: &TapeConfig,
+	source_positions: Option<SourcePositions>,
+) -> String {
+	let mut transpiled = TranspiledC::new(options, tape_config, source_positions, None);
+	transpiled.uses_clock = instr_seq
+		.iter()
+		.any(|instr| matches!(instr, RawInstr::Clock));
+	transpiled.uses_random = instr_seq
+		.iter()
+		.any(|instr| matches!(instr, RawInstr::Random));
+	transpiled.uses_extended = instr_seq
+		.iter()
+		.any(|instr| matches!(instr, RawInstr::Extended(_)));
+	transpiled.uses_host_call = instr_seq
+		.iter()
+		.any(|instr| matches!(instr, RawInstr::HostCall));
+	transpiled.emit_header(options, &[]);
 	transpiled.emit_raw_instr_seq(instr_seq);
 	transpiled.emit_footer();
 	transpiled.code
 }
 
-pub fn transpile_soup_to_c(instr_seq: Vec<SoupInstr>) -> String {
-	let mut transpiled = TranspiledC::new();
-	transpiled.emit_header();
+/// The tape starts as configured (see `tapeconfig`).
+pub fn transpile_soup_to_c(
+	instr_seq: &[SoupInstr],
+	options: &COptions,
+	tape_config: &TapeConfig,
+) -> String {
+	let wrap_facts = wrap_facts(instr_seq, tape_config);
+	let mut transpiled = TranspiledC::new(options, tape_config, None, Some(wrap_facts));
+	let mut block_move_offsets = Vec::new();
+	if options.codegen == CodegenPreference::Size {
+		collect_block_move_offsets(instr_seq, &mut block_move_offsets);
+		block_move_offsets.sort();
+		block_move_offsets.dedup();
+	}
+	transpiled.helper_loops = helper_loops(instr_seq, options);
+	transpiled.uses_clock =
+		soup_uses(instr_seq, &|instr| matches!(instr, SoupInstr::Clock { .. }));
+	transpiled.uses_random = soup_uses(instr_seq, &|instr| {
+		matches!(instr, SoupInstr::Random { .. })
+	});
+	transpiled.uses_extended = soup_uses(instr_seq, &|instr| {
+		matches!(instr, SoupInstr::Extended { .. })
+	});
+	transpiled.uses_host_call = soup_uses(instr_seq, &|instr| {
+		matches!(instr, SoupInstr::HostCall { .. })
+	});
+	transpiled.emit_header(options, &block_move_offsets);
 	transpiled.emit_soup_instr_seq(instr_seq);
 	transpiled.emit_footer();
 	transpiled.code
 }
 
-// Head relative positions are sorted for output readability purposes
-fn sort_cell_deltas(cell_deltas: HashMap<isize, isize>) -> Vec<(isize, isize)> {
-	let mut cell_deltas = cell_deltas
-		.iter()
-		.map(|(&k, &v)| (k, v))
-		.collect::<Vec<(isize, isize)>>();
-	cell_deltas.sort_by(|(ka, _), (kb, _)| ka.partial_cmp(kb).unwrap());
-	cell_deltas
+/// The line without the spaces that C does not need (outside of literals), that is all of them
+/// but those between two words or between two symbols that would make another token.
+fn without_spaces(line: &str) -> String {
+	let chars: Vec<char> = line.chars().collect();
+	let mut compact = String::with_capacity(line.len());
+	let mut literal_quote = None;
+	let mut escaped = false;
+	for (index, &c) in chars.iter().enumerate() {
+		if let Some(quote) = literal_quote {
+			if escaped {
+				escaped = false;
+			} else if c == '\\' {
+				escaped = true;
+			} else if c == quote {
+				literal_quote = None;
+			}
+		} else if c == '"' || c == '\'' {
+			literal_quote = Some(c);
+		} else if c == ' ' {
+			let previous = compact.chars().last();
+			let next = chars[index + 1..].iter().copied().find(|&next| next != ' ');
+			let needed = match (previous, next) {
+				(Some(previous), Some(next)) => {
+					let word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+					(word(previous) && word(next))
+						|| (previous == next && "+-&|<>=/".contains(next))
+						|| (next == '=' && "+-*/%&|^<>=!".contains(previous))
+						|| matches!((previous, next), ('/', '*') | ('*', '/') | ('-', '>'))
+				}
+				_ => false,
+			};
+			if !needed || compact.ends_with(' ') {
+				continue;
+			}
+		}
+		compact.push(c);
+	}
+	compact
 }
 
 fn h(relative_head: isize) -> String {
@@ -168,3 +965,105 @@ fn h(relative_head: isize) -> String {
 		format!("h + {}", relative_head)
 	}
 }
+
+/// Name of the helper function that does the block moves of the given offset.
+fn block_move_fn(offset: isize) -> &'static str {
+	if offset == 1 {
+		"block_move_right"
+	} else {
+		"block_move_left"
+	}
+}
+
+fn collect_block_move_offsets(instr_seq: &[SoupInstr], offsets: &mut Vec<isize>) {
+	for instr in instr_seq {
+		match instr {
+			SoupInstr::BlockMove { offset } => offsets.push(*offset),
+			SoupInstr::Loop(body, _) => collect_block_move_offsets(body, offsets),
+			_ => (),
+		}
+	}
+}
+
+/// Name of the helper function that runs the loop of the given index.
+fn helper_loop_fn(index: usize) -> String {
+	format!("loop_{}", index)
+}
+
+/// Bodies of the loops that are calls to helper functions, with the index of their helper: the
+/// loops that appear enough times when optimizing for size (but for those with the `hot`
+/// attribute, see `LoopAttributes`), and the loops nested too deep (see `COptions::max_nesting`).
+fn helper_loops(instr_seq: &[SoupInstr], options: &COptions) -> HashMap<Vec<SoupInstr>, usize> {
+	let mut shared = HashSet::new();
+	if options.codegen == CodegenPreference::Size {
+		let mut counts = HashMap::new();
+		let mut hot = HashSet::new();
+		count_loop_bodies(instr_seq, &mut counts, &mut hot);
+		shared = counts
+			.into_iter()
+			.filter(|&(body, count)| {
+				count >= SHARED_LOOP_THRESHOLD
+					&& instr_count(body) >= SHARED_LOOP_MIN_BODY
+					&& !hot.contains(body)
+			})
+			.map(|(body, _)| body)
+			.collect();
+	}
+	let mut indices = HashMap::new();
+	collect_helper_loops(instr_seq, 0, options.max_nesting, &shared, &mut indices);
+	indices
+		.into_iter()
+		.map(|(body, index)| (body.to_vec(), index))
+		.collect()
+}
+
+/// Adds the bodies of the loops of the sequence that are calls to helper functions to `indices`
+/// (numbered in the order they are found), `depth` being the number of loops the sequence is in
+/// within its function.
+fn collect_helper_loops<'a>(
+	instr_seq: &'a [SoupInstr],
+	depth: usize,
+	max_nesting: Option<usize>,
+	shared: &HashSet<&[SoupInstr]>,
+	indices: &mut HashMap<&'a [SoupInstr], usize>,
+) {
+	for instr in instr_seq {
+		if let SoupInstr::Loop(body, _) = instr {
+			let body = body.as_slice();
+			let is_helper = shared.contains(body)
+				|| indices.contains_key(body)
+				|| max_nesting.is_some_and(|max_nesting| depth >= max_nesting);
+			// The body of a helper is in the loop of the helper only.
+			let body_depth = if is_helper { 1 } else { depth + 1 };
+			collect_helper_loops(body, body_depth, max_nesting, shared, indices);
+			if is_helper && !indices.contains_key(body) {
+				indices.insert(body, indices.len());
+			}
+		}
+	}
+}
+
+/// Counts the occurrences of each loop body, and adds the bodies of the `hot` loops to `hot`.
+fn count_loop_bodies<'a>(
+	instr_seq: &'a [SoupInstr],
+	counts: &mut HashMap<&'a [SoupInstr], usize>,
+	hot: &mut HashSet<&'a [SoupInstr]>,
+) {
+	for instr in instr_seq {
+		if let SoupInstr::Loop(body, attributes) = instr {
+			count_loop_bodies(body, counts, hot);
+			*counts.entry(body.as_slice()).or_insert(0) += 1;
+			if attributes.hot {
+				hot.insert(body.as_slice());
+			}
+		}
+	}
+}
+
+/// Whether an instruction of the sequence (or of the loop bodies in it) matches.
+fn soup_uses(instr_seq: &[SoupInstr], matching: &dyn Fn(&SoupInstr) -> bool) -> bool {
+	instr_seq.iter().any(|instr| match instr {
+		SoupInstr::Loop(body, _) => soup_uses(body, matching),
+		instr => matching(instr),
+	})
+}
@@ -0,0 +1,38 @@
+//! Stripping of the instructions that cannot be reached before code generation, as found on the
+//! graph IR by the interval analysis (see `intervals`): those after an `@` (that ends the
+//! program) or after a loop that never exits, and the loops that are never entered. Programs
+//! made of concatenated snippets can have large dead tails that the backends would emit.
+
+use crate::astsoup::SoupInstr;
+use crate::intervals::reachable_instrs;
+use crate::tapeconfig::TapeConfig;
+
+/// The program without its unreachable instructions (see the module documentation).
+pub fn strip_dead_code(soup_prog: &[SoupInstr], tape_config: &TapeConfig) -> Vec<SoupInstr> {
+	let reachable = reachable_instrs(soup_prog, tape_config);
+	strip_instr_seq(soup_prog, &reachable, &mut 0)
+}
+
+fn strip_instr_seq(
+	instr_seq: &[SoupInstr],
+	reachable: &[bool],
+	soup_index: &mut usize,
+) -> Vec<SoupInstr> {
+	let mut stripped = Vec::new();
+	for instr in instr_seq {
+		let index = *soup_index;
+		*soup_index += 1;
+		match instr {
+			SoupInstr::Loop(body, attributes) => {
+				// The body is visited even if the loop is stripped, for the indices to follow.
+				let body = strip_instr_seq(body, reachable, soup_index);
+				if reachable[index] {
+					stripped.push(SoupInstr::Loop(body, *attributes));
+				}
+			}
+			instr if reachable[index] => stripped.push(instr.clone()),
+			_ => (),
+		}
+	}
+	stripped
+}
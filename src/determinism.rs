@@ -0,0 +1,120 @@
+//! Determinism audit: the program is compiled twice from its source code and each compiled
+//! program is run once, under the same configuration, the two runs having to agree on everything
+//! they produce. Nondeterminism could come from the iteration order of the `HashMap`s used by the
+//! compiler (see `graph` and `intervals`) or from the host (time, input).
+//!
+//! The host is kept out of the runs: they read the given input (the end of the input being read
+//! as zeros rather than stdin being read), the random extension is seeded with the configured seed
+//! or 0, and the clock extension is virtual.
+
+use crate::passes::PassManager;
+use crate::program::Program;
+use crate::tapeconfig::TapeConfig;
+use crate::tapedump::used_cells;
+use crate::tokenizer::Extensions;
+use crate::vm::Observer;
+
+/// What a run of a compiled program produced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunRecord {
+	/// Hash of the IR of the compiled program (see `Program::ir_hash`).
+	pub ir_hash: u64,
+	pub output: Vec<u8>,
+	/// Number of steps run (see `Observer::on_progress`).
+	pub steps: u64,
+	/// Final head and cells of the tape (up to the last non-zero cell or the head).
+	pub head: usize,
+	pub tape: Vec<u8>,
+}
+
+/// Observer that records the number of steps and the final tape.
+#[derive(Default)]
+struct Recorder {
+	steps: u64,
+	head: usize,
+	tape: Vec<u8>,
+}
+
+impl Observer for Recorder {
+	fn on_output(&mut self, _char_values: &[u8]) {}
+
+	fn counts_steps(&self) -> bool {
+		true
+	}
+
+	fn on_progress(&mut self, steps: u64, _head: usize) {
+		self.steps = steps;
+	}
+
+	fn on_halt(&mut self, tape: &[u8], head: usize) {
+		self.head = head;
+		self.tape = tape[used_cells(tape, head)].to_vec();
+	}
+}
+
+/// Compiles the program (optimized with the pass manager if `optimize`) and runs it on the input.
+fn compile_and_run(
+	src_code: &str,
+	extensions: &Extensions,
+	tape_config: &TapeConfig,
+	pass_manager: &PassManager,
+	optimize: bool,
+	input: &[u8],
+) -> Result<RunRecord, String> {
+	let mut program = Program::parse(src_code, extensions)
+		.map_err(|_| "the program does not parse".to_owned())?;
+	program
+		.set_tape_config(tape_config.clone())
+		.map_err(|error| error.to_string())?;
+	if optimize {
+		program
+			.optimize_with(1, pass_manager)
+			.map_err(|error| error.to_string())?;
+	}
+	let mut recorder = Recorder::default();
+	let output = program.run_observed(Some(input.to_vec()), None, &mut recorder);
+	Ok(RunRecord {
+		ir_hash: program.ir_hash(),
+		output,
+		steps: recorder.steps,
+		head: recorder.head,
+		tape: recorder.tape,
+	})
+}
+
+/// Compiles and runs the program twice, and returns the records of both runs and what differs
+/// between them (nothing if the program and the compiler are deterministic).
+pub fn check_determinism(
+	src_code: &str,
+	extensions: &Extensions,
+	tape_config: &TapeConfig,
+	pass_manager: &PassManager,
+	optimize: bool,
+	input: &[u8],
+) -> Result<([RunRecord; 2], Vec<&'static str>), String> {
+	let mut tape_config = tape_config.clone();
+	tape_config.seed = Some(tape_config.seed.unwrap_or(0));
+	let run = || {
+		compile_and_run(
+			src_code,
+			extensions,
+			&tape_config,
+			pass_manager,
+			optimize,
+			input,
+		)
+	};
+	let records = [run()?, run()?];
+	let [first, second] = &records;
+	let differences = [
+		("compiled program", first.ir_hash != second.ir_hash),
+		("output", first.output != second.output),
+		("step count", first.steps != second.steps),
+		("final head", first.head != second.head),
+		("final tape", first.tape != second.tape),
+	]
+	.iter()
+	.filter_map(|&(what, differs)| differs.then_some(what))
+	.collect();
+	Ok((records, differences))
+}
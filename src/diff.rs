@@ -0,0 +1,132 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DiffOp {
+	Equal,
+	Delete,
+	Insert,
+}
+
+/// Number of unchanged lines printed around changes.
+const CONTEXT_LINES: usize = 3;
+
+/// Line-based unified diff (in the format of `diff -u`) from `old` to `new`, empty if they have
+/// the same lines.
+pub fn unified_diff(old_name: &str, new_name: &str, old: &str, new: &str) -> String {
+	let old_lines: Vec<&str> = old.lines().collect();
+	let new_lines: Vec<&str> = new.lines().collect();
+	let script = edit_script(&old_lines, &new_lines);
+	if script.iter().all(|&op| op == DiffOp::Equal) {
+		return String::new();
+	}
+
+	// Position in the old and new lines before each operation of the script.
+	let mut positions: Vec<(usize, usize)> = Vec::with_capacity(script.len() + 1);
+	let (mut old_pos, mut new_pos) = (0, 0);
+	for &op in script.iter() {
+		positions.push((old_pos, new_pos));
+		match op {
+			DiffOp::Equal => {
+				old_pos += 1;
+				new_pos += 1;
+			}
+			DiffOp::Delete => old_pos += 1,
+			DiffOp::Insert => new_pos += 1,
+		}
+	}
+	positions.push((old_pos, new_pos));
+
+	// Ranges of script operations to print, changes with some context around them.
+	let mut hunks: Vec<(usize, usize)> = Vec::new();
+	for (index, &op) in script.iter().enumerate() {
+		if op == DiffOp::Equal {
+			continue;
+		}
+		let start = index.saturating_sub(CONTEXT_LINES);
+		let end = (index + 1 + CONTEXT_LINES).min(script.len());
+		match hunks.last_mut() {
+			Some(last) if last.1 >= start => last.1 = end,
+			_ => hunks.push((start, end)),
+		}
+	}
+
+	let mut diff = format!("--- {}\n+++ {}\n", old_name, new_name);
+	for (start, end) in hunks {
+		let (old_start, new_start) = positions[start];
+		let (old_end, new_end) = positions[end];
+		diff.push_str(&format!(
+			"@@ -{},{} +{},{} @@\n",
+			old_start + 1,
+			old_end - old_start,
+			new_start + 1,
+			new_end - new_start
+		));
+		for index in start..end {
+			let (old_pos, new_pos) = positions[index];
+			match script[index] {
+				DiffOp::Equal => diff.push_str(&format!(" {}\n", old_lines[old_pos])),
+				DiffOp::Delete => diff.push_str(&format!("-{}\n", old_lines[old_pos])),
+				DiffOp::Insert => diff.push_str(&format!("+{}\n", new_lines[new_pos])),
+			}
+		}
+	}
+	diff
+}
+
+/// Shortest edit script from `a` to `b`, using Myers' algorithm.
+fn edit_script(a: &[&str], b: &[&str]) -> Vec<DiffOp> {
+	let n = a.len() as isize;
+	let m = b.len() as isize;
+	let offset = n + m + 1;
+	let index = |k: isize| (k + offset) as usize;
+	let mut v = vec![0isize; 2 * offset as usize + 1];
+	// The state of `v` before each step, for backtracking.
+	let mut trace: Vec<Vec<isize>> = Vec::new();
+	'search: for d in 0..=(n + m) {
+		trace.push(v.clone());
+		for k in (-d..=d).step_by(2) {
+			let mut x = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+				v[index(k + 1)]
+			} else {
+				v[index(k - 1)] + 1
+			};
+			let mut y = x - k;
+			while x < n && y < m && a[x as usize] == b[y as usize] {
+				x += 1;
+				y += 1;
+			}
+			v[index(k)] = x;
+			if x >= n && y >= m {
+				break 'search;
+			}
+		}
+	}
+
+	let mut script = Vec::new();
+	let (mut x, mut y) = (n, m);
+	for (d, v) in trace.iter().enumerate().rev() {
+		let d = d as isize;
+		let k = x - y;
+		let prev_k = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+			k + 1
+		} else {
+			k - 1
+		};
+		let prev_x = v[index(prev_k)];
+		let prev_y = prev_x - prev_k;
+		while x > prev_x && y > prev_y {
+			script.push(DiffOp::Equal);
+			x -= 1;
+			y -= 1;
+		}
+		if d > 0 {
+			script.push(if x == prev_x {
+				DiffOp::Insert
+			} else {
+				DiffOp::Delete
+			});
+		}
+		x = prev_x;
+		y = prev_y;
+	}
+	script.reverse();
+	script
+}
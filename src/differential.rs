@@ -0,0 +1,43 @@
+use crate::astraw::RawInstr;
+use crate::astsoup::SoupInstr;
+use crate::tapeconfig::TapeConfig;
+use crate::vm::{run_raw, run_soup};
+
+/// Outputs of a program run by two different execution engines on the same input.
+#[derive(Debug)]
+pub struct DifferentialOutputs {
+	/// Output of the unoptimized program, considered correct.
+	pub expected: Vec<u8>,
+	pub actual: Vec<u8>,
+}
+
+impl DifferentialOutputs {
+	pub fn differ(&self) -> bool {
+		self.expected != self.actual
+	}
+}
+
+/// Runs both the raw program and the soup program (supposedly optimized from the raw program) on
+/// the given input.
+pub fn run_raw_and_soup(
+	raw_prog: &[RawInstr],
+	soup_prog: &[SoupInstr],
+	input: &[u8],
+) -> DifferentialOutputs {
+	DifferentialOutputs {
+		expected: run_raw(
+			raw_prog,
+			Some(input.to_vec()),
+			&TapeConfig::default(),
+			None,
+			None,
+			None,
+		),
+		actual: run_soup(
+			soup_prog,
+			Some(input.to_vec()),
+			&TapeConfig::default(),
+			None,
+		),
+	}
+}
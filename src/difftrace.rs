@@ -0,0 +1,378 @@
+//! Differential tracing of two execution engines (`difftrace`): the program runs on both engines
+//! with the same input, and their tapes are compared at checkpoints, before each byte output and
+//! when the program halts, which finds where the engines diverge much closer to the bug than
+//! comparing their outputs does. The tapes are first compared by their hashes, then the engines
+//! run again up to the first checkpoint where they differ to give the cells that differ, with the
+//! raw instructions (and so the span of source code) and the soup instructions that ran since the
+//! last checkpoint where they agreed.
+//!
+//! The heads are not compared, as optimized programs output cells at an offset from the head. The
+//! `soups-across-io` pass moves cell updates across outputs, which makes tapes differ at outputs
+//! while the program is right, so the program is optimized without it.
+
+use crate::astraw::RawInstr;
+use crate::astsoup::SoupInstr;
+use crate::bytecode::{DEFAULT_MAX_UNROLLED_BODY_LEN, flatten, peephole, unroll_tiny_loops};
+use crate::graph::graphify;
+use crate::hash::stable_hash;
+use crate::irtext::print_soup;
+use crate::passes::PassManager;
+use crate::sandbox::panic_message;
+use crate::tapeconfig::TapeConfig;
+use crate::vm::{Observer, RunLimits, RunStage, run_bytecode, run_graph, run_raw, run_soup};
+use std::collections::BTreeSet;
+use std::ops::Range;
+use std::panic::AssertUnwindSafe;
+
+/// Maximum number of differing cells given in a `Divergence`.
+const MAX_CELLS: usize = 16;
+
+#[derive(Debug, Clone)]
+pub struct DiffTraceOptions {
+	/// The two engines, the JIT being unsupported (as compiled code cannot be observed).
+	pub stages: [RunStage; 2],
+	pub input: Vec<u8>,
+	/// Number of steps each run can take (see `Observer::on_progress`).
+	pub max_steps: u64,
+}
+
+impl Default for DiffTraceOptions {
+	fn default() -> DiffTraceOptions {
+		DiffTraceOptions {
+			stages: [RunStage::Raw, RunStage::Soup],
+			input: Vec::new(),
+			max_steps: 100_000_000,
+		}
+	}
+}
+
+/// What an engine does at a checkpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+	Output(u8),
+	Halt,
+	/// The engine stopped on an error (such as running more than the maximum number of steps)
+	/// instead of reaching the checkpoint.
+	Error(String),
+}
+
+#[derive(Debug)]
+pub enum DiffTrace {
+	/// The engines agree at every checkpoint, and both halted if `complete` (otherwise both
+	/// stopped on an error at the same checkpoint, or one of them reached the step limit).
+	Agree {
+		outputs: usize,
+		complete: bool,
+	},
+	Diverge(Box<Divergence>),
+}
+
+/// The first checkpoint where the engines disagree.
+#[derive(Debug)]
+pub struct Divergence {
+	/// Number of bytes output (the same by both engines) before the checkpoint.
+	pub outputs: usize,
+	/// What each engine does at the checkpoint.
+	pub events: [Event; 2],
+	/// The first cells that differ at the checkpoint, by index, with their values in each engine
+	/// (none if the tapes are the same and only the events differ).
+	pub cells: Vec<(usize, [u8; 2])>,
+	/// Raw instructions (indices in the raw program) from the first to the last that ran since
+	/// the last checkpoint where the engines agreed, in the raw engine.
+	pub raw_instrs: Option<Range<usize>>,
+	/// Soup instructions that ran since the last checkpoint where the engines agreed, in the soup
+	/// engine, by index (in the order of `astsoup::instr_count`) with their IR text.
+	pub soup_instrs: Vec<(usize, String)>,
+}
+
+/// Runs the raw program and its optimized soup on the two engines of the options, on the default
+/// tape, and finds the first checkpoint where they disagree.
+pub fn diff_trace(
+	raw_prog: &[RawInstr],
+	pass_manager: &PassManager,
+	options: &DiffTraceOptions,
+) -> DiffTrace {
+	if options.stages.contains(&RunStage::Jit) {
+		panic!("the JIT cannot be traced");
+	}
+	let mut pass_manager = PassManager {
+		mem_limit: None,
+		tape_config: Some(TapeConfig::default()),
+		..pass_manager.clone()
+	};
+	pass_manager
+		.disabled_passes
+		.push("soups-across-io".to_owned());
+	let soup_prog = pass_manager.optimize(raw_prog).unwrap();
+	let engines = Engines {
+		raw_prog,
+		soup_prog: &soup_prog,
+		input: &options.input,
+		max_steps: options.max_steps,
+	};
+
+	let checkpoints = options.stages.map(|stage| {
+		let mut recorder = Recorder::default();
+		let error = engines.run(stage, &mut recorder).err();
+		if error.is_some() {
+			// The engine did not halt.
+			recorder.checkpoints.pop();
+		}
+		(recorder.checkpoints, error)
+	});
+	let [(checkpoints_a, error_a), (checkpoints_b, error_b)] = &checkpoints;
+	fn event(checkpoints: &[Checkpoint], error: &Option<String>, index: usize) -> Event {
+		match checkpoints.get(index) {
+			Some(checkpoint) => checkpoint.event.clone(),
+			None => Event::Error(error.clone().unwrap_or_default()),
+		}
+	}
+	// Engines that stop on an error at the same checkpoint agree, even if they stop on different
+	// errors (such as a head that goes before the first cell, which they report differently).
+	let Some(index) = (0..checkpoints_a.len().max(checkpoints_b.len()))
+		.find(|&index| checkpoints_a.get(index) != checkpoints_b.get(index))
+	else {
+		return DiffTrace::Agree {
+			outputs: checkpoints_a.len() - usize::from(error_a.is_none()),
+			complete: error_a.is_none() && error_b.is_none(),
+		};
+	};
+	let events = [
+		event(checkpoints_a, error_a, index),
+		event(checkpoints_b, error_b, index),
+	];
+	// Engines count steps differently, one can reach the step limit long before the other.
+	if events
+		.iter()
+		.any(|event| matches!(event, Event::Error(error) if is_step_limit(error)))
+	{
+		return DiffTrace::Agree {
+			outputs: index,
+			complete: false,
+		};
+	}
+
+	// The engines run again, up to the checkpoint, to get their tapes there and the instructions
+	// that ran since the previous checkpoint.
+	let capture = |stage: RunStage| {
+		let mut capture = Capture::new(index);
+		engines.run(stage, &mut capture).ok();
+		capture
+	};
+	let [tape_a, tape_b] = options.stages.map(|stage| capture(stage).tape);
+	let cells = match (tape_a, tape_b) {
+		(Some(tape_a), Some(tape_b)) => {
+			let cell = |tape: &[u8], index: usize| tape.get(index).copied().unwrap_or(0);
+			(0..tape_a.len().max(tape_b.len()))
+				.map(|index| (index, [cell(&tape_a, index), cell(&tape_b, index)]))
+				.filter(|(_, [a, b])| a != b)
+				.take(MAX_CELLS)
+				.collect()
+		}
+		_ => Vec::new(),
+	};
+	let raw_instrs = capture(RunStage::Raw).instrs;
+	let soup_instrs = capture(RunStage::Soup).instrs;
+	DiffTrace::Diverge(Box::new(Divergence {
+		outputs: index,
+		events,
+		cells,
+		raw_instrs: raw_instrs
+			.first()
+			.zip(raw_instrs.last())
+			.map(|(&first, &last)| first..last + 1),
+		soup_instrs: soup_instrs
+			.into_iter()
+			.map(|index| (index, soup_instr_text(&soup_prog, index)))
+			.collect(),
+	}))
+}
+
+/// The program, ready to run on each engine.
+struct Engines<'a> {
+	raw_prog: &'a [RawInstr],
+	soup_prog: &'a [SoupInstr],
+	input: &'a [u8],
+	max_steps: u64,
+}
+
+impl Engines<'_> {
+	/// Runs the program on the engine, returning the message of the error it stopped on if any.
+	fn run(&self, stage: RunStage, observer: &mut dyn Observer) -> Result<(), String> {
+		let mut observers: Vec<Box<dyn Observer + '_>> = vec![
+			Box::new(observer),
+			Box::new(RunLimits {
+				max_steps: Some(self.max_steps),
+				..RunLimits::default()
+			}),
+		];
+		let input = Some(self.input.to_vec());
+		let tape_config = &TapeConfig::default();
+		std::panic::catch_unwind(AssertUnwindSafe(|| match stage {
+			RunStage::Raw => run_raw(
+				self.raw_prog,
+				input,
+				tape_config,
+				Some(&mut observers),
+				None,
+				None,
+			),
+			RunStage::Soup => run_soup(self.soup_prog, input, tape_config, Some(&mut observers)),
+			RunStage::Graph => run_graph(
+				&graphify(self.soup_prog),
+				input,
+				tape_config,
+				Some(&mut observers),
+			),
+			RunStage::Bytecode | RunStage::Jit => {
+				let mut ops = flatten(self.soup_prog);
+				peephole(&mut ops);
+				unroll_tiny_loops(&mut ops, DEFAULT_MAX_UNROLLED_BODY_LEN);
+				run_bytecode(&ops, input, tape_config, None, Some(&mut observers), None)
+			}
+		}))
+		.map(|_| ())
+		.map_err(|payload| panic_message(&*payload))
+	}
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Checkpoint {
+	/// Hash of the tape without its trailing zeros.
+	tape_hash: u64,
+	event: Event,
+}
+
+fn hash_tape(tape: &[u8]) -> u64 {
+	let len = tape
+		.iter()
+		.rposition(|&cell| cell != 0)
+		.map_or(0, |index| index + 1);
+	stable_hash(&tape[..len])
+}
+
+/// Observer of a run that records its checkpoints.
+#[derive(Default)]
+struct Recorder {
+	checkpoints: Vec<Checkpoint>,
+}
+
+impl Observer for Recorder {
+	fn on_output(&mut self, _char_values: &[u8]) {}
+
+	fn watches_output_tape(&self) -> bool {
+		true
+	}
+
+	fn on_output_tape(&mut self, tape: &[u8], char_values: &[u8]) {
+		let tape_hash = hash_tape(tape);
+		self.checkpoints
+			.extend(char_values.iter().map(|&char_value| Checkpoint {
+				tape_hash,
+				event: Event::Output(char_value),
+			}));
+	}
+
+	/// Also called when the VM stops on an error, the checkpoint is then removed by `diff_trace`.
+	fn on_halt(&mut self, tape: &[u8], _head: usize) {
+		self.checkpoints.push(Checkpoint {
+			tape_hash: hash_tape(tape),
+			event: Event::Halt,
+		});
+	}
+}
+
+/// Observer of a run that gets its tape at the given checkpoint, and the instructions that ran
+/// between the previous checkpoint and that one.
+struct Capture {
+	checkpoint: usize,
+	/// Number of checkpoints passed.
+	passed: usize,
+	tape: Option<Vec<u8>>,
+	/// Indices of the instructions that ran since the previous checkpoint.
+	instrs: Vec<usize>,
+	instr_set: BTreeSet<usize>,
+}
+
+impl Capture {
+	fn new(checkpoint: usize) -> Capture {
+		Capture {
+			checkpoint,
+			passed: 0,
+			tape: None,
+			instrs: Vec::new(),
+			instr_set: BTreeSet::new(),
+		}
+	}
+
+	fn pass(&mut self, tape: &[u8], count: usize) {
+		if self.passed <= self.checkpoint && self.checkpoint < self.passed + count {
+			self.tape = Some(tape.to_vec());
+			self.instrs = std::mem::take(&mut self.instr_set).into_iter().collect();
+		} else if self.passed < self.checkpoint {
+			self.instr_set.clear();
+		}
+		self.passed += count;
+	}
+}
+
+impl Observer for Capture {
+	fn on_output(&mut self, _char_values: &[u8]) {}
+
+	fn traces_instrs(&self) -> bool {
+		true
+	}
+
+	fn on_instr(&mut self, instr_index: usize, _head: usize, _cell_value: u8) {
+		if self.passed <= self.checkpoint {
+			self.instr_set.insert(instr_index);
+		}
+	}
+
+	fn watches_output_tape(&self) -> bool {
+		true
+	}
+
+	fn on_output_tape(&mut self, tape: &[u8], char_values: &[u8]) {
+		self.pass(tape, char_values.len());
+	}
+
+	/// Also called when the VM stops on an error, which gives the instructions that ran since the
+	/// last checkpoint.
+	fn on_halt(&mut self, tape: &[u8], _head: usize) {
+		if self.passed <= self.checkpoint {
+			self.pass(tape, 1);
+		}
+	}
+}
+
+/// IR text of the soup instruction of the given index (in the order of `astsoup::instr_count`),
+/// without the body if it is a loop.
+fn soup_instr_text(soup_prog: &[SoupInstr], index: usize) -> String {
+	fn find(instr_seq: &[SoupInstr], index: &mut usize) -> Option<String> {
+		for instr in instr_seq {
+			if *index == 0 {
+				return Some(match instr {
+					SoupInstr::Loop(..) => "loop { ... }".to_owned(),
+					_ => print_soup(std::slice::from_ref(instr))
+						.trim_end()
+						.to_owned(),
+				});
+			}
+			*index -= 1;
+			if let SoupInstr::Loop(body, _) = instr {
+				if let Some(text) = find(body, index) {
+					return Some(text);
+				}
+			}
+		}
+		None
+	}
+	let mut index = index;
+	find(soup_prog, &mut index).unwrap()
+}
+
+/// The error is that the run exceeded its number of steps (see `VmMem::step`).
+fn is_step_limit(error: &str) -> bool {
+	error.starts_with("the program ran more than")
+}
@@ -0,0 +1,123 @@
+//! Encodings of the output of the interpreted programs for its presentation on stdout, as the
+//! output is made of bytes that are not necessarily text.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputEncoding {
+	/// The bytes are written as they are.
+	Raw,
+	/// The bytes are decoded as UTF-8 (invalid sequences becoming U+FFFD) and a newline is added.
+	Utf8Lossy,
+	/// Two lowercase hexadecimal digits per byte, and a newline.
+	Hex,
+	/// Base64 (with padding), and a newline.
+	Base64,
+}
+
+impl OutputEncoding {
+	pub fn from_name(name: &str) -> Option<OutputEncoding> {
+		match name {
+			"raw" => Some(OutputEncoding::Raw),
+			"utf8-lossy" => Some(OutputEncoding::Utf8Lossy),
+			"hex" => Some(OutputEncoding::Hex),
+			"base64" => Some(OutputEncoding::Base64),
+			_ => None,
+		}
+	}
+
+	pub fn encode(self, output: &[u8]) -> Vec<u8> {
+		let mut encoded = self.encode_part(output);
+		if self != OutputEncoding::Raw {
+			encoded.push(b'\n');
+		}
+		encoded
+	}
+
+	/// Same as `encode` without the final newline, for a part of the output that ends on a
+	/// boundary of the encoding (see `StreamEncoder`).
+	fn encode_part(self, output: &[u8]) -> Vec<u8> {
+		match self {
+			OutputEncoding::Raw => output.to_vec(),
+			OutputEncoding::Utf8Lossy => String::from_utf8_lossy(output).into_owned().into_bytes(),
+			OutputEncoding::Hex => output
+				.iter()
+				.map(|byte| format!("{:02x}", byte))
+				.collect::<String>()
+				.into_bytes(),
+			OutputEncoding::Base64 => base64(output).into_bytes(),
+		}
+	}
+}
+
+/// Encoding of the output as it comes, the concatenation of the encoded parts being the same as
+/// the encoding of the whole output. The bytes that cannot be encoded yet (an incomplete UTF-8
+/// sequence or base64 group) are kept until the next part.
+#[derive(Debug)]
+pub struct StreamEncoder {
+	encoding: OutputEncoding,
+	pending: Vec<u8>,
+}
+
+impl StreamEncoder {
+	pub fn new(encoding: OutputEncoding) -> StreamEncoder {
+		StreamEncoder {
+			encoding,
+			pending: Vec::new(),
+		}
+	}
+
+	/// Encodes as much of the output so far as possible, the given bytes coming after it.
+	pub fn encode(&mut self, output: &[u8]) -> Vec<u8> {
+		self.pending.extend_from_slice(output);
+		let ready_len = match self.encoding {
+			OutputEncoding::Raw | OutputEncoding::Hex => self.pending.len(),
+			OutputEncoding::Utf8Lossy => utf8_ready_len(&self.pending),
+			OutputEncoding::Base64 => self.pending.len() - self.pending.len() % 3,
+		};
+		let ready: Vec<u8> = self.pending.drain(..ready_len).collect();
+		self.encoding.encode_part(&ready)
+	}
+
+	/// Encodes the rest of the output, once it is complete.
+	pub fn finish(&mut self) -> Vec<u8> {
+		let rest = std::mem::take(&mut self.pending);
+		self.encoding.encode(&rest)
+	}
+}
+
+/// Length of the longest prefix of the bytes that does not end in an incomplete (but possibly
+/// valid once complete) UTF-8 sequence, invalid sequences being part of it.
+fn utf8_ready_len(bytes: &[u8]) -> usize {
+	let mut ready_len = 0;
+	loop {
+		match std::str::from_utf8(&bytes[ready_len..]) {
+			Ok(_) => return bytes.len(),
+			Err(error) => match error.error_len() {
+				Some(invalid_len) => ready_len += error.valid_up_to() + invalid_len,
+				None => return ready_len + error.valid_up_to(),
+			},
+		}
+	}
+}
+
+fn base64(bytes: &[u8]) -> String {
+	const ALPHABET: &[u8; 64] =
+		b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+	let mut encoded = String::new();
+	for chunk in bytes.chunks(3) {
+		let group = chunk
+			.iter()
+			.enumerate()
+			.fold(0u32, |group, (index, &byte)| {
+				group | (byte as u32) << (16 - 8 * index)
+			});
+		// A chunk of n bytes gives n + 1 digits, and padding up to 4 characters.
+		for index in 0..4 {
+			if index <= chunk.len() {
+				encoded.push(ALPHABET[(group >> (18 - 6 * index) & 63) as usize] as char);
+			} else {
+				encoded.push('=');
+			}
+		}
+	}
+	encoded
+}
@@ -0,0 +1,85 @@
+//! Context of runtime errors: the last instructions run by the VM are kept in a ring buffer (with
+//! the head and the value of the cell under it before each of them), that is printed (to stderr)
+//! if the VM stops on an error.
+
+use crate::parser::{instr_spans, line_and_column};
+use crate::tokenizer::Extensions;
+use crate::vm::Observer;
+use std::collections::VecDeque;
+use std::ops::Range;
+
+/// An instruction that ran, with the head and the value of the cell under it before it ran.
+struct Step {
+	instr_index: usize,
+	head: usize,
+	cell_value: u8,
+}
+
+/// Observer of the VM that prints the last instructions run when the program stops on an error,
+/// the instructions run being only known when running a program that is not optimized.
+pub struct ErrorContext {
+	src_code: String,
+	/// Span in the source code of each raw instruction.
+	spans: Vec<Range<usize>>,
+	steps: VecDeque<Step>,
+	/// Number of steps kept.
+	len: usize,
+}
+
+impl ErrorContext {
+	pub fn new(src_code: &str, extensions: &Extensions, len: usize) -> ErrorContext {
+		ErrorContext {
+			src_code: src_code.to_owned(),
+			spans: instr_spans(src_code, extensions),
+			steps: VecDeque::with_capacity(len),
+			len,
+		}
+	}
+
+	fn format_steps(&self) -> String {
+		let mut text = format!(
+			"Last {} instructions run before the error (oldest first):\n",
+			self.steps.len()
+		);
+		for step in &self.steps {
+			let span = &self.spans[step.instr_index];
+			let (line, column) = line_and_column(&self.src_code, span.start);
+			// A loop is only shown by its opening bracket.
+			let instr = match &self.src_code[span.clone()] {
+				loop_src if loop_src.starts_with('[') => "[",
+				instr => instr,
+			};
+			text.push_str(&format!(
+				"\tline {} column {}: `{}` with the head on cell {} (of value {})\n",
+				line, column, instr, step.head, step.cell_value
+			));
+		}
+		text
+	}
+}
+
+impl Observer for ErrorContext {
+	fn on_output(&mut self, _char_values: &[u8]) {}
+
+	fn traces_instrs(&self) -> bool {
+		self.len != 0
+	}
+
+	fn on_instr(&mut self, instr_index: usize, head: usize, cell_value: u8) {
+		if self.steps.len() == self.len {
+			self.steps.pop_front();
+		}
+		self.steps.push_back(Step {
+			instr_index,
+			head,
+			cell_value,
+		});
+	}
+
+	fn on_halt(&mut self, _tape: &[u8], _head: usize) {
+		// The VM stops on errors by panicking.
+		if std::thread::panicking() && !self.steps.is_empty() {
+			eprint!("{}", self.format_steps());
+		}
+	}
+}
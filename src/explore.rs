@@ -0,0 +1,469 @@
+//! Exploration of the behavior of a program over all its inputs of bounded length.
+//!
+//! Input bytes are not enumerated: a cell that holds an input byte (plus some constant) is kept
+//! symbolic, and the exploration only splits when the program tests such a cell against zero
+//! (the zero branch fixing the input byte, the non-zero branch excluding one value for it).
+//! Symbolic cells that are output are widened to "some value depending on input byte i" instead
+//! of being split, unless a target output is searched for (they are then fixed to the expected
+//! byte). Reading past the input length gives 0, like the VM does at the end of its input, so
+//! shorter inputs are covered too.
+
+use crate::astraw::RawInstr;
+use crate::extended::ExtInstr;
+use crate::machine::MachineModel;
+use std::collections::BTreeMap;
+
+/// Value of a cell during exploration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Value {
+	Concrete(u8),
+	/// The input byte of the given index plus the offset (wrapping).
+	Symbolic {
+		input_index: usize,
+		offset: u8,
+	},
+}
+
+/// What is known about an input byte.
+#[derive(Debug, Clone)]
+enum InputConstraint {
+	Fixed(u8),
+	/// The byte can be any value for which the element of this array is false.
+	Excluded(Box<[bool; 256]>),
+}
+
+/// A byte output by an explored path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum OutputByte {
+	Known(u8),
+	/// Depends on the input byte of the given index, plus the offset (wrapping).
+	Input {
+		input_index: usize,
+		offset: u8,
+	},
+}
+
+#[derive(Debug, Clone)]
+pub struct ExploreOptions {
+	/// Maximum length of the inputs given to the program.
+	pub max_input_len: usize,
+	/// Paths that run more instructions than that are cut.
+	pub max_steps_per_path: u64,
+	/// Maximum number of paths explored.
+	pub max_paths: usize,
+	/// Only look for paths that output exactly this (paths are then pruned as soon as their
+	/// output is not a prefix of it), and stop at the first one found.
+	pub target: Option<Vec<u8>>,
+	pub machine: MachineModel,
+}
+
+impl Default for ExploreOptions {
+	fn default() -> ExploreOptions {
+		ExploreOptions {
+			max_input_len: 8,
+			max_steps_per_path: 1_000_000,
+			max_paths: 100_000,
+			target: None,
+			machine: MachineModel::default(),
+		}
+	}
+}
+
+/// An output that the program can produce, with an input that makes it produce it.
+#[derive(Debug, Clone)]
+pub struct ReachableOutput {
+	pub output: Vec<OutputByte>,
+	pub input: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Exploration {
+	/// Distinct outputs of the paths that reached the end of the program.
+	pub outputs: Vec<ReachableOutput>,
+	/// Number of paths explored.
+	pub paths: usize,
+	/// All the paths were explored until the end of the program (none was cut by a limit or
+	/// stopped by running off the left end of the tape), so `outputs` contains every output the
+	/// program can produce on inputs of bounded length (up to the widening of output bytes).
+	pub complete: bool,
+}
+
+/// State of an execution path.
+#[derive(Debug, Clone)]
+struct Path {
+	pc: usize,
+	loop_stack: Vec<usize>,
+	tape: Vec<Value>,
+	head: usize,
+	output: Vec<OutputByte>,
+	inputs: Vec<InputConstraint>,
+	steps: u64,
+	/// Number of reads of the virtual clock, as counted by the VM.
+	clock: u64,
+	/// Storage of the extended-type-1 extension (see `machine`), the missing cells being zeros.
+	storage: Vec<Value>,
+}
+
+impl Path {
+	/// Value of the cell under the head, with the fixed input bytes replaced by their value.
+	fn cell(&self) -> Value {
+		match self
+			.tape
+			.get(self.head)
+			.copied()
+			.unwrap_or(Value::Concrete(0))
+		{
+			Value::Symbolic {
+				input_index,
+				offset,
+			} => match self.inputs[input_index] {
+				InputConstraint::Fixed(byte) => Value::Concrete(byte.wrapping_add(offset)),
+				InputConstraint::Excluded(_) => Value::Symbolic {
+					input_index,
+					offset,
+				},
+			},
+			value => value,
+		}
+	}
+
+	/// Value of the storage cell used with the cell under the head.
+	fn storage_cell(&self, options: &ExploreOptions) -> Value {
+		self.storage
+			.get(options.machine.storage_index(self.head))
+			.copied()
+			.unwrap_or(Value::Concrete(0))
+	}
+
+	fn set_cell(&mut self, value: Value) {
+		if self.tape.len() <= self.head {
+			self.tape.resize(self.head + 1, Value::Concrete(0));
+		}
+		self.tape[self.head] = value;
+	}
+
+	fn add_to_cell(&mut self, delta: u8) {
+		let value = match self.cell() {
+			Value::Concrete(value) => Value::Concrete(value.wrapping_add(delta)),
+			Value::Symbolic {
+				input_index,
+				offset,
+			} => Value::Symbolic {
+				input_index,
+				offset: offset.wrapping_add(delta),
+			},
+		};
+		self.set_cell(value);
+	}
+
+	/// Can the given input byte take the given value.
+	fn input_allows(&self, input_index: usize, byte: u8) -> bool {
+		match &self.inputs[input_index] {
+			InputConstraint::Fixed(fixed) => *fixed == byte,
+			InputConstraint::Excluded(excluded) => !excluded[byte as usize],
+		}
+	}
+
+	/// Tells if the cell under the head is zero, pushing the other possibility as a new path
+	/// when both are possible (that path resumes at `decision_pc`, where the test is done again
+	/// with the input byte fixed).
+	fn cell_is_zero(&mut self, paths: &mut Vec<Path>, decision_pc: usize) -> bool {
+		match self.cell() {
+			Value::Concrete(value) => value == 0,
+			Value::Symbolic {
+				input_index,
+				offset,
+			} => {
+				// The cell is zero if the input byte is this value.
+				let zero_byte = offset.wrapping_neg();
+				let can_be_zero = self.input_allows(input_index, zero_byte);
+				if let InputConstraint::Excluded(excluded) = &mut self.inputs[input_index] {
+					excluded[zero_byte as usize] = true;
+					let can_be_non_zero = excluded.iter().any(|&excluded| !excluded);
+					if can_be_zero && !can_be_non_zero {
+						self.inputs[input_index] = InputConstraint::Fixed(zero_byte);
+						return true;
+					} else if can_be_zero {
+						let mut zero_path = self.clone();
+						zero_path.inputs[input_index] = InputConstraint::Fixed(zero_byte);
+						zero_path.pc = decision_pc;
+						paths.push(zero_path);
+					}
+				}
+				false
+			}
+		}
+	}
+
+	/// An input that satisfies the constraints, unconstrained bytes being printable if possible.
+	fn example_input(&self) -> Vec<u8> {
+		let mut input: Vec<u8> = self
+			.inputs
+			.iter()
+			.map(|constraint| match constraint {
+				InputConstraint::Fixed(byte) => *byte,
+				InputConstraint::Excluded(excluded) => (b'a'..=b'z')
+					.chain(b' '..=b'~')
+					.chain(0..=255)
+					.find(|&byte| !excluded[byte as usize])
+					.unwrap(),
+			})
+			.collect();
+		// Trailing zeros are the same as the end of the input.
+		while input.last() == Some(&0) {
+			input.pop();
+		}
+		input
+	}
+}
+
+/// Explores the paths the program can take on all inputs of length at most
+/// `options.max_input_len`, see the module documentation.
+pub fn explore(raw_prog: &[RawInstr], options: &ExploreOptions) -> Exploration {
+	let mut outputs: BTreeMap<Vec<OutputByte>, Vec<u8>> = BTreeMap::new();
+	let mut paths = vec![Path {
+		pc: 0,
+		loop_stack: Vec::new(),
+		tape: Vec::new(),
+		head: 0,
+		output: Vec::new(),
+		inputs: Vec::new(),
+		steps: 0,
+		clock: 0,
+		storage: Vec::new(),
+	}];
+	let mut path_count = 0;
+	let mut complete = true;
+	'paths: while let Some(mut path) = paths.pop() {
+		if path_count == options.max_paths {
+			complete = false;
+			break;
+		}
+		path_count += 1;
+		'steps: loop {
+			if path.steps == options.max_steps_per_path {
+				complete = false;
+				continue 'paths;
+			}
+			path.steps += 1;
+			// Reaching the end of a loop body jumps back at its beginning or exits the loop.
+			while let Some(&loop_index) = path.loop_stack.last() {
+				let body_end = match raw_prog[loop_index] {
+					RawInstr::BracketLoop { body_len, .. } => loop_index + 1 + body_len,
+					_ => unreachable!(),
+				};
+				if path.pc < body_end {
+					break;
+				} else if !path.cell_is_zero(&mut paths, path.pc) {
+					path.pc = loop_index + 1;
+					// Counts as a step, so that `[]` cannot loop forever here.
+					continue 'steps;
+				} else {
+					path.loop_stack.pop();
+				}
+			}
+			let instr = match raw_prog.get(path.pc) {
+				Some(instr) => instr,
+				None => break,
+			};
+			path.pc += 1;
+			match instr {
+				RawInstr::Plus(n) => path.add_to_cell(*n as u8),
+				RawInstr::Minus(n) => path.add_to_cell((*n as u8).wrapping_neg()),
+				RawInstr::Left(n) => {
+					if path.head < *n {
+						complete = false;
+						continue 'paths;
+					}
+					path.head -= n;
+				}
+				RawInstr::Right(n) => path.head += n,
+				RawInstr::Dot => {
+					let output_byte = match path.cell() {
+						Value::Concrete(value) => OutputByte::Known(value),
+						Value::Symbolic {
+							input_index,
+							offset,
+						} => match &options.target {
+							Some(target) => match target.get(path.output.len()) {
+								Some(&expected) => {
+									let byte = expected.wrapping_sub(offset);
+									if !path.input_allows(input_index, byte) {
+										continue 'paths;
+									}
+									path.inputs[input_index] = InputConstraint::Fixed(byte);
+									OutputByte::Known(expected)
+								}
+								None => continue 'paths,
+							},
+							None => OutputByte::Input {
+								input_index,
+								offset,
+							},
+						},
+					};
+					if let (Some(target), OutputByte::Known(byte)) = (&options.target, output_byte)
+					{
+						if target.get(path.output.len()) != Some(&byte) {
+							continue 'paths;
+						}
+					}
+					path.output.push(output_byte);
+				}
+				RawInstr::Comma => {
+					let input_index = path.inputs.len();
+					if input_index < options.max_input_len {
+						path.inputs
+							.push(InputConstraint::Excluded(Box::new([false; 256])));
+						path.set_cell(Value::Symbolic {
+							input_index,
+							offset: 0,
+						});
+					} else {
+						path.set_cell(Value::Concrete(0));
+					}
+				}
+				RawInstr::Clock => {
+					path.set_cell(Value::Concrete(path.clock as u8));
+					path.clock += 1;
+				}
+				RawInstr::Random => {
+					// The random bytes depend on the seed, that is not explored.
+					complete = false;
+					continue 'paths;
+				}
+				RawInstr::HostCall => {
+					// What the host returns is not known.
+					complete = false;
+					continue 'paths;
+				}
+				RawInstr::Extended(ExtInstr::End) => break 'steps,
+				RawInstr::Extended(ExtInstr::Store) => {
+					let storage_index = options.machine.storage_index(path.head);
+					if path.storage.len() <= storage_index {
+						path.storage.resize(storage_index + 1, Value::Concrete(0));
+					}
+					path.storage[storage_index] = path.cell();
+				}
+				RawInstr::Extended(ExtInstr::Load) => path.set_cell(path.storage_cell(options)),
+				RawInstr::Extended(instr) => match (path.cell(), path.storage_cell(options)) {
+					(Value::Concrete(cell), Value::Concrete(storage)) => {
+						path.set_cell(Value::Concrete(instr.apply(cell, storage).0))
+					}
+					_ => {
+						// Bitwise operations on input bytes are not explored.
+						complete = false;
+						continue 'paths;
+					}
+				},
+				RawInstr::BracketLoop { body_len, .. } => {
+					if !path.cell_is_zero(&mut paths, path.pc - 1) {
+						path.loop_stack.push(path.pc - 1);
+					} else {
+						path.pc += body_len;
+					}
+				}
+			}
+		}
+
+		if let Some(target) = &options.target {
+			if path.output.len() != target.len() {
+				continue;
+			}
+		}
+		// Among the inputs that give the same output, prefer the shortest and most printable.
+		let input = path.example_input();
+		let niceness = |input: &[u8]| {
+			let unprintable = input.iter().filter(|byte| !(b' '..=b'~').contains(byte));
+			(input.len(), unprintable.count())
+		};
+		let example = outputs.entry(path.output).or_insert_with(|| input.clone());
+		if niceness(&input) < niceness(example) {
+			*example = input;
+		}
+		if options.target.is_some() {
+			break;
+		}
+	}
+
+	Exploration {
+		outputs: outputs
+			.into_iter()
+			.map(|(output, input)| ReachableOutput { output, input })
+			.collect(),
+		paths: path_count,
+		complete,
+	}
+}
+
+/// What is known of the output of a program without running it (see `predict_output`).
+#[derive(Debug, Clone)]
+pub enum OutputPrediction {
+	/// The program ends within the step budget with this output, whatever its input.
+	Exact(Vec<u8>),
+	/// The program reads input or random bytes or calls the host, so its output may depend on
+	/// them.
+	DependsOnInput,
+	/// The program does not end within the step budget, or runs off the left end of the tape.
+	Unknown,
+}
+
+/// Predicts the output of a program that reads no input (nor random bytes, nor host calls) by
+/// exploring its single path for at most `max_steps` instructions, starting from a tape of zeros.
+pub fn predict_output(
+	raw_prog: &[RawInstr],
+	max_steps: u64,
+	machine: &MachineModel,
+) -> OutputPrediction {
+	if raw_prog.iter().any(|instr| {
+		matches!(
+			instr,
+			RawInstr::Comma | RawInstr::Random | RawInstr::HostCall
+		)
+	}) {
+		return OutputPrediction::DependsOnInput;
+	}
+	let options = ExploreOptions {
+		max_input_len: 0,
+		max_steps_per_path: max_steps,
+		max_paths: 1,
+		target: None,
+		machine: machine.clone(),
+	};
+	let exploration = explore(raw_prog, &options);
+	match exploration.outputs.first() {
+		Some(reachable) if exploration.complete => OutputPrediction::Exact(
+			reachable
+				.output
+				.iter()
+				.map(|output_byte| match output_byte {
+					OutputByte::Known(byte) => *byte,
+					OutputByte::Input { .. } => unreachable!(),
+				})
+				.collect(),
+		),
+		_ => OutputPrediction::Unknown,
+	}
+}
+
+/// Text representation of an output, bytes that depend on the input being printed as
+/// `{in[INDEX]+OFFSET}` and non-printable bytes (and `{` and `\`) being escaped.
+pub fn output_to_text(output: &[OutputByte]) -> String {
+	let mut text = String::new();
+	for output_byte in output {
+		match *output_byte {
+			OutputByte::Known(byte)
+				if (b' '..=b'~').contains(&byte) && byte != b'{' && byte != b'\\' =>
+			{
+				text.push(byte as char)
+			}
+			OutputByte::Known(b'\n') => text.push_str("\\n"),
+			OutputByte::Known(byte) => text.push_str(&format!("\\x{:02x}", byte)),
+			OutputByte::Input {
+				input_index,
+				offset,
+			} => text.push_str(&format!("{{in[{}]+{}}}", input_index, offset)),
+		}
+	}
+	text
+}
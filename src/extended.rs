@@ -0,0 +1,116 @@
+//! Instructions of Extended Brainfuck Type I (see `Extensions::extended_type_1`): besides the
+//! tape there is a storage cell, that can be copied to and from the cell under the head and
+//! combined with it by bitwise operations, and an instruction that ends the program.
+//!
+//! The optimizations do not look into these instructions, they only know which of them write
+//! the cell they operate on.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExtInstr {
+	/// `@`, ends the program.
+	End,
+	/// `$`, copies the cell into the storage cell.
+	Store,
+	/// `!`, copies the storage cell into the cell.
+	Load,
+	/// `}`, shifts the bits of the cell right by one.
+	ShiftRight,
+	/// `{`, shifts the bits of the cell left by one (the leftmost bit being lost).
+	ShiftLeft,
+	/// `~`, inverts the bits of the cell.
+	Not,
+	/// `^`, sets the cell to its bitwise xor with the storage cell.
+	Xor,
+	/// `&`, sets the cell to its bitwise and with the storage cell.
+	And,
+	/// `|`, sets the cell to its bitwise or with the storage cell.
+	Or,
+}
+
+const ALL: [ExtInstr; 9] = [
+	ExtInstr::End,
+	ExtInstr::Store,
+	ExtInstr::Load,
+	ExtInstr::ShiftRight,
+	ExtInstr::ShiftLeft,
+	ExtInstr::Not,
+	ExtInstr::Xor,
+	ExtInstr::And,
+	ExtInstr::Or,
+];
+
+impl ExtInstr {
+	pub fn to_char(self) -> char {
+		match self {
+			ExtInstr::End => '@',
+			ExtInstr::Store => '$',
+			ExtInstr::Load => '!',
+			ExtInstr::ShiftRight => '}',
+			ExtInstr::ShiftLeft => '{',
+			ExtInstr::Not => '~',
+			ExtInstr::Xor => '^',
+			ExtInstr::And => '&',
+			ExtInstr::Or => '|',
+		}
+	}
+
+	pub fn from_char(c: char) -> Option<ExtInstr> {
+		ALL.iter().copied().find(|instr| instr.to_char() == c)
+	}
+
+	/// Name in the textual IR (see `irtext`).
+	pub fn name(self) -> &'static str {
+		match self {
+			ExtInstr::End => "end",
+			ExtInstr::Store => "store",
+			ExtInstr::Load => "load",
+			ExtInstr::ShiftRight => "shift_right",
+			ExtInstr::ShiftLeft => "shift_left",
+			ExtInstr::Not => "not",
+			ExtInstr::Xor => "xor",
+			ExtInstr::And => "and",
+			ExtInstr::Or => "or",
+		}
+	}
+
+	pub fn from_name(name: &str) -> Option<ExtInstr> {
+		ALL.iter().copied().find(|instr| instr.name() == name)
+	}
+
+	/// The instruction may change the value of the cell it operates on.
+	pub fn writes_cell(self) -> bool {
+		!matches!(self, ExtInstr::End | ExtInstr::Store)
+	}
+
+	/// New values of the cell and of the storage cell after the instruction (that must not be
+	/// `End`).
+	pub fn apply(self, cell: u8, storage: u8) -> (u8, u8) {
+		match self {
+			ExtInstr::End => panic!("`End` has no effect on the cells"),
+			ExtInstr::Store => (cell, cell),
+			ExtInstr::Load => (storage, storage),
+			ExtInstr::ShiftRight => (cell >> 1, storage),
+			ExtInstr::ShiftLeft => (cell << 1, storage),
+			ExtInstr::Not => (!cell, storage),
+			ExtInstr::Xor => (cell ^ storage, storage),
+			ExtInstr::And => (cell & storage, storage),
+			ExtInstr::Or => (cell | storage, storage),
+		}
+	}
+
+	/// C statement doing the instruction on the given cell and storage cell, that works whether
+	/// cells are `int`s (that stay in 0..=255) or `unsigned char`s.
+	pub fn c_statement(self, cell: &str, storage: &str) -> String {
+		match self {
+			ExtInstr::End => "return 0;".to_owned(),
+			ExtInstr::Store => format!("{} = {};", storage, cell),
+			ExtInstr::Load => format!("{} = {};", cell, storage),
+			ExtInstr::ShiftRight => format!("{} >>= 1;", cell),
+			ExtInstr::ShiftLeft => format!("{} = ({} << 1) & 255;", cell, cell),
+			ExtInstr::Not => format!("{} ^= 255;", cell),
+			ExtInstr::Xor => format!("{} ^= {};", cell, storage),
+			ExtInstr::And => format!("{} &= {};", cell, storage),
+			ExtInstr::Or => format!("{} |= {};", cell, storage),
+		}
+	}
+}
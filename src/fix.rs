@@ -0,0 +1,93 @@
+//! Repair of the brainfuck sources that do not parse because of mechanical problems (`check
+//! --fix`), as happens with programs pasted from poorly formatted web pages: an unmatched `[` is
+//! closed after the last instruction of the source code, and an unmatched bracket that looks like
+//! it is part of a comment (it is right next to a letter or a digit, as in `see [1`, or it is on a
+//! line with more letters and digits than instructions) is removed. Other parsing errors are left
+//! to the user.
+
+use crate::parser::ParsingError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Repair {
+	/// The unmatched `[` at the given position is closed by a `]` after the last instruction.
+	Closed { pos: usize },
+	/// The unmatched bracket at the given position, in a comment, is removed.
+	Removed { pos: usize },
+}
+
+#[derive(Debug, Clone)]
+pub struct Fix {
+	pub src_code: String,
+	/// Repairs done, in the order of their positions.
+	pub repairs: Vec<Repair>,
+	/// Errors that were not repaired, that the fixed source code still has.
+	pub unrepaired: Vec<ParsingError>,
+}
+
+/// Repairs what can be of the parsing errors of the source code (as given by the parser).
+pub fn fix(src_code: &str, errors: &[ParsingError]) -> Fix {
+	let mut repairs = Vec::new();
+	let mut unrepaired = Vec::new();
+	for error in errors {
+		match *error {
+			ParsingError::UnmatchedOpeningBracket { pos }
+			| ParsingError::UnmatchedClosingBracket { pos }
+				if in_comment(src_code, pos) =>
+			{
+				repairs.push(Repair::Removed { pos })
+			}
+			ParsingError::UnmatchedOpeningBracket { pos } => repairs.push(Repair::Closed { pos }),
+			_ => unrepaired.push(error.clone()),
+		}
+	}
+	repairs.sort_by_key(|repair| match *repair {
+		Repair::Closed { pos } | Repair::Removed { pos } => pos,
+	});
+	let removed = |pos: usize| repairs.contains(&Repair::Removed { pos });
+	let closing_brackets = repairs
+		.iter()
+		.filter(|repair| matches!(repair, Repair::Closed { .. }))
+		.count();
+	// The closing brackets go right after the last instruction that is kept, before what may
+	// be a trailing comment.
+	let end_of_code = src_code
+		.char_indices()
+		.rfind(|&(pos, c)| "+-<>.,[]".contains(c) && !removed(pos))
+		.map_or(0, |(pos, _)| pos + 1);
+	let mut fixed = String::with_capacity(src_code.len() + closing_brackets);
+	for (pos, c) in src_code.char_indices() {
+		if pos == end_of_code {
+			fixed.extend(std::iter::repeat_n(']', closing_brackets));
+		}
+		if !removed(pos) {
+			fixed.push(c);
+		}
+	}
+	if end_of_code == src_code.len() {
+		fixed.extend(std::iter::repeat_n(']', closing_brackets));
+	}
+	Fix {
+		src_code: fixed,
+		repairs,
+		unrepaired,
+	}
+}
+
+/// The bracket at the given position looks like it is part of a comment.
+fn in_comment(src_code: &str, pos: usize) -> bool {
+	let next_to_word = [
+		src_code[..pos].chars().next_back(),
+		src_code[pos + 1..].chars().next(),
+	]
+	.iter()
+	.flatten()
+	.any(|c| c.is_alphanumeric());
+	let line_start = src_code[..pos].rfind('\n').map_or(0, |index| index + 1);
+	let line_end = src_code[pos..]
+		.find('\n')
+		.map_or(src_code.len(), |index| pos + index);
+	let line = &src_code[line_start..line_end];
+	let word_chars = line.chars().filter(|c| c.is_alphanumeric()).count();
+	let instr_chars = line.chars().filter(|&c| "+-<>.,[]".contains(c)).count();
+	next_to_word || word_chars > instr_chars
+}
@@ -0,0 +1,74 @@
+//! Frame rate limiting of the output of programs run by the VM, for animations that would
+//! otherwise finish instantly: a frame starts at each escape sequence that moves the cursor home
+//! or clears the screen (`ESC [ ... H`, `ESC [ ... f` or `ESC [ ... J`), and the program is paused
+//! there until it is time for the next frame.
+
+use crate::vm::Observer;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+/// Where the output is in an escape sequence.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum EscapeState {
+	None,
+	/// After `ESC`.
+	Escape,
+	/// After `ESC [` and maybe some parameters.
+	Csi,
+}
+
+/// Observer of the VM that limits the number of frames output per second.
+pub struct FrameLimiter {
+	frame_duration: Duration,
+	/// When the next frame can start, none before the first frame.
+	next_frame: Option<Instant>,
+	escape_state: EscapeState,
+	/// The output is printed by the limiter (when the VM does not print it itself).
+	print: bool,
+}
+
+impl FrameLimiter {
+	pub fn new(frames_per_second: f64, print: bool) -> FrameLimiter {
+		assert!(frames_per_second > 0.0);
+		FrameLimiter {
+			frame_duration: Duration::from_secs_f64(1.0 / frames_per_second),
+			next_frame: None,
+			escape_state: EscapeState::None,
+			print,
+		}
+	}
+
+	/// Shows the previous frame and waits until the new one can start.
+	fn start_frame(&mut self) {
+		std::io::stdout().flush().ok();
+		let now = Instant::now();
+		let start = match self.next_frame {
+			Some(next_frame) if now < next_frame => {
+				std::thread::sleep(next_frame - now);
+				next_frame
+			}
+			_ => now,
+		};
+		self.next_frame = Some(start + self.frame_duration);
+	}
+}
+
+impl Observer for FrameLimiter {
+	fn on_output(&mut self, char_values: &[u8]) {
+		for &char_value in char_values {
+			if self.print {
+				std::io::stdout().write_all(&[char_value]).ok();
+			}
+			self.escape_state = match (self.escape_state, char_value) {
+				(_, 0x1b) => EscapeState::Escape,
+				(EscapeState::Escape, b'[') => EscapeState::Csi,
+				(EscapeState::Csi, b'0'..=b'9' | b';') => EscapeState::Csi,
+				(EscapeState::Csi, b'H' | b'f' | b'J') => {
+					self.start_frame();
+					EscapeState::None
+				}
+				_ => EscapeState::None,
+			};
+		}
+	}
+}
@@ -0,0 +1,114 @@
+//! Frontends turn the source code of a language into brainfuck tokens (see `tokenizer`), that
+//! keep the positions of their source code, so that the parser and the tools that point at the
+//! source code (errors, warnings, spans) work the same for every source language. The frontend
+//! is part of the `Extensions` given with the source code, `--dialect` selecting it by name.
+
+use crate::tokenizer::{Extensions, Token, TokenKind, push_token, tokenize_brainfuck};
+
+/// A source language (a dialect of brainfuck) that is tokenized into brainfuck tokens.
+pub trait Frontend: Send + Sync {
+	/// Name of the dialect, as given to `--dialect`.
+	fn name(&self) -> &'static str;
+
+	/// Splits the source code into tokens (sorted by position), comments and whitespace being
+	/// `Trivia` tokens only if `keep_trivia` (see `tokenizer::tokenize_with_trivia`).
+	fn tokenize(&self, src_code: &str, extensions: &Extensions, keep_trivia: bool) -> Vec<Token>;
+
+	/// The opt-in syntax extensions (see `Extensions`) apply to the dialect.
+	fn supports_extensions(&self) -> bool {
+		false
+	}
+}
+
+impl std::fmt::Debug for dyn Frontend {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "Frontend({})", self.name())
+	}
+}
+
+/// Brainfuck itself, with the syntax extensions.
+pub struct Brainfuck;
+
+impl Frontend for Brainfuck {
+	fn name(&self) -> &'static str {
+		"brainfuck"
+	}
+
+	fn tokenize(&self, src_code: &str, extensions: &Extensions, keep_trivia: bool) -> Vec<Token> {
+		tokenize_brainfuck(src_code, extensions, keep_trivia)
+	}
+
+	fn supports_extensions(&self) -> bool {
+		true
+	}
+}
+
+/// Ook!, where each instruction is a pair of the words `Ook.`, `Ook?` and `Ook!` (such as
+/// `Ook. Ook?` for `>`), anything else being a comment.
+pub struct Ook;
+
+impl Ook {
+	fn kind(first: u8, second: u8) -> Option<TokenKind> {
+		match (first, second) {
+			(b'.', b'?') => Some(TokenKind::Right),
+			(b'?', b'.') => Some(TokenKind::Left),
+			(b'.', b'.') => Some(TokenKind::Plus),
+			(b'!', b'!') => Some(TokenKind::Minus),
+			(b'!', b'.') => Some(TokenKind::Dot),
+			(b'.', b'!') => Some(TokenKind::Comma),
+			(b'!', b'?') => Some(TokenKind::OpeningBracket),
+			(b'?', b'!') => Some(TokenKind::ClosingBracket),
+			_ => None,
+		}
+	}
+}
+
+impl Frontend for Ook {
+	fn name(&self) -> &'static str {
+		"ook"
+	}
+
+	fn tokenize(&self, src_code: &str, _extensions: &Extensions, keep_trivia: bool) -> Vec<Token> {
+		// Positions of the words (the punctuation of a word being 3 bytes after its start).
+		let words: Vec<usize> = src_code
+			.match_indices("Ook")
+			.map(|(pos, _)| pos)
+			.filter(|&pos| matches!(src_code.as_bytes().get(pos + 3), Some(b'.' | b'?' | b'!')))
+			.collect();
+		let mut tokens = Vec::new();
+		// End of the last instruction, the source code after it up to the next one is trivia.
+		let mut end = 0;
+		let trivia = |tokens: &mut Vec<Token>, from: usize, to: usize| {
+			if from < to {
+				push_token(tokens, TokenKind::Trivia, from, to - from, keep_trivia);
+			}
+		};
+		// A word that does not pair with the next one is ignored like a comment.
+		let mut index = 0;
+		while index + 1 < words.len() {
+			let (first, second) = (words[index], words[index + 1]);
+			let bytes = src_code.as_bytes();
+			match Ook::kind(bytes[first + 3], bytes[second + 3]) {
+				Some(kind) if src_code[first + 4..second].trim().is_empty() => {
+					trivia(&mut tokens, end, first);
+					push_token(&mut tokens, kind, first, second + 4 - first, keep_trivia);
+					end = second + 4;
+					index += 2;
+				}
+				_ => index += 1,
+			}
+		}
+		trivia(&mut tokens, end, src_code.len());
+		tokens
+	}
+}
+
+/// The frontends that `--dialect` can select.
+pub const FRONTENDS: &[&dyn Frontend] = &[&Brainfuck, &Ook];
+
+pub fn frontend(name: &str) -> Option<&'static dyn Frontend> {
+	FRONTENDS
+		.iter()
+		.copied()
+		.find(|frontend| frontend.name() == name)
+}
@@ -0,0 +1,85 @@
+//! Generation of brainfuck code for common chores.
+
+use crate::cost::CostModel;
+use crate::prelude::routine;
+
+/// Adds the moves from the cell at `pos` to the cell at `target` to the code.
+fn move_to(code: &mut String, pos: &mut usize, target: usize) {
+	if target > *pos {
+		code.extend(std::iter::repeat_n('>', target - *pos));
+	} else {
+		code.extend(std::iter::repeat_n('<', *pos - target));
+	}
+	*pos = target;
+}
+
+/// Code that adds the value to the cell under the head (without making it go above 255), using
+/// the next cell (that must be zero and stays zero) as the counter of a multiplication loop when
+/// that is cheaper for the cost model than adding the value directly.
+fn add_to_cell(value: u8, cell_value_max: u8, cost_model: &CostModel) -> String {
+	let value = value as u32;
+	let mut best = "+".repeat(value as usize);
+	let mut best_cost = cost_model.code_cost(&best);
+	// Adds `times * step` in a loop then corrects the difference with `rest`.
+	for times in 2..=value {
+		for step in 2..=value.div_ceil(times) {
+			let product = times * step;
+			if product + cell_value_max as u32 > 255 {
+				break;
+			}
+			let rest = if product > value {
+				"-".repeat((product - value) as usize)
+			} else {
+				"+".repeat((value - product) as usize)
+			};
+			let code = format!(
+				">{}[<{}>-]<{}",
+				"+".repeat(times as usize),
+				"+".repeat(step as usize),
+				rest
+			);
+			let cost = cost_model.code_cost(&code);
+			if cost < best_cost {
+				best = code;
+				best_cost = cost;
+			}
+		}
+	}
+	best
+}
+
+/// Code that prints the number in the given radix (from 2 to 10), its digits being computed by
+/// division loops (see the `divmod` routine of the prelude). The cells from the one under the
+/// head must be zeros, and are zeros after (the head ending where it started). The code that
+/// sets cells to values is the cheapest for the cost model.
+pub fn print_number(number: u8, radix: u8, cost_model: &CostModel) -> String {
+	assert!((2..=10).contains(&radix));
+	let mut digit_count = 1;
+	while (radix as usize).pow(digit_count) <= number as usize {
+		digit_count += 1;
+	}
+	let digit_count = digit_count as usize;
+	let divmod = routine("divmod").unwrap().code;
+
+	let mut code = add_to_cell(number, 0, cost_model);
+	// Each division leaves the remainder (a digit) two cells after the divided cell and the
+	// quotient three cells after it, where the next division happens.
+	let mut pos = 0;
+	for _ in 1..digit_count {
+		code.push('>');
+		code.extend(std::iter::repeat_n('+', radix as usize));
+		code.push('<');
+		code.push_str(divmod);
+		code.push_str(">[-]>>");
+		pos += 3;
+	}
+	// The last quotient is the most significant digit, the cell after each digit is zero.
+	let print_digit = format!("{}.[-]", add_to_cell(b'0', radix - 1, cost_model));
+	code.push_str(&print_digit);
+	for division in (0..digit_count - 1).rev() {
+		move_to(&mut code, &mut pos, division * 3 + 2);
+		code.push_str(&print_digit);
+	}
+	move_to(&mut code, &mut pos, 0);
+	code
+}
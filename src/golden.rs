@@ -0,0 +1,94 @@
+//! Golden tests of the optimizer: each program of a corpus demonstrates an optimization, and the
+//! textual soup IR (see `irtext`) of the program optimized at level 1 has to match the snapshot
+//! checked in next to it (with the `.soup` extension), snapshots being written when blessing.
+//!
+//! A pass that changes the optimized IR of a program makes its case fail with a diff, so that
+//! a contributor adding a pass can bless the snapshots and show the changes in review.
+
+use crate::conformance::corpus_programs;
+use crate::diff::unified_diff;
+use crate::program::Program;
+use crate::tokenizer::Extensions;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum GoldenVerdict {
+	Match,
+	/// Unified diff from the snapshot to the actual IR.
+	Mismatch(String),
+	Written,
+	/// There is no snapshot yet (bless to write it).
+	Missing,
+}
+
+impl GoldenVerdict {
+	pub fn is_failure(&self) -> bool {
+		matches!(self, GoldenVerdict::Mismatch(_) | GoldenVerdict::Missing)
+	}
+}
+
+#[derive(Debug)]
+pub struct GoldenResult {
+	pub program_name: String,
+	pub verdict: GoldenVerdict,
+}
+
+#[derive(Debug)]
+pub struct GoldenOptions {
+	pub corpus_dir: PathBuf,
+	/// Writes the snapshots instead of checking them.
+	pub bless: bool,
+}
+
+impl Default for GoldenOptions {
+	fn default() -> GoldenOptions {
+		GoldenOptions {
+			corpus_dir: PathBuf::from("bf_programs/optimizer"),
+			bless: false,
+		}
+	}
+}
+
+/// Checks the optimized IR of every program of the corpus against its snapshot, the given
+/// callback being called with the result of each case as soon as it is known.
+pub fn run_golden(
+	options: &GoldenOptions,
+	mut on_result: impl FnMut(&GoldenResult),
+) -> Result<Vec<GoldenResult>, String> {
+	let mut results = Vec::new();
+	for src_path in corpus_programs(&options.corpus_dir)? {
+		let program_name = src_path.file_stem().unwrap().to_string_lossy().into_owned();
+		let src_code = std::fs::read_to_string(&src_path)
+			.map_err(|error| format!("cannot read `{}`: {}", src_path.display(), error))?;
+		let mut program = Program::parse(&src_code, &Extensions::default())
+			.map_err(|_| format!("cannot parse `{}`", src_path.display()))?;
+		program.optimize(1);
+		let ir_text = program.ir_text();
+
+		let snapshot_path = src_path.with_extension("soup");
+		let verdict = if options.bless {
+			std::fs::write(&snapshot_path, &ir_text).map_err(|error| {
+				format!("cannot write `{}`: {}", snapshot_path.display(), error)
+			})?;
+			GoldenVerdict::Written
+		} else {
+			match std::fs::read_to_string(&snapshot_path) {
+				Ok(snapshot) if snapshot == ir_text => GoldenVerdict::Match,
+				Ok(snapshot) => GoldenVerdict::Mismatch(unified_diff(
+					&format!("{} (snapshot)", program_name),
+					&format!("{} (actual)", program_name),
+					&snapshot,
+					&ir_text,
+				)),
+				Err(_) => GoldenVerdict::Missing,
+			}
+		};
+		let result = GoldenResult {
+			program_name,
+			verdict,
+		};
+		on_result(&result);
+		results.push(result);
+	}
+	Ok(results)
+}
@@ -1,45 +1,133 @@
+//! Control flow graph form of the soup intermediate representation, where loops are replaced by
+//! branches between basic blocks, for analyses that need to follow the control flow (see `reloop`
+//! for the way back to structured control flow).
 
-use std::collections::HashMap;
 use crate::astsoup::SoupInstr;
+use crate::extended::ExtInstr;
+use std::collections::HashMap;
 
-enum BlockInstr {
-	Soup {
-		cell_deltas: HashMap<isize, isize>,
-		head_delta: isize,
-	},
-	Output,
-	Input,
-	MultFixedLoop {
-		// Cell delta on head is -1 here.
-		cell_deltas: HashMap<isize, isize>,
-	},
-	SoupFixedLoop {
-		cell_deltas: HashMap<isize, isize>,
-	},
-	SoupMovingLoop {
-		cell_deltas: HashMap<isize, isize>,
-		head_delta: isize,
-	},
-	Loop(Vec<BlockInstr>),
-}
-
-type BlockId = u64;
+pub type BlockId = u64;
 
-enum Terminator {
+#[derive(Debug, Clone)]
+pub enum Terminator {
 	Goto(BlockId),
-	Branch { if_zero: BlockId, if_non_zero: BlockId },
+	/// Branches on the value of the cell under the head.
+	Branch {
+		if_zero: BlockId,
+		if_non_zero: BlockId,
+	},
+	/// End of the program.
+	Halt,
 }
 
-struct Block {
-	soup_instrs: Vec<BlockInstr>,
-	terminator: Terminator,
+#[derive(Debug, Clone)]
+pub struct Block {
+	/// The instructions of the block, each with its index in the soup program (counting the
+	/// instructions in pre-order, loops before their bodies). Loops with a single soup as their
+	/// body are kept as instructions, other loops (`SoupInstr::Loop`) become branches between
+	/// blocks so they are never in a block.
+	pub soup_instrs: Vec<(usize, SoupInstr)>,
+	pub terminator: Terminator,
 }
 
-struct Graph {
-	blocks: HashMap<BlockId, Block>,
+#[derive(Debug, Clone)]
+pub struct Graph {
+	pub blocks: HashMap<BlockId, Block>,
+	pub entry: BlockId,
+	/// Block where the body of each loop starts, by the index of the loop in the soup program.
+	pub loop_bodies: HashMap<usize, BlockId>,
 	next_id: BlockId,
 }
 
-fn grahify(raw_prog: &Vec<SoupInstr>) -> Graph {
-	todo!()
+impl Graph {
+	fn new_block(&mut self) -> BlockId {
+		let id = self.next_id;
+		self.next_id += 1;
+		self.blocks.insert(
+			id,
+			Block {
+				soup_instrs: Vec::new(),
+				terminator: Terminator::Halt,
+			},
+		);
+		id
+	}
+
+	/// Adds the block to the graph, and returns its id.
+	pub fn add_block(&mut self, block: Block) -> BlockId {
+		let id = self.new_block();
+		self.blocks.insert(id, block);
+		id
+	}
+
+	/// Blocks that the terminator of the given block can jump to.
+	pub fn successors(&self, id: BlockId) -> Vec<BlockId> {
+		match self.blocks[&id].terminator {
+			Terminator::Goto(target) => vec![target],
+			Terminator::Branch {
+				if_zero,
+				if_non_zero,
+			} => vec![if_zero, if_non_zero],
+			Terminator::Halt => Vec::new(),
+		}
+	}
+
+	/// Adds the instructions of the sequence at the end of the given block (creating blocks for
+	/// the loops), and returns the block where the control flow continues after the sequence.
+	fn add_instr_seq(
+		&mut self,
+		instr_seq: &[SoupInstr],
+		mut current: BlockId,
+		soup_index: &mut usize,
+	) -> BlockId {
+		for instr in instr_seq {
+			let index = *soup_index;
+			*soup_index += 1;
+			let SoupInstr::Loop(body, _) = instr else {
+				let ends_program = matches!(
+					instr,
+					SoupInstr::Extended {
+						instr: ExtInstr::End,
+						..
+					}
+				);
+				self.blocks
+					.get_mut(&current)
+					.unwrap()
+					.soup_instrs
+					.push((index, instr.clone()));
+				if ends_program {
+					// The block halts after the instruction, what follows is in a block that
+					// cannot be reached.
+					current = self.new_block();
+				}
+				continue;
+			};
+			let header = self.new_block();
+			let body_start = self.new_block();
+			let after = self.new_block();
+			self.loop_bodies.insert(index, body_start);
+			self.blocks.get_mut(&current).unwrap().terminator = Terminator::Goto(header);
+			self.blocks.get_mut(&header).unwrap().terminator = Terminator::Branch {
+				if_zero: after,
+				if_non_zero: body_start,
+			};
+			let body_end = self.add_instr_seq(body, body_start, soup_index);
+			self.blocks.get_mut(&body_end).unwrap().terminator = Terminator::Goto(header);
+			current = after;
+		}
+		current
+	}
+}
+
+pub fn graphify(soup_prog: &[SoupInstr]) -> Graph {
+	let mut graph = Graph {
+		blocks: HashMap::new(),
+		entry: 0,
+		loop_bodies: HashMap::new(),
+		next_id: 0,
+	};
+	graph.entry = graph.new_block();
+	graph.add_instr_seq(soup_prog, graph.entry, &mut 0);
+	graph
 }
@@ -0,0 +1,71 @@
+use std::hash::{Hash, Hasher};
+
+/// FNV-1a hasher whose results do not depend on the platform nor on the Rust version (unlike the
+/// standard library's default hasher), integers being hashed as little-endian 64-bit values.
+pub struct StableHasher {
+	state: u64,
+}
+
+impl StableHasher {
+	pub fn new() -> StableHasher {
+		StableHasher {
+			state: 0xcbf29ce484222325,
+		}
+	}
+}
+
+impl Default for StableHasher {
+	fn default() -> StableHasher {
+		StableHasher::new()
+	}
+}
+
+impl Hasher for StableHasher {
+	fn finish(&self) -> u64 {
+		self.state
+	}
+
+	fn write(&mut self, bytes: &[u8]) {
+		for &byte in bytes {
+			self.state ^= byte as u64;
+			self.state = self.state.wrapping_mul(0x100000001b3);
+		}
+	}
+
+	fn write_u8(&mut self, i: u8) {
+		self.write_u64(i as u64);
+	}
+	fn write_u16(&mut self, i: u16) {
+		self.write_u64(i as u64);
+	}
+	fn write_u32(&mut self, i: u32) {
+		self.write_u64(i as u64);
+	}
+	fn write_u64(&mut self, i: u64) {
+		self.write(&i.to_le_bytes());
+	}
+	fn write_usize(&mut self, i: usize) {
+		self.write_u64(i as u64);
+	}
+	fn write_i8(&mut self, i: i8) {
+		self.write_i64(i as i64);
+	}
+	fn write_i16(&mut self, i: i16) {
+		self.write_i64(i as i64);
+	}
+	fn write_i32(&mut self, i: i32) {
+		self.write_i64(i as i64);
+	}
+	fn write_i64(&mut self, i: i64) {
+		self.write(&i.to_le_bytes());
+	}
+	fn write_isize(&mut self, i: isize) {
+		self.write_i64(i as i64);
+	}
+}
+
+pub fn stable_hash<T: Hash + ?Sized>(value: &T) -> u64 {
+	let mut hasher = StableHasher::new();
+	value.hash(&mut hasher);
+	hasher.finish()
+}
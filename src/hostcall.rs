@@ -0,0 +1,26 @@
+//! Host calls of the `host-call` extension (see `Extensions::host_call`): the program calls its
+//! host with the value of the current cell, which is set to the value returned by the host, so
+//! that embedders can give programs a controlled access to features of the host (such as
+//! plotting a pixel in a demo).
+//!
+//! A program run by the VM calls `Observer::on_host_call` (see `HostCallback` to answer with a
+//! closure), the cell being left unchanged if no observer answers. A program compiled to C calls
+//! the function `C_FUNCTION`, which it declares but does not define, so it has to be linked with
+//! an object file that defines it as `unsigned char xxbf_host_call(unsigned char value)`.
+
+use crate::vm::Observer;
+
+/// Name of the function called by programs compiled to C.
+pub const C_FUNCTION: &str = "xxbf_host_call";
+
+/// Observer of the VM that answers the host calls with the closure, which gets the value of the
+/// cell and returns its new value.
+pub struct HostCallback<F>(pub F);
+
+impl<F: FnMut(u8) -> u8> Observer for HostCallback<F> {
+	fn on_output(&mut self, _char_values: &[u8]) {}
+
+	fn on_host_call(&mut self, cell_value: u8) -> Option<u8> {
+		Some((self.0)(cell_value))
+	}
+}
@@ -0,0 +1,392 @@
+//! Interval analysis of the cell values over the graph IR, to find the instructions that can
+//! make a cell wrap around (go above 255 or below 0), the instructions that cannot be reached
+//! (see `deadcode`), and the loops that always run the same number of times (see
+//! `astsoup::fold_constant_loops`).
+//!
+//! Each cell is approximated by an interval of the values it can hold, cells being identified by
+//! their offset from an origin that is reset when the head position is lost (after a moving loop,
+//! or where control flow joins with different head positions). Loops are handled by iterating to
+//! a fixpoint, widening the intervals that keep growing to the full range.
+
+use crate::astsoup::{SoupInstr, instr_count};
+use crate::graph::{BlockId, Graph, Terminator, graphify};
+use crate::tapeconfig::TapeConfig;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Interval {
+	low: i64,
+	high: i64,
+}
+
+impl Interval {
+	const FULL: Interval = Interval { low: 0, high: 255 };
+	const ZERO: Interval = Interval { low: 0, high: 0 };
+
+	fn join(self, other: Interval) -> Interval {
+		Interval {
+			low: self.low.min(other.low),
+			high: self.high.max(other.high),
+		}
+	}
+
+	fn widen(self, newer: Interval) -> Interval {
+		Interval {
+			low: if newer.low < self.low { 0 } else { self.low },
+			high: if newer.high > self.high {
+				255
+			} else {
+				self.high
+			},
+		}
+	}
+
+	fn fits_in_a_cell(self) -> bool {
+		0 <= self.low && self.high <= 255
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct State {
+	/// Intervals of the cells, by offset from the origin.
+	cells: BTreeMap<isize, Interval>,
+	/// Interval of the cells that are not in `cells`.
+	other_cells: Interval,
+	/// Offset of the head from the origin.
+	head: isize,
+}
+
+impl State {
+	fn unknown() -> State {
+		State {
+			cells: BTreeMap::new(),
+			other_cells: Interval::FULL,
+			head: 0,
+		}
+	}
+
+	fn cell(&self, relative_head: isize) -> Interval {
+		*self
+			.cells
+			.get(&(self.head + relative_head))
+			.unwrap_or(&self.other_cells)
+	}
+
+	fn set_cell(&mut self, relative_head: isize, interval: Interval) {
+		self.cells.insert(self.head + relative_head, interval);
+	}
+
+	/// Combines the intervals of the two states cell by cell.
+	fn combine(&self, other: &State, f: impl Fn(Interval, Interval) -> Interval) -> State {
+		if self.head != other.head {
+			return State::unknown();
+		}
+		let offsets: BTreeSet<isize> = self
+			.cells
+			.keys()
+			.chain(other.cells.keys())
+			.copied()
+			.collect();
+		State {
+			cells: offsets
+				.into_iter()
+				.map(|offset| {
+					let interval =
+						|state: &State| *state.cells.get(&offset).unwrap_or(&state.other_cells);
+					(offset, f(interval(self), interval(other)))
+				})
+				.collect(),
+			other_cells: f(self.other_cells, other.other_cells),
+			head: self.head,
+		}
+	}
+
+	/// Adds the given interval to a cell, returns false if that can make it wrap around (the cell
+	/// is then considered to hold any value).
+	fn add_to_cell(&mut self, relative_head: isize, low: i64, high: i64) -> bool {
+		let cell = self.cell(relative_head);
+		let sum = Interval {
+			low: cell.low + low,
+			high: cell.high + high,
+		};
+		let fits = sum.fits_in_a_cell();
+		self.set_cell(relative_head, if fits { sum } else { Interval::FULL });
+		fits
+	}
+
+	/// The instruction is a loop that is entered and never exits, as its body does not change
+	/// the cell under the head.
+	fn never_exits(&self, instr: &SoupInstr) -> bool {
+		match instr {
+			SoupInstr::SoupFixedLoop { cell_deltas } => {
+				self.cell(0).low > 0 && cell_deltas.get(&0).is_none_or(|delta| delta % 256 == 0)
+			}
+			_ => false,
+		}
+	}
+
+	/// Applies the instruction, and returns false if it can make a cell wrap around.
+	fn apply(&mut self, instr: &SoupInstr) -> bool {
+		match instr {
+			SoupInstr::Soup {
+				cell_deltas,
+				head_delta,
+			} => {
+				let mut never_wraps = true;
+				for (&relative_head, &delta) in cell_deltas {
+					let delta = delta as i64;
+					never_wraps &= self.add_to_cell(relative_head, delta, delta);
+				}
+				self.head += head_delta;
+				never_wraps
+			}
+			SoupInstr::Seek { delta } => {
+				self.head += delta;
+				true
+			}
+			SoupInstr::Output { .. } => true,
+			SoupInstr::Input { offset }
+			| SoupInstr::Clock { offset }
+			| SoupInstr::Random { offset }
+			| SoupInstr::HostCall { offset } => {
+				self.set_cell(*offset, Interval::FULL);
+				true
+			}
+			SoupInstr::Extended { instr, offset } => {
+				if instr.writes_cell() {
+					self.set_cell(*offset, Interval::FULL);
+				}
+				// The cell stays in 0..=255 (see `ExtInstr::c_statement`).
+				true
+			}
+			SoupInstr::MultFixedLoop { cell_deltas } => {
+				// The loop runs as many times as the value of the cell under the head.
+				let iterations = self.cell(0);
+				let mut never_wraps = true;
+				for (&relative_head, &delta) in cell_deltas {
+					if relative_head != 0 {
+						let delta = delta as i64;
+						let (low, high) = if delta >= 0 {
+							(delta * iterations.low, delta * iterations.high)
+						} else {
+							(delta * iterations.high, delta * iterations.low)
+						};
+						never_wraps &= self.add_to_cell(relative_head, low, high);
+					}
+				}
+				self.set_cell(0, Interval::ZERO);
+				never_wraps
+			}
+			SoupInstr::SoupFixedLoop { cell_deltas } => {
+				// The number of iterations is unknown (and reaching zero often relies on
+				// wrapping).
+				let entered = self.cell(0) != Interval::ZERO;
+				for &relative_head in cell_deltas.keys() {
+					self.set_cell(relative_head, Interval::FULL);
+				}
+				self.set_cell(0, Interval::ZERO);
+				!entered || cell_deltas.values().all(|&delta| delta == 0)
+			}
+			SoupInstr::SoupMovingLoop { cell_deltas, .. } => {
+				let entered = self.cell(0) != Interval::ZERO;
+				if entered {
+					*self = State::unknown();
+				}
+				self.set_cell(0, Interval::ZERO);
+				!entered || cell_deltas.values().all(|&delta| delta == 0)
+			}
+			SoupInstr::BlockMove { .. } => {
+				// The cell under the head is added to its destination, which can wrap around.
+				let entered = self.cell(0) != Interval::ZERO;
+				if entered {
+					*self = State::unknown();
+				}
+				self.set_cell(0, Interval::ZERO);
+				!entered
+			}
+			SoupInstr::PrintUntilZero { .. }
+			| SoupInstr::ReadBlock { .. }
+			| SoupInstr::ReadUntilZero { .. } => {
+				if self.cell(0) != Interval::ZERO {
+					*self = State::unknown();
+				}
+				self.set_cell(0, Interval::ZERO);
+				true
+			}
+			SoupInstr::Loop(..) => unreachable!(),
+		}
+	}
+}
+
+/// For each instruction of a soup program, whether it can make a cell wrap around.
+#[derive(Debug, Clone)]
+pub struct WrapFacts {
+	/// Indexed by the index of the instruction in the soup program (in pre-order, see
+	/// `graph::Block::soup_instrs`).
+	may_wrap: Vec<bool>,
+}
+
+impl WrapFacts {
+	pub fn may_wrap(&self, soup_index: usize) -> bool {
+		self.may_wrap[soup_index]
+	}
+
+	/// No instruction of the program can make a cell wrap around, so cells can be represented by
+	/// wider integers without changing the behavior of the program.
+	pub fn never_wraps(&self) -> bool {
+		!self.may_wrap.iter().any(|&may_wrap| may_wrap)
+	}
+}
+
+/// Number of times the entry state of a block is recomputed before widening.
+const VISITS_BEFORE_WIDENING: u32 = 3;
+
+/// The cells and the head start as configured (see `tapeconfig`).
+pub fn wrap_facts(soup_prog: &[SoupInstr], tape_config: &TapeConfig) -> WrapFacts {
+	let graph = graphify(soup_prog);
+	let entry_states = fixpoint(&graph, tape_config);
+	let mut may_wrap = vec![false; instr_count(soup_prog)];
+	for (id, entry_state) in entry_states {
+		let mut state = entry_state;
+		for (soup_index, instr) in &graph.blocks[&id].soup_instrs {
+			if !state.apply(instr) {
+				may_wrap[*soup_index] = true;
+			}
+		}
+	}
+	WrapFacts { may_wrap }
+}
+
+/// For each instruction of a soup program (in pre-order, see `graph::Block::soup_instrs`),
+/// whether the control flow can reach it, a loop being reachable if its body can be entered.
+/// The cells and the head start as configured (see `tapeconfig`).
+pub fn reachable_instrs(soup_prog: &[SoupInstr], tape_config: &TapeConfig) -> Vec<bool> {
+	let graph = graphify(soup_prog);
+	let entry_states = fixpoint(&graph, tape_config);
+	let mut reachable = vec![false; instr_count(soup_prog)];
+	for (id, entry_state) in &entry_states {
+		let mut state = entry_state.clone();
+		for (soup_index, instr) in &graph.blocks[id].soup_instrs {
+			reachable[*soup_index] = true;
+			if state.never_exits(instr) {
+				break;
+			}
+			state.apply(instr);
+		}
+	}
+	for (&soup_index, body_start) in &graph.loop_bodies {
+		reachable[soup_index] = entry_states.contains_key(body_start);
+	}
+	reachable
+}
+
+/// For each instruction of a soup program (in pre-order, see `graph::Block::soup_instrs`), the
+/// number of times it runs if it is a `MultFixedLoop` that always finds the same value in the
+/// cell under the head. The cells and the head start as configured (see `tapeconfig`).
+pub fn constant_loop_counts(soup_prog: &[SoupInstr], tape_config: &TapeConfig) -> Vec<Option<u8>> {
+	let graph = graphify(soup_prog);
+	let entry_states = fixpoint(&graph, tape_config);
+	let mut counts = vec![None; instr_count(soup_prog)];
+	for (id, entry_state) in entry_states {
+		let mut state = entry_state;
+		for (soup_index, instr) in &graph.blocks[&id].soup_instrs {
+			if state.never_exits(instr) {
+				break;
+			}
+			if let SoupInstr::MultFixedLoop { .. } = instr {
+				let cell = state.cell(0);
+				if cell.low == cell.high {
+					counts[*soup_index] = Some(cell.low as u8);
+				}
+			}
+			state.apply(instr);
+		}
+	}
+	counts
+}
+
+/// State at the entry of each reachable block.
+fn fixpoint(graph: &Graph, tape_config: &TapeConfig) -> HashMap<BlockId, State> {
+	let mut entry_states: HashMap<BlockId, State> = HashMap::new();
+	let mut visits: HashMap<BlockId, u32> = HashMap::new();
+	entry_states.insert(
+		graph.entry,
+		State {
+			cells: (0..)
+				.zip(&tape_config.initial_tape)
+				.filter(|&(_, &value)| value != 0)
+				.map(|(index, &value)| {
+					let value = value as i64;
+					(
+						index,
+						Interval {
+							low: value,
+							high: value,
+						},
+					)
+				})
+				.collect(),
+			other_cells: Interval::ZERO,
+			head: tape_config.head_start as isize,
+		},
+	);
+	let mut worklist: BTreeSet<BlockId> = BTreeSet::from([graph.entry]);
+	'blocks: while let Some(id) = worklist.pop_first() {
+		let mut state = entry_states[&id].clone();
+		let block = &graph.blocks[&id];
+		for (_, instr) in &block.soup_instrs {
+			if state.never_exits(instr) {
+				continue 'blocks;
+			}
+			state.apply(instr);
+		}
+		let mut successor_states = Vec::new();
+		match block.terminator {
+			Terminator::Goto(target) => successor_states.push((target, state)),
+			Terminator::Branch {
+				if_zero,
+				if_non_zero,
+			} => {
+				let cell = state.cell(0);
+				if cell.low == 0 {
+					let mut zero_state = state.clone();
+					zero_state.set_cell(0, Interval::ZERO);
+					successor_states.push((if_zero, zero_state));
+				}
+				if cell.high > 0 {
+					let mut non_zero_state = state;
+					non_zero_state.set_cell(
+						0,
+						Interval {
+							low: cell.low.max(1),
+							high: cell.high,
+						},
+					);
+					successor_states.push((if_non_zero, non_zero_state));
+				}
+			}
+			Terminator::Halt => (),
+		}
+
+		for (target, new_state) in successor_states {
+			let merged = match entry_states.get(&target) {
+				None => new_state,
+				Some(old_state) => {
+					let joined = old_state.combine(&new_state, Interval::join);
+					let visit_count = visits.entry(target).or_insert(0);
+					*visit_count += 1;
+					if *visit_count > VISITS_BEFORE_WIDENING {
+						old_state.combine(&joined, Interval::widen)
+					} else {
+						joined
+					}
+				}
+			};
+			if entry_states.get(&target) != Some(&merged) {
+				entry_states.insert(target, merged);
+				worklist.insert(target);
+			}
+		}
+	}
+	entry_states
+}
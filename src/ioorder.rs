@@ -0,0 +1,56 @@
+//! Conservative check that an optimization keeps the order of the inputs and outputs of a
+//! program (see `PassManager::strict_io`), so that the bytes a program outputs interleave with
+//! the bytes it reads as in the raw interpreter (prompts coming before the inputs they ask for).
+//!
+//! The check compares the I/O signatures of the program before and after a pass, a signature
+//! being what is left of the program when only its I/O instructions and the loops around them
+//! are kept. A pass that keeps the signature cannot have reordered I/O, a pass that changes it
+//! may have (or may only have removed loops that are never entered).
+
+use crate::astsoup::SoupInstr;
+use crate::extended::ExtInstr;
+
+/// I/O signature of the program, in the syntax of brainfuck (`.` and `,`, and `~`, `?` and `@`
+/// for the clock, the random bytes and the end of the program), with a loop around each I/O
+/// instruction that runs in a loop.
+pub fn io_signature(soup_prog: &[SoupInstr]) -> String {
+	let mut signature = String::new();
+	add_io_signature(soup_prog, &mut signature);
+	signature
+}
+
+fn add_io_signature(instr_seq: &[SoupInstr], signature: &mut String) {
+	for instr in instr_seq {
+		match instr {
+			SoupInstr::Output { .. } => signature.push('.'),
+			SoupInstr::Input { .. } => signature.push(','),
+			SoupInstr::Clock { .. } => signature.push('~'),
+			SoupInstr::Random { .. } => signature.push('?'),
+			SoupInstr::HostCall { .. } => signature.push('%'),
+			SoupInstr::Extended {
+				instr: ExtInstr::End,
+				..
+			} => signature.push('@'),
+			SoupInstr::PrintUntilZero { .. } => signature.push_str("[.]"),
+			SoupInstr::ReadBlock { .. } | SoupInstr::ReadUntilZero { .. } => {
+				signature.push_str("[,]")
+			}
+			SoupInstr::Loop(body, _) => {
+				let mut body_signature = String::new();
+				add_io_signature(body, &mut body_signature);
+				if !body_signature.is_empty() {
+					signature.push('[');
+					signature.push_str(&body_signature);
+					signature.push(']');
+				}
+			}
+			SoupInstr::Soup { .. }
+			| SoupInstr::Seek { .. }
+			| SoupInstr::Extended { .. }
+			| SoupInstr::MultFixedLoop { .. }
+			| SoupInstr::SoupFixedLoop { .. }
+			| SoupInstr::SoupMovingLoop { .. }
+			| SoupInstr::BlockMove { .. } => (),
+		}
+	}
+}
@@ -0,0 +1,196 @@
+//! What a program does with its input (not to be confused with `ioorder::io_signature`): whether
+//! it reads input at all, whether its output can depend on what it reads, and how many bytes it
+//! reads at most, for example so that an embedder runs the programs that read nothing as batch
+//! jobs and only gives a terminal to the others.
+//!
+//! The analysis is conservative: a program said to read no input never reads, and a program said
+//! to have an output that does not depend on its input outputs the same bytes for every input
+//! (or runs forever for every input), but a program can be said to depend on its input without
+//! actually depending on it. It follows which cells may hold a value derived from the input, by
+//! offset from the head, and once a loop decides on such a cell, everything that comes after it
+//! is considered to depend on the input (as the loop may not even end for some inputs).
+
+use crate::astsoup::SoupInstr;
+use std::collections::BTreeSet;
+
+/// I/O signature of a program (see `analyze_io`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoSignature {
+	/// The program has instructions that read input (that may never run).
+	pub reads_input: bool,
+	/// The output may depend on the input, through the values output or through which
+	/// instructions run.
+	pub output_depends_on_input: bool,
+	/// Number of bytes of input read at most, if it is bounded (it is not found to be when the
+	/// program reads in a loop).
+	pub max_input_len: Option<usize>,
+}
+
+pub fn analyze_io(soup_prog: &[SoupInstr]) -> IoSignature {
+	let mut taint = Taint::default();
+	taint.run_instr_seq(soup_prog);
+	IoSignature {
+		reads_input: reads_input(soup_prog),
+		output_depends_on_input: taint.output_depends,
+		max_input_len: max_input_len(soup_prog),
+	}
+}
+
+fn reads_input(instr_seq: &[SoupInstr]) -> bool {
+	instr_seq.iter().any(|instr| match instr {
+		SoupInstr::Input { .. }
+		| SoupInstr::ReadBlock { .. }
+		| SoupInstr::ReadUntilZero { .. } => true,
+		SoupInstr::Loop(body, _) => reads_input(body),
+		_ => false,
+	})
+}
+
+fn max_input_len(instr_seq: &[SoupInstr]) -> Option<usize> {
+	let mut len = 0;
+	for instr in instr_seq {
+		match instr {
+			SoupInstr::Input { .. } => len += 1,
+			SoupInstr::ReadBlock { .. } | SoupInstr::ReadUntilZero { .. } => return None,
+			SoupInstr::Loop(body, _) if reads_input(body) => return None,
+			_ => (),
+		}
+	}
+	Some(len)
+}
+
+/// What may depend on the input at some point of the program.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Taint {
+	/// Position of the head, relative to some origin.
+	head: isize,
+	/// Cells that may hold a value derived from the input, by position relative to the origin.
+	cells: BTreeSet<isize>,
+	/// Any cell may hold a value derived from the input (the position of the head being lost).
+	all_cells: bool,
+	/// Which instructions run may depend on the input.
+	control: bool,
+	output_depends: bool,
+}
+
+impl Taint {
+	fn is_clean(&self) -> bool {
+		self.cells.is_empty() && !self.all_cells && !self.control
+	}
+
+	fn cell(&self, offset: isize) -> bool {
+		self.all_cells || self.cells.contains(&(self.head + offset))
+	}
+
+	fn set_cell(&mut self, offset: isize, tainted: bool) {
+		if tainted {
+			self.cells.insert(self.head + offset);
+		} else {
+			self.cells.remove(&(self.head + offset));
+		}
+	}
+
+	fn output(&mut self, offset: isize) {
+		self.output_depends |= self.control || self.cell(offset);
+	}
+
+	/// The loop on the cell under the head ran, moving the head by an unknown amount.
+	fn lose_head(&mut self) {
+		if self.is_clean() {
+			// Nothing depends on the input, no matter where the head is.
+			self.cells.clear();
+		} else {
+			self.all_cells = true;
+			self.control = true;
+		}
+	}
+
+	/// The loop on the cell under the head ran, ending with a zero in that cell.
+	fn exit_loop(&mut self) {
+		self.set_cell(0, false);
+	}
+
+	fn run_instr_seq(&mut self, instr_seq: &[SoupInstr]) {
+		for instr in instr_seq {
+			self.run_instr(instr);
+		}
+	}
+
+	fn run_instr(&mut self, instr: &SoupInstr) {
+		match instr {
+			// Adding constants to cells keeps them as dependent on the input as they were.
+			SoupInstr::Soup { head_delta, .. } => self.head += head_delta,
+			SoupInstr::Seek { delta } => self.head += delta,
+			SoupInstr::Output { offset } => self.output(*offset),
+			SoupInstr::Input { offset } => self.set_cell(*offset, true),
+			SoupInstr::Clock { offset } | SoupInstr::Random { offset } => {
+				self.set_cell(*offset, false)
+			}
+			// The value returned by the host may depend on the value given, the cell stays as
+			// dependent on the input as it was.
+			SoupInstr::HostCall { .. } => (),
+			SoupInstr::Extended { instr, offset } => {
+				// The storage may hold values derived from the input.
+				if instr.writes_cell() && !self.is_clean() {
+					self.set_cell(*offset, true);
+				}
+			}
+			SoupInstr::MultFixedLoop { cell_deltas } => {
+				if self.cell(0) {
+					for &offset in cell_deltas.keys() {
+						self.set_cell(offset, true);
+					}
+				}
+				self.exit_loop();
+			}
+			SoupInstr::SoupFixedLoop { cell_deltas } => {
+				// The number of iterations depends on the input, the loop may not even end.
+				if self.cell(0) {
+					self.control = true;
+					for &offset in cell_deltas.keys() {
+						self.set_cell(offset, true);
+					}
+				}
+				self.exit_loop();
+			}
+			SoupInstr::SoupMovingLoop { .. } | SoupInstr::BlockMove { .. } => {
+				self.lose_head();
+				self.exit_loop();
+			}
+			SoupInstr::PrintUntilZero { .. } => {
+				self.output_depends |= !self.is_clean();
+				self.lose_head();
+				self.exit_loop();
+			}
+			SoupInstr::ReadBlock { .. } | SoupInstr::ReadUntilZero { .. } => {
+				self.all_cells = true;
+				self.control = true;
+			}
+			SoupInstr::Loop(body, _) => self.run_loop(body),
+		}
+	}
+
+	fn run_loop(&mut self, body: &[SoupInstr]) {
+		if self.is_clean() && !reads_input(body) {
+			// Nothing in the loop depends on the input, only the head may have moved.
+			self.lose_head();
+			return;
+		}
+		// Runs the body until what may depend on the input at the start of an iteration is
+		// known, which ends as the positions of the cells are bounded while the head comes back.
+		loop {
+			self.control |= self.cell(0);
+			let before = self.clone();
+			self.run_instr_seq(body);
+			if self.head != before.head {
+				self.head = before.head;
+				self.lose_head();
+			}
+			self.cells.extend(&before.cells);
+			if *self == before {
+				break;
+			}
+		}
+		self.exit_loop();
+	}
+}
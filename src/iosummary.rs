@@ -0,0 +1,61 @@
+//! Output of the programs run by the VM on a given input (rather than interactively): the output
+//! is printed as it comes instead of when the program halts, and the bytes read and output are
+//! counted, so that a run stopped by an error still shows how far it got.
+
+use crate::encoding::{OutputEncoding, StreamEncoder};
+use crate::vm::Observer;
+use std::io::Write;
+
+/// Observer of the VM that prints the output as it comes and keeps track of the input read.
+pub struct IoSummary {
+	/// Encoder of the printed output, if the output is printed by the observer (rather than by a
+	/// pager, etc.).
+	encoder: Option<StreamEncoder>,
+	output_len: usize,
+	input_len: usize,
+	/// Number of bytes read by the program (reads after the end of the input not counting).
+	input_read: usize,
+}
+
+impl IoSummary {
+	/// Observer of a run on an input of the given length, that prints the output with the given
+	/// encoding if any.
+	pub fn new(input_len: usize, encoding: Option<OutputEncoding>) -> IoSummary {
+		IoSummary {
+			encoder: encoding.map(StreamEncoder::new),
+			output_len: 0,
+			input_len,
+			input_read: 0,
+		}
+	}
+
+	/// Summary of the run so far, such as "2 output bytes, 5 of 8 input bytes read".
+	pub fn summary(&self) -> String {
+		format!(
+			"{} output bytes, {} of {} input bytes read",
+			self.output_len, self.input_read, self.input_len
+		)
+	}
+}
+
+impl Observer for IoSummary {
+	fn on_output(&mut self, char_values: &[u8]) {
+		if let Some(encoder) = &mut self.encoder {
+			std::io::stdout()
+				.write_all(&encoder.encode(char_values))
+				.ok();
+		}
+		self.output_len += char_values.len();
+	}
+
+	fn on_input(&mut self, char_values: &[u8]) {
+		self.input_read = (self.input_read + char_values.len()).min(self.input_len);
+	}
+
+	fn on_halt(&mut self, _tape: &[u8], _head: usize) {
+		if let Some(encoder) = &mut self.encoder {
+			std::io::stdout().write_all(&encoder.finish()).ok();
+			std::io::stdout().flush().ok();
+		}
+	}
+}
@@ -0,0 +1,364 @@
+//! Human-readable textual format of the soup intermediate representation, one instruction per
+//! line, loop bodies being enclosed in braces. For example `+++[->++<]>.` is printed as
+//!
+//! ```text
+//! soup {0: +3} head +0
+//! mult_loop {0: -1, 1: +2}
+//! output +1
+//! seek +1
+//! ```
+//!
+//! The attributes of a loop follow `loop`, as in `loop @no-unroll {` (see `LoopAttributes`).
+//! Comments start with `#` and go to the end of the line.
+
+use crate::astraw::{LoopAttributes, RawInstr, top_level};
+use crate::astsoup::SoupInstr;
+use crate::extended::ExtInstr;
+use crate::tokenizer::DEFAULT_HOST_CALL_CHAR;
+use std::collections::BTreeMap;
+
+/// Prints the raw program as brainfuck source code (without comments).
+pub fn print_raw(instr_seq: &[RawInstr]) -> String {
+	let mut text = String::new();
+	for (instr, body) in top_level(instr_seq) {
+		match instr {
+			RawInstr::Plus(n) => text.extend(std::iter::repeat_n('+', *n)),
+			RawInstr::Minus(n) => text.extend(std::iter::repeat_n('-', *n)),
+			RawInstr::Left(n) => text.extend(std::iter::repeat_n('<', *n)),
+			RawInstr::Right(n) => text.extend(std::iter::repeat_n('>', *n)),
+			RawInstr::Dot => text.push('.'),
+			RawInstr::Comma => text.push(','),
+			RawInstr::Clock => text.push('~'),
+			RawInstr::Random => text.push('?'),
+			RawInstr::HostCall => text.push(DEFAULT_HOST_CALL_CHAR),
+			RawInstr::Extended(instr) => text.push(instr.to_char()),
+			RawInstr::BracketLoop { .. } => {
+				text.push('[');
+				text.push_str(&print_raw(body));
+				text.push(']');
+			}
+		}
+	}
+	text
+}
+
+pub fn print_soup(instr_seq: &[SoupInstr]) -> String {
+	let mut text = String::new();
+	print_soup_instr_seq(&mut text, instr_seq, 0);
+	text
+}
+
+fn print_soup_instr_seq(text: &mut String, instr_seq: &[SoupInstr], indent_level: usize) {
+	for instr in instr_seq {
+		text.extend(std::iter::repeat_n('\t', indent_level));
+		match instr {
+			SoupInstr::Soup {
+				cell_deltas,
+				head_delta,
+			} => text.push_str(&format!(
+				"soup {} head {:+}\n",
+				cell_deltas_to_text(cell_deltas),
+				head_delta
+			)),
+			SoupInstr::Seek { delta } => text.push_str(&format!("seek {:+}\n", delta)),
+			SoupInstr::Output { offset: 0 } => text.push_str("output\n"),
+			SoupInstr::Output { offset } => text.push_str(&format!("output {:+}\n", offset)),
+			SoupInstr::Input { offset: 0 } => text.push_str("input\n"),
+			SoupInstr::Input { offset } => text.push_str(&format!("input {:+}\n", offset)),
+			SoupInstr::Clock { offset: 0 } => text.push_str("clock\n"),
+			SoupInstr::Clock { offset } => text.push_str(&format!("clock {:+}\n", offset)),
+			SoupInstr::Random { offset: 0 } => text.push_str("random\n"),
+			SoupInstr::Random { offset } => text.push_str(&format!("random {:+}\n", offset)),
+			SoupInstr::HostCall { offset: 0 } => text.push_str("host_call\n"),
+			SoupInstr::HostCall { offset } => text.push_str(&format!("host_call {:+}\n", offset)),
+			SoupInstr::Extended { instr, offset: 0 } => {
+				text.push_str(&format!("ext {}\n", instr.name()))
+			}
+			SoupInstr::Extended { instr, offset } => {
+				text.push_str(&format!("ext {} {:+}\n", instr.name(), offset))
+			}
+			SoupInstr::MultFixedLoop { cell_deltas } => {
+				text.push_str(&format!("mult_loop {}\n", cell_deltas_to_text(cell_deltas)))
+			}
+			SoupInstr::SoupFixedLoop { cell_deltas } => text.push_str(&format!(
+				"fixed_loop {}\n",
+				cell_deltas_to_text(cell_deltas)
+			)),
+			SoupInstr::SoupMovingLoop {
+				cell_deltas,
+				head_delta,
+			} => text.push_str(&format!(
+				"moving_loop {} head {:+}\n",
+				cell_deltas_to_text(cell_deltas),
+				head_delta
+			)),
+			SoupInstr::BlockMove { offset } => {
+				text.push_str(&format!("block_move {:+}\n", offset))
+			}
+			SoupInstr::PrintUntilZero { stride } => {
+				text.push_str(&format!("print_until_zero {:+}\n", stride))
+			}
+			SoupInstr::ReadBlock { stride } => {
+				text.push_str(&format!("read_block {:+}\n", stride))
+			}
+			SoupInstr::ReadUntilZero { stride } => {
+				text.push_str(&format!("read_until_zero {:+}\n", stride))
+			}
+			SoupInstr::Loop(body, attributes) => {
+				text.push_str("loop ");
+				for name in attributes.names() {
+					text.push_str(&format!("@{} ", name));
+				}
+				text.push_str("{\n");
+				print_soup_instr_seq(text, body, indent_level + 1);
+				text.extend(std::iter::repeat_n('\t', indent_level));
+				text.push_str("}\n");
+			}
+		}
+	}
+}
+
+fn cell_deltas_to_text(cell_deltas: &BTreeMap<isize, isize>) -> String {
+	let entries: Vec<String> = cell_deltas
+		.iter()
+		.map(|(relative_head, delta)| format!("{}: {:+}", relative_head, delta))
+		.collect();
+	format!("{{{}}}", entries.join(", "))
+}
+
+#[derive(Debug)]
+pub struct IrTextError {
+	/// Line number (starting at 1) where the error was found.
+	pub line: usize,
+	pub message: String,
+}
+
+impl std::fmt::Display for IrTextError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(
+			f,
+			"IR parsing error on line {}: {}",
+			self.line, self.message
+		)
+	}
+}
+
+/// Parses the format produced by `print_soup` (indentation does not matter).
+pub fn parse_soup(text: &str) -> Result<Vec<SoupInstr>, IrTextError> {
+	let mut words = Words::new(text);
+	let instr_seq = parse_soup_instr_seq(&mut words)?;
+	match words.next() {
+		None => Ok(instr_seq),
+		Some(word) => Err(words.error(format!("unexpected `{}`", word))),
+	}
+}
+
+/// Stream of the words of the IR text, braces, commas and colons being words by themselves.
+struct Words<'a> {
+	words: std::iter::Peekable<std::vec::IntoIter<(usize, &'a str)>>,
+	line: usize,
+}
+
+impl<'a> Words<'a> {
+	fn new(text: &'a str) -> Words<'a> {
+		let mut words = Vec::new();
+		for (line_index, line) in text.lines().enumerate() {
+			let line = line.split('#').next().unwrap();
+			let mut word_start = None;
+			for (index, c) in line.char_indices() {
+				let is_separator = c.is_whitespace() || matches!(c, '{' | '}' | ',' | ':');
+				if is_separator {
+					if let Some(start) = word_start.take() {
+						words.push((line_index + 1, &line[start..index]));
+					}
+					if !c.is_whitespace() {
+						words.push((line_index + 1, &line[index..index + 1]));
+					}
+				} else if word_start.is_none() {
+					word_start = Some(index);
+				}
+			}
+			if let Some(start) = word_start {
+				words.push((line_index + 1, &line[start..]));
+			}
+		}
+		Words {
+			words: words.into_iter().peekable(),
+			line: 1,
+		}
+	}
+
+	fn next(&mut self) -> Option<&'a str> {
+		let (line, word) = self.words.next()?;
+		self.line = line;
+		Some(word)
+	}
+
+	fn peek(&mut self) -> Option<&'a str> {
+		self.words.peek().map(|&(_, word)| word)
+	}
+
+	fn error(&self, message: String) -> IrTextError {
+		IrTextError {
+			line: self.line,
+			message,
+		}
+	}
+
+	fn expect(&mut self, expected: &str) -> Result<(), IrTextError> {
+		match self.next() {
+			Some(word) if word == expected => Ok(()),
+			Some(word) => Err(self.error(format!("expected `{}` but got `{}`", expected, word))),
+			None => Err(self.error(format!("expected `{}` but got nothing", expected))),
+		}
+	}
+
+	fn integer(&mut self) -> Result<isize, IrTextError> {
+		match self.next() {
+			Some(word) => word
+				.parse()
+				.map_err(|_| self.error(format!("expected an integer but got `{}`", word))),
+			None => Err(self.error("expected an integer but got nothing".to_owned())),
+		}
+	}
+}
+
+fn parse_soup_instr_seq(words: &mut Words) -> Result<Vec<SoupInstr>, IrTextError> {
+	let mut instr_seq = Vec::new();
+	while let Some(word) = words.peek() {
+		if word == "}" {
+			break;
+		}
+		words.next();
+		instr_seq.push(match word {
+			"soup" => SoupInstr::Soup {
+				cell_deltas: parse_cell_deltas(words)?,
+				head_delta: parse_head_delta(words)?,
+			},
+			"seek" => SoupInstr::Seek {
+				delta: words.integer()?,
+			},
+			"output" => SoupInstr::Output {
+				offset: parse_optional_offset(words),
+			},
+			"input" => SoupInstr::Input {
+				offset: parse_optional_offset(words),
+			},
+			"clock" => SoupInstr::Clock {
+				offset: parse_optional_offset(words),
+			},
+			"random" => SoupInstr::Random {
+				offset: parse_optional_offset(words),
+			},
+			"host_call" => SoupInstr::HostCall {
+				offset: parse_optional_offset(words),
+			},
+			"ext" => {
+				let name = words.next().unwrap_or("");
+				let instr = ExtInstr::from_name(name).ok_or_else(|| {
+					words.error(format!("unknown extended instruction `{}`", name))
+				})?;
+				SoupInstr::Extended {
+					instr,
+					offset: parse_optional_offset(words),
+				}
+			}
+			"mult_loop" => {
+				let cell_deltas = parse_cell_deltas(words)?;
+				if cell_deltas.get(&0) != Some(&-1) {
+					return Err(words.error("mult_loop must have a delta of -1 at 0".to_owned()));
+				}
+				SoupInstr::MultFixedLoop { cell_deltas }
+			}
+			"fixed_loop" => SoupInstr::SoupFixedLoop {
+				cell_deltas: parse_cell_deltas(words)?,
+			},
+			"moving_loop" => SoupInstr::SoupMovingLoop {
+				cell_deltas: parse_cell_deltas(words)?,
+				head_delta: parse_head_delta(words)?,
+			},
+			"block_move" => {
+				let offset = words.integer()?;
+				if offset != 1 && offset != -1 {
+					return Err(
+						words.error("block_move must have an offset of +1 or -1".to_owned())
+					);
+				}
+				SoupInstr::BlockMove { offset }
+			}
+			"print_until_zero" => SoupInstr::PrintUntilZero {
+				stride: parse_stride(words, word)?,
+			},
+			"read_block" => SoupInstr::ReadBlock {
+				stride: parse_stride(words, word)?,
+			},
+			"read_until_zero" => SoupInstr::ReadUntilZero {
+				stride: parse_stride(words, word)?,
+			},
+			"loop" => {
+				let mut attributes = LoopAttributes::default();
+				while let Some(name) = words.peek().and_then(|word| word.strip_prefix('@')) {
+					if !attributes.set(name) {
+						return Err(words.error(format!("unknown loop attribute `@{}`", name)));
+					}
+					words.next();
+				}
+				words.expect("{")?;
+				let body = parse_soup_instr_seq(words)?;
+				words.expect("}")?;
+				SoupInstr::Loop(body, attributes)
+			}
+			_ => return Err(words.error(format!("unknown instruction `{}`", word))),
+		});
+	}
+	Ok(instr_seq)
+}
+
+fn parse_cell_deltas(words: &mut Words) -> Result<BTreeMap<isize, isize>, IrTextError> {
+	let mut cell_deltas = BTreeMap::new();
+	words.expect("{")?;
+	if words.peek() == Some("}") {
+		words.next();
+		return Ok(cell_deltas);
+	}
+	loop {
+		let relative_head = words.integer()?;
+		words.expect(":")?;
+		let delta = words.integer()?;
+		if cell_deltas.insert(relative_head, delta).is_some() {
+			return Err(words.error(format!("duplicate cell delta at {}", relative_head)));
+		}
+		match words.next() {
+			Some(",") => continue,
+			Some("}") => return Ok(cell_deltas),
+			Some(word) => {
+				return Err(words.error(format!("expected `,` or `}}` but got `{}`", word)));
+			}
+			None => return Err(words.error("expected `,` or `}` but got nothing".to_owned())),
+		}
+	}
+}
+
+fn parse_head_delta(words: &mut Words) -> Result<isize, IrTextError> {
+	words.expect("head")?;
+	words.integer()
+}
+
+fn parse_stride(words: &mut Words, instr_name: &str) -> Result<isize, IrTextError> {
+	let stride = words.integer()?;
+	if stride == 0 {
+		return Err(words.error(format!("{} must have a non-zero stride", instr_name)));
+	}
+	Ok(stride)
+}
+
+/// Parses the offset of an input or output, which is 0 when omitted.
+fn parse_optional_offset(words: &mut Words) -> isize {
+	match words.peek().and_then(|word| word.parse().ok()) {
+		Some(offset) => {
+			words.next();
+			offset
+		}
+		None => 0,
+	}
+}
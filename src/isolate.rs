@@ -0,0 +1,189 @@
+//! Process isolation of runs (`--isolate`), for services that run programs submitted by their
+//! users: the run happens in a child process (such as `xxbf` itself) with resource limits set by
+//! the kernel, so that even a bug in the VM or in the JIT (a crash, an infinite loop that does
+//! not count steps, unbounded memory) only takes down the child. The resources used by the child
+//! are reported once it exits.
+//!
+//! xxbf has no server nor judge mode of its own (there is no `xxbf serve`): a service isolates
+//! each run by running `xxbf --isolate` (or by calling `run_isolated`) itself.
+//!
+//! This relies on `setrlimit` and `wait4`, whose constants and structures differ between systems:
+//! only the values of Linux (64-bit) and macOS are known, the module is not available elsewhere.
+
+use std::os::raw::{c_int, c_long};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::process::{Command, ExitStatus};
+use std::time::Duration;
+
+#[repr(C)]
+struct RLimit {
+	cur: u64,
+	max: u64,
+}
+
+#[repr(C)]
+struct TimeVal {
+	sec: c_long,
+	usec: SUSeconds,
+}
+
+#[cfg(target_os = "linux")]
+type SUSeconds = c_long;
+#[cfg(target_os = "macos")]
+type SUSeconds = i32;
+
+#[repr(C)]
+struct RUsage {
+	user_time: TimeVal,
+	system_time: TimeVal,
+	/// In units of `MAX_RSS_UNIT` bytes.
+	max_rss: c_long,
+	others: [c_long; 13],
+}
+
+extern "C" {
+	fn setrlimit(resource: c_int, limit: *const RLimit) -> c_int;
+	fn wait4(pid: c_int, status: *mut c_int, options: c_int, usage: *mut RUsage) -> c_int;
+}
+
+const RLIMIT_CPU: c_int = 0;
+const RLIMIT_CORE: c_int = 4;
+#[cfg(target_os = "linux")]
+const RLIMIT_AS: c_int = 9;
+#[cfg(target_os = "macos")]
+const RLIMIT_AS: c_int = 5;
+
+#[cfg(target_os = "linux")]
+const MAX_RSS_UNIT: u64 = 1024;
+#[cfg(target_os = "macos")]
+const MAX_RSS_UNIT: u64 = 1;
+
+/// Limits of the child process, the default being the preset of `--isolate`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceLimits {
+	/// CPU time (user and system), the child being killed (by `SIGXCPU`) when it exceeds it.
+	pub cpu_seconds: u64,
+	/// Virtual memory, the allocations that exceed it fail (which aborts a Rust program).
+	pub memory_bytes: u64,
+}
+
+impl Default for ResourceLimits {
+	fn default() -> ResourceLimits {
+		ResourceLimits {
+			cpu_seconds: 10,
+			memory_bytes: 1 << 30,
+		}
+	}
+}
+
+/// Resources used by a child process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceUsage {
+	pub user_time: Duration,
+	pub system_time: Duration,
+	/// Peak of resident memory.
+	pub max_rss_bytes: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IsolatedRun {
+	pub status: ExitStatus,
+	pub usage: ResourceUsage,
+}
+
+impl IsolatedRun {
+	/// The child was killed for exceeding its CPU time.
+	pub fn exceeded_cpu_time(&self) -> bool {
+		// `SIGXCPU`, or `SIGKILL` once the hard limit is reached too.
+		matches!(self.status.signal(), Some(24 | 9))
+	}
+
+	/// Exit code to exit with to give the status of the child, following the convention of
+	/// shells (128 plus the signal number if the child was killed by a signal).
+	pub fn exit_code(&self) -> i32 {
+		self.status
+			.code()
+			.unwrap_or_else(|| 128 + self.status.signal().unwrap_or(0))
+	}
+}
+
+impl std::fmt::Display for IsolatedRun {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match (self.status.code(), self.status.signal()) {
+			(Some(code), _) => write!(f, "exited with code {}", code)?,
+			(None, Some(signal)) => write!(f, "killed by signal {}", signal)?,
+			(None, None) => write!(f, "ended")?,
+		}
+		if self.exceeded_cpu_time() {
+			write!(f, " (CPU time limit exceeded)")?;
+		}
+		write!(
+			f,
+			", {:.3} s of user time, {:.3} s of system time, {} KiB of resident memory at most",
+			self.usage.user_time.as_secs_f64(),
+			self.usage.system_time.as_secs_f64(),
+			self.usage.max_rss_bytes / 1024
+		)
+	}
+}
+
+/// Runs the command in a child process within the limits and waits for it to exit. The child
+/// inherits what the command does not set, such as the standard streams.
+pub fn run_isolated(
+	command: &mut Command,
+	limits: &ResourceLimits,
+) -> std::io::Result<IsolatedRun> {
+	let limits = *limits;
+	// Safety: only calls `setrlimit` between the fork and the exec, which is async-signal-safe.
+	unsafe {
+		command.pre_exec(move || {
+			// The hard limit is a second later, so that `SIGXCPU` comes first.
+			set_limit(RLIMIT_CPU, limits.cpu_seconds, limits.cpu_seconds + 1)?;
+			set_limit(RLIMIT_AS, limits.memory_bytes, limits.memory_bytes)?;
+			// A crash of the child must not fill the disk of the host.
+			set_limit(RLIMIT_CORE, 0, 0)
+		});
+	}
+	let child = command.spawn()?;
+	let mut status: c_int = 0;
+	let mut usage = RUsage {
+		user_time: TimeVal { sec: 0, usec: 0 },
+		system_time: TimeVal { sec: 0, usec: 0 },
+		max_rss: 0,
+		others: [0; 13],
+	};
+	// The child is waited for by `wait4` (that gives the resources it used) rather than by
+	// `Child::wait`, it must not be waited for again.
+	loop {
+		// Safety: the pointers are to valid locals of the layouts `wait4` expects.
+		if unsafe { wait4(child.id() as c_int, &mut status, 0, &mut usage) } != -1 {
+			break;
+		}
+		let error = std::io::Error::last_os_error();
+		if error.kind() != std::io::ErrorKind::Interrupted {
+			return Err(error);
+		}
+	}
+	Ok(IsolatedRun {
+		status: ExitStatus::from_raw(status),
+		usage: ResourceUsage {
+			user_time: duration(&usage.user_time),
+			system_time: duration(&usage.system_time),
+			max_rss_bytes: usage.max_rss as u64 * MAX_RSS_UNIT,
+		},
+	})
+}
+
+fn set_limit(resource: c_int, cur: u64, max: u64) -> std::io::Result<()> {
+	let limit = RLimit { cur, max };
+	// Safety: the pointer is to a valid local of the layout `setrlimit` expects.
+	if unsafe { setrlimit(resource, &limit) } == 0 {
+		Ok(())
+	} else {
+		Err(std::io::Error::last_os_error())
+	}
+}
+
+fn duration(time: &TimeVal) -> Duration {
+	Duration::from_secs(time.sec as u64) + Duration::from_micros(time.usec as u64)
+}
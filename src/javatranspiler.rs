@@ -0,0 +1,146 @@
+//! Transpilation of the linear intermediate representation (see `lowering`) to Java source
+//! code, with a class whose `main` method runs the program (loops being `while` statements,
+//! input and output going through `System.in` and `System.out`).
+//!
+//! Cells are `byte`s, that wrap around on compound assignments. The output is buffered, and
+//! flushed before reading input. As with the C backend, the tape has a fixed size and reading at
+//! the end of the input gives 255. The JVM limits the size of the code of a method, so the top
+//! level of the program is split into several methods.
+
+use crate::lowering::{LinearInstr, LinearProgram};
+
+/// Number of cells of the tape array of the generated program.
+pub const TAPE_SIZE: usize = 30000;
+
+/// Number of instructions after which the top level of the program continues in a new method.
+const INSTRS_PER_METHOD: usize = 1000;
+
+struct TranspiledJava {
+	code: String,
+	indent_level: u32,
+}
+
+impl TranspiledJava {
+	fn emit_line(&mut self, line_content: &str) {
+		self.code
+			.extend(std::iter::repeat_n("\t", self.indent_level as usize));
+		self.code.push_str(line_content);
+		self.code.push('\n');
+	}
+
+	fn emit_indent(&mut self) {
+		self.indent_level += 1;
+	}
+	fn emit_unindent(&mut self) {
+		self.indent_level -= 1;
+	}
+
+	fn emit_block(&mut self, header: &str, emit_body: impl FnOnce(&mut TranspiledJava)) {
+		self.emit_line(header);
+		self.emit_line("{");
+		self.emit_indent();
+		emit_body(self);
+		self.emit_unindent();
+		self.emit_line("}");
+	}
+
+	fn emit_instr(&mut self, instr: &LinearInstr) {
+		match *instr {
+			LinearInstr::Add { offset, value } => {
+				self.emit_line(&format!("m[{}] += {};", h(offset), value as i8))
+			}
+			LinearInstr::Set { offset, value } => {
+				self.emit_line(&format!("m[{}] = {};", h(offset), value as i8))
+			}
+			LinearInstr::MulAdd {
+				source,
+				target,
+				factor,
+			} => self.emit_line(&format!(
+				"m[{}] += m[{}] * {};",
+				h(target),
+				h(source),
+				factor as i8
+			)),
+			LinearInstr::Move(delta) => self.emit_line(&format!("h += {};", delta)),
+			LinearInstr::Output { offset } => {
+				self.emit_line(&format!("out.write(m[{}]);", h(offset)))
+			}
+			LinearInstr::Input { offset } => {
+				self.emit_line(&format!("m[{}] = (byte) System.in.read();", h(offset)))
+			}
+			LinearInstr::Flush => self.emit_line("out.flush();"),
+			LinearInstr::LoopStart(_) => {
+				self.emit_line("while (m[h] != 0)");
+				self.emit_line("{");
+				self.emit_indent();
+			}
+			LinearInstr::LoopEnd(_) => {
+				self.emit_unindent();
+				self.emit_line("}");
+			}
+		}
+	}
+}
+
+/// Java source of a class of the given name that runs the program.
+pub fn transpile_linear_to_java(program: &LinearProgram, class_name: &str) -> String {
+	let mut transpiled = TranspiledJava {
+		code: String::new(),
+		indent_level: 0,
+	};
+	// Splits the top level between methods (a single loop can still be too large).
+	let mut parts: Vec<&[LinearInstr]> = Vec::new();
+	let mut part_start = 0;
+	let mut loop_depth = 0;
+	for (index, instr) in program.instrs.iter().enumerate() {
+		match instr {
+			LinearInstr::LoopStart(_) => loop_depth += 1,
+			LinearInstr::LoopEnd(_) => loop_depth -= 1,
+			_ => (),
+		}
+		if loop_depth == 0 && index + 1 - part_start >= INSTRS_PER_METHOD {
+			parts.push(&program.instrs[part_start..=index]);
+			part_start = index + 1;
+		}
+	}
+	parts.push(&program.instrs[part_start..]);
+
+	transpiled.emit_line("import java.io.BufferedOutputStream;");
+	transpiled.emit_line("import java.io.IOException;");
+	transpiled.emit_line("");
+	transpiled.emit_block(&format!("public class {}", class_name), |java| {
+		java.emit_line(&format!("static byte[] m = new byte[{}];", TAPE_SIZE));
+		java.emit_line("static int h = 0;");
+		java.emit_line("static BufferedOutputStream out = new BufferedOutputStream(System.out);");
+		java.emit_line("");
+		java.emit_block(
+			"public static void main(String[] args) throws IOException",
+			|java| {
+				for part_index in 0..parts.len() {
+					java.emit_line(&format!("part{}();", part_index));
+				}
+			},
+		);
+		for (part_index, part) in parts.iter().enumerate() {
+			java.emit_line("");
+			java.emit_block(
+				&format!("static void part{}() throws IOException", part_index),
+				|java| {
+					for instr in part.iter() {
+						java.emit_instr(instr);
+					}
+				},
+			);
+		}
+	});
+	transpiled.code
+}
+
+fn h(relative_head: isize) -> String {
+	if relative_head == 0 {
+		"h".to_owned()
+	} else {
+		format!("h + {}", relative_head)
+	}
+}
@@ -0,0 +1,253 @@
+//! Just-in-time compilation of the hot loops of the bytecode, used by the bytecode interpreter.
+//!
+//! When a backward jump has been taken `threshold` times, the ops from its target to the jump are
+//! transpiled to a C function, compiled by the system C compiler (`cc`) to a shared library that
+//! is then loaded. The interpreter calls the function instead of interpreting the ops it covers.
+//! The function can be entered at any of its ops, and it returns the index of the op where the
+//! interpreter must resume: after the loop, on jumps that leave it, and on the ops it does not
+//! handle itself (input and output, and memory accesses outside of the allocated tape, that the
+//! interpreter handles by growing the tape). If anything goes wrong with the C compiler, the JIT
+//! disables itself and the interpreter keeps interpreting.
+//!
+//! The C code and the library are written to a directory that only the user can access, created
+//! for each compilation under a random name (so that no other user can replace the library before
+//! it is loaded) and removed once the library is loaded. Loading libraries relies on `dlopen`, the
+//! JIT is only available on Unix systems, elsewhere it never compiles anything.
+
+use crate::bytecode::Op;
+#[cfg(unix)]
+use std::ffi::CString;
+#[cfg(unix)]
+use std::os::raw::{c_char, c_int, c_void};
+#[cfg(unix)]
+use std::path::PathBuf;
+
+#[cfg(unix)]
+#[link(name = "dl")]
+extern "C" {
+	fn dlopen(filename: *const c_char, flag: c_int) -> *mut c_void;
+	fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+}
+
+#[cfg(unix)]
+const RTLD_NOW: c_int = 2;
+
+/// Whether the JIT can compile loops on this system.
+pub const AVAILABLE: bool = cfg!(unix);
+
+/// Number of iterations after which a loop is compiled when no threshold is specified.
+pub const DEFAULT_JIT_THRESHOLD: u64 = 10_000;
+
+/// Signature of the compiled functions: tape, tape length, head (updated), entry op index, and
+/// the returned value is the op index where the interpreter resumes.
+type CompiledFn = unsafe extern "C" fn(*mut u8, i64, *mut i64, i64) -> i64;
+
+pub struct Jit {
+	threshold: u64,
+	/// Number of times each backward jump was taken (indexed by op index).
+	back_edge_counts: Vec<u64>,
+	/// Compiled function that can be entered at each op, if any.
+	entries: Vec<Option<CompiledFn>>,
+	/// Set when the C compiler failed, no compilation is attempted after that.
+	disabled: bool,
+}
+
+impl Jit {
+	pub fn new(ops: &[Op], threshold: u64) -> Jit {
+		Jit {
+			threshold,
+			back_edge_counts: vec![0; ops.len()],
+			entries: vec![None; ops.len()],
+			disabled: false,
+		}
+	}
+
+	/// Compiled function to call when the interpreter is about to run the op of the given index.
+	pub fn entry(&self, op_index: usize) -> Option<CompiledFn> {
+		self.entries.get(op_index).copied().flatten()
+	}
+
+	/// Runs the compiled function from the op of the given index, returns the index of the op
+	/// where the interpreter must resume.
+	pub fn call(
+		compiled_fn: CompiledFn,
+		tape: &mut [u8],
+		head: &mut usize,
+		op_index: usize,
+	) -> usize {
+		let mut head_i64 = *head as i64;
+		// The compiled code only accesses the tape within its length.
+		let resume_index = unsafe {
+			compiled_fn(
+				tape.as_mut_ptr(),
+				tape.len() as i64,
+				&mut head_i64,
+				op_index as i64,
+			)
+		};
+		*head = head_i64 as usize;
+		resume_index as usize
+	}
+
+	/// To be called when the backward jump at `jump_index` to `target` is taken, compiles the
+	/// loop once it is hot.
+	pub fn on_back_edge(&mut self, ops: &[Op], jump_index: usize, target: usize) {
+		self.back_edge_counts[jump_index] += 1;
+		if self.back_edge_counts[jump_index] != self.threshold || self.disabled {
+			return;
+		}
+		match self.compile(ops, target..jump_index + 1) {
+			Some(compiled_fn) => {
+				let loop_ops = ops[target..=jump_index].iter();
+				for (entry, op) in self.entries[target..=jump_index].iter_mut().zip(loop_ops) {
+					if handled_by_compiled_code(op) {
+						*entry = Some(compiled_fn);
+					}
+				}
+			}
+			None => self.disabled = true,
+		}
+	}
+
+	#[cfg(unix)]
+	fn compile(&mut self, ops: &[Op], range: std::ops::Range<usize>) -> Option<CompiledFn> {
+		let c_code = transpile_ops(ops, range);
+		let dir_path = create_private_dir().ok()?;
+		let compiled_fn = compile_in(&dir_path, &c_code);
+		std::fs::remove_dir_all(&dir_path).ok();
+		compiled_fn
+	}
+
+	#[cfg(not(unix))]
+	fn compile(&mut self, _ops: &[Op], _range: std::ops::Range<usize>) -> Option<CompiledFn> {
+		None
+	}
+}
+
+/// Creates a new directory in the temporary directory that only the user can access, under a
+/// random name. Creating it fails if anything exists under that name, it is not reused.
+#[cfg(unix)]
+fn create_private_dir() -> std::io::Result<PathBuf> {
+	use std::collections::hash_map::RandomState;
+	use std::hash::BuildHasher;
+	use std::os::unix::fs::DirBuilderExt;
+	use std::time::SystemTime;
+	let mut attempt = 0u32;
+	loop {
+		// Each `RandomState` is seeded from the randomness of the system.
+		let name = RandomState::new().hash_one((std::process::id(), SystemTime::now(), attempt));
+		let dir_path = std::env::temp_dir().join(format!("xxbf-jit-{:016x}", name));
+		match std::fs::DirBuilder::new().mode(0o700).create(&dir_path) {
+			Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists && attempt < 16 => {
+				attempt += 1;
+			}
+			result => return result.map(|()| dir_path),
+		}
+	}
+}
+
+/// Compiles the C code in the given directory and loads the library.
+#[cfg(unix)]
+fn compile_in(dir_path: &std::path::Path, c_code: &str) -> Option<CompiledFn> {
+	let c_path = dir_path.join("jit.c");
+	let so_path = dir_path.join("jit.so");
+	std::fs::write(&c_path, c_code).ok()?;
+	let status = std::process::Command::new("cc")
+		.args(["-O2", "-shared", "-fPIC", "-o"])
+		.arg(&so_path)
+		.arg(&c_path)
+		.stderr(std::process::Stdio::null())
+		.status();
+	if !status.ok()?.success() {
+		return None;
+	}
+	let so_path_c = CString::new(so_path.to_str()?).ok()?;
+	let symbol = CString::new("xxbf_jit").unwrap();
+	// The library stays loaded until the end of the process (it can be removed from the file
+	// system once loaded).
+	unsafe {
+		let handle = dlopen(so_path_c.as_ptr(), RTLD_NOW);
+		if handle.is_null() {
+			return None;
+		}
+		let function = dlsym(handle, symbol.as_ptr());
+		if function.is_null() {
+			return None;
+		}
+		Some(std::mem::transmute::<*mut c_void, CompiledFn>(function))
+	}
+}
+
+/// The ops that the compiled code runs, the others make it return to the interpreter.
+fn handled_by_compiled_code(op: &Op) -> bool {
+	matches!(
+		op,
+		Op::Add { .. }
+			| Op::Set { .. }
+			| Op::MulAdd { .. }
+			| Op::Move(_)
+			| Op::JumpIfZero(_)
+			| Op::JumpIfNonZero(_)
+	)
+}
+
+fn transpile_ops(ops: &[Op], range: std::ops::Range<usize>) -> String {
+	let mut code = String::new();
+	code.push_str("long xxbf_jit(unsigned char *m, long len, long *head, long entry)\n{\n");
+	code.push_str("\tlong h = *head;\n");
+	code.push_str("\tswitch (entry)\n\t{\n");
+	for op_index in range.clone() {
+		if handled_by_compiled_code(&ops[op_index]) {
+			code.push_str(&format!("\t\tcase {}: goto op_{};\n", op_index, op_index));
+		}
+	}
+	code.push_str("\t\tdefault: *head = h; return entry;\n\t}\n");
+	// Makes the compiled code return to the interpreter, which resumes at the given op.
+	let leave = |op_index: usize| format!("{{ *head = h; return {}; }}", op_index);
+	// Makes the compiled code leave if the cell at the given offset is outside of the tape.
+	let check = |op_index: usize, offset: i32| {
+		format!(
+			"\tif (h + {} < 0 || h + {} >= len) {}\n",
+			offset,
+			offset,
+			leave(op_index)
+		)
+	};
+	let jump = |target: u32| {
+		if range.contains(&(target as usize)) {
+			format!("goto op_{};", target)
+		} else {
+			leave(target as usize)
+		}
+	};
+	for op_index in range.clone() {
+		code.push_str(&format!("op_{}:\n", op_index));
+		match ops[op_index] {
+			Op::Add { offset, value } => {
+				code.push_str(&check(op_index, offset));
+				code.push_str(&format!("\tm[h + {}] += {};\n", offset, value));
+			}
+			Op::Set { offset, value } => {
+				code.push_str(&check(op_index, offset));
+				code.push_str(&format!("\tm[h + {}] = {};\n", offset, value));
+			}
+			Op::MulAdd { offset, factor } => {
+				code.push_str(&check(op_index, 0));
+				code.push_str(&check(op_index, offset));
+				code.push_str(&format!("\tm[h + {}] += m[h] * {};\n", offset, factor));
+			}
+			Op::Move(delta) => code.push_str(&format!("\th += {};\n", delta)),
+			Op::JumpIfZero(target) => {
+				code.push_str(&check(op_index, 0));
+				code.push_str(&format!("\tif (!m[h]) {}\n", jump(target)));
+			}
+			Op::JumpIfNonZero(target) => {
+				code.push_str(&check(op_index, 0));
+				code.push_str(&format!("\tif (m[h]) {}\n", jump(target)));
+			}
+			_ => code.push_str(&format!("\t{}\n", leave(op_index))),
+		}
+	}
+	code.push_str(&format!("\t{}\n}}\n", leave(range.end)));
+	code
+}
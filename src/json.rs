@@ -0,0 +1,168 @@
+//! Minimal JSON support for the files that xxbf writes (core dumps, compilation reports and
+//! benchmark baselines) and reads back (core dumps and benchmark baselines).
+
+pub(crate) fn json_string(text: &str) -> String {
+	let mut escaped = String::from("\"");
+	for c in text.chars() {
+		match c {
+			'"' => escaped.push_str("\\\""),
+			'\\' => escaped.push_str("\\\\"),
+			'\n' => escaped.push_str("\\n"),
+			'\t' => escaped.push_str("\\t"),
+			c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+			c => escaped.push(c),
+		}
+	}
+	escaped.push('"');
+	escaped
+}
+
+/// The JSON values that the files are made of (numbers are non-negative integers).
+pub(crate) enum Json {
+	Null,
+	Number(u64),
+	String(String),
+	Array(Vec<Json>),
+	Object(Vec<(String, Json)>),
+}
+
+/// Parses the whole text as a JSON value.
+pub(crate) fn parse_json(text: &str) -> Result<Json, String> {
+	let mut parser = JsonParser { text, pos: 0 };
+	let value = parser.parse_value()?;
+	parser.skip_whitespace();
+	if parser.pos != text.len() {
+		return Err(parser.error("trailing characters"));
+	}
+	Ok(value)
+}
+
+struct JsonParser<'a> {
+	text: &'a str,
+	pos: usize,
+}
+
+impl JsonParser<'_> {
+	fn error(&self, message: &str) -> String {
+		format!("{} at byte {}", message, self.pos)
+	}
+
+	fn skip_whitespace(&mut self) {
+		let rest = &self.text[self.pos..];
+		self.pos += rest.len() - rest.trim_start().len();
+	}
+
+	fn peek(&self) -> Option<char> {
+		self.text[self.pos..].chars().next()
+	}
+
+	fn expect(&mut self, expected: char) -> Result<(), String> {
+		self.skip_whitespace();
+		if self.peek() == Some(expected) {
+			self.pos += expected.len_utf8();
+			Ok(())
+		} else {
+			Err(self.error(&format!("expected `{}`", expected)))
+		}
+	}
+
+	fn parse_value(&mut self) -> Result<Json, String> {
+		self.skip_whitespace();
+		match self.peek() {
+			Some('n') if self.text[self.pos..].starts_with("null") => {
+				self.pos += 4;
+				Ok(Json::Null)
+			}
+			Some('0'..='9') => {
+				let digits_len = self.text[self.pos..]
+					.find(|c: char| !c.is_ascii_digit())
+					.unwrap_or(self.text.len() - self.pos);
+				let digits = &self.text[self.pos..self.pos + digits_len];
+				let number = digits.parse().map_err(|_| self.error("invalid number"))?;
+				self.pos += digits_len;
+				Ok(Json::Number(number))
+			}
+			Some('"') => Ok(Json::String(self.parse_string()?)),
+			Some('[') => {
+				self.pos += 1;
+				let mut values = Vec::new();
+				self.skip_whitespace();
+				if self.peek() == Some(']') {
+					self.pos += 1;
+					return Ok(Json::Array(values));
+				}
+				loop {
+					values.push(self.parse_value()?);
+					self.skip_whitespace();
+					if self.peek() == Some(']') {
+						self.pos += 1;
+						return Ok(Json::Array(values));
+					}
+					self.expect(',')?;
+				}
+			}
+			Some('{') => {
+				self.pos += 1;
+				let mut fields = Vec::new();
+				self.skip_whitespace();
+				if self.peek() == Some('}') {
+					self.pos += 1;
+					return Ok(Json::Object(fields));
+				}
+				loop {
+					self.skip_whitespace();
+					let name = self.parse_string()?;
+					self.expect(':')?;
+					fields.push((name, self.parse_value()?));
+					self.skip_whitespace();
+					if self.peek() == Some('}') {
+						self.pos += 1;
+						return Ok(Json::Object(fields));
+					}
+					self.expect(',')?;
+				}
+			}
+			_ => Err(self.error("unexpected character")),
+		}
+	}
+
+	fn parse_string(&mut self) -> Result<String, String> {
+		self.expect('"')?;
+		let mut string = String::new();
+		let mut chars = self.text[self.pos..].char_indices();
+		while let Some((offset, c)) = chars.next() {
+			match c {
+				'"' => {
+					self.pos += offset + 1;
+					return Ok(string);
+				}
+				'\\' => {
+					let escaped = match chars.next().map(|(_, c)| c) {
+						Some('"') => '"',
+						Some('\\') => '\\',
+						Some('/') => '/',
+						Some('n') => '\n',
+						Some('t') => '\t',
+						Some('r') => '\r',
+						Some('b') => '\u{8}',
+						Some('f') => '\u{c}',
+						Some('u') => {
+							let digits: String = (0..4)
+								.filter_map(|_| chars.next())
+								.map(|(_, c)| c)
+								.collect();
+							u32::from_str_radix(&digits, 16)
+								.ok()
+								.and_then(char::from_u32)
+								.ok_or_else(|| self.error("invalid unicode escape"))?
+						}
+						_ => return Err(self.error("invalid escape")),
+					};
+					string.push(escaped);
+				}
+				c => string.push(c),
+			}
+		}
+		Err(self.error("unterminated string"))
+	}
+}
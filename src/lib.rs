@@ -0,0 +1,78 @@
+pub mod analysis;
+pub mod arm64asm;
+pub mod astraw;
+pub mod astsoup;
+pub mod bench;
+pub mod bisect;
+pub mod bytecode;
+pub mod cache;
+pub mod cancel;
+pub mod config;
+pub mod conformance;
+pub mod coredump;
+pub mod cost;
+pub mod ctranspiler;
+pub mod deadcode;
+pub mod determinism;
+pub mod diff;
+pub mod differential;
+pub mod difftrace;
+pub mod encoding;
+pub mod errorcontext;
+pub mod explore;
+pub mod extended;
+pub mod fix;
+pub mod framerate;
+pub mod frontend;
+pub mod gen;
+pub mod golden;
+pub mod graph;
+pub mod hash;
+pub mod hostcall;
+pub mod intervals;
+pub mod ioorder;
+pub mod iosignature;
+pub mod iosummary;
+pub mod irtext;
+#[cfg(any(
+	all(target_os = "linux", target_pointer_width = "64"),
+	target_os = "macos"
+))]
+pub mod isolate;
+pub mod javatranspiler;
+pub mod jit;
+pub mod json;
+pub mod lineinput;
+pub mod log;
+pub mod loopprofile;
+pub mod lowering;
+pub mod luatranspiler;
+pub mod machine;
+pub mod objectfile;
+pub mod pager;
+pub mod parser;
+pub mod passes;
+#[cfg(feature = "plugins")]
+pub mod plugin;
+pub mod prelude;
+pub mod program;
+pub mod progress;
+pub mod project;
+pub mod reloop;
+pub mod report;
+pub mod runprogress;
+pub mod sandbox;
+pub mod shared;
+pub mod shtranspiler;
+pub mod stdinpolicy;
+pub mod tapeconfig;
+pub mod tapedump;
+pub mod tapeinit;
+pub mod termination;
+pub mod timing;
+pub mod tokenizer;
+pub mod verify;
+pub mod vm;
+pub mod warnings;
+pub mod watch;
+pub mod wattranspiler;
@@ -0,0 +1,164 @@
+//! Line input for programs run interactively by the VM: when a program reads and there is no input
+//! left, a whole line is read (with a minimal line editor if stdin is a terminal) and its bytes,
+//! the newline included, are given to the following reads. This is what most interactive programs
+//! expect, as they read a line of text and then answer it.
+//!
+//! The line editor handles backspace and the history of the lines read (with the up and down
+//! arrows). Ctrl-D on an empty line gives the end of the input (a 0 byte), and Ctrl-C stops the
+//! program.
+
+use crate::vm::Observer;
+use std::io::{IsTerminal, Read, Write};
+use std::process::{Command, Stdio};
+
+const CTRL_C: u8 = 0x03;
+const CTRL_D: u8 = 0x04;
+const BACKSPACE: u8 = 0x08;
+const ESCAPE: u8 = 0x1b;
+const DELETE: u8 = 0x7f;
+
+/// Observer of the VM that gives the interactive input a line at a time.
+#[derive(Default)]
+pub struct LineInput {
+	/// Lines read so far (without their newline), the last one being the most recent.
+	history: Vec<Vec<u8>>,
+}
+
+impl LineInput {
+	pub fn new() -> LineInput {
+		LineInput::default()
+	}
+
+	/// Reads a line with the line editor, from stdin that must be a terminal.
+	fn edit_line(&mut self) -> Vec<u8> {
+		let saved_settings = stty(&["-g"]);
+		// Without canonical mode nor echo nor signals, every key is read as it is pressed.
+		stty(&["-icanon", "-echo", "-isig", "min", "1"]);
+		let mut line: Vec<u8> = Vec::new();
+		// Index in the history of the line being shown, the line being edited if past the end.
+		let mut history_index = self.history.len();
+		let mut stdout = std::io::stdout();
+		let result = loop {
+			stdout.flush().ok();
+			let Some(byte) = read_byte() else {
+				break line;
+			};
+			match byte {
+				b'\r' | b'\n' => {
+					stdout.write_all(b"\n").ok();
+					if !line.is_empty() {
+						self.history.push(line.clone());
+					}
+					line.push(b'\n');
+					break line;
+				}
+				CTRL_D if line.is_empty() => break Vec::new(),
+				CTRL_C => {
+					if let Some(settings) = &saved_settings {
+						stty(&[settings.trim()]);
+					}
+					stdout.write_all(b"\x1b[39m\n").ok();
+					std::process::exit(130);
+				}
+				BACKSPACE | DELETE => {
+					// Removes the last character (with its UTF-8 continuation bytes).
+					while let Some(removed) = line.pop() {
+						if removed & 0xc0 != 0x80 {
+							break;
+						}
+					}
+					stdout.write_all(b"\x08 \x08").ok();
+				}
+				ESCAPE => {
+					let arrow = read_escape_sequence();
+					let shown = match arrow {
+						Some(b'A') if history_index > 0 => history_index - 1,
+						Some(b'B') if history_index < self.history.len() => history_index + 1,
+						_ => continue,
+					};
+					erase(&line);
+					history_index = shown;
+					line = self.history.get(shown).cloned().unwrap_or_default();
+					stdout.write_all(&line).ok();
+				}
+				byte if byte < 0x20 => (),
+				byte => {
+					line.push(byte);
+					stdout.write_all(&[byte]).ok();
+				}
+			}
+		};
+		if let Some(settings) = &saved_settings {
+			stty(&[settings.trim()]);
+		}
+		result
+	}
+}
+
+impl Observer for LineInput {
+	fn on_output(&mut self, _char_values: &[u8]) {}
+
+	fn read_interactive_input(&mut self) -> Option<Vec<u8>> {
+		print!("\x1b[36m");
+		std::io::stdout().flush().ok();
+		let line = if std::io::stdin().is_terminal() {
+			self.edit_line()
+		} else {
+			read_line()
+		};
+		print!("\x1b[39m");
+		Some(line)
+	}
+}
+
+fn read_byte() -> Option<u8> {
+	std::io::stdin().lock().bytes().next()?.ok()
+}
+
+/// Reads a line from stdin (that is not a terminal) as it is, up to its newline included.
+fn read_line() -> Vec<u8> {
+	let mut line = Vec::new();
+	while let Some(byte) = read_byte() {
+		line.push(byte);
+		if byte == b'\n' {
+			break;
+		}
+	}
+	line
+}
+
+/// Reads the rest of an escape sequence (after the escape character), and returns its final
+/// character if it is a control sequence (such as `A` for `ESC [ A`, the up arrow).
+fn read_escape_sequence() -> Option<u8> {
+	if read_byte()? != b'[' {
+		return None;
+	}
+	loop {
+		let byte = read_byte()?;
+		if (0x40..=0x7e).contains(&byte) {
+			return Some(byte);
+		}
+	}
+}
+
+/// Erases the shown line from the terminal, the cursor being at its end.
+fn erase(line: &[u8]) {
+	let columns = String::from_utf8_lossy(line).chars().count();
+	if columns > 0 {
+		print!("\x1b[{}D\x1b[K", columns);
+	}
+}
+
+/// Runs `stty` on the terminal of stdin with the given arguments, and returns its output.
+fn stty(args: &[&str]) -> Option<String> {
+	let output = Command::new("stty")
+		.args(args)
+		.stdin(Stdio::inherit())
+		.stderr(Stdio::null())
+		.output()
+		.ok()?;
+	output
+		.status
+		.success()
+		.then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
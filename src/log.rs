@@ -0,0 +1,176 @@
+//! Leveled logging (to stderr, so that it does not mix with the output of programs) of what the
+//! compiler does, one line per event: a name and fields, as text (`[info] parse instrs=42`) or as
+//! JSON objects.
+//!
+//! Each `-v` raises the level: `info` reports the phases (parsing, optimization, compilation,
+//! running), `debug` the result of each optimization pass and the sizes of the IR, and `trace`
+//! dumps the settings, the source code and the IR.
+
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+	Info = 1,
+	Debug = 2,
+	Trace = 3,
+}
+
+impl Level {
+	fn name(self) -> &'static str {
+		match self {
+			Level::Info => "info",
+			Level::Debug => "debug",
+			Level::Trace => "trace",
+		}
+	}
+}
+
+/// Events of levels up to this one are logged (none if 0).
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(0);
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// Sets the level from the number of `-v` given (3 or more meaning `trace`).
+pub fn set_verbosity(verbosity: u8) {
+	MAX_LEVEL.store(verbosity.min(Level::Trace as u8), Ordering::Relaxed);
+}
+
+pub fn set_json(json: bool) {
+	JSON.store(json, Ordering::Relaxed);
+}
+
+pub fn enabled(level: Level) -> bool {
+	level as u8 <= MAX_LEVEL.load(Ordering::Relaxed)
+}
+
+/// A value of a field, formatted both ways.
+pub trait LogValue {
+	fn to_text(&self) -> String;
+	fn to_json(&self) -> String;
+}
+
+impl LogValue for usize {
+	fn to_text(&self) -> String {
+		self.to_string()
+	}
+	fn to_json(&self) -> String {
+		self.to_string()
+	}
+}
+
+impl LogValue for bool {
+	fn to_text(&self) -> String {
+		self.to_string()
+	}
+	fn to_json(&self) -> String {
+		self.to_string()
+	}
+}
+
+impl LogValue for f64 {
+	fn to_text(&self) -> String {
+		format!("{:.3}", self)
+	}
+	fn to_json(&self) -> String {
+		format!("{:.3}", self)
+	}
+}
+
+impl LogValue for str {
+	/// Quoted if needed to keep fields separated by spaces.
+	fn to_text(&self) -> String {
+		if self.is_empty() || self.contains(|c: char| c.is_whitespace() || c == '"') {
+			format!("{:?}", self)
+		} else {
+			self.to_owned()
+		}
+	}
+	fn to_json(&self) -> String {
+		let mut json = String::from("\"");
+		for c in self.chars() {
+			match c {
+				'"' => json.push_str("\\\""),
+				'\\' => json.push_str("\\\\"),
+				'\n' => json.push_str("\\n"),
+				'\t' => json.push_str("\\t"),
+				c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+				c => json.push(c),
+			}
+		}
+		json.push('"');
+		json
+	}
+}
+
+impl LogValue for String {
+	fn to_text(&self) -> String {
+		self.as_str().to_text()
+	}
+	fn to_json(&self) -> String {
+		self.as_str().to_json()
+	}
+}
+
+impl<T: LogValue + ?Sized> LogValue for &T {
+	fn to_text(&self) -> String {
+		(**self).to_text()
+	}
+	fn to_json(&self) -> String {
+		(**self).to_json()
+	}
+}
+
+/// An event being built, logged by `emit` (nothing is formatted if its level is not enabled).
+pub struct Event {
+	level: Level,
+	name: &'static str,
+	/// Absent if the level is not enabled.
+	fields: Option<Vec<(&'static str, String)>>,
+}
+
+/// Starts an event, to which fields are added before it is emitted.
+pub fn event(level: Level, name: &'static str) -> Event {
+	Event {
+		level,
+		name,
+		fields: enabled(level).then(Vec::new),
+	}
+}
+
+impl Event {
+	pub fn field(mut self, name: &'static str, value: impl LogValue) -> Event {
+		if let Some(fields) = &mut self.fields {
+			let value = if JSON.load(Ordering::Relaxed) {
+				value.to_json()
+			} else {
+				value.to_text()
+			};
+			fields.push((name, value));
+		}
+		self
+	}
+
+	pub fn emit(self) {
+		let Some(fields) = self.fields else {
+			return;
+		};
+		let line = if JSON.load(Ordering::Relaxed) {
+			let mut line = format!(
+				"{{\"level\": \"{}\", \"event\": {}",
+				self.level.name(),
+				self.name.to_json()
+			);
+			for (name, value) in fields {
+				line.push_str(&format!(", {}: {}", name.to_json(), value));
+			}
+			line.push('}');
+			line
+		} else {
+			let mut line = format!("[{}] {}", self.level.name(), self.name);
+			for (name, value) in fields {
+				line.push_str(&format!(" {}={}", name, value));
+			}
+			line
+		};
+		eprintln!("{}", line);
+	}
+}
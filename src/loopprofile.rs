@@ -0,0 +1,147 @@
+//! Profile of the loops of a program run by the VM (`--profile-loops`): for each loop, how many
+//! times it ran and a histogram of its numbers of iterations, as a loop that runs once with a
+//! million iterations does not call for the same optimization as a loop that runs a million times
+//! with one iteration. Only known when running a program that is not optimized.
+
+use crate::astraw::RawInstr;
+use crate::parser::{instr_spans, lines_and_columns};
+use crate::tokenizer::Extensions;
+use crate::vm::Observer;
+use std::collections::BTreeMap;
+
+/// What a loop did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoopProfile {
+	/// Number of times the loop was reached, including when it was skipped.
+	pub runs: u64,
+	pub iterations: u64,
+	/// Number of runs by number of iterations rounded down to a power of two (`0` for the runs
+	/// that were skipped, `4` for the runs of 4 to 7 iterations).
+	pub histogram: BTreeMap<u64, u64>,
+}
+
+impl LoopProfile {
+	fn add_run(&mut self, iterations: u64) {
+		self.runs += 1;
+		self.iterations += iterations;
+		let bucket = match iterations {
+			0 => 0,
+			_ => 1 << iterations.ilog2(),
+		};
+		*self.histogram.entry(bucket).or_insert(0) += 1;
+	}
+}
+
+/// Observer of the VM that profiles the loops of a raw program, and prints (to stderr) their
+/// profiles when the program halts.
+pub struct LoopProfiler {
+	src_code: String,
+	extensions: Extensions,
+	/// The loop that starts at each instruction, if any, by the index of its body's first
+	/// instruction (that runs once per iteration).
+	loop_of_body_start: Vec<Option<usize>>,
+	is_loop: Vec<bool>,
+	/// Iterations of the current run of each loop (of the last run if it ended), by index.
+	current_runs: BTreeMap<usize, u64>,
+	profiles: BTreeMap<usize, LoopProfile>,
+}
+
+impl LoopProfiler {
+	pub fn new(raw_prog: &[RawInstr], src_code: &str, extensions: &Extensions) -> LoopProfiler {
+		let mut loop_of_body_start = vec![None; raw_prog.len() + 1];
+		let mut is_loop = vec![false; raw_prog.len()];
+		for (index, instr) in raw_prog.iter().enumerate() {
+			if let RawInstr::BracketLoop { body_len, .. } = instr {
+				is_loop[index] = true;
+				// An empty loop runs forever once entered, it has no iterations to count.
+				if *body_len != 0 {
+					loop_of_body_start[index + 1] = Some(index);
+				}
+			}
+		}
+		LoopProfiler {
+			src_code: src_code.to_owned(),
+			extensions: extensions.clone(),
+			loop_of_body_start,
+			is_loop,
+			current_runs: BTreeMap::new(),
+			profiles: BTreeMap::new(),
+		}
+	}
+
+	/// The profiles of the loops that were reached, by index of the loop in the raw program.
+	pub fn profiles(&mut self) -> &BTreeMap<usize, LoopProfile> {
+		self.end_runs();
+		&self.profiles
+	}
+
+	/// Ends the current runs of the loops, that are not followed by other runs.
+	fn end_runs(&mut self) {
+		for (index, iterations) in std::mem::take(&mut self.current_runs) {
+			self.profiles.entry(index).or_default().add_run(iterations);
+		}
+	}
+
+	fn format_profiles(&mut self) -> String {
+		let src_code = self.src_code.clone();
+		let spans = instr_spans(&src_code, &self.extensions);
+		let profiles = self.profiles();
+		let positions =
+			lines_and_columns(&src_code, profiles.keys().map(|&index| spans[index].start));
+		let mut loops: Vec<_> = profiles.iter().zip(positions).collect();
+		loops.sort_by_key(|((_, profile), _)| std::cmp::Reverse(profile.iterations));
+		let mut text = String::from("Loops by number of iterations:\n");
+		for ((_, profile), (line, column)) in loops {
+			let histogram: Vec<String> = profile
+				.histogram
+				.iter()
+				.map(|(&bucket, &runs)| match bucket {
+					0 | 1 => format!("{}: {}", bucket, runs),
+					_ => format!("{}-{}: {}", bucket, 2 * bucket - 1, runs),
+				})
+				.collect();
+			text.push_str(&format!(
+				"\tline {} column {}: {} iterations in {} runs (runs by iterations: {})\n",
+				line,
+				column,
+				profile.iterations,
+				profile.runs,
+				histogram.join(", ")
+			));
+		}
+		text
+	}
+}
+
+impl Observer for LoopProfiler {
+	fn on_output(&mut self, _char_values: &[u8]) {}
+
+	fn traces_instrs(&self) -> bool {
+		true
+	}
+
+	fn on_instr(&mut self, instr_index: usize, _head: usize, cell_value: u8) {
+		if let Some(loop_index) = self.loop_of_body_start[instr_index] {
+			*self.current_runs.entry(loop_index).or_insert(0) += 1;
+		}
+		if self.is_loop[instr_index] {
+			// A new run of the loop, the previous one (if any) has ended.
+			if let Some(iterations) = self.current_runs.remove(&instr_index) {
+				self.profiles
+					.entry(instr_index)
+					.or_default()
+					.add_run(iterations);
+			}
+			if cell_value == 0 {
+				self.profiles.entry(instr_index).or_default().add_run(0);
+			} else {
+				self.current_runs.insert(instr_index, 0);
+			}
+		}
+	}
+
+	fn on_halt(&mut self, _tape: &[u8], _head: usize) {
+		// The profile is also useful when the program stops on an error.
+		eprint!("{}", self.format_profiles());
+	}
+}
@@ -0,0 +1,228 @@
+//! Target-agnostic lowering of the soup intermediate representation to a linear form with
+//! labelled loops, that the backends other than C translate instruction by instruction.
+//!
+//! The decisions that do not depend on the target are made here once:
+//! - head movements are folded into the offsets of the instructions that follow them, the head
+//!   only being actually moved before the beginning and the end of loops,
+//! - the special loops (block moves, print and read loops) become loops of simple instructions,
+//! - the output is flushed before each input and at the end of the program (for backends that
+//!   buffer it),
+//! - the runtime helpers needed by the program (input, output) are listed.
+//!
+//! The C backend is deliberately not built on this lowering: it works on the soup intermediate
+//! representation directly, as the wrap analysis it relies on is indexed by soup instruction and
+//! as it emits library calls for the special loops. It thus makes the same decisions on its own
+//! (its head movements are offsets of the soup instructions, it flushes the output before inputs
+//! and its helpers depend on its options).
+
+use crate::astsoup::SoupInstr;
+use std::collections::BTreeMap;
+
+/// Identifies a loop, loops being numbered in the order of their beginnings in the program (so
+/// that the loops of a program that was not optimized are numbered like its bracket pairs, see
+/// `parser::bracket_pairs`).
+pub type LoopId = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinearInstr {
+	/// Adds the value (wrapping) to the cell at the offset from the head.
+	Add {
+		offset: isize,
+		value: u8,
+	},
+	Set {
+		offset: isize,
+		value: u8,
+	},
+	/// Adds the cell at `source` times the factor (wrapping) to the cell at `target` (both are
+	/// offsets from the head).
+	MulAdd {
+		source: isize,
+		target: isize,
+		factor: u8,
+	},
+	Move(isize),
+	Output {
+		offset: isize,
+	},
+	Input {
+		offset: isize,
+	},
+	/// Writes the output buffered by the backend, if any.
+	Flush,
+	/// Beginning of a loop that runs as long as the cell under the head is not zero.
+	LoopStart(LoopId),
+	/// End of the loop of the same id (jumping back to the test of its beginning).
+	LoopEnd(LoopId),
+}
+
+#[derive(Debug, Clone)]
+pub struct LinearProgram {
+	pub instrs: Vec<LinearInstr>,
+	/// The program reads input, so a runtime helper for input is needed.
+	pub uses_input: bool,
+	/// The program writes output, so a runtime helper for output is needed.
+	pub uses_output: bool,
+}
+
+struct Lowering {
+	instrs: Vec<LinearInstr>,
+	/// Head movement not yet emitted, the offsets of the emitted instructions take it into
+	/// account.
+	pending_move: isize,
+	loop_count: usize,
+}
+
+impl Lowering {
+	fn emit_pending_move(&mut self) {
+		if self.pending_move != 0 {
+			self.instrs.push(LinearInstr::Move(self.pending_move));
+			self.pending_move = 0;
+		}
+	}
+
+	fn add(&mut self, offset: isize, value: u8) {
+		if value != 0 {
+			self.instrs.push(LinearInstr::Add {
+				offset: self.pending_move + offset,
+				value,
+			});
+		}
+	}
+
+	fn set(&mut self, offset: isize, value: u8) {
+		self.instrs.push(LinearInstr::Set {
+			offset: self.pending_move + offset,
+			value,
+		});
+	}
+
+	fn mul_add(&mut self, source: isize, target: isize, factor: u8) {
+		self.instrs.push(LinearInstr::MulAdd {
+			source: self.pending_move + source,
+			target: self.pending_move + target,
+			factor,
+		});
+	}
+
+	fn output(&mut self, offset: isize) {
+		self.instrs.push(LinearInstr::Output {
+			offset: self.pending_move + offset,
+		});
+	}
+
+	fn input(&mut self, offset: isize) {
+		self.instrs.push(LinearInstr::Flush);
+		self.instrs.push(LinearInstr::Input {
+			offset: self.pending_move + offset,
+		});
+	}
+
+	fn cell_deltas(&mut self, cell_deltas: &BTreeMap<isize, isize>) {
+		for (&offset, &delta) in cell_deltas {
+			self.add(offset, delta.rem_euclid(256) as u8);
+		}
+	}
+
+	fn lower_loop(&mut self, lower_body: impl FnOnce(&mut Lowering)) {
+		let id = self.loop_count;
+		self.loop_count += 1;
+		self.emit_pending_move();
+		self.instrs.push(LinearInstr::LoopStart(id));
+		lower_body(self);
+		self.emit_pending_move();
+		self.instrs.push(LinearInstr::LoopEnd(id));
+	}
+
+	fn lower_instr_seq(&mut self, instr_seq: &[SoupInstr]) {
+		for instr in instr_seq {
+			match instr {
+				SoupInstr::Soup {
+					cell_deltas,
+					head_delta,
+				} => {
+					self.cell_deltas(cell_deltas);
+					self.pending_move += head_delta;
+				}
+				SoupInstr::Seek { delta } => self.pending_move += delta,
+				SoupInstr::Output { offset } => self.output(*offset),
+				SoupInstr::Input { offset } => self.input(*offset),
+				SoupInstr::Clock { .. } => panic!(
+					"the clock extension is only supported by the interpreter and the C backend"
+				),
+				SoupInstr::Extended { .. } => panic!(
+					"the extended-type-1 extension is only supported by the interpreter and the C \
+					 backend"
+				),
+				SoupInstr::Random { .. } => panic!(
+					"the random extension is only supported by the interpreter and the C backend"
+				),
+				SoupInstr::HostCall { .. } => panic!(
+					"the host-call extension is only supported by the interpreter and the C \
+					 backend"
+				),
+				SoupInstr::MultFixedLoop { cell_deltas } => {
+					for (&offset, &delta) in cell_deltas {
+						if offset != 0 {
+							self.mul_add(0, offset, delta.rem_euclid(256) as u8);
+						}
+					}
+					self.set(0, 0);
+				}
+				SoupInstr::SoupFixedLoop { cell_deltas } => {
+					self.lower_loop(|lowering| lowering.cell_deltas(cell_deltas))
+				}
+				SoupInstr::SoupMovingLoop {
+					cell_deltas,
+					head_delta,
+				} => self.lower_loop(|lowering| {
+					lowering.cell_deltas(cell_deltas);
+					lowering.pending_move += head_delta;
+				}),
+				SoupInstr::BlockMove { offset } => self.lower_loop(|lowering| {
+					lowering.mul_add(0, *offset, 1);
+					lowering.set(0, 0);
+					lowering.pending_move -= offset;
+				}),
+				SoupInstr::PrintUntilZero { stride } => self.lower_loop(|lowering| {
+					lowering.output(0);
+					lowering.pending_move += stride;
+				}),
+				SoupInstr::ReadBlock { stride } => self.lower_loop(|lowering| {
+					lowering.input(0);
+					lowering.pending_move += stride;
+				}),
+				SoupInstr::ReadUntilZero { stride } => self.lower_loop(|lowering| {
+					lowering.pending_move += stride;
+					lowering.input(0);
+				}),
+				SoupInstr::Loop(body, _) => {
+					self.lower_loop(|lowering| lowering.lower_instr_seq(body))
+				}
+			}
+		}
+	}
+}
+
+pub fn lower(soup_prog: &[SoupInstr]) -> LinearProgram {
+	let mut lowering = Lowering {
+		instrs: Vec::new(),
+		pending_move: 0,
+		loop_count: 0,
+	};
+	lowering.lower_instr_seq(soup_prog);
+	// The final position of the head does not matter.
+	let mut instrs = lowering.instrs;
+	instrs.push(LinearInstr::Flush);
+	let uses_input = instrs
+		.iter()
+		.any(|instr| matches!(instr, LinearInstr::Input { .. }));
+	let uses_output = instrs
+		.iter()
+		.any(|instr| matches!(instr, LinearInstr::Output { .. }));
+	LinearProgram {
+		instrs,
+		uses_input,
+		uses_output,
+	}
+}
@@ -0,0 +1,104 @@
+//! Transpilation of the linear intermediate representation (see `lowering`) to a standalone Lua
+//! script.
+//!
+//! The tape is a table whose missing entries read as 0, so it is unbounded in both directions.
+//! Only Lua 5.1 features are used, so that the script also runs on LuaJIT (and OpenResty). As
+//! with the C backend, reading at the end of the input gives 255.
+
+use crate::lowering::{LinearInstr, LinearProgram};
+
+struct TranspiledLua {
+	code: String,
+	indent_level: u32,
+}
+
+impl TranspiledLua {
+	fn emit_line(&mut self, line_content: &str) {
+		self.code
+			.extend(std::iter::repeat_n("\t", self.indent_level as usize));
+		self.code.push_str(line_content);
+		self.code.push('\n');
+	}
+
+	fn emit_indent(&mut self) {
+		self.indent_level += 1;
+	}
+	fn emit_unindent(&mut self) {
+		self.indent_level -= 1;
+	}
+
+	fn emit_header(&mut self, program: &LinearProgram) {
+		self.emit_line("local m = setmetatable({}, {__index = function() return 0 end})");
+		self.emit_line("local h = 0");
+		if program.uses_input {
+			self.emit_line("local function input()");
+			self.emit_indent();
+			self.emit_line("local c = io.read(1)");
+			self.emit_line("return c and c:byte() or 255");
+			self.emit_unindent();
+			self.emit_line("end");
+		}
+	}
+
+	fn emit_instr(&mut self, instr: &LinearInstr) {
+		match *instr {
+			LinearInstr::Add { offset, value } => {
+				let cell = m(offset);
+				self.emit_line(&format!("{} = ({} + {}) % 256", cell, cell, value));
+			}
+			LinearInstr::Set { offset, value } => {
+				self.emit_line(&format!("{} = {}", m(offset), value))
+			}
+			LinearInstr::MulAdd {
+				source,
+				target,
+				factor,
+			} => {
+				let cell = m(target);
+				let added = match factor {
+					1 => m(source),
+					_ => format!("{} * {}", m(source), factor),
+				};
+				self.emit_line(&format!("{} = ({} + {}) % 256", cell, cell, added));
+			}
+			LinearInstr::Move(delta) if delta < 0 => {
+				self.emit_line(&format!("h = h - {}", -delta))
+			}
+			LinearInstr::Move(delta) => self.emit_line(&format!("h = h + {}", delta)),
+			LinearInstr::Output { offset } => {
+				self.emit_line(&format!("io.write(string.char({}))", m(offset)))
+			}
+			LinearInstr::Input { offset } => self.emit_line(&format!("{} = input()", m(offset))),
+			LinearInstr::Flush => self.emit_line("io.flush()"),
+			LinearInstr::LoopStart(_) => {
+				self.emit_line("while m[h] ~= 0 do");
+				self.emit_indent();
+			}
+			LinearInstr::LoopEnd(_) => {
+				self.emit_unindent();
+				self.emit_line("end");
+			}
+		}
+	}
+}
+
+pub fn transpile_linear_to_lua(program: &LinearProgram) -> String {
+	let mut transpiled = TranspiledLua {
+		code: String::new(),
+		indent_level: 0,
+	};
+	transpiled.emit_header(program);
+	for instr in &program.instrs {
+		transpiled.emit_instr(instr);
+	}
+	assert!(transpiled.indent_level == 0);
+	transpiled.code
+}
+
+fn m(relative_head: isize) -> String {
+	if relative_head == 0 {
+		"m[h]".to_owned()
+	} else {
+		format!("m[h + {}]", relative_head)
+	}
+}
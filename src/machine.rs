@@ -0,0 +1,43 @@
+//! Model of the machine that programs run on, for dialects that have more than the tape. The
+//! storage of the Extended Brainfuck Type I instructions (see `extended`) can be a single
+//! register, or a second tape that has a storage cell for each cell of the tape.
+//!
+//! The optimizations are the same for every model, as the extended instructions keep the offset
+//! of the cell they operate on (which gives their storage cell on a second tape).
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Storage {
+	/// A single storage cell, as in Extended Brainfuck Type I.
+	#[default]
+	Register,
+	/// A second tape, an instruction using the storage cell of the same index as the cell it
+	/// operates on.
+	Tape,
+}
+
+impl Storage {
+	/// Parses the name given to `--storage`.
+	pub fn from_name(name: &str) -> Option<Storage> {
+		match name {
+			"register" => Some(Storage::Register),
+			"tape" => Some(Storage::Tape),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MachineModel {
+	pub storage: Storage,
+}
+
+impl MachineModel {
+	/// Index of the storage cell used by an instruction that operates on the cell of the given
+	/// index.
+	pub fn storage_index(&self, cell_index: usize) -> usize {
+		match self.storage {
+			Storage::Register => 0,
+			Storage::Tape => cell_index,
+		}
+	}
+}
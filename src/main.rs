@@ -1,9 +1,55 @@
-mod graph;
-mod astraw;
-mod astsoup;
-mod ctranspiler;
-mod parser;
-mod vm;
+use std::convert::TryInto;
+use std::ops::Range;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use xxbf::arm64asm::Arm64Os;
+use xxbf::bench::{BenchOptions, run_benchmarks};
+use xxbf::bisect::{BisectResult, bisect};
+use xxbf::bytecode::{DEFAULT_MAX_UNROLLED_BODY_LEN, dump_bytecode, print_bytecode};
+use xxbf::cache;
+use xxbf::config::{CodegenConfig, Target};
+use xxbf::conformance::{ConformanceOptions, Verdict, run_conformance};
+use xxbf::coredump::{CoreDump, CoreRecorder, DEFAULT_CORE_FILE};
+use xxbf::cost::CostModel;
+use xxbf::ctranspiler::{BraceStyle, CFormat, COptions, CodegenPreference};
+use xxbf::determinism::check_determinism;
+use xxbf::diff::unified_diff;
+use xxbf::difftrace::{DiffTrace, DiffTraceOptions, Event, diff_trace};
+use xxbf::encoding::OutputEncoding;
+use xxbf::errorcontext::ErrorContext;
+use xxbf::explore::{
+	ExploreOptions, OutputByte, OutputPrediction, explore, output_to_text, predict_output,
+};
+use xxbf::fix::{Fix, Repair, fix};
+use xxbf::framerate::FrameLimiter;
+use xxbf::frontend::{FRONTENDS, frontend};
+use xxbf::gen::print_number;
+use xxbf::golden::{GoldenOptions, GoldenVerdict, run_golden};
+use xxbf::iosummary::IoSummary;
+use xxbf::jit::{self, DEFAULT_JIT_THRESHOLD};
+use xxbf::lineinput::LineInput;
+use xxbf::log::{Level, event};
+use xxbf::loopprofile::LoopProfiler;
+use xxbf::machine::Storage;
+use xxbf::objectfile::link_executable;
+use xxbf::pager::Pager;
+use xxbf::parser::{instr_spans, line_and_column};
+use xxbf::passes::PassManager;
+use xxbf::prelude::expand_includes;
+use xxbf::program::{Program, Stage};
+use xxbf::project::{DEFAULT_MANIFEST, Manifest, build_project, test_project};
+use xxbf::report::{CompileReport, count_loops};
+use xxbf::runprogress::{ProgressPeriod, ProgressReporter};
+use xxbf::sandbox::Sandbox;
+use xxbf::stdinpolicy::{StdinFallback, StdinPolicy};
+use xxbf::tapeconfig::{TapeConfig, parse_region};
+use xxbf::tapedump::{TapeDumper, parse_range};
+use xxbf::termination::check_termination;
+use xxbf::timing::print_phase_time;
+use xxbf::tokenizer::{Extensions, host_call_char};
+use xxbf::verify::{VerifyOptions, verify_target};
+use xxbf::vm::{OUTPUT_LIMIT_EXIT_CODE, Observer, OutputLimitExceeded, RunLimits, RunStage};
+use xxbf::watch::{Watch, Watchpoints};
 
 #[derive(Debug)]
 enum WhatToDo {
@@ -13,12 +59,82 @@ enum WhatToDo {
 	Compile {
 		target: CompileTarget,
 		dst_file_path: Option<String>,
+		/// Path of the JSON compile report to write (see `report`).
+		report: Option<String>,
+		/// Precompute the start of the program that only computes the tape (see `tapeinit`).
+		tape_init: bool,
+	},
+	Bisect {
+		input: Option<String>,
+	},
+	DiffTrace {
+		options: DiffTraceOptions,
+	},
+	Check {
+		termination: bool,
+		/// Step budget of the output prediction.
+		max_steps: u64,
+		/// Writes a repaired copy of a source code that does not parse (see `fix`).
+		fix: bool,
+	},
+	Explore {
+		options: ExploreOptions,
+	},
+	Conformance {
+		options: ConformanceOptions,
+	},
+	/// Times the optimizer and the interpreter on the programs of a corpus (see `bench`).
+	Bench {
+		options: BenchOptions,
+	},
+	/// Checks the optimized IR of the programs of a corpus against their snapshots.
+	Golden {
+		options: GoldenOptions,
+	},
+	Debug {
+		/// Path of a core dump file to inspect.
+		core: Option<String>,
+	},
+	/// Removes the cached optimized programs (see `cache`).
+	ClearCache,
+	/// Compiles the programs of a project (see `project`).
+	Build {
+		/// Path of the manifest.
+		manifest: String,
+	},
+	/// Runs the test cases of a project.
+	Test {
+		/// Path of the manifest.
+		manifest: String,
+	},
+	/// Compares the outputs of the program compiled by a backend and of the interpreter (see
+	/// `verify`).
+	VerifyTarget {
+		options: VerifyOptions,
+	},
+	/// Prints generated code (see `gen`).
+	Gen {
+		/// The code prints this number.
+		number: Option<u8>,
+		radix: u8,
 	},
 }
 
 #[derive(Debug)]
 enum CompileTarget {
 	C,
+	Lua,
+	Java,
+	/// POSIX shell script.
+	Sh,
+	/// WebAssembly text format.
+	Wat,
+	/// AArch64 assembly.
+	Arm64Asm(Arm64Os),
+	/// Native x86-64 Linux object file.
+	Object,
+	/// Object file linked by the system linker.
+	Executable,
 }
 
 #[derive(Debug)]
@@ -30,80 +146,962 @@ enum SrcSettings {
 
 #[derive(Debug)]
 struct Settings {
+	#[allow(dead_code)] // Only logged at the `trace` level.
 	path: Option<String>,
 	help: bool,
-	verbose: bool,
+	/// Number of `-v` given (see `log`).
+	verbosity: u8,
+	log_json: bool,
 	src: SrcSettings,
+	extensions: Extensions,
 	optimize: bool,
+	pass_manager: PassManager,
+	c_options: COptions,
+	print_ir: bool,
+	print_ir_hash: bool,
+	print_bytecode: bool,
+	dump_bytecode: bool,
+	/// See `bytecode::unroll_tiny_loops`.
+	max_unrolled_body_len: usize,
+	jit_threshold: Option<u64>,
+	/// Execution engine forced with `--run-stage`.
+	run_stage: Option<RunStage>,
+	/// Number of output lines after which the interpreted program is paused.
+	paged_output: Option<usize>,
+	/// The interactive input of the interpreted program is read a line at a time.
+	line_input: bool,
+	/// The interpreted program runs within the limits of the sandbox preset (see `sandbox`).
+	sandbox: bool,
+	/// Number of bytes after which the output of the interpreted program is truncated and the
+	/// program stopped.
+	max_output: Option<usize>,
+	/// Compile and run the program twice, checking that both runs agree.
+	determinism_check: bool,
+	/// Maximal number of frames per second output by the interpreted program.
+	fps: Option<f64>,
+	/// How the output of the interpreted program is printed (when it is given an input).
+	output_encoding: OutputEncoding,
+	/// Dump the tape when the interpreted program halts, the range being the dumped cells (by
+	/// default the used cells).
+	dump_tape: Option<Option<Range<usize>>>,
+	/// Report the progress of the interpreted program periodically.
+	progress: Option<ProgressPeriod>,
+	/// Conditions that stop the interpreted program when they become true.
+	watches: Vec<Watch>,
+	/// Path of the core dump file written when the interpreted program stops on an error.
+	core_dump: Option<String>,
+	/// Number of last instructions run printed when the interpreted program stops on an error.
+	error_context: Option<usize>,
+	/// Print the numbers of iterations of the loops of the interpreted program.
+	profile_loops: bool,
+	/// The interpreted program runs in a child process with resource limits (see `isolate`), this
+	/// being the position of `--isolate` in the command line arguments.
+	isolate: Option<usize>,
+	/// What the interpreted program may do with stdin, the default depending on `-i` (see
+	/// `stdinpolicy`).
+	stdin_policy: Option<StdinPolicy>,
+	tape_config: TapeConfig,
+	warnings: bool,
+	/// Use the cache of optimized programs (see `cache`).
+	cache: bool,
 	what_to_do: WhatToDo,
 }
 
 impl Settings {
 	fn from_cmdline_args() -> Settings {
-		let mut args = std::env::args();
+		// Number of arguments taken from the command line (including the one that was peeked at,
+		// if any), to know the position of the current argument.
+		let taken_count = std::cell::Cell::new(0);
+		let mut args = std::env::args()
+			.inspect(|_| taken_count.set(taken_count.get() + 1))
+			.peekable();
+		let mut line_directives = false;
+		// A cell index or the name of a region, that can be given after.
+		let mut head_start: Option<String> = None;
 		let mut settings = Settings {
 			path: args.next(),
 			help: false,
-			verbose: false,
+			verbosity: 0,
+			log_json: false,
 			src: SrcSettings::None,
+			extensions: Extensions::default(),
 			optimize: true,
+			pass_manager: PassManager::default(),
+			c_options: COptions::default(),
+			print_ir: false,
+			print_ir_hash: false,
+			print_bytecode: false,
+			dump_bytecode: false,
+			max_unrolled_body_len: DEFAULT_MAX_UNROLLED_BODY_LEN,
+			jit_threshold: None,
+			run_stage: None,
+			paged_output: None,
+			line_input: false,
+			sandbox: false,
+			max_output: None,
+			determinism_check: false,
+			fps: None,
+			output_encoding: OutputEncoding::Utf8Lossy,
+			dump_tape: None,
+			progress: None,
+			watches: Vec::new(),
+			core_dump: None,
+			error_context: None,
+			profile_loops: false,
+			isolate: None,
+			stdin_policy: None,
+			tape_config: TapeConfig::default(),
+			warnings: true,
+			cache: true,
 			what_to_do: WhatToDo::Interpret { input: None },
 		};
+		if args.next_if(|arg| arg == "bisect").is_some() {
+			settings.what_to_do = WhatToDo::Bisect { input: None };
+		} else if args.next_if(|arg| arg == "difftrace").is_some() {
+			if let Some(src_file_path) = args.next_if(|arg| !arg.starts_with('-')) {
+				settings.src = SrcSettings::FilePath(src_file_path);
+			}
+			settings.what_to_do = WhatToDo::DiffTrace {
+				options: DiffTraceOptions::default(),
+			};
+		} else if args.next_if(|arg| arg == "check").is_some() {
+			settings.what_to_do = WhatToDo::Check {
+				termination: false,
+				max_steps: ExploreOptions::default().max_steps_per_path,
+				fix: false,
+			};
+		} else if args.next_if(|arg| arg == "explore").is_some() {
+			settings.what_to_do = WhatToDo::Explore {
+				options: ExploreOptions::default(),
+			};
+		} else if args.next_if(|arg| arg == "conformance").is_some() {
+			settings.what_to_do = WhatToDo::Conformance {
+				options: ConformanceOptions::default(),
+			};
+		} else if args.next_if(|arg| arg == "bench").is_some() {
+			settings.what_to_do = WhatToDo::Bench {
+				options: BenchOptions::default(),
+			};
+		} else if args.next_if(|arg| arg == "debug").is_some() {
+			settings.what_to_do = WhatToDo::Debug { core: None };
+		} else if args.next_if(|arg| arg == "cache").is_some() {
+			let cache_subcommand = args.next();
+			settings.what_to_do = match cache_subcommand.as_deref() {
+				Some("clear") => WhatToDo::ClearCache,
+				Some(name) => panic!("unknown cache subcommand `{}`", name),
+				None => panic!("`cache` expects a subcommand (`clear`)"),
+			};
+		} else if args.next_if(|arg| arg == "dev").is_some() {
+			let dev_subcommand = args.next();
+			settings.what_to_do = match dev_subcommand.as_deref() {
+				Some("golden") => WhatToDo::Golden {
+					options: GoldenOptions::default(),
+				},
+				Some(name) => panic!("unknown dev subcommand `{}`", name),
+				None => panic!("`dev` expects a subcommand (`golden`)"),
+			};
+		} else if args.next_if(|arg| arg == "build").is_some() {
+			settings.what_to_do = WhatToDo::Build {
+				manifest: DEFAULT_MANIFEST.to_owned(),
+			};
+		} else if args.next_if(|arg| arg == "test").is_some() {
+			settings.what_to_do = WhatToDo::Test {
+				manifest: DEFAULT_MANIFEST.to_owned(),
+			};
+		} else if args.next_if(|arg| arg == "gen").is_some() {
+			settings.what_to_do = WhatToDo::Gen {
+				number: None,
+				radix: 10,
+			};
+		} else if args.next_if(|arg| arg == "verify-target").is_some() {
+			let backend_name = args
+				.next()
+				.expect("`verify-target` expects a backend (such as `c`) and a source file");
+			settings.src = SrcSettings::FilePath(
+				args.next()
+					.expect("`verify-target` expects a backend (such as `c`) and a source file"),
+			);
+			settings.what_to_do = WhatToDo::VerifyTarget {
+				options: VerifyOptions {
+					backend_name,
+					inputs_dir: None,
+					c_options: COptions::default(),
+				},
+			};
+		}
 		while let Some(arg) = args.next() {
 			if arg == "-h" || arg == "--help" {
 				settings.help = true;
 			} else if arg == "-v" || arg == "--verbose" {
-				settings.verbose = true;
+				settings.verbosity += 1;
+			} else if arg == "-vv" {
+				settings.verbosity += 2;
+			} else if arg == "-vvv" {
+				settings.verbosity += 3;
+			} else if arg == "--log-format" {
+				let format = args.next().unwrap();
+				settings.log_json = match format.as_str() {
+					"text" => false,
+					"json" => true,
+					_ => panic!("unknown log format `{}`", format),
+				};
 			} else if arg == "-s" || arg == "--src" {
 				settings.src = SrcSettings::Src(args.next().unwrap());
 			} else if arg == "-f" || arg == "--src-file" {
 				settings.src = SrcSettings::FilePath(args.next().unwrap());
+			} else if arg == "-x" || arg == "--extension" {
+				let extension = args.next().unwrap();
+				match extension.as_str() {
+					"comments" => settings.extensions.comments = true,
+					"clock" => settings.extensions.clock = true,
+					"random" => settings.extensions.random = true,
+					"extended-type-1" => settings.extensions.extended_type_1 = true,
+					"include" => settings.extensions.include = true,
+					"loop-attributes" => settings.extensions.loop_attributes = true,
+					name => match host_call_char(name) {
+						Some(c) => settings.extensions.host_call = Some(c),
+						None => panic!("unknown extension `{}`", extension),
+					},
+				}
+			} else if arg == "--dialect" {
+				let name = args.next().unwrap();
+				settings.extensions.frontend = frontend(&name).unwrap_or_else(|| {
+					panic!(
+						"unknown dialect `{}` (expected one of {})",
+						name,
+						FRONTENDS
+							.iter()
+							.map(|frontend| format!("`{}`", frontend.name()))
+							.collect::<Vec<_>>()
+							.join(", ")
+					)
+				});
+			} else if arg == "-w" || arg == "--no-warnings" {
+				settings.warnings = false;
+			} else if arg == "--no-cache" {
+				settings.cache = false;
 			} else if arg == "-O0" || arg == "--no-optimizations" {
 				settings.optimize = false;
+			} else if let Some(pass_name) = arg.strip_prefix("--print-ir-after=") {
+				settings
+					.pass_manager
+					.print_ir_after
+					.push(pass_name.to_owned());
+			} else if arg == "--opt-fuel" {
+				settings.pass_manager.fuel = Some(args.next().unwrap().parse().unwrap());
+			} else if arg == "--opt-time-budget" {
+				settings.pass_manager.time_budget =
+					Some(Duration::from_millis(args.next().unwrap().parse().unwrap()));
+			} else if arg == "--compiler-mem-limit" {
+				let limit = args.next().unwrap();
+				settings.pass_manager.mem_limit = Some(
+					parse_byte_size(&limit)
+						.unwrap_or_else(|| panic!("invalid memory limit `{}`", limit)),
+				);
+			} else if arg == "--strict-io" {
+				settings.pass_manager.strict_io = true;
+				settings.c_options.strict_io = true;
+			} else if arg == "--plugin" {
+				let path = args.next().unwrap();
+				#[cfg(feature = "plugins")]
+				xxbf::plugin::load_plugin(&path, &mut settings.pass_manager)
+					.unwrap_or_else(|error| panic!("{}", error));
+				#[cfg(not(feature = "plugins"))]
+				panic!(
+					"cannot load plugin `{}`, xxbf was built without the `plugins` feature",
+					path
+				);
+			} else if arg == "--time-passes" {
+				settings.pass_manager.time_passes = true;
+			} else if arg == "--c-trap-on-overflow" {
+				settings.c_options.trap_on_overflow = true;
+			} else if arg == "--c-write-fn" {
+				settings.c_options.write_fn = Some(args.next().unwrap());
+			} else if arg == "--c-read-fn" {
+				settings.c_options.read_fn = Some(args.next().unwrap());
+			} else if arg == "--c-max-nesting" {
+				settings.c_options.max_nesting = Some(
+					args.next()
+						.unwrap()
+						.parse()
+						.ok()
+						.filter(|&depth| depth > 0)
+						.unwrap_or_else(|| panic!("`--c-max-nesting` expects a positive integer")),
+				);
+			} else if arg == "--c-indent" {
+				let indent = args.next().unwrap();
+				settings.c_options.format.indent = if indent == "tab" {
+					"\t".to_owned()
+				} else {
+					let width = indent.parse().unwrap_or_else(|_| {
+						panic!("`--c-indent` expects `tab` or a number of spaces")
+					});
+					" ".repeat(width)
+				};
+			} else if arg == "--c-brace-style" {
+				let style = args.next().unwrap();
+				settings.c_options.format.brace_style = match style.as_str() {
+					"next-line" => BraceStyle::NextLine,
+					"same-line" => BraceStyle::SameLine,
+					_ => panic!("unknown brace style `{}`", style),
+				};
+			} else if arg == "--c-line-width" {
+				settings.c_options.format.max_line_width = Some(
+					args.next()
+						.unwrap()
+						.parse()
+						.ok()
+						.filter(|&width| width > 0)
+						.unwrap_or_else(|| panic!("`--c-line-width` expects a positive integer")),
+				);
+			} else if arg == "--c-compact" {
+				settings.c_options.format = CFormat::compact();
+			} else if arg == "--c-line-directives" {
+				line_directives = true;
+			} else if arg == "--init-tape" {
+				let path = args.next().unwrap();
+				settings.tape_config.initial_tape = std::fs::read(&path)
+					.unwrap_or_else(|error| panic!("cannot read `{}`: {}", path, error));
+			} else if arg == "--init-tape-hex" {
+				let hex = args.next().unwrap();
+				settings.tape_config.initial_tape =
+					parse_hex_bytes(&hex).unwrap_or_else(|| panic!("invalid hex bytes `{}`", hex));
+			} else if arg == "--head-start" {
+				head_start = Some(args.next().unwrap());
+			} else if arg == "--storage" {
+				let storage = args.next().unwrap();
+				settings.tape_config.machine.storage = Storage::from_name(&storage)
+					.unwrap_or_else(|| panic!("unknown storage `{}`", storage));
+			} else if arg == "--seed" {
+				let seed = args.next().unwrap();
+				settings.tape_config.seed = Some(
+					seed.parse()
+						.unwrap_or_else(|_| panic!("invalid seed `{}`", seed)),
+				);
+			} else if arg == "--tape-region" {
+				let region = args.next().unwrap();
+				let (name, size, protection) = parse_region(&region).unwrap_or_else(|| {
+					panic!(
+						"invalid tape region `{}` (expected `name:size` or `name:size:ro`)",
+						region
+					)
+				});
+				if settings.tape_config.region(&name).is_some() {
+					panic!("tape region `{}` given twice", name);
+				}
+				settings.tape_config.add_region(name, size, protection);
+			} else if arg == "--codegen" {
+				let preference = args.next().unwrap();
+				settings.c_options.codegen = match preference.as_str() {
+					"prefer-speed" => CodegenPreference::Speed,
+					"prefer-size" => CodegenPreference::Size,
+					_ => panic!("unknown code generation preference `{}`", preference),
+				};
+			} else if arg == "--print-ir-diff" {
+				settings.pass_manager.print_ir_diff = true;
+			} else if arg == "--print-ir" {
+				settings.print_ir = true;
+			} else if arg == "--print-ir-hash" {
+				settings.print_ir_hash = true;
+			} else if arg == "--print-bytecode" {
+				settings.print_bytecode = true;
+			} else if arg == "--dump-bytecode" {
+				settings.dump_bytecode = true;
+			} else if arg == "--max-unrolled-body-len" {
+				settings.max_unrolled_body_len = args
+					.next()
+					.and_then(|len| len.parse().ok())
+					.expect("`--max-unrolled-body-len` expects a number of ops");
+			} else if !arg.starts_with('-')
+				&& matches!(settings.src, SrcSettings::None)
+				&& matches!(
+					settings.what_to_do,
+					WhatToDo::Interpret { .. } | WhatToDo::Compile { .. }
+				) {
+				// As in `xxbf script.b`, which is how a script starting with a `#!/usr/bin/env
+				// xxbf` shebang line is run.
+				settings.src = SrcSettings::FilePath(arg);
 			} else if arg == "-c" || arg == "--compile" {
 				settings.what_to_do = WhatToDo::Compile {
 					target: CompileTarget::C,
 					dst_file_path: None,
+					report: None,
+					tape_init: false,
 				};
 			} else if let WhatToDo::Interpret { ref mut input } = settings.what_to_do {
 				if arg == "-i" || arg == "--input" {
 					*input = args.next();
+				} else if arg == "--stdin-policy" {
+					let name = args.next().unwrap();
+					settings.stdin_policy =
+						Some(StdinPolicy::from_name(&name).unwrap_or_else(|| {
+							panic!(
+								"unknown stdin policy `{}` (expected `prompt`, `silent` or \
+								`closed`)",
+								name
+							)
+						}));
+				} else if arg == "--jit" {
+					settings.jit_threshold = Some(DEFAULT_JIT_THRESHOLD);
+				} else if arg == "--run-stage" {
+					let name = args.next().unwrap();
+					settings.run_stage = Some(RunStage::from_name(&name).unwrap_or_else(|| {
+						panic!(
+							"unknown run stage `{}` (expected `raw`, `soup`, `graph`, `bytecode` \
+							or `jit`)",
+							name
+						)
+					}));
+				} else if arg == "--paged-output" {
+					settings.paged_output = Some(
+						args.next()
+							.and_then(|lines| lines.parse().ok())
+							.filter(|&lines| lines > 0)
+							.expect("`--paged-output` expects a non-zero number of lines"),
+					);
+				} else if arg == "--fps" {
+					settings.fps = Some(
+						args.next()
+							.and_then(|fps| fps.parse().ok())
+							.filter(|&fps: &f64| fps > 0.0)
+							.expect("`--fps` expects a positive number of frames per second"),
+					);
+				} else if arg == "--output-encoding" {
+					let name = args.next().unwrap();
+					settings.output_encoding = OutputEncoding::from_name(&name)
+						.unwrap_or_else(|| panic!("unknown output encoding `{}`", name));
+				} else if arg == "--determinism-check" {
+					settings.determinism_check = true;
+				} else if arg == "--line-input" {
+					settings.line_input = true;
+				} else if arg == "--sandbox" {
+					settings.sandbox = true;
+				} else if arg == "--isolate" {
+					if !cfg!(any(
+						all(target_os = "linux", target_pointer_width = "64"),
+						target_os = "macos"
+					)) {
+						panic!("`--isolate` is only available on Linux and macOS");
+					}
+					settings.isolate = Some(taken_count.get() - 1);
+				} else if arg == "--max-output" {
+					settings.max_output = Some(
+						args.next()
+							.and_then(|len| len.parse().ok())
+							.expect("`--max-output` expects a number of bytes"),
+					);
+				} else if arg == "--dump-tape" {
+					settings.dump_tape = Some(None);
+				} else if let Some(range) = arg.strip_prefix("--dump-tape=") {
+					settings.dump_tape = Some(Some(parse_range(range).unwrap_or_else(|| {
+						panic!("invalid cell range `{}` (expected `start..end`)", range)
+					})));
+				} else if arg == "--progress" {
+					settings.progress = Some(ProgressPeriod::EverySecond);
+				} else if let Some(millions) = arg.strip_prefix("--progress=") {
+					settings.progress = Some(ProgressPeriod::EveryMillionSteps(
+						millions
+							.parse()
+							.ok()
+							.filter(|&millions| millions > 0)
+							.unwrap_or_else(|| {
+								panic!(
+									"`--progress=` expects a positive number of millions of steps"
+								)
+							}),
+					));
+				} else if arg == "--watch" {
+					let condition = args.next().unwrap();
+					settings
+						.watches
+						.push(Watch::parse(&condition).unwrap_or_else(|| {
+							panic!(
+								"invalid watch condition `{}` (expected such as `cell[5] == 0` or \
+							`head > 100`)",
+								condition
+							)
+						}));
+				} else if arg == "--error-context" {
+					settings.error_context = Some(
+						args.next()
+							.and_then(|len| len.parse().ok())
+							.expect("`--error-context` expects a number of instructions"),
+					);
+				} else if arg == "--profile-loops" {
+					settings.profile_loops = true;
+				} else if arg == "--core-dump" {
+					settings.core_dump = Some(DEFAULT_CORE_FILE.to_owned());
+				} else if let Some(path) = arg.strip_prefix("--core-dump=") {
+					settings.core_dump = Some(path.to_owned());
+				} else if arg == "--jit-threshold" {
+					settings.jit_threshold = Some(
+						args.next()
+							.and_then(|threshold| threshold.parse().ok())
+							.expect("`--jit-threshold` expects a number of iterations"),
+					);
 				} else {
 					panic!("unknown cmdline argument `{}` (for interpretation)", arg);
 				}
 			} else if let WhatToDo::Compile {
+				ref mut target,
 				ref mut dst_file_path,
-				..
+				ref mut report,
+				ref mut tape_init,
 			} = settings.what_to_do
 			{
 				if arg == "-o" || arg == "--output-file" {
 					*dst_file_path = args.next();
+				} else if arg == "--emit" {
+					let mode = args.next().unwrap();
+					match mode.as_str() {
+						"tape-init" => *tape_init = true,
+						_ => panic!("unknown emit mode `{}`", mode),
+					}
+				} else if arg == "--report" {
+					*report = Some(args.next().expect("`--report` expects a file path"));
+				} else if arg == "--target" {
+					let target_name = args.next().unwrap();
+					*target = match target_name.as_str() {
+						"c" => CompileTarget::C,
+						"lua" => CompileTarget::Lua,
+						"java" => CompileTarget::Java,
+						"sh" => CompileTarget::Sh,
+						"wat" => CompileTarget::Wat,
+						"arm64-linux" => CompileTarget::Arm64Asm(Arm64Os::Linux),
+						"arm64-macos" => CompileTarget::Arm64Asm(Arm64Os::MacOs),
+						"object" => CompileTarget::Object,
+						"executable" => CompileTarget::Executable,
+						_ => panic!("unknown compilation target `{}`", target_name),
+					};
 				} else {
 					panic!("unknown cmdline argument `{}` (for compilation)", arg);
 				}
+			} else if let WhatToDo::DiffTrace { ref mut options } = settings.what_to_do {
+				if arg == "-i" || arg == "--input" {
+					options.input = args.next().unwrap().into_bytes();
+				} else if arg == "--stages" {
+					let names = args.next().unwrap();
+					let stages: Vec<RunStage> = names
+						.split(',')
+						.map(|name| {
+							RunStage::from_name(name)
+								.filter(|&stage| stage != RunStage::Jit)
+								.unwrap_or_else(|| {
+									panic!(
+										"unknown traceable stage `{}` (expected `raw`, `soup`, \
+										`graph` or `bytecode`)",
+										name
+									)
+								})
+						})
+						.collect();
+					options.stages = stages.try_into().unwrap_or_else(|_| {
+						panic!("`--stages` expects two stages (such as `raw,soup`)")
+					});
+				} else if arg == "--max-steps" {
+					options.max_steps = args.next().unwrap().parse().unwrap();
+				} else {
+					panic!(
+						"unknown cmdline argument `{}` (for differential tracing)",
+						arg
+					);
+				}
+			} else if let WhatToDo::Bisect { ref mut input } = settings.what_to_do {
+				if arg == "-i" || arg == "--input" {
+					*input = args.next();
+				} else {
+					panic!("unknown cmdline argument `{}` (for bisection)", arg);
+				}
+			} else if let WhatToDo::Check {
+				ref mut termination,
+				ref mut max_steps,
+				ref mut fix,
+			} = settings.what_to_do
+			{
+				if arg == "--termination" {
+					*termination = true;
+				} else if arg == "--fix" {
+					*fix = true;
+				} else if arg == "--max-steps" {
+					*max_steps = args.next().unwrap().parse().unwrap();
+				} else {
+					panic!("unknown cmdline argument `{}` (for checking)", arg);
+				}
+			} else if let WhatToDo::Explore { ref mut options } = settings.what_to_do {
+				if arg == "--max-input-len" {
+					options.max_input_len = args.next().unwrap().parse().unwrap();
+				} else if arg == "--max-steps" {
+					options.max_steps_per_path = args.next().unwrap().parse().unwrap();
+				} else if arg == "--max-paths" {
+					options.max_paths = args.next().unwrap().parse().unwrap();
+				} else if arg == "--target" {
+					options.target = Some(args.next().unwrap().into_bytes());
+				} else {
+					panic!("unknown cmdline argument `{}` (for exploration)", arg);
+				}
+			} else if let WhatToDo::Conformance { ref mut options } = settings.what_to_do {
+				if arg == "--corpus" {
+					options.corpus_dir = args.next().unwrap().into();
+				} else if arg == "--backend" {
+					options.backend_names.push(args.next().unwrap());
+				} else if arg == "--bless" {
+					options.bless = true;
+				} else {
+					panic!(
+						"unknown cmdline argument `{}` (for conformance testing)",
+						arg
+					);
+				}
+			} else if let WhatToDo::Bench { ref mut options } = settings.what_to_do {
+				if arg == "--corpus" {
+					options.corpus_dir = args.next().unwrap().into();
+				} else if arg == "--runs" {
+					options.runs = args
+						.next()
+						.unwrap()
+						.parse()
+						.ok()
+						.filter(|&runs| runs > 0)
+						.unwrap_or_else(|| panic!("`--runs` expects a positive integer"));
+				} else if arg == "--baseline" {
+					options.baseline = Some(args.next().unwrap().into());
+				} else if arg == "--save" {
+					options.save = Some(args.next().unwrap().into());
+				} else if arg == "--fail-on-regression" {
+					let threshold = args.next().unwrap();
+					options.fail_on_regression = Some(
+						threshold
+							.strip_suffix('%')
+							.unwrap_or(&threshold)
+							.parse()
+							.ok()
+							.filter(|&threshold: &f64| threshold >= 0.0)
+							.unwrap_or_else(|| {
+								panic!(
+									"`--fail-on-regression` expects a percentage (such as `5%`)"
+								)
+							}),
+					);
+				} else {
+					panic!("unknown cmdline argument `{}` (for benchmarking)", arg);
+				}
+			} else if let WhatToDo::Golden { ref mut options } = settings.what_to_do {
+				if arg == "--corpus" {
+					options.corpus_dir = args.next().unwrap().into();
+				} else if arg == "--bless" {
+					options.bless = true;
+				} else {
+					panic!("unknown cmdline argument `{}` (for golden testing)", arg);
+				}
+			} else if let WhatToDo::Debug { ref mut core } = settings.what_to_do {
+				if arg == "--core" {
+					*core = Some(args.next().unwrap());
+				} else {
+					panic!("unknown cmdline argument `{}` (for debugging)", arg);
+				}
+			} else if let WhatToDo::Build { ref mut manifest }
+			| WhatToDo::Test { ref mut manifest } = settings.what_to_do
+			{
+				if arg == "--manifest" {
+					*manifest = args.next().expect("`--manifest` expects a file path");
+				} else {
+					panic!("unknown cmdline argument `{}` (for a project)", arg);
+				}
+			} else if let WhatToDo::Gen {
+				ref mut number,
+				ref mut radix,
+			} = settings.what_to_do
+			{
+				if arg == "--number" {
+					*number = Some(args.next().unwrap().parse().unwrap_or_else(|_| {
+						panic!("`--number` expects an integer from 0 to 255 (a cell value)")
+					}));
+				} else if arg == "--radix" {
+					*radix = args
+						.next()
+						.unwrap()
+						.parse()
+						.ok()
+						.filter(|radix| (2..=10).contains(radix))
+						.unwrap_or_else(|| panic!("`--radix` expects an integer from 2 to 10"));
+				} else {
+					panic!("unknown cmdline argument `{}` (for code generation)", arg);
+				}
+			} else if let WhatToDo::VerifyTarget { ref mut options } = settings.what_to_do {
+				if arg == "--inputs" {
+					options.inputs_dir = Some(args.next().unwrap().into());
+				} else {
+					panic!(
+						"unknown cmdline argument `{}` (for target verification)",
+						arg
+					);
+				}
+			} else if let WhatToDo::ClearCache = settings.what_to_do {
+				panic!(
+					"unknown cmdline argument `{}` (for clearing the cache)",
+					arg
+				);
 			} else {
 				unreachable!();
 			}
 		}
+		if let Err(error) = settings.extensions.check() {
+			panic!("{}", error);
+		}
+		if let WhatToDo::Explore { ref mut options } = settings.what_to_do {
+			options.machine = settings.tape_config.machine.clone();
+		}
+		if let WhatToDo::VerifyTarget { ref mut options } = settings.what_to_do {
+			options.c_options = settings.c_options.clone();
+		}
+		if let Some(head_start) = head_start {
+			settings.tape_config.head_start = match settings.tape_config.region(&head_start) {
+				Some(region) => region.cells.start,
+				None => head_start.parse().unwrap_or_else(|_| {
+					panic!("`--head-start` expects a cell index or a tape region name")
+				}),
+			};
+		}
+		let uses_jit =
+			settings.jit_threshold.is_some() || settings.run_stage == Some(RunStage::Jit);
+		if uses_jit && !jit::AVAILABLE {
+			panic!("The JIT is not available on this system")
+		}
+		match settings.run_stage {
+			Some(RunStage::Jit) => {
+				settings.jit_threshold = settings.jit_threshold.or(Some(DEFAULT_JIT_THRESHOLD));
+			}
+			Some(_) if settings.jit_threshold.is_some() => {
+				panic!("`--jit` can only be used with `--run-stage jit`")
+			}
+			Some(RunStage::Raw) => settings.optimize = false,
+			Some(_)
+				if !settings.watches.is_empty()
+					|| settings.core_dump.is_some()
+					|| settings.error_context.is_some()
+					|| settings.profile_loops =>
+			{
+				panic!(
+					"`--watch`, `--core-dump`, `--error-context` and `--profile-loops` need \
+					`--run-stage raw`"
+				)
+			}
+			_ => (),
+		}
+		if !settings.watches.is_empty()
+			|| settings.core_dump.is_some()
+			|| settings.error_context.is_some()
+			|| settings.profile_loops
+		{
+			// Watchpoints are hit after instructions of the source code, core dumps and error
+			// contexts tell which instructions ran last, and loop profiles are about the loops of
+			// the source code.
+			settings.optimize = false;
+		}
+		if line_directives {
+			// `#line` directives refer to the source file, if any.
+			settings.c_options.line_directives = Some(match &settings.src {
+				SrcSettings::FilePath(src_file_path) => src_file_path.clone(),
+				_ => "<src>".to_owned(),
+			});
+		}
 		settings
 	}
 }
 
-#[derive(Debug)]
-enum Prog {
-	Raw(Vec<astraw::RawInstr>),
-	Soup(Vec<astsoup::SoupInstr>),
-}
-
 fn main() {
-	let settings = Settings::from_cmdline_args();
-	if settings.verbose {
-		dbg!(&settings);
-	}
+	let mut settings = Settings::from_cmdline_args();
+	xxbf::log::set_verbosity(settings.verbosity);
+	xxbf::log::set_json(settings.log_json);
+	event(Level::Trace, "settings")
+		.field("settings", format!("{:?}", settings))
+		.emit();
 	if settings.help {
 		println!("Help comming soon.");
 	}
 
+	#[cfg(any(
+		all(target_os = "linux", target_pointer_width = "64"),
+		target_os = "macos"
+	))]
+	if let (Some(isolate_position), WhatToDo::Interpret { .. }) =
+		(settings.isolate, &settings.what_to_do)
+	{
+		use xxbf::isolate::{ResourceLimits, run_isolated};
+		// The child is this very command without `--isolate` (but with the other arguments that
+		// happen to be `--isolate`, such as an input).
+		let exe = std::env::current_exe().expect("cannot find the path of the executable");
+		let mut command = std::process::Command::new(exe);
+		command.args(
+			std::env::args_os()
+				.enumerate()
+				.filter(|&(position, _)| position != isolate_position)
+				.skip(1)
+				.map(|(_, arg)| arg),
+		);
+		let run = run_isolated(&mut command, &ResourceLimits::default())
+			.unwrap_or_else(|error| panic!("cannot run the isolated child process: {}", error));
+		eprintln!("Isolated run {}.", run);
+		std::process::exit(run.exit_code());
+	}
+
+	if let WhatToDo::Conformance { options } = &settings.what_to_do {
+		let results = run_conformance(options, |result| {
+			let verdict = match &result.verdict {
+				Verdict::Pass => "pass".to_owned(),
+				Verdict::Fail { expected, actual } => format!(
+					"FAIL, expected output {:?}, actual output {:?}",
+					String::from_utf8_lossy(expected),
+					String::from_utf8_lossy(actual)
+				),
+				Verdict::Error(error) => format!("ERROR, {}", error),
+				Verdict::GoldenMatch => "golden match".to_owned(),
+				Verdict::GoldenMismatch => "GOLDEN MISMATCH".to_owned(),
+				Verdict::GoldenWritten => "golden written".to_owned(),
+				Verdict::Skipped => "skipped (no toolchain nor golden file)".to_owned(),
+			};
+			println!(
+				"{} {} {}: {}",
+				result.backend_name,
+				result.program_name,
+				if result.optimized { "-O1" } else { "-O0" },
+				verdict
+			);
+		})
+		.unwrap_or_else(|error| panic!("{}", error));
+		let failures = results
+			.iter()
+			.filter(|result| result.verdict.is_failure())
+			.count();
+		println!("{} cases, {} failures.", results.len(), failures);
+		if failures > 0 {
+			std::process::exit(1);
+		}
+		return;
+	}
+
+	if let WhatToDo::Bench { options } = &settings.what_to_do {
+		if options.fail_on_regression.is_some() && options.baseline.is_none() {
+			panic!("`--fail-on-regression` needs a baseline given with `--baseline`");
+		}
+		let results = run_benchmarks(options, |result| {
+			let time_ms = |time: std::time::Duration| time.as_secs_f64() * 1000.0;
+			match (result.baseline_time, result.change()) {
+				(Some(baseline_time), Some(change)) => println!(
+					"{}: {:.3} ms (baseline {:.3} ms, {:+.1}%){}",
+					result.name,
+					time_ms(result.time),
+					time_ms(baseline_time),
+					change,
+					if result.regressed { " REGRESSION" } else { "" }
+				),
+				_ => println!("{}: {:.3} ms", result.name, time_ms(result.time)),
+			}
+		})
+		.unwrap_or_else(|error| panic!("{}", error));
+		let regressions = results.iter().filter(|result| result.regressed).count();
+		println!("{} benchmarks, {} regressions.", results.len(), regressions);
+		if regressions > 0 {
+			std::process::exit(1);
+		}
+		return;
+	}
+
+	if let WhatToDo::Golden { options } = &settings.what_to_do {
+		let results = run_golden(options, |result| {
+			let verdict = match &result.verdict {
+				GoldenVerdict::Match => "match".to_owned(),
+				GoldenVerdict::Mismatch(diff) => format!("MISMATCH\n{}", diff.trim_end()),
+				GoldenVerdict::Written => "written".to_owned(),
+				GoldenVerdict::Missing => "MISSING (bless to write the snapshot)".to_owned(),
+			};
+			println!("{}: {}", result.program_name, verdict);
+		})
+		.unwrap_or_else(|error| panic!("{}", error));
+		let failures = results
+			.iter()
+			.filter(|result| result.verdict.is_failure())
+			.count();
+		println!("{} cases, {} failures.", results.len(), failures);
+		if failures > 0 {
+			std::process::exit(1);
+		}
+		return;
+	}
+
+	if let WhatToDo::Debug { core } = &settings.what_to_do {
+		let core_path = core
+			.as_deref()
+			.expect("`debug` expects a core dump file given with `--core`");
+		let text = std::fs::read_to_string(core_path)
+			.unwrap_or_else(|error| panic!("cannot read `{}`: {}", core_path, error));
+		let core_dump = CoreDump::from_json(&text)
+			.unwrap_or_else(|error| panic!("invalid core dump `{}`: {}", core_path, error));
+		print!("{}", core_dump.report());
+		return;
+	}
+
+	if let WhatToDo::Build { manifest } = &settings.what_to_do {
+		let manifest =
+			Manifest::load(Path::new(manifest)).unwrap_or_else(|error| panic!("{}", error));
+		build_project(&manifest, |program, target, path| {
+			println!("Built {} ({}) in {}", program.name, target, path.display());
+		})
+		.unwrap_or_else(|error| panic!("{}", error));
+		return;
+	}
+
+	if let WhatToDo::Test { manifest } = &settings.what_to_do {
+		let manifest =
+			Manifest::load(Path::new(manifest)).unwrap_or_else(|error| panic!("{}", error));
+		let results = test_project(&manifest, |result| {
+			let verdict = match &result.verdict {
+				Verdict::Pass => "pass".to_owned(),
+				Verdict::Fail { expected, actual } => format!(
+					"FAIL, expected output {:?}, actual output {:?}",
+					String::from_utf8_lossy(expected),
+					String::from_utf8_lossy(actual)
+				),
+				Verdict::Error(error) => format!("ERROR, {}", error),
+				Verdict::Skipped => "skipped (no toolchain)".to_owned(),
+				Verdict::GoldenMatch | Verdict::GoldenMismatch | Verdict::GoldenWritten => {
+					unreachable!()
+				}
+			};
+			println!(
+				"{} {} {}: {}",
+				result.program_name, result.test_name, result.runner, verdict
+			);
+		})
+		.unwrap_or_else(|error| panic!("{}", error));
+		let failures = results
+			.iter()
+			.filter(|result| result.verdict.is_failure())
+			.count();
+		println!("{} cases, {} failures.", results.len(), failures);
+		if failures > 0 {
+			std::process::exit(1);
+		}
+		return;
+	}
+
+	if let WhatToDo::Gen { number, radix } = settings.what_to_do {
+		let number = number.expect("`gen` expects `--number`");
+		println!("{}", print_number(number, radix, &CostModel::default()));
+		return;
+	}
+
+	if let WhatToDo::ClearCache = settings.what_to_do {
+		let removed =
+			cache::clear().unwrap_or_else(|error| panic!("cannot clear the cache: {}", error));
+		println!("Removed {} cached programs.", removed);
+		return;
+	}
+
+	let src_file = match &settings.src {
+		SrcSettings::FilePath(src_file_path) => Some(src_file_path.clone()),
+		_ => None,
+	};
 	let src_code = match settings.src {
 		SrcSettings::Src(src_code) => src_code,
 		SrcSettings::FilePath(src_file_path) => std::fs::read_to_string(src_file_path).expect("h"),
@@ -112,62 +1110,852 @@ fn main() {
 			return;
 		}
 	};
-	if settings.verbose {
-		dbg!(&src_code);
+	let src_code = if settings.extensions.include {
+		expand_includes(&src_code).unwrap_or_else(|error| panic!("{}", error))
+	} else {
+		src_code
+	};
+	event(Level::Trace, "src")
+		.field("bytes", src_code.len())
+		.field("src", &src_code)
+		.emit();
+
+	// The program is only run on its tape (or compiled for it), so the passes can rely on it.
+	settings.pass_manager.tape_config = Some(settings.tape_config.clone());
+
+	// The cache is not used when the optimization is to be observed (or when the warnings depend
+	// on the tape configuration), nor with passes from plugins (that the cache key does not know).
+	let use_cache = settings.cache
+		&& settings.optimize
+		&& matches!(
+			settings.what_to_do,
+			WhatToDo::Interpret { .. } | WhatToDo::Compile { report: None, .. }
+		) && settings.tape_config.is_default()
+		&& settings.pass_manager.print_ir_after.is_empty()
+		&& !settings.pass_manager.print_ir_diff
+		&& !settings.pass_manager.time_passes
+		&& settings.pass_manager.custom_passes.is_empty()
+		// What is optimized within a time budget depends on the machine and its load.
+		&& settings.pass_manager.time_budget.is_none();
+	let cache_key = if use_cache {
+		Some(cache::cache_key(
+			&src_code,
+			&settings.extensions,
+			&settings.pass_manager,
+		))
+	} else {
+		None
+	};
+	let start = Instant::now();
+	let cached = cache_key.and_then(cache::load);
+	event(Level::Info, "cache")
+		.field("enabled", cache_key.is_some())
+		.field("hit", cached.is_some())
+		.emit();
+
+	let cached_warnings = cached.as_ref().map(|entry| entry.warnings.clone());
+	let mut program = match cached {
+		Some(entry) => Program::from_optimized(&src_code, &settings.extensions, entry.soup_prog),
+		None => match Program::parse(&src_code, &settings.extensions) {
+			Ok(program) => program,
+			Err(error_vec) => {
+				let fixed = match settings.what_to_do {
+					WhatToDo::Check { fix: true, .. } => {
+						if settings.extensions.include
+							|| settings.extensions.frontend.name() != "brainfuck"
+						{
+							panic!("`--fix` only fixes brainfuck source code without includes");
+						}
+						Some(fix(&src_code, &error_vec))
+					}
+					_ => None,
+				};
+				for error in error_vec {
+					error.print(&src_code, None, true);
+				}
+				if let Some(fixed) = fixed {
+					write_fix(&src_code, src_file.as_deref(), fixed, &settings.extensions);
+				}
+				return;
+			}
+		},
+	};
+	let parse_time = start.elapsed();
+	if settings.pass_manager.time_passes {
+		print_phase_time("parse", parse_time, program.instr_count(), "instrs");
+	}
+	event(Level::Info, "parse")
+		.field("instrs", program.instr_count())
+		.field("time_ms", start.elapsed().as_secs_f64() * 1000.0)
+		.emit();
+	if xxbf::log::enabled(Level::Trace) {
+		event(Level::Trace, "ir")
+			.field("ir", program.ir_text())
+			.emit();
+	}
+	program
+		.set_tape_config(settings.tape_config)
+		.unwrap_or_else(|error| panic!("{}", error));
+	program.set_max_unrolled_body_len(settings.max_unrolled_body_len);
+	let warnings = cached_warnings.unwrap_or_else(|| program.warnings());
+	if settings.warnings {
+		for warning in &warnings {
+			warning.print(&src_code, None, true);
+		}
 	}
+	let raw_instrs = program.instr_count();
 
-	let parsing_result = parser::parse_instr_seq(&src_code);
-	let mut prog = Prog::Raw(match parsing_result {
-		Ok(prog) => prog,
-		Err(error_vec) => {
-			for error in error_vec {
-				error.print(&src_code, None, true);
+	if let WhatToDo::Bisect { input } = &settings.what_to_do {
+		let raw_prog = match program.stage() {
+			Stage::Raw(raw_prog) => raw_prog,
+			_ => unreachable!(),
+		};
+		let input = input.as_deref().unwrap_or("").as_bytes();
+		match bisect(raw_prog, &settings.pass_manager, input) {
+			BisectResult::NoDifference { transformations } => println!(
+				"No difference in output with all the {} transformations applied.",
+				transformations
+			),
+			BisectResult::BadSoupify { outputs } => {
+				println!("The output already differs with no transformation applied (soupify).");
+				print_differential_outputs(&outputs.expected, &outputs.actual);
+			}
+			BisectResult::BadTransformation {
+				index,
+				pass_name,
+				outputs,
+			} => {
+				println!(
+					"The output differs starting with transformation {} (from pass {}), see it \
+					with `--opt-fuel {} --print-ir-after={}`.",
+					index, pass_name, index, pass_name
+				);
+				print_differential_outputs(&outputs.expected, &outputs.actual);
 			}
-			return;
 		}
-	});
-	if settings.verbose {
-		dbg!(&prog);
+		return;
+	}
+
+	if let WhatToDo::DiffTrace { options } = &settings.what_to_do {
+		let raw_prog = match program.stage() {
+			Stage::Raw(raw_prog) => raw_prog,
+			_ => unreachable!(),
+		};
+		// The errors the engines stop on are reported with the divergence.
+		std::panic::set_hook(Box::new(|_| {}));
+		let stage_names = options
+			.stages
+			.map(|stage| format!("{:?}", stage).to_lowercase());
+		match diff_trace(raw_prog, &settings.pass_manager, options) {
+			DiffTrace::Agree {
+				outputs,
+				complete: true,
+			} => println!(
+				"The {} and {} stages agree on all the {} bytes output and on the final tape.",
+				stage_names[0], stage_names[1], outputs
+			),
+			DiffTrace::Agree {
+				outputs,
+				complete: false,
+			} => println!(
+				"The {} and {} stages agree on the {} bytes output before the run stopped (on an \
+				error or after `--max-steps` steps).",
+				stage_names[0], stage_names[1], outputs
+			),
+			DiffTrace::Diverge(divergence) => {
+				println!(
+					"The tapes diverge after {} bytes output:",
+					divergence.outputs
+				);
+				for (stage_name, event) in stage_names.iter().zip(&divergence.events) {
+					match event {
+						Event::Output(char_value) => {
+							println!("  {}: about to output {}", stage_name, char_value)
+						}
+						Event::Halt => println!("  {}: halts", stage_name),
+						Event::Error(error) => println!("  {}: stops ({})", stage_name, error),
+					}
+				}
+				for (index, values) in &divergence.cells {
+					println!(
+						"  cell {}: {} in {}, {} in {}",
+						index, values[0], stage_names[0], values[1], stage_names[1]
+					);
+				}
+				if let Some(raw_instrs) = &divergence.raw_instrs {
+					let spans = instr_spans(&src_code, &settings.extensions);
+					let (start_line, start_column) =
+						line_and_column(&src_code, spans[raw_instrs.start].start);
+					let (end_line, end_column) =
+						line_and_column(&src_code, spans[raw_instrs.end - 1].start);
+					println!(
+						"Source code run since the last agreement: line {} column {} to line {} \
+						column {}.",
+						start_line, start_column, end_line, end_column
+					);
+				}
+				if !divergence.soup_instrs.is_empty() {
+					println!("Soup instructions run since the last agreement:");
+					for (index, text) in &divergence.soup_instrs {
+						println!("  {}: {}", index, text);
+					}
+				}
+			}
+		}
+		return;
+	}
+
+	if let WhatToDo::Check {
+		termination,
+		max_steps,
+		..
+	} = settings.what_to_do
+	{
+		let raw_prog = match program.stage() {
+			Stage::Raw(raw_prog) => raw_prog,
+			_ => unreachable!(),
+		};
+		if termination {
+			let spans = instr_spans(&src_code, &settings.extensions);
+			let report = check_termination(raw_prog);
+			for loop_verdict in report.loops {
+				let (line, column) =
+					line_and_column(&src_code, spans[loop_verdict.loop_index].start);
+				println!(
+					"Loop on line {} column {}: {} ({})",
+					line, column, loop_verdict.verdict, loop_verdict.reason
+				);
+			}
+			println!("Program: {}", report.program);
+		}
+		let io_signature = program.io_signature();
+		if io_signature.reads_input {
+			println!(
+				"Input: read ({}), the output {} on it",
+				match io_signature.max_input_len {
+					Some(max_input_len) => format!("at most {} bytes", max_input_len),
+					None => "no bound found".to_owned(),
+				},
+				if io_signature.output_depends_on_input {
+					"may depend"
+				} else {
+					"does not depend"
+				}
+			);
+		} else {
+			println!("Input: not read");
+		}
+		let tape_config = program.tape_config();
+		let tape_is_default = tape_config.initial_tape.is_empty() && tape_config.head_start == 0;
+		match predict_output(raw_prog, max_steps, &tape_config.machine) {
+			_ if !tape_is_default => {
+				println!("Output: not predicted (the tape does not start with zeros)")
+			}
+			OutputPrediction::Exact(output) => {
+				let output: Vec<_> = output.into_iter().map(OutputByte::Known).collect();
+				println!(
+					"Output ({} bytes): \"{}\"",
+					output.len(),
+					output_to_text(&output)
+				);
+			}
+			OutputPrediction::DependsOnInput => {
+				println!(
+					"Output: not predicted (the program reads input or random bytes or calls the \
+					 host)"
+				)
+			}
+			OutputPrediction::Unknown => println!(
+				"Output: not predicted (the program does not end within {} steps or runs off the \
+				tape)",
+				max_steps
+			),
+		}
+		return;
 	}
 
-	if settings.optimize {
-		prog = Prog::Soup(astsoup::soupify(match prog {
-			Prog::Raw(ref raw_prog) => raw_prog,
-			_ => panic!("xxbf bug"),
-		}));
-		if settings.verbose {
-			dbg!(&prog);
+	if let WhatToDo::Explore { options } = &settings.what_to_do {
+		let raw_prog = match program.stage() {
+			Stage::Raw(raw_prog) => raw_prog,
+			_ => unreachable!(),
+		};
+		let exploration = explore(raw_prog, options);
+		println!(
+			"Explored {} paths on inputs of length at most {}{}.",
+			exploration.paths,
+			options.max_input_len,
+			if exploration.complete {
+				""
+			} else {
+				" (incomplete, some paths were cut by a limit or ran off the tape)"
+			}
+		);
+		match &options.target {
+			Some(_) => match exploration.outputs.first() {
+				Some(reachable) => println!(
+					"Target output reachable with input {:?}.",
+					String::from_utf8_lossy(&reachable.input)
+				),
+				None if exploration.complete => println!("Target output unreachable."),
+				None => println!("Target output not found."),
+			},
+			None => {
+				for reachable in exploration.outputs {
+					println!(
+						"Output \"{}\" with input {:?}",
+						output_to_text(&reachable.output),
+						String::from_utf8_lossy(&reachable.input)
+					);
+				}
+			}
+		}
+		return;
+	}
+
+	let mut pass_stats = Vec::new();
+	let mut optimize_time = Duration::ZERO;
+	if settings.optimize && matches!(program.stage(), Stage::Raw(_)) {
+		let start = Instant::now();
+		match program.optimize_with_stats(1, &settings.pass_manager) {
+			Ok(stats) => pass_stats = stats,
+			Err(error) => {
+				eprintln!("{}", error);
+				std::process::exit(1);
+			}
+		}
+		optimize_time = start.elapsed();
+		if let (Some(key), Stage::Soup(soup_prog)) = (cache_key, program.stage()) {
+			cache::store(key, soup_prog, &warnings);
+		}
+		event(Level::Info, "optimize")
+			.field("instrs", program.instr_count())
+			.field("time_ms", optimize_time.as_secs_f64() * 1000.0)
+			.emit();
+		if xxbf::log::enabled(Level::Trace) {
+			event(Level::Trace, "ir")
+				.field("ir", program.ir_text())
+				.emit();
+		}
+	}
+
+	if settings.print_ir
+		|| settings.print_ir_hash
+		|| settings.print_bytecode
+		|| settings.dump_bytecode
+	{
+		if settings.print_ir {
+			print!("{}", program.ir_text());
+		}
+		if settings.print_ir_hash {
+			println!("{:016x}", program.ir_hash());
 		}
+		if settings.print_bytecode {
+			print!("{}", print_bytecode(&program.to_bytecode()));
+		}
+		if settings.dump_bytecode {
+			print!("{}", dump_bytecode(&program.to_bytecode()));
+		}
+		return;
+	}
+
+	if let WhatToDo::VerifyTarget { options } = &settings.what_to_do {
+		let results = verify_target(&program, options, |result| {
+			let verdict = match &result.verdict {
+				Verdict::Pass => "pass".to_owned(),
+				Verdict::Fail { expected, actual } => {
+					let first_difference = expected
+						.iter()
+						.zip(actual)
+						.take_while(|(expected, actual)| expected == actual)
+						.count();
+					format!(
+						"FAIL, outputs differ from byte {}, interpreter output {:?}, compiled \
+						program output {:?}",
+						first_difference,
+						String::from_utf8_lossy(expected),
+						String::from_utf8_lossy(actual)
+					)
+				}
+				Verdict::Error(error) => format!("ERROR, {}", error),
+				_ => unreachable!(),
+			};
+			println!("{}: {}", result.input_name, verdict);
+		})
+		.unwrap_or_else(|error| panic!("{}", error));
+		let failures = results
+			.iter()
+			.filter(|result| result.verdict.is_failure())
+			.count();
+		println!("{} inputs, {} failures.", results.len(), failures);
+		if failures > 0 {
+			std::process::exit(1);
+		}
+		return;
+	}
+
+	if let (true, WhatToDo::Interpret { input }) =
+		(settings.determinism_check, &settings.what_to_do)
+	{
+		let input = input.as_deref().unwrap_or("").as_bytes();
+		let (records, differences) = check_determinism(
+			&src_code,
+			&settings.extensions,
+			program.tape_config(),
+			&settings.pass_manager,
+			settings.optimize,
+			input,
+		)
+		.unwrap_or_else(|error| panic!("{}", error));
+		for (index, record) in records.iter().enumerate() {
+			println!(
+				"Run {}: IR hash {:016x}, {} output bytes, {} steps, head at cell {}, {} used \
+				cells.",
+				index + 1,
+				record.ir_hash,
+				record.output.len(),
+				record.steps,
+				record.head,
+				record.tape.len()
+			);
+		}
+		if differences.is_empty() {
+			println!("Deterministic.");
+		} else {
+			println!("Nondeterministic: the {} differ.", differences.join(", "));
+			std::process::exit(1);
+		}
+		return;
 	}
 
 	match settings.what_to_do {
-		WhatToDo::Interpret { input } => {
+		WhatToDo::Interpret { mut input } => {
+			if settings.sandbox {
+				if settings.jit_threshold.is_some() {
+					panic!("`--sandbox` cannot be used with the JIT");
+				}
+				if settings.line_input || settings.paged_output.is_some() || settings.fps.is_some()
+				{
+					panic!(
+						"`--sandbox` cannot be used with `--line-input`, `--paged-output` nor \
+						`--fps`"
+					);
+				}
+				if matches!(
+					settings.run_stage,
+					Some(RunStage::Soup | RunStage::Graph | RunStage::Jit)
+				) {
+					panic!("`--sandbox` can only run with the `raw` or `bytecode` engine");
+				}
+				if matches!(
+					settings.stdin_policy,
+					Some(StdinPolicy::Prompt | StdinPolicy::Silent)
+				) {
+					panic!("`--sandbox` can only be used with `--stdin-policy closed`");
+				}
+				// The program never waits for the user, reading 0 once the input is exhausted.
+				let input = input.get_or_insert_with(String::new);
+				Sandbox::default()
+					.check_input(input.as_bytes())
+					.unwrap_or_else(|error| panic!("{}", error));
+			}
+			if settings.line_input && settings.stdin_policy.is_some() {
+				panic!("`--line-input` cannot be used with `--stdin-policy`");
+			}
+			// Stdin is read by the VM when it is run without input, after the given input (if
+			// any) that is then given by an observer.
+			let mut stdin_fallback = None;
+			match settings
+				.stdin_policy
+				.unwrap_or_else(|| StdinPolicy::default_for(input.is_some()))
+			{
+				StdinPolicy::Closed => {
+					input.get_or_insert_with(String::new);
+				}
+				StdinPolicy::Prompt if input.is_none() => (),
+				policy => {
+					let given = input.take().unwrap_or_default().into_bytes();
+					stdin_fallback = Some(StdinFallback::new(given, policy));
+				}
+			}
 			let interact_with_user = input.is_some();
-			let input = input.map(|s| s.bytes().collect());
-			let output = match prog {
-				Prog::Raw(raw_prog) => vm::run_raw(raw_prog, input),
-				Prog::Soup(soup_prog) => vm::run_soup(soup_prog, input),
-			};
-			let output_string: String = output.iter().map(|&x| x as char).collect();
+			let input: Option<Vec<u8>> = input.map(|s| s.bytes().collect());
+			// The pager and the frame limiter print the output as it comes, even when an input
+			// is given.
+			let prints_output = settings.paged_output.is_some() || settings.fps.is_some();
+			// When an input is given, the output is printed as it comes (by the pager or the frame
+			// limiter if any) and what was read and output is reported if the run stops on an
+			// error.
+			let mut io_summary = IoSummary::new(
+				input.as_ref().map_or(0, |input| input.len()),
+				(!prints_output).then_some(settings.output_encoding),
+			);
+			let mut core_recorder = CoreRecorder::default();
+			let mut observers: Vec<Box<dyn Observer + '_>> = Vec::new();
 			if interact_with_user {
-				println!("{}", output_string);
+				observers.push(Box::new(&mut io_summary));
+			}
+			if let Some(stdin_fallback) = stdin_fallback {
+				observers.push(Box::new(stdin_fallback));
+			}
+			match (settings.paged_output, settings.fps) {
+				(Some(_), Some(_)) => {
+					panic!("`--paged-output` and `--fps` cannot be used together")
+				}
+				(Some(lines_per_page), None) => {
+					observers.push(Box::new(Pager::new(lines_per_page, interact_with_user)))
+				}
+				(None, Some(fps)) => {
+					observers.push(Box::new(FrameLimiter::new(fps, interact_with_user)))
+				}
+				(None, None) => (),
+			}
+			if settings.sandbox {
+				observers.push(Box::new(Sandbox::default()));
+			}
+			if let Some(max_output_len) = settings.max_output {
+				if settings.jit_threshold.is_some() {
+					panic!("`--max-output` cannot be used with the JIT");
+				}
+				observers.push(Box::new(RunLimits {
+					max_output_len: Some(max_output_len),
+					..RunLimits::default()
+				}));
+			}
+			if let Some(range) = settings.dump_tape {
+				observers.push(Box::new(TapeDumper::new(range)));
+			}
+			if settings.line_input {
+				if interact_with_user {
+					panic!("`--line-input` cannot be used with `-i`");
+				}
+				observers.push(Box::new(LineInput::new()));
+			}
+			if let Some(period) = settings.progress {
+				if settings.jit_threshold.is_some() {
+					panic!("`--progress` cannot be used with the JIT");
+				}
+				observers.push(Box::new(ProgressReporter::new(period)));
+			}
+			if settings.core_dump.is_some() {
+				if settings.jit_threshold.is_some() {
+					panic!("`--core-dump` cannot be used with the JIT");
+				}
+				observers.push(Box::new(&mut core_recorder));
+			}
+			if let Some(len) = settings.error_context {
+				if settings.jit_threshold.is_some() {
+					panic!("`--error-context` cannot be used with the JIT");
+				}
+				observers.push(Box::new(ErrorContext::new(
+					&src_code,
+					&settings.extensions,
+					len,
+				)));
+			}
+			if settings.profile_loops {
+				if settings.jit_threshold.is_some() {
+					panic!("`--profile-loops` cannot be used with the JIT");
+				}
+				let raw_prog = match program.stage() {
+					Stage::Raw(raw_prog) => raw_prog,
+					_ => unreachable!(),
+				};
+				observers.push(Box::new(LoopProfiler::new(
+					raw_prog,
+					&src_code,
+					&settings.extensions,
+				)));
+			}
+			let mut watchpoints = Watchpoints::new(settings.watches);
+			let jit_threshold = settings.jit_threshold;
+			let run_stage = settings.run_stage;
+			// The VM stops on errors by panicking, the core dump is written before the panic goes
+			// on. Exceeding the output limit is not an error, its panic is not reported.
+			let default_hook = std::panic::take_hook();
+			std::panic::set_hook(Box::new(move |info| {
+				if !info.payload().is::<OutputLimitExceeded>() {
+					default_hook(info);
+				}
+			}));
+			let start = Instant::now();
+			let run_result =
+				std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| match jit_threshold {
+					Some(_) if !watchpoints.watches().is_empty() => {
+						panic!("`--watch` cannot be used with the JIT")
+					}
+					None if !watchpoints.watches().is_empty() => {
+						let observer: Option<&mut dyn Observer> =
+							(!observers.is_empty()).then_some(&mut observers);
+						program.run_watched(input, &mut watchpoints, observer)
+					}
+					_ if run_stage.is_some() => {
+						let observer: Option<&mut dyn Observer> =
+							(!observers.is_empty()).then_some(&mut observers);
+						program.run_at_stage(
+							input,
+							run_stage.unwrap(),
+							jit_threshold.unwrap_or(DEFAULT_JIT_THRESHOLD),
+							observer,
+						)
+					}
+					_ if !observers.is_empty() => {
+						program.run_observed(input, jit_threshold, &mut observers)
+					}
+					Some(jit_threshold) => program.run_with_jit(input, jit_threshold),
+					None => program.run(input),
+				}));
+			drop(observers);
+			match run_result {
+				Ok(output) => {
+					event(Level::Info, "run")
+						.field("output_bytes", output.len())
+						.field("time_ms", start.elapsed().as_secs_f64() * 1000.0)
+						.emit();
+				}
+				Err(payload) if payload.is::<OutputLimitExceeded>() => {
+					if interact_with_user && prints_output {
+						println!();
+					}
+					let limit = payload.downcast_ref::<OutputLimitExceeded>().unwrap();
+					eprintln!(
+						"Output limit exceeded: the output was truncated to {} bytes.",
+						limit.max_output_len
+					);
+					std::process::exit(OUTPUT_LIMIT_EXIT_CODE);
+				}
+				Err(payload) => {
+					if interact_with_user {
+						eprintln!("The run stopped after {}.", io_summary.summary());
+					}
+					if let Some(core_path) = &settings.core_dump {
+						let error = payload
+							.downcast_ref::<String>()
+							.cloned()
+							.or_else(|| {
+								payload
+									.downcast_ref::<&str>()
+									.map(|&error| error.to_owned())
+							})
+							.unwrap_or_else(|| "unknown error".to_owned());
+						let core_dump = core_recorder.into_core_dump(
+							error,
+							src_file,
+							src_code,
+							&settings.extensions,
+						);
+						std::fs::write(core_path, core_dump.to_json()).unwrap_or_else(|error| {
+							panic!("cannot write `{}`: {}", core_path, error)
+						});
+						eprintln!("Core dump written to `{}`.", core_path);
+					}
+					std::panic::resume_unwind(payload);
+				}
+			}
+			if interact_with_user && prints_output {
+				println!();
+			}
+			if let Some(hit) = &watchpoints.hit {
+				let span = &instr_spans(&src_code, &settings.extensions)[hit.instr_index];
+				let (line, column) = line_and_column(&src_code, span.start);
+				println!(
+					"Watchpoint `{}` hit after the instruction on line {} column {}.",
+					watchpoints.watches()[hit.watch_index].text,
+					line,
+					column
+				);
 			}
 		}
+		WhatToDo::Bisect { .. }
+		| WhatToDo::DiffTrace { .. }
+		| WhatToDo::Check { .. }
+		| WhatToDo::Explore { .. }
+		| WhatToDo::Conformance { .. }
+		| WhatToDo::Bench { .. }
+		| WhatToDo::Golden { .. }
+		| WhatToDo::Debug { .. }
+		| WhatToDo::ClearCache
+		| WhatToDo::Gen { .. }
+		| WhatToDo::VerifyTarget { .. }
+		| WhatToDo::Build { .. }
+		| WhatToDo::Test { .. } => unreachable!(),
 		WhatToDo::Compile {
 			target,
 			dst_file_path,
+			report,
+			tape_init,
 		} => {
-			let output_code = match target {
-				CompileTarget::C => match prog {
-					Prog::Raw(raw_prog) => ctranspiler::transpile_raw_to_c(raw_prog),
-					Prog::Soup(soup_prog) => ctranspiler::transpile_soup_to_c(soup_prog),
-				},
+			if tape_init {
+				assert!(
+					matches!(target, CompileTarget::C),
+					"`--emit tape-init` is only supported when compiling to C"
+				);
+				assert!(
+					settings.optimize,
+					"`--emit tape-init` cannot be used with `-O0`"
+				);
+				// The instructions that run out of steps are left to the compiled program.
+				let default_hook = std::panic::take_hook();
+				std::panic::set_hook(Box::new(|_| {}));
+				let precomputed_instrs =
+					program.precompute_tape(xxbf::tapeinit::DEFAULT_MAX_STEPS);
+				std::panic::set_hook(default_hook);
+				let tape_config = program.tape_config();
+				eprintln!(
+					"Precomputed {} instructions: the tape starts with {} cells, the head on cell \
+					{}.",
+					precomputed_instrs,
+					tape_config.initial_tape.len(),
+					tape_config.head_start
+				);
+				if let Some(dst_file_path) = &dst_file_path {
+					// The tape is also given as a binary file, for `--init-tape`.
+					let tape_path = Path::new(dst_file_path).with_extension("tape");
+					std::fs::write(&tape_path, &tape_config.initial_tape).unwrap_or_else(
+						|error| panic!("cannot write `{}`: {}", tape_path.display(), error),
+					);
+				}
+			}
+			let codegen_target = match target {
+				CompileTarget::C => Target::C,
+				CompileTarget::Lua => Target::Lua,
+				CompileTarget::Sh => Target::Sh,
+				CompileTarget::Wat => Target::Wat,
+				CompileTarget::Java => {
+					// The public class of a Java source file is named after the file.
+					let class_name = dst_file_path
+						.as_ref()
+						.and_then(|path| Path::new(path).file_stem())
+						.and_then(|stem| stem.to_str())
+						.unwrap_or("Main");
+					Target::Java(class_name.to_owned())
+				}
+				CompileTarget::Arm64Asm(os) => Target::Arm64Asm(os),
+				CompileTarget::Object | CompileTarget::Executable => Target::Object,
 			};
-			if let Some(dst_file_path) = dst_file_path {
+			let codegen_config = CodegenConfig {
+				target: codegen_target,
+				c_options: settings.c_options.clone(),
+				tape_config: program.tape_config().clone(),
+			};
+			if let Target::C = codegen_config.target {
+				assert!(
+					codegen_config.tape_config.min_tape_len()
+						<= codegen_config.c_options.tape_size,
+					"the tape configuration does not fit in the tape ({} cells)",
+					codegen_config.c_options.tape_size
+				);
+			}
+			let start = Instant::now();
+			let output_code = program
+				.compile(&codegen_config)
+				.unwrap_or_else(|error| panic!("{}", error));
+			let codegen_time = start.elapsed();
+			if settings.pass_manager.time_passes {
+				print_phase_time("codegen", codegen_time, output_code.len(), "bytes");
+			}
+			if let Some(report_path) = report {
+				let report = CompileReport {
+					src_file,
+					target: format!("{:?}", target),
+					optimized: settings.optimize,
+					raw_instrs,
+					passes: pass_stats,
+					loops: count_loops(program.stage()),
+					warnings,
+					output_bytes: output_code.len(),
+					parse_time,
+					optimize_time,
+					codegen_time,
+				};
+				std::fs::write(&report_path, report.to_json(&src_code))
+					.unwrap_or_else(|error| panic!("cannot write `{}`: {}", report_path, error));
+			}
+			event(Level::Info, "codegen")
+				.field("target", format!("{:?}", target))
+				.field("bytes", output_code.len())
+				.field("time_ms", start.elapsed().as_secs_f64() * 1000.0)
+				.emit();
+			if let CompileTarget::Executable = target {
+				let executable_path =
+					dst_file_path.expect("compiling to an executable requires `-o`");
+				let object_path = format!("{}.o", executable_path);
+				std::fs::write(&object_path, output_code).expect("h");
+				let linked = link_executable(&object_path, &executable_path);
+				std::fs::remove_file(&object_path).ok();
+				if let Err(error) = linked {
+					panic!("{}", error);
+				}
+			} else if let Some(dst_file_path) = dst_file_path {
 				std::fs::write(dst_file_path, output_code).expect("h");
 			} else {
-				print!("{}", output_code);
+				std::io::Write::write_all(&mut std::io::stdout(), &output_code).unwrap();
 			}
 		}
 	}
 }
+
+/// Reports the repairs of `check --fix` with a preview of their diff, and writes the repaired
+/// source code next to the source file (`prog.b` giving `prog.fixed.b`), or prints it if there is
+/// no file.
+fn write_fix(src_code: &str, src_file: Option<&str>, fixed: Fix, extensions: &Extensions) {
+	for repair in &fixed.repairs {
+		let (description, pos) = match *repair {
+			Repair::Closed { pos } => ("Closed the unmatched `[`", pos),
+			Repair::Removed { pos } => ("Removed the unmatched bracket in a comment", pos),
+		};
+		let (line, column) = line_and_column(src_code, pos);
+		println!("{} on line {} column {}.", description, line, column);
+	}
+	if !fixed.unrepaired.is_empty() {
+		println!(
+			"Cannot fix {} of the errors, nothing was written.",
+			fixed.unrepaired.len()
+		);
+		return;
+	}
+	if Program::parse(&fixed.src_code, extensions).is_err() {
+		println!("The fixed source code still does not parse, nothing was written.");
+		return;
+	}
+	let Some(src_file) = src_file else {
+		println!("{}", fixed.src_code.trim_end_matches('\n'));
+		return;
+	};
+	let path = Path::new(src_file);
+	let stem = path.file_stem().unwrap().to_string_lossy();
+	let fixed_path = path.with_file_name(match path.extension() {
+		Some(extension) => format!("{}.fixed.{}", stem, extension.to_string_lossy()),
+		None => format!("{}.fixed", stem),
+	});
+	let fixed_name = fixed_path.to_string_lossy();
+	print!(
+		"{}",
+		unified_diff(src_file, &fixed_name, src_code, &fixed.src_code)
+	);
+	std::fs::write(&fixed_path, &fixed.src_code)
+		.unwrap_or_else(|error| panic!("cannot write `{}`: {}", fixed_name, error));
+	println!("Wrote the fixed source code to `{}`.", fixed_name);
+}
+
+fn print_differential_outputs(expected: &[u8], actual: &[u8]) {
+	println!("Expected output: {:?}", String::from_utf8_lossy(expected));
+	println!("Actual output:   {:?}", String::from_utf8_lossy(actual));
+}
+
+/// Parses bytes written in hexadecimal and separated by whitespace (such as `48 65 6c`).
+fn parse_hex_bytes(text: &str) -> Option<Vec<u8>> {
+	text.split_whitespace()
+		.map(|byte| u8::from_str_radix(byte, 16).ok())
+		.collect()
+}
+
+/// Parses a number of bytes with an optional `K`, `M` or `G` suffix (powers of 1024).
+fn parse_byte_size(text: &str) -> Option<usize> {
+	let (number, multiplier) = match text.chars().last()? {
+		'K' | 'k' => (&text[..text.len() - 1], 1 << 10),
+		'M' | 'm' => (&text[..text.len() - 1], 1 << 20),
+		'G' | 'g' => (&text[..text.len() - 1], 1 << 30),
+		_ => (text, 1),
+	};
+	number.parse::<usize>().ok()?.checked_mul(multiplier)
+}
@@ -0,0 +1,438 @@
+//! Native code generation for x86-64 Linux, from the linear intermediate representation (see
+//! `lowering`) to an ELF relocatable object file that can be linked into an executable by the
+//! system linker (`ld`) alone, without a C compiler or a libc: the code defines `_start` and does
+//! its input and output with system calls.
+//!
+//! The head is kept in `rbx` as a pointer to the cell under the head. The output is buffered in
+//! `r13` (buffer) and `r12` (number of bytes in the buffer), the buffer being flushed when full
+//! and where the lowering places flushes. As with the C backend, the tape has a fixed size and
+//! the head is not checked to stay within it, and reading at the end of the input gives 255.
+
+use crate::lowering::{LinearInstr, LinearProgram};
+
+/// Number of cells of the tape array of the generated program.
+pub const TAPE_SIZE: usize = 30000;
+
+const OUTPUT_BUFFER_SIZE: usize = 4096;
+
+/// Machine code being generated, with labels that can be jumped to before being placed.
+struct Assembler {
+	code: Vec<u8>,
+	/// Position of each label in the code, once placed.
+	labels: Vec<Option<usize>>,
+	/// Positions of 32-bit displacements to fill with the position of a label (relative to the
+	/// end of the displacement).
+	fixups: Vec<(usize, usize)>,
+}
+
+type Label = usize;
+
+impl Assembler {
+	fn bytes(&mut self, bytes: &[u8]) {
+		self.code.extend_from_slice(bytes);
+	}
+
+	fn i32(&mut self, value: i32) {
+		self.code.extend_from_slice(&value.to_le_bytes());
+	}
+
+	fn new_label(&mut self) -> Label {
+		self.labels.push(None);
+		self.labels.len() - 1
+	}
+
+	fn place_label(&mut self, label: Label) {
+		self.labels[label] = Some(self.code.len());
+	}
+
+	fn label_displacement(&mut self, label: Label) {
+		self.fixups.push((self.code.len(), label));
+		self.i32(0);
+	}
+
+	fn jump(&mut self, label: Label) {
+		self.bytes(&[0xe9]);
+		self.label_displacement(label);
+	}
+
+	/// Jumps to the label if the cell under the head is zero.
+	fn jump_if_zero(&mut self, label: Label) {
+		self.bytes(&[0x80, 0x3b, 0x00, 0x0f, 0x84]); // cmp byte [rbx], 0 ; je
+		self.label_displacement(label);
+	}
+
+	fn call(&mut self, label: Label) {
+		self.bytes(&[0xe8]);
+		self.label_displacement(label);
+	}
+
+	fn add(&mut self, offset: isize, value: u8) {
+		self.bytes(&[0x80, 0x83]); // add byte [rbx + offset], value
+		self.i32(offset as i32);
+		self.bytes(&[value]);
+	}
+
+	fn set(&mut self, offset: isize, value: u8) {
+		self.bytes(&[0xc6, 0x83]); // mov byte [rbx + offset], value
+		self.i32(offset as i32);
+		self.bytes(&[value]);
+	}
+
+	fn mul_add(&mut self, source: isize, target: isize, factor: u8) {
+		self.bytes(&[0x0f, 0xb6, 0x83]); // movzx eax, byte [rbx + source]
+		self.i32(source as i32);
+		if factor != 1 {
+			self.bytes(&[0x69, 0xc0]); // imul eax, eax, factor
+			self.i32(factor as i32);
+		}
+		self.bytes(&[0x00, 0x83]); // add byte [rbx + target], al
+		self.i32(target as i32);
+	}
+
+	fn move_head(&mut self, delta: isize) {
+		self.bytes(&[0x48, 0x81, 0xc3]); // add rbx, delta
+		self.i32(delta as i32);
+	}
+
+	fn output(&mut self, offset: isize, flush: Label) {
+		self.bytes(&[0x8a, 0x83]); // mov al, byte [rbx + offset]
+		self.i32(offset as i32);
+		self.bytes(&[0x43, 0x88, 0x44, 0x25, 0x00]); // mov byte [r13 + r12], al
+		self.bytes(&[0x49, 0xff, 0xc4]); // inc r12
+		self.bytes(&[0x49, 0x81, 0xfc]); // cmp r12, OUTPUT_BUFFER_SIZE
+		self.i32(OUTPUT_BUFFER_SIZE as i32);
+		self.bytes(&[0x75, 0x05]); // jne over the call
+		self.call(flush);
+	}
+
+	fn input(&mut self, offset: isize) {
+		self.bytes(&[0x48, 0x8d, 0xb3]); // lea rsi, [rbx + offset]
+		self.i32(offset as i32);
+		self.bytes(&[0x31, 0xc0]); // xor eax, eax (read)
+		self.bytes(&[0x31, 0xff]); // xor edi, edi (stdin)
+		self.bytes(&[0xba, 0x01, 0x00, 0x00, 0x00]); // mov edx, 1
+		self.bytes(&[0x0f, 0x05]); // syscall
+		self.bytes(&[0x48, 0x85, 0xc0]); // test rax, rax
+		self.bytes(&[0x7f, 0x03]); // jg over the mov
+		self.bytes(&[0xc6, 0x06, 0xff]); // mov byte [rsi], 255
+	}
+
+	/// Writes the output buffer to stdout and empties it.
+	fn emit_flush_function(&mut self, flush: Label) {
+		self.place_label(flush);
+		self.bytes(&[0x4d, 0x85, 0xe4]); // test r12, r12
+		self.bytes(&[0x74, 0x15]); // je to the ret
+		self.bytes(&[0xb8, 0x01, 0x00, 0x00, 0x00]); // mov eax, 1 (write)
+		self.bytes(&[0xbf, 0x01, 0x00, 0x00, 0x00]); // mov edi, 1 (stdout)
+		self.bytes(&[0x4c, 0x89, 0xee]); // mov rsi, r13
+		self.bytes(&[0x4c, 0x89, 0xe2]); // mov rdx, r12
+		self.bytes(&[0x0f, 0x05]); // syscall
+		self.bytes(&[0x45, 0x31, 0xe4]); // xor r12d, r12d
+		self.bytes(&[0xc3]); // ret
+	}
+
+	fn resolve_fixups(&mut self) {
+		for &(position, label) in &self.fixups {
+			let target = self.labels[label].unwrap();
+			let displacement = target as i64 - (position as i64 + 4);
+			self.code[position..position + 4]
+				.copy_from_slice(&(displacement as i32).to_le_bytes());
+		}
+	}
+}
+
+/// A reference from the code to the `.bss` section (tape and output buffer), the code being
+/// generated as if the section was at address 0.
+struct BssRelocation {
+	/// Position of the 32-bit displacement in the code.
+	position: usize,
+	/// Offset of the referenced data in the section.
+	bss_offset: usize,
+}
+
+pub fn emit_object(program: &LinearProgram) -> Vec<u8> {
+	let mut asm = Assembler {
+		code: Vec::new(),
+		labels: Vec::new(),
+		fixups: Vec::new(),
+	};
+	let loop_count = program
+		.instrs
+		.iter()
+		.filter(|instr| matches!(instr, LinearInstr::LoopStart(_)))
+		.count();
+	// The start and end labels of each loop.
+	let loop_labels: Vec<(Label, Label)> = (0..loop_count)
+		.map(|_| (asm.new_label(), asm.new_label()))
+		.collect();
+	let flush = asm.new_label();
+	let mut relocations = Vec::new();
+
+	asm.bytes(&[0x48, 0x8d, 0x1d]); // lea rbx, [rip + tape]
+	relocations.push(BssRelocation {
+		position: asm.code.len(),
+		bss_offset: 0,
+	});
+	asm.i32(0);
+	asm.bytes(&[0x4c, 0x8d, 0x2d]); // lea r13, [rip + output buffer]
+	relocations.push(BssRelocation {
+		position: asm.code.len(),
+		bss_offset: TAPE_SIZE,
+	});
+	asm.i32(0);
+	asm.bytes(&[0x45, 0x31, 0xe4]); // xor r12d, r12d
+
+	for instr in &program.instrs {
+		match *instr {
+			LinearInstr::Add { offset, value } => asm.add(offset, value),
+			LinearInstr::Set { offset, value } => asm.set(offset, value),
+			LinearInstr::MulAdd {
+				source,
+				target,
+				factor,
+			} => asm.mul_add(source, target, factor),
+			LinearInstr::Move(delta) => asm.move_head(delta),
+			LinearInstr::Output { offset } => asm.output(offset, flush),
+			LinearInstr::Input { offset } => asm.input(offset),
+			LinearInstr::Flush => asm.call(flush),
+			LinearInstr::LoopStart(id) => {
+				let (start, end) = loop_labels[id];
+				asm.place_label(start);
+				asm.jump_if_zero(end);
+			}
+			LinearInstr::LoopEnd(id) => {
+				let (start, end) = loop_labels[id];
+				asm.jump(start);
+				asm.place_label(end);
+			}
+		}
+	}
+	asm.bytes(&[0xb8, 0x3c, 0x00, 0x00, 0x00]); // mov eax, 60 (exit)
+	asm.bytes(&[0x31, 0xff]); // xor edi, edi
+	asm.bytes(&[0x0f, 0x05]); // syscall
+	asm.emit_flush_function(flush);
+	asm.resolve_fixups();
+
+	write_elf_object(&asm.code, TAPE_SIZE + OUTPUT_BUFFER_SIZE, &relocations)
+}
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_RELA: u32 = 4;
+const SHT_NOBITS: u32 = 8;
+const SHF_WRITE: u64 = 1;
+const SHF_ALLOC: u64 = 2;
+const SHF_EXECINSTR: u64 = 4;
+const SHF_INFO_LINK: u64 = 0x40;
+const R_X86_64_PC32: u64 = 2;
+
+struct SectionHeader {
+	name: &'static str,
+	kind: u32,
+	flags: u64,
+	/// Offset of the content in the file, and its size.
+	content: (usize, usize),
+	link: u32,
+	info: u32,
+	align: u64,
+	entry_size: u64,
+}
+
+/// Lays out an ELF64 relocatable object file with the code in `.text` (defining the global
+/// `_start` symbol at its beginning) and a zero-initialized `.bss` section of the given size.
+fn write_elf_object(code: &[u8], bss_size: usize, relocations: &[BssRelocation]) -> Vec<u8> {
+	// Index of the sections, the first one being the null section.
+	const TEXT: u32 = 1;
+	const BSS: u16 = 2;
+	const SYMTAB: u32 = 4;
+	const STRTAB: u32 = 5;
+
+	let mut file = vec![0; 64];
+	/// Appends the content (aligned) to the file, returns its offset and size.
+	fn append(file: &mut Vec<u8>, content: &[u8]) -> (usize, usize) {
+		while !file.len().is_multiple_of(8) {
+			file.push(0);
+		}
+		let offset = file.len();
+		file.extend_from_slice(content);
+		(offset, content.len())
+	}
+
+	let text = append(&mut file, code);
+
+	let mut rela = Vec::new();
+	for relocation in relocations {
+		rela.extend_from_slice(&(relocation.position as u64).to_le_bytes());
+		// Against the symbol of index 1, the `.bss` section.
+		rela.extend_from_slice(&((1 << 32) | R_X86_64_PC32).to_le_bytes());
+		// The displacement is relative to the end of the 4 bytes it takes.
+		rela.extend_from_slice(&(relocation.bss_offset as i64 - 4).to_le_bytes());
+	}
+	let rela = append(&mut file, &rela);
+
+	let strtab_content = b"\0_start\0";
+	let mut symtab = vec![0; 24];
+	// The `.bss` section symbol (local).
+	symtab.extend_from_slice(&0u32.to_le_bytes());
+	symtab.extend_from_slice(&[3, 0]);
+	symtab.extend_from_slice(&BSS.to_le_bytes());
+	symtab.extend_from_slice(&[0; 16]);
+	// The `_start` function (global).
+	symtab.extend_from_slice(&1u32.to_le_bytes());
+	symtab.extend_from_slice(&[(1 << 4) | 2, 0]);
+	symtab.extend_from_slice(&(TEXT as u16).to_le_bytes());
+	symtab.extend_from_slice(&0u64.to_le_bytes());
+	symtab.extend_from_slice(&(code.len() as u64).to_le_bytes());
+	let symtab = append(&mut file, &symtab);
+	let strtab = append(&mut file, strtab_content);
+
+	let mut sections = vec![
+		SectionHeader {
+			name: "",
+			kind: 0,
+			flags: 0,
+			content: (0, 0),
+			link: 0,
+			info: 0,
+			align: 0,
+			entry_size: 0,
+		},
+		SectionHeader {
+			name: ".text",
+			kind: SHT_PROGBITS,
+			flags: SHF_ALLOC | SHF_EXECINSTR,
+			content: text,
+			link: 0,
+			info: 0,
+			align: 16,
+			entry_size: 0,
+		},
+		SectionHeader {
+			name: ".bss",
+			kind: SHT_NOBITS,
+			flags: SHF_ALLOC | SHF_WRITE,
+			content: (file.len(), bss_size),
+			link: 0,
+			info: 0,
+			align: 32,
+			entry_size: 0,
+		},
+		SectionHeader {
+			name: ".rela.text",
+			kind: SHT_RELA,
+			flags: SHF_INFO_LINK,
+			content: rela,
+			link: SYMTAB,
+			info: TEXT,
+			align: 8,
+			entry_size: 24,
+		},
+		SectionHeader {
+			name: ".symtab",
+			kind: SHT_SYMTAB,
+			flags: 0,
+			content: symtab,
+			link: STRTAB,
+			// Index of the first global symbol.
+			info: 2,
+			align: 8,
+			entry_size: 24,
+		},
+		SectionHeader {
+			name: ".strtab",
+			kind: SHT_STRTAB,
+			flags: 0,
+			content: strtab,
+			link: 0,
+			info: 0,
+			align: 1,
+			entry_size: 0,
+		},
+		// Tells the linker that the stack does not need to be executable.
+		SectionHeader {
+			name: ".note.GNU-stack",
+			kind: SHT_PROGBITS,
+			flags: 0,
+			content: (file.len(), 0),
+			link: 0,
+			info: 0,
+			align: 1,
+			entry_size: 0,
+		},
+	];
+	let mut shstrtab_content = vec![0];
+	let mut name_offsets = Vec::new();
+	for section in &sections {
+		name_offsets.push(if section.name.is_empty() {
+			0
+		} else {
+			let offset = shstrtab_content.len();
+			shstrtab_content.extend_from_slice(section.name.as_bytes());
+			shstrtab_content.push(0);
+			offset
+		});
+	}
+	name_offsets.push(shstrtab_content.len());
+	shstrtab_content.extend_from_slice(b".shstrtab\0");
+	let shstrtab = append(&mut file, &shstrtab_content);
+	sections.push(SectionHeader {
+		name: ".shstrtab",
+		kind: SHT_STRTAB,
+		flags: 0,
+		content: shstrtab,
+		link: 0,
+		info: 0,
+		align: 1,
+		entry_size: 0,
+	});
+
+	let section_headers_offset = append(&mut file, &[]).0;
+	for (section, name_offset) in sections.iter().zip(name_offsets) {
+		file.extend_from_slice(&(name_offset as u32).to_le_bytes());
+		file.extend_from_slice(&section.kind.to_le_bytes());
+		file.extend_from_slice(&section.flags.to_le_bytes());
+		file.extend_from_slice(&0u64.to_le_bytes());
+		file.extend_from_slice(&(section.content.0 as u64).to_le_bytes());
+		file.extend_from_slice(&(section.content.1 as u64).to_le_bytes());
+		file.extend_from_slice(&section.link.to_le_bytes());
+		file.extend_from_slice(&section.info.to_le_bytes());
+		file.extend_from_slice(&section.align.to_le_bytes());
+		file.extend_from_slice(&section.entry_size.to_le_bytes());
+	}
+
+	let mut header = Vec::with_capacity(64);
+	// 64-bit, little endian, version 1, System V ABI.
+	header.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0]);
+	header.extend_from_slice(&[0; 8]);
+	header.extend_from_slice(&1u16.to_le_bytes()); // Relocatable.
+	header.extend_from_slice(&62u16.to_le_bytes()); // x86-64.
+	header.extend_from_slice(&1u32.to_le_bytes());
+	header.extend_from_slice(&0u64.to_le_bytes()); // No entry point.
+	header.extend_from_slice(&0u64.to_le_bytes()); // No program headers.
+	header.extend_from_slice(&(section_headers_offset as u64).to_le_bytes());
+	header.extend_from_slice(&0u32.to_le_bytes());
+	header.extend_from_slice(&64u16.to_le_bytes());
+	header.extend_from_slice(&0u16.to_le_bytes());
+	header.extend_from_slice(&0u16.to_le_bytes());
+	header.extend_from_slice(&64u16.to_le_bytes());
+	header.extend_from_slice(&(sections.len() as u16).to_le_bytes());
+	header.extend_from_slice(&((sections.len() - 1) as u16).to_le_bytes());
+	file[..64].copy_from_slice(&header);
+	file
+}
+
+/// Links the object file into an executable with the system linker.
+pub fn link_executable(object_path: &str, executable_path: &str) -> Result<(), String> {
+	let status = std::process::Command::new("ld")
+		.arg("-o")
+		.arg(executable_path)
+		.arg(object_path)
+		.status()
+		.map_err(|error| format!("could not run the linker `ld`: {}", error))?;
+	if status.success() {
+		Ok(())
+	} else {
+		Err(format!("the linker `ld` failed ({})", status))
+	}
+}
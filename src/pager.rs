@@ -0,0 +1,69 @@
+//! Paging of the output of programs run by the VM, for programs that print screens (such as
+//! animations) that would otherwise scroll by instantly: the VM is paused after every page of
+//! output lines until a key is pressed.
+
+use crate::vm::Observer;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+
+/// Observer of the VM that pauses the program after every given number of output lines.
+pub struct Pager {
+	lines_per_page: usize,
+	/// Number of lines output since the last pause.
+	line_count: usize,
+	/// The output is printed by the pager (when the VM does not print it itself).
+	print: bool,
+}
+
+impl Pager {
+	pub fn new(lines_per_page: usize, print: bool) -> Pager {
+		assert!(lines_per_page > 0);
+		Pager {
+			lines_per_page,
+			line_count: 0,
+			print,
+		}
+	}
+}
+
+impl Observer for Pager {
+	fn on_output(&mut self, char_values: &[u8]) {
+		for &char_value in char_values {
+			if self.print {
+				std::io::stdout().write_all(&[char_value]).ok();
+			}
+			if char_value == b'\n' {
+				self.line_count += 1;
+				if self.line_count == self.lines_per_page {
+					self.line_count = 0;
+					std::io::stdout().flush().ok();
+					wait_for_key();
+				}
+			}
+		}
+	}
+}
+
+/// Waits for a key to be pressed on the terminal, which is read directly (rather than through
+/// stdin, that can be the input of the program). Does not wait if there is no terminal.
+fn wait_for_key() {
+	let Ok(mut tty) = File::open("/dev/tty") else {
+		return;
+	};
+	// Without canonical mode, a key press is read without waiting for a whole line.
+	let stty = |tty: &File, settings: &[&str]| {
+		tty.try_clone().ok().map(|tty| {
+			Command::new("stty")
+				.args(settings)
+				.stdin(tty)
+				.stderr(Stdio::null())
+				.status()
+		})
+	};
+	stty(&tty, &["-icanon", "-echo", "min", "1"]);
+	eprint!("-- press a key to continue --");
+	tty.read_exact(&mut [0]).ok();
+	eprint!("\r                             \r");
+	stty(&tty, &["icanon", "echo"]);
+}
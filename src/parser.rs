@@ -1,82 +1,406 @@
-use crate::astraw::RawInstr;
+use crate::astraw::{LoopAttributes, RawInstr};
+use crate::frontend::{Brainfuck, Frontend};
+use crate::progress::{Phase, Progress, Reporter};
+use crate::tokenizer::{Extensions, Token, TokenKind, tokenize, tokenize_with_trivia};
 
-pub fn parse_instr_seq(src_code: &str) -> Result<Vec<RawInstr>, Vec<ParsingError>> {
-	// A scope is either the whole program or a bracket loop and its content.
-	// Only the bottom scope isn't a bracket loop (and thus doesn't have an opening bracket pos),
-	// this bottom scope should always be there (such design is for convenience).
-	struct Scope {
-		opening_bracket_pos: Option<usize>,
-		instr_seq: Vec<RawInstr>,
+pub fn parse_instr_seq(
+	src_code: &str,
+	extensions: &Extensions,
+) -> Result<Vec<RawInstr>, Vec<ParsingError>> {
+	parse_tokens(&tokenize(src_code, extensions))
+}
+
+/// Same as `parse_instr_seq` but also returns the comment and whitespace spans of the source
+/// code as `Trivia` tokens (sorted by position), for tools that have to preserve them.
+pub fn parse_instr_seq_with_trivia(
+	src_code: &str,
+	extensions: &Extensions,
+) -> Result<(Vec<RawInstr>, Vec<Token>), Vec<ParsingError>> {
+	let tokens = tokenize_with_trivia(src_code, extensions);
+	let instr_seq = parse_tokens(&tokens)?;
+	let trivia = tokens
+		.into_iter()
+		.filter(|token| token.kind == TokenKind::Trivia)
+		.collect();
+	Ok((instr_seq, trivia))
+}
+
+/// Returns the positions of the matching opening and closing brackets of the program, sorted by
+/// opening bracket position. Unmatched brackets are ignored so that this works on erroneous
+/// source code too.
+pub fn bracket_pairs(src_code: &str, extensions: &Extensions) -> Vec<(usize, usize)> {
+	parse_tokens_and_bracket_pairs(
+		&tokenize(src_code, extensions),
+		&mut Reporter::start(None, Phase::Parsing),
+	)
+	.1
+}
+
+/// Span in the source code of each instruction of the raw program parsed from it, the span of a
+/// loop going from its opening bracket to its closing bracket.
+pub fn instr_spans(src_code: &str, extensions: &Extensions) -> Vec<std::ops::Range<usize>> {
+	let mut spans: Vec<std::ops::Range<usize>> = Vec::new();
+	let mut open_loops: Vec<usize> = Vec::new();
+	for token in tokenize(src_code, extensions) {
+		match token.kind {
+			TokenKind::ClosingBracket => {
+				let index = open_loops.pop().unwrap();
+				spans[index].end = token.pos + token.len;
+			}
+			TokenKind::Trivia
+			| TokenKind::UnterminatedBlockComment
+			| TokenKind::LoopAttribute(_)
+			| TokenKind::UnknownLoopAttribute => (),
+			kind => {
+				if kind == TokenKind::OpeningBracket {
+					open_loops.push(spans.len());
+				}
+				spans.push(token.pos..token.pos + token.len);
+			}
+		}
 	}
-	struct ScopeStack(Vec<Scope>);
-	impl ScopeStack {
-		fn top_instr_seq(&mut self) -> &mut Vec<RawInstr> {
-			&mut self.0.last_mut().unwrap().instr_seq
+	spans
+}
+
+/// Line and column numbers (starting at 1) of the given position in the source code.
+pub fn line_and_column(src_code: &str, pos: usize) -> (usize, usize) {
+	let before = &src_code[..pos];
+	let line_number = before.matches('\n').count() + 1;
+	let column = pos - before.rfind('\n').map_or(0, |index| index + 1) + 1;
+	(line_number, column)
+}
+
+/// Same as `line_and_column` for each of the given positions, that must be in increasing order
+/// (which takes linear time instead of quadratic time).
+pub fn lines_and_columns(
+	src_code: &str,
+	positions: impl Iterator<Item = usize>,
+) -> Vec<(usize, usize)> {
+	let mut line_number = 1;
+	let mut line_start = 0;
+	let mut scanned = 0;
+	positions
+		.map(|pos| {
+			for (index, byte) in src_code.as_bytes()[scanned..pos].iter().enumerate() {
+				if *byte == b'\n' {
+					line_number += 1;
+					line_start = scanned + index + 1;
+				}
+			}
+			scanned = pos;
+			(line_number, pos - line_start + 1)
+		})
+		.collect()
+}
+
+/// A modification of the source code, the bytes in `range` are replaced by `replacement`.
+#[derive(Debug, Clone)]
+pub struct TextEdit {
+	pub range: std::ops::Range<usize>,
+	pub replacement: String,
+}
+
+impl TextEdit {
+	/// The source code with the edit applied, if the range is within it and on character
+	/// boundaries.
+	pub fn apply(&self, src_code: &str) -> Result<String, InvalidEdit> {
+		let std::ops::Range { start, end } = self.range;
+		if start > end || !src_code.is_char_boundary(start) || !src_code.is_char_boundary(end) {
+			return Err(InvalidEdit {
+				range: self.range.clone(),
+			});
+		}
+		let mut new_src_code = src_code.to_owned();
+		new_src_code.replace_range(self.range.clone(), &self.replacement);
+		Ok(new_src_code)
+	}
+}
+
+/// The range of a `TextEdit` is not a range of characters of the source code it is applied to.
+#[derive(Debug, Clone)]
+pub struct InvalidEdit {
+	pub range: std::ops::Range<usize>,
+}
+
+impl std::fmt::Display for InvalidEdit {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(
+			f,
+			"the edited range {}..{} is not a range of characters of the source code",
+			self.range.start, self.range.end
+		)
+	}
+}
+
+/// Parses the source code obtained by applying `edit` to `old_src_code`, given `old_instr_seq`
+/// the result of the parsing of `old_src_code`. Only the content of the innermost bracket loop
+/// that contains the whole edited range is parsed again, the rest of the old program is reused.
+/// Falls back to parsing everything when the edit is not contained in a bracket loop, when the
+/// edited loop content does not parse on its own (so that errors have correct positions), when
+/// the comments or loop attributes extensions are enabled (an edit could then comment out a
+/// bracket out of the loop), or for dialects other than brainfuck (whose brackets may not be
+/// single characters). Fails if the edit cannot be applied to `old_src_code`.
+pub fn reparse(
+	old_src_code: &str,
+	mut old_instr_seq: Vec<RawInstr>,
+	edit: &TextEdit,
+	extensions: &Extensions,
+) -> Result<ParsingResult, InvalidEdit> {
+	let new_src_code = edit.apply(old_src_code)?;
+	if extensions.comments
+		|| extensions.loop_attributes
+		|| extensions.frontend.name() != Brainfuck.name()
+	{
+		return Ok(parse_instr_seq(&new_src_code, extensions));
+	}
+	let (opening_bracket_pos, closing_bracket_pos, path) =
+		match enclosing_loop(&tokenize(old_src_code, extensions), &edit.range) {
+			Some(enclosing_loop) => enclosing_loop,
+			None => return Ok(parse_instr_seq(&new_src_code, extensions)),
+		};
+	let new_closing_bracket_pos = closing_bracket_pos + edit.replacement.len() - edit.range.len();
+	let new_body_src_code = &new_src_code[opening_bracket_pos + 1..new_closing_bracket_pos];
+	// The body is not a whole source code, a `#!` at its start is not a shebang line.
+	let new_body_tokens = extensions
+		.frontend
+		.tokenize(new_body_src_code, extensions, false);
+	let new_body = match parse_tokens(&new_body_tokens) {
+		Ok(new_body) => new_body,
+		Err(_) => return Ok(parse_instr_seq(&new_src_code, extensions)),
+	};
+
+	// The loop is the last of the enclosing loops, all of them see their body length change.
+	let loop_index = *path.last().unwrap();
+	let old_body_len = match old_instr_seq[loop_index] {
+		RawInstr::BracketLoop { body_len, .. } => body_len,
+		_ => panic!("the old program does not match the old source code"),
+	};
+	let new_body_len = new_body.len();
+	old_instr_seq.splice(loop_index + 1..loop_index + 1 + old_body_len, new_body);
+	for index in path {
+		if let RawInstr::BracketLoop {
+			ref mut body_len, ..
+		} = old_instr_seq[index]
+		{
+			*body_len = *body_len + new_body_len - old_body_len;
 		}
 	}
-	let mut scope_stack: ScopeStack = ScopeStack(vec![Scope {
-		opening_bracket_pos: None,
-		instr_seq: Vec::new(),
-	}]);
+	Ok(Ok(old_instr_seq))
+}
+
+/// Finds the innermost bracket loop whose content contains the given range, and returns the
+/// positions of its brackets and its path in the program (the indices in the instruction
+/// sequence of the enclosing loops from the outermost to the loop itself).
+fn enclosing_loop(
+	tokens: &[Token],
+	range: &std::ops::Range<usize>,
+) -> Option<(usize, usize, Vec<usize>)> {
+	// The scopes opened before the range, with their opening bracket positions and their index
+	// in the instruction sequence. Every token but closing brackets becomes one instruction.
+	let mut open_scopes: Vec<(usize, usize)> = Vec::new();
+	let mut instr_count = 0;
+	let mut tokens = tokens.iter().peekable();
+	while let Some(token) = tokens.next_if(|token| token.pos < range.start) {
+		match token.kind {
+			TokenKind::OpeningBracket => {
+				open_scopes.push((token.pos, instr_count));
+				instr_count += 1;
+			}
+			TokenKind::ClosingBracket => {
+				open_scopes.pop()?;
+			}
+			TokenKind::Trivia
+			| TokenKind::UnterminatedBlockComment
+			| TokenKind::LoopAttribute(_)
+			| TokenKind::UnknownLoopAttribute => (),
+			_ => instr_count += 1,
+		}
+	}
+
+	// Close scopes until one closes at or after the end of the range.
+	let mut depth_in_range = 0;
+	for token in tokens {
+		match token.kind {
+			TokenKind::OpeningBracket => depth_in_range += 1,
+			TokenKind::ClosingBracket if depth_in_range > 0 => depth_in_range -= 1,
+			TokenKind::ClosingBracket => {
+				let (opening_bracket_pos, _) = open_scopes.last()?;
+				if token.pos >= range.end {
+					let path = open_scopes.iter().map(|&(_, index)| index).collect();
+					return Some((*opening_bracket_pos, token.pos, path));
+				}
+				open_scopes.pop();
+			}
+			_ => (),
+		}
+	}
+	None
+}
+
+/// Same as `parse_instr_seq` but also returns what `bracket_pairs` would.
+pub fn parse_instr_seq_and_bracket_pairs(
+	src_code: &str,
+	extensions: &Extensions,
+	progress: Option<&mut dyn Progress>,
+) -> (ParsingResult, Vec<(usize, usize)>) {
+	let mut reporter = Reporter::start(progress, Phase::Parsing);
+	let tokens = tokenize(src_code, extensions);
+	// Tokenization is counted as half of the parsing.
+	reporter.report(1, 2);
+	let result = parse_tokens_and_bracket_pairs(&tokens, &mut reporter);
+	reporter.finish();
+	result
+}
+
+fn parse_tokens(tokens: &[Token]) -> Result<Vec<RawInstr>, Vec<ParsingError>> {
+	parse_tokens_and_bracket_pairs(tokens, &mut Reporter::start(None, Phase::Parsing)).0
+}
+
+pub type ParsingResult = Result<Vec<RawInstr>, Vec<ParsingError>>;
+
+/// Number of tokens parsed between progress reports.
+const PROGRESS_STEP: usize = 1 << 16;
+
+fn parse_tokens_and_bracket_pairs(
+	tokens: &[Token],
+	reporter: &mut Reporter,
+) -> (ParsingResult, Vec<(usize, usize)>) {
+	// A scope is a bracket loop being parsed, its body is what was pushed to the instruction
+	// sequence since its opening bracket.
+	struct Scope {
+		opening_bracket_pos: usize,
+		/// Index of the bracket loop instruction in the sequence.
+		instr_index: usize,
+	}
+	let mut scope_stack: Vec<Scope> = Vec::new();
+	// The attributes given to the next loop, with the position of the first of them.
+	let mut pending_attributes: Option<(LoopAttributes, usize)> = None;
+	let mut instr_seq: Vec<RawInstr> = Vec::new();
 
 	let mut errors: Vec<ParsingError> = Vec::new();
+	let mut bracket_pairs: Vec<(usize, usize)> = Vec::new();
 
-	for (pos, c) in src_code.char_indices() {
-		match c {
-			'+' => scope_stack.top_instr_seq().push(RawInstr::Plus),
-			'-' => scope_stack.top_instr_seq().push(RawInstr::Minus),
-			'<' => scope_stack.top_instr_seq().push(RawInstr::Left),
-			'>' => scope_stack.top_instr_seq().push(RawInstr::Right),
-			'.' => scope_stack.top_instr_seq().push(RawInstr::Dot),
-			',' => scope_stack.top_instr_seq().push(RawInstr::Comma),
-			'[' => scope_stack.0.push(Scope {
-				opening_bracket_pos: Some(pos),
-				instr_seq: Vec::new(),
-			}),
-			']' => {
-				if scope_stack.0.len() >= 2 {
-					let poped_instr_seq = scope_stack.0.pop().unwrap().instr_seq;
-					scope_stack
-						.top_instr_seq()
-						.push(RawInstr::BracketLoop(poped_instr_seq));
+	for (token_index, token) in tokens.iter().enumerate() {
+		if reporter.is_listened() && token_index.is_multiple_of(PROGRESS_STEP) {
+			reporter.report(tokens.len() + token_index, 2 * tokens.len());
+		}
+		let pos = token.pos;
+		if let Some((_, attribute_pos)) = pending_attributes {
+			if !matches!(
+				token.kind,
+				TokenKind::OpeningBracket | TokenKind::LoopAttribute(_) | TokenKind::Trivia
+			) {
+				errors.push(ParsingError::MisplacedLoopAttribute { pos: attribute_pos });
+				pending_attributes = None;
+			}
+		}
+		match token.kind {
+			TokenKind::Plus => instr_seq.push(RawInstr::Plus(token.count)),
+			TokenKind::Minus => instr_seq.push(RawInstr::Minus(token.count)),
+			TokenKind::Left => instr_seq.push(RawInstr::Left(token.count)),
+			TokenKind::Right => instr_seq.push(RawInstr::Right(token.count)),
+			TokenKind::Dot => instr_seq.push(RawInstr::Dot),
+			TokenKind::Comma => instr_seq.push(RawInstr::Comma),
+			TokenKind::Clock => instr_seq.push(RawInstr::Clock),
+			TokenKind::Random => instr_seq.push(RawInstr::Random),
+			TokenKind::HostCall => instr_seq.push(RawInstr::HostCall),
+			TokenKind::Extended(instr) => instr_seq.push(RawInstr::Extended(instr)),
+			TokenKind::OpeningBracket => {
+				scope_stack.push(Scope {
+					opening_bracket_pos: pos,
+					instr_index: instr_seq.len(),
+				});
+				// The body length is known when the closing bracket is reached.
+				instr_seq.push(RawInstr::BracketLoop {
+					body_len: 0,
+					attributes: pending_attributes.take().unwrap_or_default().0,
+				});
+			}
+			TokenKind::ClosingBracket => {
+				if let Some(poped_scope) = scope_stack.pop() {
+					bracket_pairs.push((poped_scope.opening_bracket_pos, pos));
+					let body_len = instr_seq.len() - poped_scope.instr_index - 1;
+					if let RawInstr::BracketLoop {
+						body_len: ref mut loop_body_len,
+						..
+					} = instr_seq[poped_scope.instr_index]
+					{
+						*loop_body_len = body_len;
+					}
 				} else {
 					errors.push(ParsingError::UnmatchedClosingBracket { pos });
 				}
 			}
-			_ => (),
+			TokenKind::Trivia => (),
+			TokenKind::UnterminatedBlockComment => {
+				errors.push(ParsingError::UnterminatedBlockComment { pos })
+			}
+			TokenKind::LoopAttribute(attributes) => {
+				let (pending, _) = pending_attributes.get_or_insert((attributes, pos));
+				pending.no_unroll |= attributes.no_unroll;
+				pending.hot |= attributes.hot;
+			}
+			TokenKind::UnknownLoopAttribute => {
+				errors.push(ParsingError::UnknownLoopAttribute { pos })
+			}
 		}
 	}
+	if let Some((_, attribute_pos)) = pending_attributes {
+		errors.push(ParsingError::MisplacedLoopAttribute { pos: attribute_pos });
+	}
 
-	assert!(scope_stack.0.len() != 0);
-	while scope_stack.0.len() >= 2 {
-		// The use of `.remove(1)` here instead of `.pop().unwrap()` ensures that errors are
-		// sorted according to their `pos`.
-		let opening_bracket_pos = scope_stack.0.remove(1).opening_bracket_pos.unwrap();
+	for scope in scope_stack {
 		errors.push(ParsingError::UnmatchedOpeningBracket {
-			pos: opening_bracket_pos,
+			pos: scope.opening_bracket_pos,
 		});
 	}
 
-	if errors.is_empty() {
-		assert!(scope_stack.0.len() == 1);
-		Ok(scope_stack.0.pop().unwrap().instr_seq)
+	// An unterminated block comment goes to the end of the source code, it is reported after any
+	// unmatched opening bracket that comes before it.
+	errors.sort_by_key(|error| error.pos());
+	bracket_pairs.sort_unstable();
+
+	let parsing_result = if errors.is_empty() {
+		Ok(instr_seq)
 	} else {
 		Err(errors)
-	}
+	};
+	(parsing_result, bracket_pairs)
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ParsingError {
-	UnmatchedOpeningBracket { pos: usize },
-	UnmatchedClosingBracket { pos: usize },
+	UnmatchedOpeningBracket {
+		pos: usize,
+	},
+	UnmatchedClosingBracket {
+		pos: usize,
+	},
+	UnterminatedBlockComment {
+		pos: usize,
+	},
+	/// A loop attribute that is not right before a loop (see `Extensions::loop_attributes`).
+	MisplacedLoopAttribute {
+		pos: usize,
+	},
+	UnknownLoopAttribute {
+		pos: usize,
+	},
 }
 
 impl ParsingError {
-	pub fn print(self, src_code: &str, src_code_name: Option<&str>, ansi_escape_codes: bool) {
-		let error_index = match self {
+	pub fn pos(&self) -> usize {
+		match *self {
 			ParsingError::UnmatchedOpeningBracket { pos } => pos,
 			ParsingError::UnmatchedClosingBracket { pos } => pos,
-		};
+			ParsingError::UnterminatedBlockComment { pos } => pos,
+			ParsingError::MisplacedLoopAttribute { pos } => pos,
+			ParsingError::UnknownLoopAttribute { pos } => pos,
+		}
+	}
+
+	pub fn print(self, src_code: &str, src_code_name: Option<&str>, ansi_escape_codes: bool) {
+		let error_index = self.pos();
 
 		// Find the line that contains the error.
 		let mut line_number = 1;
@@ -114,6 +438,9 @@ impl ParsingError {
 		let error_variant_as_string = match self {
 			ParsingError::UnmatchedClosingBracket { pos: _ } => "Unmatched closing bracket",
 			ParsingError::UnmatchedOpeningBracket { pos: _ } => "Unmatched opening bracket",
+			ParsingError::UnterminatedBlockComment { pos: _ } => "Unterminated block comment",
+			ParsingError::MisplacedLoopAttribute { pos: _ } => "Loop attribute not before a loop",
+			ParsingError::UnknownLoopAttribute { pos: _ } => "Unknown loop attribute",
 		};
 		println!(
 			"{}{}Parsing error{} on line {} column {}{}: {}{}",
@@ -173,7 +500,7 @@ impl ParsingError {
 		let carret_column = carret_column;
 
 		// Print a carret under the erroneous character.
-		println!("");
+		println!();
 		for _ in 0..carret_column {
 			print!(" ");
 		}
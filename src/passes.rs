@@ -0,0 +1,677 @@
+use crate::astraw::{RawInstr, top_level};
+use crate::astsoup::{
+	SoupInstr, classify_loops, clean_up, fold_constant_loops, fold_head_deltas, fuse_loops,
+	instr_count, ir_memory, merge_soups_across_io, recognize_block_moves, recognize_print_loops,
+	recognize_read_loops, seek, soupify,
+};
+use crate::diff::unified_diff;
+use crate::ioorder::io_signature;
+use crate::irtext::print_soup;
+use crate::log::{self, Level};
+use crate::progress::{Phase, Progress, Reporter};
+use crate::tapeconfig::TapeConfig;
+use crate::timing::{print_peak_ir_memory, print_phase_time};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// An optimization pass over the soup intermediate representation. Besides the passes of xxbf
+/// (see `PASSES`), passes can be registered in a pass manager (see `PassManager::register_pass`).
+pub trait Pass: Send + Sync {
+	/// Name of the pass, as given to `print_ir_after`.
+	fn name(&self) -> &'static str;
+
+	/// Transforms the program, calling `Fuel::consume` before each transformation.
+	fn run(&self, soup_prog: &mut Vec<SoupInstr>, fuel: &mut Fuel);
+
+	/// Same as `run`, for a program known to start on the given tape (see
+	/// `PassManager::tape_config`), which the passes that evaluate the start of the program use.
+	fn run_on_tape(
+		&self,
+		soup_prog: &mut Vec<SoupInstr>,
+		fuel: &mut Fuel,
+		_tape_config: &TapeConfig,
+	) {
+		self.run(soup_prog, fuel)
+	}
+
+	/// The pass gives the same result when run on chunks of the program split right after
+	/// top-level loops as when run on the whole program, so it can be run on chunks in parallel.
+	fn chunk_safe(&self) -> bool {
+		false
+	}
+}
+
+impl std::fmt::Debug for dyn Pass {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "Pass({})", self.name())
+	}
+}
+
+/// A pass of xxbf.
+pub struct BuiltinPass {
+	pub name: &'static str,
+	pub run: fn(&mut Vec<SoupInstr>, &mut Fuel),
+	/// Replaces `run` when the tape the program starts on is known (see `Pass::run_on_tape`).
+	pub run_on_tape: Option<fn(&mut Vec<SoupInstr>, &mut Fuel, &TapeConfig)>,
+	/// See `Pass::chunk_safe`.
+	pub chunk_safe: bool,
+}
+
+impl Pass for BuiltinPass {
+	fn name(&self) -> &'static str {
+		self.name
+	}
+
+	fn run(&self, soup_prog: &mut Vec<SoupInstr>, fuel: &mut Fuel) {
+		(self.run)(soup_prog, fuel)
+	}
+
+	fn run_on_tape(
+		&self,
+		soup_prog: &mut Vec<SoupInstr>,
+		fuel: &mut Fuel,
+		tape_config: &TapeConfig,
+	) {
+		match self.run_on_tape {
+			Some(run_on_tape) => run_on_tape(soup_prog, fuel, tape_config),
+			None => (self.run)(soup_prog, fuel),
+		}
+	}
+
+	fn chunk_safe(&self) -> bool {
+		self.chunk_safe
+	}
+}
+
+/// A pass registered in a pass manager, that runs right after the pass of the given name.
+#[derive(Debug, Clone)]
+pub struct CustomPass {
+	pub after: String,
+	pub pass: Arc<dyn Pass>,
+}
+
+/// Limits the number of transformations that the passes can apply, so that a miscompilation can
+/// be bisected to the transformation that introduced it, and the time they can take (see
+/// `PassManager::time_budget`). Each pass must call `consume` before each transformation it would
+/// apply, and not apply it if it returns false.
+#[derive(Debug, Clone)]
+pub struct Fuel {
+	remaining: Option<u64>,
+	consumed: u64,
+	current_pass: &'static str,
+	last_pass: Option<&'static str>,
+	deadline: Option<Instant>,
+	out_of_time: bool,
+}
+
+impl Fuel {
+	pub fn new(limit: Option<u64>) -> Fuel {
+		Fuel {
+			remaining: limit,
+			consumed: 0,
+			current_pass: SOUPIFY_NAME,
+			last_pass: None,
+			deadline: None,
+			out_of_time: false,
+		}
+	}
+
+	pub fn consume(&mut self) -> bool {
+		if self.is_out_of_time() {
+			return false;
+		}
+		match self.remaining {
+			Some(0) => return false,
+			Some(ref mut remaining) => *remaining -= 1,
+			None => (),
+		}
+		self.consumed += 1;
+		self.last_pass = Some(self.current_pass);
+		true
+	}
+
+	/// Number of transformations applied so far.
+	pub fn consumed(&self) -> u64 {
+		self.consumed
+	}
+
+	/// Name of the pass that applied the last transformation.
+	pub fn last_pass(&self) -> Option<&'static str> {
+		self.last_pass
+	}
+
+	/// The time budget expired, no more transformations are applied.
+	pub fn is_out_of_time(&mut self) -> bool {
+		if !self.out_of_time {
+			self.out_of_time = self
+				.deadline
+				.is_some_and(|deadline| Instant::now() >= deadline);
+		}
+		self.out_of_time
+	}
+}
+
+/// Name under which the conversion of the raw program to soup appears in the pass pipeline,
+/// it is always the first stage and can be named in `print_ir_after`.
+pub const SOUPIFY_NAME: &str = "soupify";
+
+/// The passes of xxbf, applied after soupification in this order.
+pub const PASSES: &[BuiltinPass] = &[
+	BuiltinPass {
+		name: "classify-loops",
+		run: |soup_prog, fuel| classify_loops(soup_prog, fuel),
+		run_on_tape: None,
+		chunk_safe: true,
+	},
+	BuiltinPass {
+		name: "block-moves",
+		run: |soup_prog, fuel| recognize_block_moves(soup_prog, fuel),
+		run_on_tape: None,
+		chunk_safe: true,
+	},
+	BuiltinPass {
+		name: "print-loops",
+		run: |soup_prog, fuel| recognize_print_loops(soup_prog, fuel),
+		run_on_tape: None,
+		chunk_safe: true,
+	},
+	BuiltinPass {
+		name: "read-loops",
+		run: |soup_prog, fuel| recognize_read_loops(soup_prog, fuel),
+		run_on_tape: None,
+		chunk_safe: true,
+	},
+	BuiltinPass {
+		name: "fold-head-deltas",
+		run: fold_head_deltas,
+		run_on_tape: None,
+		chunk_safe: true,
+	},
+	BuiltinPass {
+		name: "soups-across-io",
+		run: merge_soups_across_io,
+		run_on_tape: None,
+		chunk_safe: true,
+	},
+	BuiltinPass {
+		name: "fuse-loops",
+		run: fuse_loops,
+		run_on_tape: None,
+		// Chunks start right after a loop, so a loop at the start of a chunk could be fused.
+		chunk_safe: false,
+	},
+	BuiltinPass {
+		name: "constant-loops",
+		// The loops can only be evaluated from the start of the program, on a known tape.
+		run: |_, _| (),
+		run_on_tape: Some(|soup_prog, fuel, tape_config| {
+			fold_constant_loops(soup_prog, fuel, tape_config)
+		}),
+		chunk_safe: false,
+	},
+	BuiltinPass {
+		name: "clean-up",
+		run: clean_up,
+		run_on_tape: None,
+		chunk_safe: true,
+	},
+	BuiltinPass {
+		// Last, as the other passes only look for head moves in soups.
+		name: "seek",
+		run: |soup_prog, fuel| seek(soup_prog, fuel),
+		run_on_tape: None,
+		chunk_safe: true,
+	},
+];
+
+/// Programs with at least this many raw instructions are optimized in parallel.
+const PARALLEL_THRESHOLD: usize = 1 << 16;
+
+/// Approximate number of raw instructions in each chunk optimized in parallel. It is a constant
+/// (and not derived from the number of threads) so that the result does not depend on the
+/// machine, even for passes that are not perfectly chunk-safe.
+const CHUNK_SIZE: usize = 1 << 14;
+
+/// Runs the pass pipeline and handles its debugging options.
+#[derive(Debug, Clone, Default)]
+pub struct PassManager {
+	/// Names of the passes after which the IR is printed (to stderr), `all` meaning all of them.
+	pub print_ir_after: Vec<String>,
+	/// Print (to stderr) the diff of the IR before and after each pass that changed it.
+	pub print_ir_diff: bool,
+	/// Maximum number of transformations the passes can apply (see `Fuel`).
+	pub fuel: Option<u64>,
+	/// Print (to stderr) the time taken by each pass and the size of the IR after it, and the
+	/// peak memory taken by the IR.
+	pub time_passes: bool,
+	/// Maximum memory (in bytes, see `ir_memory`) the IR can take, the optimization stops with
+	/// an error if it is exceeded after a pass.
+	pub mem_limit: Option<usize>,
+	/// Undo the passes that may change the order of the inputs and outputs (see `ioorder`),
+	/// such passes being otherwise only logged (at the `info` level).
+	pub strict_io: bool,
+	/// Passes that run besides those of xxbf, in the order they were registered (see
+	/// `register_pass`).
+	pub custom_passes: Vec<CustomPass>,
+	/// Tape the optimized program starts on, if known, so that the loops at its start can be
+	/// evaluated (see `fold_constant_loops`). The optimized program must then only be run on it.
+	pub tape_config: Option<TapeConfig>,
+	/// Names of the passes that are not run (custom passes registered after them still run).
+	pub disabled_passes: Vec<String>,
+	/// Time after which the passes stop applying transformations, the optimization then
+	/// returning the program as transformed so far (which, unlike with `fuel`, depends on the
+	/// speed of the machine).
+	pub time_budget: Option<Duration>,
+}
+
+/// What a pass did, as reported by `optimize_with_stats`.
+#[derive(Debug, Clone)]
+pub struct PassStats {
+	pub name: String,
+	/// Number of instructions of the IR after the pass (see `instr_count`).
+	pub instrs: usize,
+	/// Memory taken by the IR after the pass (see `ir_memory`).
+	pub ir_bytes: usize,
+	pub transformations: u64,
+	pub duration: Duration,
+}
+
+/// The IR took more memory than the `mem_limit` of the pass manager.
+#[derive(Debug)]
+pub struct IrTooLarge {
+	pub pass_name: &'static str,
+	pub ir_memory: usize,
+	pub mem_limit: usize,
+}
+
+impl std::fmt::Display for IrTooLarge {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(
+			f,
+			"IR too large after {} ({} bytes, the limit is {} bytes), try -O0",
+			self.pass_name, self.ir_memory, self.mem_limit
+		)
+	}
+}
+
+impl PassManager {
+	/// Makes the pass run right after the pass of the given name (`soupify` to run first), after
+	/// the passes already registered there. Panics if there is no pass of that name or if there
+	/// already is a pass of the same name as the given one.
+	pub fn register_pass(&mut self, after: &str, pass: impl Pass + 'static) {
+		let pipeline = self.pipeline();
+		let is_registered = |name: &str| pipeline.iter().any(|pass| pass.name() == name);
+		if after != SOUPIFY_NAME && !is_registered(after) {
+			panic!(
+				"cannot register pass `{}` after unknown pass `{}`",
+				pass.name(),
+				after
+			);
+		}
+		if pass.name() == SOUPIFY_NAME || is_registered(pass.name()) {
+			panic!("there already is a pass named `{}`", pass.name());
+		}
+		self.custom_passes.push(CustomPass {
+			after: after.to_owned(),
+			pass: Arc::new(pass),
+		});
+	}
+
+	/// The passes applied after soupification, in order: those of xxbf (see `PASSES`) with the
+	/// registered passes inserted.
+	pub fn pipeline(&self) -> Vec<&dyn Pass> {
+		let mut pipeline = Vec::with_capacity(PASSES.len() + self.custom_passes.len());
+		self.push_passes_after(SOUPIFY_NAME, &mut pipeline);
+		for pass in PASSES {
+			pipeline.push(pass as &dyn Pass);
+			self.push_passes_after(pass.name, &mut pipeline);
+		}
+		pipeline
+	}
+
+	fn push_passes_after<'a>(&'a self, name: &str, pipeline: &mut Vec<&'a dyn Pass>) {
+		for custom_pass in self.custom_passes.iter().filter(|pass| pass.after == name) {
+			pipeline.push(custom_pass.pass.as_ref());
+			self.push_passes_after(custom_pass.pass.name(), pipeline);
+		}
+	}
+
+	pub fn optimize(&self, raw_prog: &[RawInstr]) -> Result<Vec<SoupInstr>, IrTooLarge> {
+		Ok(self.optimize_and_get_fuel(raw_prog)?.0)
+	}
+
+	/// Same as `optimize` but also returns the fuel after the last pass, which tells how many
+	/// transformations were applied.
+	pub fn optimize_and_get_fuel(
+		&self,
+		raw_prog: &[RawInstr],
+	) -> Result<(Vec<SoupInstr>, Fuel), IrTooLarge> {
+		self.optimize_impl(raw_prog, None, None)
+	}
+
+	/// Same as `optimize`, the progress being reported after each pass (see `progress`).
+	pub fn optimize_with_progress(
+		&self,
+		raw_prog: &[RawInstr],
+		progress: &mut dyn Progress,
+	) -> Result<Vec<SoupInstr>, IrTooLarge> {
+		Ok(self.optimize_impl(raw_prog, None, Some(progress))?.0)
+	}
+
+	/// Same as `optimize` but also returns what each pass did, in the order they ran (passes run
+	/// in parallel on chunks of the program being reported as one).
+	pub fn optimize_with_stats(
+		&self,
+		raw_prog: &[RawInstr],
+	) -> Result<(Vec<SoupInstr>, Vec<PassStats>), IrTooLarge> {
+		let mut stats = Vec::new();
+		let (soup_prog, _) = self.optimize_impl(raw_prog, Some(&mut stats), None)?;
+		Ok((soup_prog, stats))
+	}
+
+	fn optimize_impl(
+		&self,
+		raw_prog: &[RawInstr],
+		mut stats: Option<&mut Vec<PassStats>>,
+		progress: Option<&mut dyn Progress>,
+	) -> Result<(Vec<SoupInstr>, Fuel), IrTooLarge> {
+		// The raw program is kept alive during the whole optimization.
+		let raw_memory = std::mem::size_of_val(raw_prog);
+		let mut peak_memory = raw_memory;
+		let mut fuel = Fuel::new(self.fuel);
+		fuel.deadline = self
+			.time_budget
+			.map(|time_budget| Instant::now() + time_budget);
+		// Soupification and each pass are a step of the progress.
+		let mut reporter = Reporter::start(progress, Phase::Optimization);
+		let mut pipeline = self.pipeline();
+		pipeline.retain(|pass| !self.disabled_passes.iter().any(|name| name == pass.name()));
+		let step_count = 1 + pipeline.len();
+		let (mut soup_prog, passes_done) = if self.can_optimize_in_parallel(raw_prog) {
+			self.optimize_chunks_in_parallel(
+				raw_prog,
+				&pipeline,
+				&mut fuel,
+				&mut stats,
+				&mut reporter,
+			)
+		} else {
+			let start = Instant::now();
+			let soup_prog = soupify(raw_prog);
+			if self.time_passes {
+				print_phase_time(
+					SOUPIFY_NAME,
+					start.elapsed(),
+					instr_count(&soup_prog),
+					"instrs",
+				);
+			}
+			pass_done(SOUPIFY_NAME, start, &soup_prog, 0, &mut stats);
+			(soup_prog, 0)
+		};
+		reporter.report(1 + passes_done, step_count);
+		let first_pass_name = match passes_done {
+			0 => SOUPIFY_NAME,
+			_ => pipeline[passes_done - 1].name(),
+		};
+		self.check_ir_memory(first_pass_name, raw_memory, &soup_prog, &mut peak_memory)?;
+		let mut ir_text = self.needs_ir_text().then(|| print_soup(&soup_prog));
+		self.after_pass(SOUPIFY_NAME, None, ir_text.as_deref());
+
+		for (pass_index, pass) in pipeline.iter().enumerate().skip(passes_done) {
+			if fuel.is_out_of_time() {
+				// The remaining passes would not apply any transformation.
+				log::event(Level::Info, "opt_time_budget")
+					.field("skipped_from", pass.name())
+					.emit();
+				break;
+			}
+			fuel.current_pass = pass.name();
+			let consumed_before = fuel.consumed;
+			let start = Instant::now();
+			let io_order_before = self.checks_io_order().then(|| {
+				(
+					io_signature(&soup_prog),
+					self.strict_io.then(|| soup_prog.clone()),
+				)
+			});
+			match &self.tape_config {
+				Some(tape_config) => pass.run_on_tape(&mut soup_prog, &mut fuel, tape_config),
+				None => pass.run(&mut soup_prog, &mut fuel),
+			}
+			if let Some((signature, soup_prog_before)) = io_order_before {
+				if io_signature(&soup_prog) != signature {
+					log::event(Level::Info, "io_order")
+						.field("pass", pass.name())
+						.field("undone", self.strict_io)
+						.emit();
+					if let Some(soup_prog_before) = soup_prog_before {
+						soup_prog = soup_prog_before;
+					}
+				}
+			}
+			if self.time_passes {
+				print_phase_time(
+					pass.name(),
+					start.elapsed(),
+					instr_count(&soup_prog),
+					"instrs",
+				);
+			}
+			let transformations = fuel.consumed - consumed_before;
+			pass_done(pass.name(), start, &soup_prog, transformations, &mut stats);
+			self.check_ir_memory(pass.name(), raw_memory, &soup_prog, &mut peak_memory)?;
+			let new_ir_text = self.needs_ir_text().then(|| print_soup(&soup_prog));
+			self.after_pass(pass.name(), ir_text.as_deref(), new_ir_text.as_deref());
+			ir_text = new_ir_text;
+			reporter.report(2 + pass_index, step_count);
+		}
+		if self.time_passes {
+			print_peak_ir_memory(peak_memory);
+		}
+		log::event(Level::Debug, "ir_memory")
+			.field("peak_bytes", peak_memory)
+			.field("transformations", fuel.consumed as usize)
+			.emit();
+		Ok((soup_prog, fuel))
+	}
+
+	/// Updates the peak memory taken by the IR and checks it against the limit, the memory being
+	/// measured after each pass (and not during it).
+	fn check_ir_memory(
+		&self,
+		pass_name: &'static str,
+		raw_memory: usize,
+		soup_prog: &[SoupInstr],
+		peak_memory: &mut usize,
+	) -> Result<(), IrTooLarge> {
+		let memory = raw_memory + ir_memory(soup_prog);
+		*peak_memory = (*peak_memory).max(memory);
+		match self.mem_limit {
+			Some(mem_limit) if memory > mem_limit => Err(IrTooLarge {
+				pass_name,
+				ir_memory: memory,
+				mem_limit,
+			}),
+			_ => Ok(()),
+		}
+	}
+
+	/// The order in which transformations are applied matters when the fuel is limited, the IR
+	/// printing options print the whole program after each pass, and strict I/O checks the whole
+	/// program after each pass, so these prevent the parallel optimization.
+	fn can_optimize_in_parallel(&self, raw_prog: &[RawInstr]) -> bool {
+		self.fuel.is_none()
+			&& !self.needs_ir_text()
+			&& !self.strict_io
+			&& raw_prog.len() >= PARALLEL_THRESHOLD
+	}
+
+	/// Runs soupification and the leading chunk-safe passes on chunks of the program in
+	/// parallel, and returns the concatenated result with the number of passes done. The
+	/// progress is reported as chunks are done.
+	fn optimize_chunks_in_parallel(
+		&self,
+		raw_prog: &[RawInstr],
+		pipeline: &[&dyn Pass],
+		fuel: &mut Fuel,
+		stats: &mut Option<&mut Vec<PassStats>>,
+		reporter: &mut Reporter,
+	) -> (Vec<SoupInstr>, usize) {
+		let start = Instant::now();
+		let passes_done = pipeline.iter().take_while(|pass| pass.chunk_safe()).count();
+		let fuel_deadline = fuel.deadline;
+		let chunks = split_into_chunks(raw_prog);
+		// The optimized chunks and the fuel used by each of them, in order.
+		type ChunkResult = Mutex<Option<(Vec<SoupInstr>, Fuel)>>;
+		let results: Vec<ChunkResult> = chunks.iter().map(|_| Mutex::new(None)).collect();
+		let next_chunk_index = AtomicUsize::new(0);
+		let done_chunk_count = AtomicUsize::new(0);
+		let thread_count = std::thread::available_parallelism()
+			.map_or(1, |n| n.get())
+			.min(chunks.len());
+		// Woken up by the threads when they are done with a chunk, to report the progress.
+		let reporting_thread = std::thread::current();
+		let step_count = 1 + pipeline.len();
+		std::thread::scope(|scope| {
+			for _ in 0..thread_count {
+				scope.spawn(|| {
+					loop {
+						let index = next_chunk_index.fetch_add(1, Ordering::Relaxed);
+						let chunk = match chunks.get(index) {
+							Some(chunk) => chunk,
+							None => break,
+						};
+						let mut chunk_fuel = Fuel::new(None);
+						chunk_fuel.deadline = fuel_deadline;
+						let mut soup_chunk = soupify(chunk);
+						for pass in &pipeline[..passes_done] {
+							chunk_fuel.current_pass = pass.name();
+							pass.run(&mut soup_chunk, &mut chunk_fuel);
+						}
+						*results[index].lock().unwrap() = Some((soup_chunk, chunk_fuel));
+						done_chunk_count.fetch_add(1, Ordering::Relaxed);
+						reporting_thread.unpark();
+					}
+				});
+			}
+			if reporter.is_listened() {
+				loop {
+					let done = done_chunk_count.load(Ordering::Relaxed);
+					reporter.report(done * (1 + passes_done), chunks.len() * step_count);
+					if done == chunks.len() {
+						break;
+					}
+					std::thread::park();
+				}
+			}
+		});
+
+		let mut soup_prog = Vec::new();
+		for result in results {
+			let (soup_chunk, chunk_fuel) = result.into_inner().unwrap().unwrap();
+			soup_prog.extend(soup_chunk);
+			fuel.consumed += chunk_fuel.consumed;
+			fuel.last_pass = chunk_fuel.last_pass.or(fuel.last_pass);
+			fuel.out_of_time |= chunk_fuel.out_of_time;
+		}
+		let pass_names: Vec<&str> = std::iter::once(SOUPIFY_NAME)
+			.chain(pipeline[..passes_done].iter().map(|pass| pass.name()))
+			.collect();
+		let name = format!("parallel({})", pass_names.join(","));
+		if self.time_passes {
+			print_phase_time(&name, start.elapsed(), instr_count(&soup_prog), "instrs");
+		}
+		pass_done(&name, start, &soup_prog, fuel.consumed, stats);
+		(soup_prog, passes_done)
+	}
+
+	/// The I/O signature is compared before and after each pass (that runs on the whole program).
+	fn checks_io_order(&self) -> bool {
+		self.strict_io || log::enabled(Level::Info)
+	}
+
+	fn needs_ir_text(&self) -> bool {
+		self.print_ir_diff || !self.print_ir_after.is_empty()
+	}
+
+	fn after_pass(&self, pass_name: &str, old_ir_text: Option<&str>, new_ir_text: Option<&str>) {
+		if self
+			.print_ir_after
+			.iter()
+			.any(|name| name == pass_name || name == "all")
+		{
+			eprintln!("# IR after {}", pass_name);
+			eprint!("{}", new_ir_text.unwrap());
+		}
+		if self.print_ir_diff {
+			if let (Some(old_ir_text), Some(new_ir_text)) = (old_ir_text, new_ir_text) {
+				eprint!(
+					"{}",
+					unified_diff(
+						&format!("before {}", pass_name),
+						&format!("after {}", pass_name),
+						old_ir_text,
+						new_ir_text
+					)
+				);
+			}
+		}
+	}
+}
+
+/// Splits the program in chunks of about `CHUNK_SIZE` instructions, each chunk but the last
+/// ending with a top-level loop (so that no soup spans two chunks).
+fn split_into_chunks(raw_prog: &[RawInstr]) -> Vec<&[RawInstr]> {
+	let mut chunks = Vec::new();
+	let mut chunk_start = 0;
+	let mut chunk_size = 0;
+	let mut index = 0;
+	for (instr, body) in top_level(raw_prog) {
+		index += 1 + body.len();
+		chunk_size += 1 + body.len();
+		if chunk_size >= CHUNK_SIZE && matches!(instr, RawInstr::BracketLoop { .. }) {
+			chunks.push(&raw_prog[chunk_start..index]);
+			chunk_start = index;
+			chunk_size = 0;
+		}
+	}
+	if chunk_start < raw_prog.len() || chunks.is_empty() {
+		chunks.push(&raw_prog[chunk_start..]);
+	}
+	chunks
+}
+
+/// Logs (at the `debug` level) and records in the stats (if any) the result of a pass that
+/// started at the given instant.
+fn pass_done(
+	pass_name: &str,
+	start: Instant,
+	soup_prog: &[SoupInstr],
+	transformations: u64,
+	stats: &mut Option<&mut Vec<PassStats>>,
+) {
+	let duration = start.elapsed();
+	if !log::enabled(Level::Debug) && stats.is_none() {
+		return;
+	}
+	let instrs = instr_count(soup_prog);
+	let ir_bytes = ir_memory(soup_prog);
+	log::event(Level::Debug, "pass")
+		.field("name", pass_name)
+		.field("instrs", instrs)
+		.field("ir_bytes", ir_bytes)
+		.field("transformations", transformations as usize)
+		.field("time_ms", duration.as_secs_f64() * 1000.0)
+		.emit();
+	if let Some(stats) = stats {
+		stats.push(PassStats {
+			name: pass_name.to_owned(),
+			instrs,
+			ir_bytes,
+			transformations,
+			duration,
+		});
+	}
+}
@@ -0,0 +1,70 @@
+//! Loading of optimization passes from shared libraries (with the `plugins` feature), for the
+//! `--plugin` option.
+//!
+//! A plugin is a Rust `cdylib` that depends on the same version of xxbf, built by the same
+//! compiler (as it is called through the Rust ABI), and that defines the registration function:
+//!
+//! ```ignore
+//! #[no_mangle]
+//! pub fn xxbf_register_passes(pass_manager: &mut xxbf::passes::PassManager) {
+//!     pass_manager.register_pass("clean-up", MyPass);
+//! }
+//! ```
+
+use crate::passes::PassManager;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_void};
+
+#[link(name = "dl")]
+extern "C" {
+	fn dlopen(filename: *const c_char, flag: c_int) -> *mut c_void;
+	fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+	fn dlerror() -> *const c_char;
+}
+
+const RTLD_NOW: c_int = 2;
+
+/// Name of the function of a plugin that registers its passes.
+pub const REGISTER_FN_NAME: &str = "xxbf_register_passes";
+
+type RegisterFn = fn(&mut PassManager);
+
+/// Loads the plugin at the given path and lets it register its passes in the pass manager. The
+/// plugin is never unloaded, as the code of its passes is in it.
+pub fn load_plugin(path: &str, pass_manager: &mut PassManager) -> Result<(), String> {
+	let path_c = CString::new(path).map_err(|_| format!("invalid plugin path `{}`", path))?;
+	let symbol = CString::new(REGISTER_FN_NAME).unwrap();
+	// Safety: the plugin is trusted to define the registration function with its signature.
+	let register_fn = unsafe {
+		let handle = dlopen(path_c.as_ptr(), RTLD_NOW);
+		if handle.is_null() {
+			return Err(format!(
+				"cannot load plugin `{}`: {}",
+				path,
+				last_dl_error()
+			));
+		}
+		let function = dlsym(handle, symbol.as_ptr());
+		if function.is_null() {
+			return Err(format!(
+				"plugin `{}` does not define `{}`",
+				path, REGISTER_FN_NAME
+			));
+		}
+		std::mem::transmute::<*mut c_void, RegisterFn>(function)
+	};
+	register_fn(pass_manager);
+	Ok(())
+}
+
+fn last_dl_error() -> String {
+	// Safety: `dlerror` returns null or a null-terminated string.
+	unsafe {
+		let error = dlerror();
+		if error.is_null() {
+			"unknown error".to_owned()
+		} else {
+			CStr::from_ptr(error).to_string_lossy().into_owned()
+		}
+	}
+}
@@ -0,0 +1,109 @@
+//! Standard prelude: a library of named routines that programs can include with lines of the
+//! form `@include <std/name>` (see `Extensions::include`), each such line being replaced by the
+//! code of the routine before parsing.
+//!
+//! A routine works on the cells from the one under the head, that are numbered from 0 in its
+//! description, and leaves the head where it started. The cells it uses as scratch space must be
+//! zeros before and are zeros after.
+
+use std::fmt;
+
+pub struct Routine {
+	pub name: &'static str,
+	pub description: &'static str,
+	pub code: &'static str,
+}
+
+pub const ROUTINES: &[Routine] = &[
+	Routine {
+		name: "copy",
+		description: "Adds cell 0 to cell 1, cell 0 keeping its value (cell 2 is scratch).",
+		code: "[>+>+<<-]>>[<<+>>-]<<",
+	},
+	Routine {
+		name: "multiply",
+		description: "Sets cell 0 to cell 0 times cell 1, and cell 1 to zero (cells 2 and 3 \
+			are scratch).",
+		code: "[>[>+>+<<-]>>[<<+>>-]<<<-]>[-]>[<<+>>-]<<",
+	},
+	Routine {
+		name: "divmod",
+		description: "Divides cell 0 by cell 1 (that must be at least 2): cell 0 becomes zero, \
+			cell 1 becomes cell 1 minus the remainder, cell 2 the remainder and cell 3 the \
+			quotient (cells 4 and 5 are scratch).",
+		code: "[->-[>+>>]>[+[-<+>]>+>>]<<<<<]",
+	},
+	Routine {
+		name: "print_u8",
+		description: "Prints cell 0 in decimal, cell 0 keeping its value (cells 1 to 6 are \
+			scratch).",
+		code: ">>++++++++++<<[->+>-[>+>>]>[+[-<+>]>+>>]<<<<<<]>>[-]>>>++++++++++<[->-[>+>>]>\
+			[+[-<+>]>+>>]<<<<<]>[-]>>[>++++++[-<++++++++>]<.<<+>+>[-]]<[<[->-<]++++++[->++++++++<\
+			]>.[-]]<<++++++[-<++++++++>]<.[-]<<[-<+>]<",
+	},
+	Routine {
+		name: "read_u8",
+		description: "Reads a number in decimal (digits up to a newline or the end of the \
+			input) into cell 0, that must be zero (modulo 256, cells 1 to 3 are scratch).",
+		code: ">+[->,[----------[--------------------------------------<<[->>>++++++++++<<<]>>>\
+			[-<<<+>>>]<[-<<+>>]<+>]]<]<",
+	},
+];
+
+pub fn routine(name: &str) -> Option<&'static Routine> {
+	ROUTINES.iter().find(|routine| routine.name == name)
+}
+
+/// An `@include` line that does not name a routine of the prelude.
+#[derive(Debug, Clone)]
+pub struct IncludeError {
+	/// Starting at 1.
+	pub line: usize,
+	pub included: String,
+}
+
+impl fmt::Display for IncludeError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"line {}: `{}` is not a routine of the prelude (`std/` followed by one of {})",
+			self.line,
+			self.included,
+			ROUTINES
+				.iter()
+				.map(|routine| format!("`{}`", routine.name))
+				.collect::<Vec<_>>()
+				.join(", ")
+		)
+	}
+}
+
+/// Replaces the `@include <std/name>` lines (leading and trailing whitespace aside) by the code
+/// of the routines.
+pub fn expand_includes(src_code: &str) -> Result<String, IncludeError> {
+	let mut expanded = String::with_capacity(src_code.len());
+	for (line_index, line) in src_code.split_inclusive('\n').enumerate() {
+		let included = line
+			.trim()
+			.strip_prefix("@include <")
+			.and_then(|rest| rest.strip_suffix('>'));
+		match included {
+			None => expanded.push_str(line),
+			Some(included) => {
+				let routine =
+					included
+						.strip_prefix("std/")
+						.and_then(routine)
+						.ok_or_else(|| IncludeError {
+							line: line_index + 1,
+							included: included.to_owned(),
+						})?;
+				expanded.push_str(routine.code);
+				if line.ends_with('\n') {
+					expanded.push('\n');
+				}
+			}
+		}
+	}
+	Ok(expanded)
+}
@@ -0,0 +1,661 @@
+use crate::arm64asm::{Arm64Os, transpile_linear_to_arm64_asm};
+use crate::astraw::RawInstr;
+use crate::astsoup::{SoupInstr, classify_loops, soupify};
+use crate::bytecode::{DEFAULT_MAX_UNROLLED_BODY_LEN, Op, flatten, peephole, unroll_tiny_loops};
+use crate::cancel::{CancelToken, RunOutcome};
+use crate::config::{CodegenConfig, OptimizeConfig, Target, VmConfig};
+use crate::ctranspiler::{COptions, SourcePositions, transpile_raw_to_c, transpile_soup_to_c};
+use crate::deadcode::strip_dead_code;
+use crate::graph::graphify;
+use crate::hash::stable_hash;
+use crate::iosignature::{IoSignature, analyze_io};
+use crate::irtext::{print_raw, print_soup};
+use crate::javatranspiler::transpile_linear_to_java;
+use crate::lowering::{LinearProgram, lower};
+use crate::luatranspiler::transpile_linear_to_lua;
+use crate::objectfile::emit_object;
+use crate::parser::{
+	ParsingError, instr_spans, lines_and_columns, parse_instr_seq_and_bracket_pairs,
+};
+use crate::passes::{Fuel, IrTooLarge, PassManager, PassStats};
+use crate::progress::{Phase, Progress, Reporter};
+use crate::sandbox::{Sandbox, SandboxOutcome, SandboxRun};
+use crate::shared::HaltState;
+use crate::shtranspiler::transpile_linear_to_sh;
+use crate::tapeconfig::TapeConfig;
+use crate::tapeinit::precompute_tape;
+use crate::tokenizer::Extensions;
+use crate::vm::{Observer, RunStage, run_bytecode, run_graph, run_raw, run_soup};
+use crate::warnings::{Warning, check};
+use crate::watch::Watchpoints;
+use crate::wattranspiler::{LoopSources, transpile_linear_to_wat};
+use std::any::Any;
+use std::panic::AssertUnwindSafe;
+use std::time::Instant;
+
+/// The intermediate representation the program is currently in.
+#[derive(Debug)]
+pub enum Stage {
+	Raw(Vec<RawInstr>),
+	Soup(Vec<SoupInstr>),
+}
+
+/// A parsed program, that can be optimized, transpiled and run.
+#[derive(Debug)]
+pub struct Program {
+	src_code: String,
+	extensions: Extensions,
+	bracket_pairs: Vec<(usize, usize)>,
+	tape_config: TapeConfig,
+	/// Tape the optimizations relied on (see `PassManager::tape_config`), the program can then
+	/// only be run on it or compiled for it.
+	optimized_for: Option<TapeConfig>,
+	/// See `bytecode::unroll_tiny_loops`.
+	max_unrolled_body_len: usize,
+	stage: Stage,
+}
+
+/// The program was optimized for a tape that starts differently from the one it is to be run on
+/// or compiled for (see `TapeConfig::same_start`).
+#[derive(Debug)]
+pub struct TapeMismatch;
+
+impl std::fmt::Display for TapeMismatch {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(
+			f,
+			"the program was optimized for another initial tape or head start, set its tape \
+			configuration before optimizing it"
+		)
+	}
+}
+
+/// Checks that a program optimized for the given tape (if any) can run on the other one.
+pub(crate) fn check_tape(
+	optimized_for: Option<&TapeConfig>,
+	tape_config: &TapeConfig,
+) -> Result<(), TapeMismatch> {
+	match optimized_for {
+		Some(optimized_for) if !optimized_for.same_start(tape_config) => Err(TapeMismatch),
+		_ => Ok(()),
+	}
+}
+
+impl Program {
+	pub fn parse(src_code: &str, extensions: &Extensions) -> Result<Program, Vec<ParsingError>> {
+		Program::parse_impl(src_code, extensions, None)
+	}
+
+	/// Same as `parse`, the progress being reported (see `progress`).
+	pub fn parse_with_progress(
+		src_code: &str,
+		extensions: &Extensions,
+		progress: &mut dyn Progress,
+	) -> Result<Program, Vec<ParsingError>> {
+		Program::parse_impl(src_code, extensions, Some(progress))
+	}
+
+	fn parse_impl(
+		src_code: &str,
+		extensions: &Extensions,
+		progress: Option<&mut dyn Progress>,
+	) -> Result<Program, Vec<ParsingError>> {
+		let (parsing_result, bracket_pairs) =
+			parse_instr_seq_and_bracket_pairs(src_code, extensions, progress);
+		Ok(Program {
+			src_code: src_code.to_owned(),
+			extensions: extensions.clone(),
+			bracket_pairs,
+			tape_config: TapeConfig::default(),
+			optimized_for: None,
+			max_unrolled_body_len: DEFAULT_MAX_UNROLLED_BODY_LEN,
+			stage: Stage::Raw(parsing_result?),
+		})
+	}
+
+	/// The program already optimized to the given IR (such as one from the cache, see `cache`)
+	/// for the default tape, the source code being only used by the backends to refer to it.
+	pub fn from_optimized(
+		src_code: &str,
+		extensions: &Extensions,
+		soup_prog: Vec<SoupInstr>,
+	) -> Program {
+		Program {
+			src_code: src_code.to_owned(),
+			extensions: extensions.clone(),
+			bracket_pairs: Vec::new(),
+			tape_config: TapeConfig::default(),
+			optimized_for: Some(TapeConfig::default()),
+			max_unrolled_body_len: DEFAULT_MAX_UNROLLED_BODY_LEN,
+			stage: Stage::Soup(soup_prog),
+		}
+	}
+
+	pub fn src_code(&self) -> &str {
+		&self.src_code
+	}
+
+	/// Positions of the matching brackets in the source code (see `parser::bracket_pairs`).
+	pub fn bracket_pairs(&self) -> &[(usize, usize)] {
+		&self.bracket_pairs
+	}
+
+	/// Configures the tape for when the program is run or transpiled to C, the other backends
+	/// do not support it (see `tapeconfig`). An optimized program keeps the tape it was optimized
+	/// for.
+	pub fn set_tape_config(&mut self, tape_config: TapeConfig) -> Result<(), TapeMismatch> {
+		check_tape(self.optimized_for.as_ref(), &tape_config)?;
+		self.tape_config = tape_config;
+		Ok(())
+	}
+
+	/// Maximum number of ops of the loop bodies that are duplicated in the bytecode of the
+	/// optimized program (see `bytecode::unroll_tiny_loops`), 0 disabling the duplication.
+	pub fn set_max_unrolled_body_len(&mut self, max_unrolled_body_len: usize) {
+		self.max_unrolled_body_len = max_unrolled_body_len;
+	}
+
+	/// Tape the optimizations relied on, if any (see `PassManager::tape_config`).
+	pub fn optimized_for(&self) -> Option<&TapeConfig> {
+		self.optimized_for.as_ref()
+	}
+
+	pub fn tape_config(&self) -> &TapeConfig {
+		&self.tape_config
+	}
+
+	pub fn stage(&self) -> &Stage {
+		&self.stage
+	}
+
+	/// Warnings about the program, the analyses that find them work on the raw program so there
+	/// are none once it is optimized. They also assume that the tape starts with zeros and the
+	/// head on the first cell, so there are none with an initial tape or a head start.
+	pub fn warnings(&self) -> Vec<Warning> {
+		match &self.stage {
+			Stage::Raw(_)
+				if !self.tape_config.initial_tape.is_empty()
+					|| self.tape_config.head_start != 0 =>
+			{
+				Vec::new()
+			}
+			Stage::Raw(raw_prog) => check(&self.src_code, raw_prog, &self.extensions),
+			Stage::Soup(_) => Vec::new(),
+		}
+	}
+
+	/// What the program does with its input (see `iosignature`), which can be known before
+	/// running it to choose how to give it its input.
+	pub fn io_signature(&self) -> IoSignature {
+		match &self.stage {
+			Stage::Raw(raw_prog) => {
+				// Loops recognized as fixed loops are known to end, and to only move values.
+				let mut soup_prog = soupify(raw_prog);
+				classify_loops(&mut soup_prog, &mut Fuel::new(None));
+				analyze_io(&soup_prog)
+			}
+			Stage::Soup(soup_prog) => analyze_io(soup_prog),
+		}
+	}
+
+	/// Number of instructions of the current intermediate representation of the program.
+	pub fn instr_count(&self) -> usize {
+		match &self.stage {
+			Stage::Raw(raw_prog) => raw_prog.len(),
+			Stage::Soup(soup_prog) => crate::astsoup::instr_count(soup_prog),
+		}
+	}
+
+	/// Textual form of the current intermediate representation of the program (see `irtext`).
+	pub fn ir_text(&self) -> String {
+		match &self.stage {
+			Stage::Raw(raw_prog) => print_raw(raw_prog),
+			Stage::Soup(soup_prog) => print_soup(soup_prog),
+		}
+	}
+
+	/// Hash of the current intermediate representation of the program, that only depends on the
+	/// representation itself (and not on the platform, the Rust version or the source comments).
+	pub fn ir_hash(&self) -> u64 {
+		match &self.stage {
+			Stage::Raw(raw_prog) => stable_hash(raw_prog),
+			Stage::Soup(soup_prog) => stable_hash(soup_prog),
+		}
+	}
+
+	/// Optimizes the program to the given level, level 0 meaning no optimization at all, for its
+	/// tape configuration (see `set_tape_config`). Optimizations already done are never undone.
+	pub fn optimize(&mut self, level: u32) {
+		// The default pass manager has no memory limit.
+		let pass_manager = PassManager {
+			tape_config: Some(self.tape_config.clone()),
+			..PassManager::default()
+		};
+		self.optimize_with(level, &pass_manager).unwrap();
+	}
+
+	/// Same as `optimize` but the given pass manager runs the optimization passes, which fails if
+	/// its memory limit is exceeded (the program is then left unchanged).
+	pub fn optimize_with(
+		&mut self,
+		level: u32,
+		pass_manager: &PassManager,
+	) -> Result<(), IrTooLarge> {
+		if level >= 1 {
+			if let Stage::Raw(raw_prog) = &self.stage {
+				self.stage = Stage::Soup(pass_manager.optimize(raw_prog)?);
+				self.optimized_for = pass_manager.tape_config.clone();
+			}
+		}
+		Ok(())
+	}
+
+	/// Same as `optimize_with` but also returns what each pass did (nothing if the program was
+	/// already optimized or if the level is 0).
+	pub fn optimize_with_stats(
+		&mut self,
+		level: u32,
+		pass_manager: &PassManager,
+	) -> Result<Vec<PassStats>, IrTooLarge> {
+		if level >= 1 {
+			if let Stage::Raw(raw_prog) = &self.stage {
+				let (soup_prog, stats) = pass_manager.optimize_with_stats(raw_prog)?;
+				self.stage = Stage::Soup(soup_prog);
+				self.optimized_for = pass_manager.tape_config.clone();
+				return Ok(stats);
+			}
+		}
+		Ok(Vec::new())
+	}
+
+	/// Same as `optimize_with`, as configured.
+	pub fn optimize_with_config(&mut self, config: &OptimizeConfig) -> Result<(), IrTooLarge> {
+		self.optimize_with(config.level, &config.pass_manager)
+	}
+
+	/// Same as `optimize_with_config`, the progress being reported (see `progress`).
+	pub fn optimize_with_progress(
+		&mut self,
+		config: &OptimizeConfig,
+		progress: &mut dyn Progress,
+	) -> Result<(), IrTooLarge> {
+		if config.level >= 1 {
+			if let Stage::Raw(raw_prog) = &self.stage {
+				let soup_prog = config
+					.pass_manager
+					.optimize_with_progress(raw_prog, progress)?;
+				self.stage = Stage::Soup(soup_prog);
+				self.optimized_for = config.pass_manager.tape_config.clone();
+				return Ok(());
+			}
+		}
+		// There is nothing to do.
+		Reporter::start(Some(progress), Phase::Optimization).finish();
+		Ok(())
+	}
+
+	/// Runs the start of the program that only computes the tape (see `tapeinit`) within the
+	/// given number of steps, the program then being what is left of it, to be run on the
+	/// computed tape (its tape configuration). Returns the number of instructions precomputed.
+	/// The program must be optimized.
+	pub fn precompute_tape(&mut self, max_steps: u64) -> usize {
+		let Stage::Soup(soup_prog) = &self.stage else {
+			panic!("only optimized programs can have their tape precomputed");
+		};
+		let tape_init = precompute_tape(soup_prog, &self.tape_config, max_steps);
+		self.stage = Stage::Soup(tape_init.residual);
+		self.optimized_for = Some(tape_init.tape_config.clone());
+		self.tape_config = tape_init.tape_config;
+		tape_init.precomputed_instrs
+	}
+
+	/// Bytecode of the program, the peephole stage and the duplication of tiny loop bodies being
+	/// only applied to optimized programs.
+	pub fn to_bytecode(&self) -> Vec<Op> {
+		match &self.stage {
+			Stage::Raw(raw_prog) => flatten(&soupify(raw_prog)),
+			Stage::Soup(soup_prog) => {
+				let mut ops = flatten(soup_prog);
+				peephole(&mut ops);
+				unroll_tiny_loops(&mut ops, self.max_unrolled_body_len);
+				ops
+			}
+		}
+	}
+
+	pub fn to_c(&self, options: &COptions) -> String {
+		self.to_c_with_tape(options, &self.tape_config)
+	}
+
+	fn to_c_with_tape(&self, options: &COptions, tape_config: &TapeConfig) -> String {
+		match &self.stage {
+			Stage::Raw(raw_prog) => {
+				let source_positions =
+					options
+						.line_directives
+						.as_ref()
+						.map(|file_name| SourcePositions {
+							file_name: file_name.clone(),
+							instr_positions: lines_and_columns(
+								&self.src_code,
+								instr_spans(&self.src_code, &self.extensions)
+									.iter()
+									.map(|span| span.start),
+							),
+						});
+				transpile_raw_to_c(raw_prog, options, tape_config, source_positions)
+			}
+			Stage::Soup(soup_prog) => transpile_soup_to_c(soup_prog, options, tape_config),
+		}
+	}
+
+	/// Generates the code of the program as configured, a tape configuration being only
+	/// supported by the C target. The unreachable instructions of an optimized program are
+	/// stripped first (see `deadcode`).
+	pub fn compile(&self, config: &CodegenConfig) -> Result<Vec<u8>, TapeMismatch> {
+		check_tape(self.optimized_for.as_ref(), &config.tape_config)?;
+		if !config.tape_config.is_default() {
+			assert!(
+				matches!(config.target, Target::C),
+				"an initial tape, a head start or tape regions are only supported by the C target"
+			);
+		}
+		Ok(match &self.stage {
+			Stage::Soup(soup_prog) => Program {
+				src_code: self.src_code.clone(),
+				extensions: self.extensions.clone(),
+				bracket_pairs: self.bracket_pairs.clone(),
+				tape_config: self.tape_config.clone(),
+				optimized_for: self.optimized_for.clone(),
+				max_unrolled_body_len: self.max_unrolled_body_len,
+				stage: Stage::Soup(strip_dead_code(soup_prog, &config.tape_config)),
+			}
+			.compile_stage(config),
+			Stage::Raw(_) => self.compile_stage(config),
+		})
+	}
+
+	fn compile_stage(&self, config: &CodegenConfig) -> Vec<u8> {
+		match &config.target {
+			Target::C => self
+				.to_c_with_tape(&config.c_options, &config.tape_config)
+				.into_bytes(),
+			Target::Lua => self.to_lua().into_bytes(),
+			Target::Java(class_name) => self.to_java(class_name).into_bytes(),
+			Target::Sh => self.to_sh().into_bytes(),
+			Target::Wat => self.to_wat().into_bytes(),
+			Target::Arm64Asm(os) => self.to_arm64_asm(*os).into_bytes(),
+			Target::Object => self.to_object(),
+		}
+	}
+
+	/// Same as `compile`, the progress being reported (only when the code generation starts and
+	/// when it ends, see `progress`).
+	pub fn compile_with_progress(
+		&self,
+		config: &CodegenConfig,
+		progress: &mut dyn Progress,
+	) -> Result<Vec<u8>, TapeMismatch> {
+		let mut reporter = Reporter::start(Some(progress), Phase::Codegen);
+		let code = self.compile(config)?;
+		reporter.finish();
+		Ok(code)
+	}
+
+	/// Linear form of the program shared by the backends other than C (see `lowering`).
+	pub fn to_linear(&self) -> LinearProgram {
+		match &self.stage {
+			Stage::Raw(raw_prog) => lower(&soupify(raw_prog)),
+			Stage::Soup(soup_prog) => lower(soup_prog),
+		}
+	}
+
+	pub fn to_lua(&self) -> String {
+		transpile_linear_to_lua(&self.to_linear())
+	}
+
+	pub fn to_sh(&self) -> String {
+		transpile_linear_to_sh(&self.to_linear())
+	}
+
+	/// WebAssembly text of the program, where loops are commented with their position in the
+	/// source code if the program is not optimized.
+	pub fn to_wat(&self) -> String {
+		let loop_sources = match &self.stage {
+			Stage::Raw(_) => Some(LoopSources {
+				src_code: &self.src_code,
+				bracket_pairs: &self.bracket_pairs,
+			}),
+			Stage::Soup(_) => None,
+		};
+		transpile_linear_to_wat(&self.to_linear(), loop_sources)
+	}
+
+	/// Java source of a class of the given name that runs the program.
+	pub fn to_java(&self, class_name: &str) -> String {
+		transpile_linear_to_java(&self.to_linear(), class_name)
+	}
+
+	/// AArch64 assembly of the program for the given operating system (see `arm64asm`).
+	pub fn to_arm64_asm(&self, os: Arm64Os) -> String {
+		transpile_linear_to_arm64_asm(&self.to_linear(), os)
+	}
+
+	/// Native x86-64 Linux object file of the program (see `objectfile`).
+	pub fn to_object(&self) -> Vec<u8> {
+		emit_object(&self.to_linear())
+	}
+
+	/// Runs the bytecode of the program (even if it is not optimized), compiling its hot loops
+	/// once they ran the given number of iterations (see `jit`).
+	pub fn run_with_jit(&self, input: Option<Vec<u8>>, jit_threshold: u64) -> Vec<u8> {
+		run_bytecode(
+			&self.to_bytecode(),
+			input,
+			&self.tape_config,
+			Some(jit_threshold),
+			None,
+			None,
+		)
+	}
+
+	/// Runs the program, reading from the given input (or interacting with the user via stdin and
+	/// stdout if there is none), and returns the output.
+	pub fn run(&self, input: Option<Vec<u8>>) -> Vec<u8> {
+		match &self.stage {
+			Stage::Raw(raw_prog) => run_raw(raw_prog, input, &self.tape_config, None, None, None),
+			Stage::Soup(_) => run_bytecode(
+				&self.to_bytecode(),
+				input,
+				&self.tape_config,
+				None,
+				None,
+				None,
+			),
+		}
+	}
+
+	/// Same as `run` (or `run_with_jit` if a JIT threshold is given), the tape being configured
+	/// by the given configuration instead of the one of the program (which an optimized program
+	/// may not support, see `optimized_for`).
+	pub fn run_with(
+		&self,
+		input: Option<Vec<u8>>,
+		config: &VmConfig,
+	) -> Result<Vec<u8>, TapeMismatch> {
+		check_tape(self.optimized_for.as_ref(), &config.tape_config)?;
+		Ok(match (&self.stage, config.jit_threshold) {
+			(Stage::Raw(raw_prog), None) => {
+				run_raw(raw_prog, input, &config.tape_config, None, None, None)
+			}
+			_ => run_bytecode(
+				&self.to_bytecode(),
+				input,
+				&config.tape_config,
+				config.jit_threshold,
+				None,
+				None,
+			),
+		})
+	}
+
+	/// Same as `run`, with the given execution engine (the JIT threshold being only used by
+	/// `RunStage::Jit`). The raw stage needs a program that is not optimized, the other stages
+	/// run the soup of the program (soupified if it is not optimized).
+	pub fn run_at_stage(
+		&self,
+		input: Option<Vec<u8>>,
+		stage: RunStage,
+		jit_threshold: u64,
+		observer: Option<&mut dyn Observer>,
+	) -> Vec<u8> {
+		let soup_prog = || match &self.stage {
+			Stage::Raw(raw_prog) => soupify(raw_prog),
+			Stage::Soup(soup_prog) => soup_prog.clone(),
+		};
+		match (stage, &self.stage) {
+			(RunStage::Raw, Stage::Raw(raw_prog)) => {
+				run_raw(raw_prog, input, &self.tape_config, observer, None, None)
+			}
+			(RunStage::Raw, Stage::Soup(_)) => {
+				panic!("the raw stage needs a program that is not optimized")
+			}
+			(RunStage::Soup, _) => run_soup(&soup_prog(), input, &self.tape_config, observer),
+			(RunStage::Graph, _) => {
+				run_graph(&graphify(&soup_prog()), input, &self.tape_config, observer)
+			}
+			(RunStage::Bytecode, _) => run_bytecode(
+				&self.to_bytecode(),
+				input,
+				&self.tape_config,
+				None,
+				observer,
+				None,
+			),
+			(RunStage::Jit, _) => run_bytecode(
+				&self.to_bytecode(),
+				input,
+				&self.tape_config,
+				Some(jit_threshold),
+				observer,
+				None,
+			),
+		}
+	}
+
+	/// Same as `run_with` (without the JIT), stopping early once the given token is cancelled
+	/// (see `cancel`).
+	pub fn run_cancellable(
+		&self,
+		input: Option<Vec<u8>>,
+		config: &VmConfig,
+		cancel: &CancelToken,
+	) -> Result<RunOutcome, TapeMismatch> {
+		check_tape(self.optimized_for.as_ref(), &config.tape_config)?;
+		let start = Instant::now();
+		let mut halt_state = HaltState::default();
+		let output = match &self.stage {
+			Stage::Raw(raw_prog) => run_raw(
+				raw_prog,
+				input,
+				&config.tape_config,
+				Some(&mut halt_state),
+				None,
+				Some(cancel),
+			),
+			Stage::Soup(_) => run_bytecode(
+				&self.to_bytecode(),
+				input,
+				&config.tape_config,
+				None,
+				Some(&mut halt_state),
+				Some(cancel),
+			),
+		};
+		Ok(RunOutcome {
+			output,
+			cancelled: cancel.is_cancelled(),
+			head: halt_state.head,
+			used_cells: halt_state.used_cells,
+			duration: start.elapsed(),
+		})
+	}
+
+	/// Runs the program on the given input within the limits of the sandbox (see `sandbox`),
+	/// without interacting with the user, and returns what it did instead of stopping on an error.
+	pub fn run_sandboxed(&self, input: Vec<u8>, sandbox: &Sandbox) -> SandboxOutcome {
+		let mut sandbox_run = SandboxRun::new(sandbox);
+		let result = match sandbox.check_input(&input) {
+			Err(error) => Err(Box::new(error) as Box<dyn Any + Send>),
+			Ok(()) => std::panic::catch_unwind(AssertUnwindSafe(|| match &self.stage {
+				Stage::Raw(raw_prog) => run_raw(
+					raw_prog,
+					Some(input),
+					&self.tape_config,
+					Some(&mut sandbox_run),
+					None,
+					None,
+				),
+				Stage::Soup(_) => run_bytecode(
+					&self.to_bytecode(),
+					Some(input),
+					&self.tape_config,
+					None,
+					Some(&mut sandbox_run),
+					None,
+				),
+			})),
+		};
+		sandbox_run.into_outcome(result)
+	}
+
+	/// Same as `run` (or `run_with_jit` if a JIT threshold is given), the observer being notified
+	/// of what the program does.
+	pub fn run_observed(
+		&self,
+		input: Option<Vec<u8>>,
+		jit_threshold: Option<u64>,
+		observer: &mut dyn Observer,
+	) -> Vec<u8> {
+		match (&self.stage, jit_threshold) {
+			(Stage::Raw(raw_prog), None) => run_raw(
+				raw_prog,
+				input,
+				&self.tape_config,
+				Some(observer),
+				None,
+				None,
+			),
+			_ => run_bytecode(
+				&self.to_bytecode(),
+				input,
+				&self.tape_config,
+				jit_threshold,
+				Some(observer),
+				None,
+			),
+		}
+	}
+
+	/// Same as `run`, stopping when one of the watchpoints is hit (see `watch`). The program
+	/// must not be optimized, so that the hit can be traced back to an instruction of the source
+	/// code.
+	pub fn run_watched(
+		&self,
+		input: Option<Vec<u8>>,
+		watchpoints: &mut Watchpoints,
+		observer: Option<&mut dyn Observer>,
+	) -> Vec<u8> {
+		match &self.stage {
+			Stage::Raw(raw_prog) => run_raw(
+				raw_prog,
+				input,
+				&self.tape_config,
+				observer,
+				Some(watchpoints),
+				None,
+			),
+			Stage::Soup(_) => panic!("watchpoints need a program that is not optimized"),
+		}
+	}
+}
@@ -0,0 +1,62 @@
+//! Progress of the compilation of (very large) programs, reported to applications embedding xxbf
+//! (such as a GUI or a language server) so that they do not appear frozen. Parsing and the
+//! optimization passes report their progress as they go, code generation only when it starts and
+//! when it ends.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+	Parsing,
+	Optimization,
+	Codegen,
+}
+
+pub trait Progress {
+	/// Called with the percentage (from 0 to 100) of the phase that is done, at least when the
+	/// phase starts and when it ends, and only when the percentage changes.
+	fn on_progress(&mut self, phase: Phase, percent: u8);
+}
+
+impl<F: FnMut(Phase, u8)> Progress for F {
+	fn on_progress(&mut self, phase: Phase, percent: u8) {
+		self(phase, percent)
+	}
+}
+
+/// Reports the progress of a phase, if there is someone to report it to.
+pub(crate) struct Reporter<'a> {
+	progress: Option<&'a mut dyn Progress>,
+	phase: Phase,
+	last_percent: Option<u8>,
+}
+
+impl<'a> Reporter<'a> {
+	/// Reports that the phase starts.
+	pub(crate) fn start(progress: Option<&'a mut dyn Progress>, phase: Phase) -> Reporter<'a> {
+		let mut reporter = Reporter {
+			progress,
+			phase,
+			last_percent: None,
+		};
+		reporter.report(0, 1);
+		reporter
+	}
+
+	/// Reports that `done` out of `total` units of work are done.
+	pub(crate) fn report(&mut self, done: usize, total: usize) {
+		if let Some(progress) = &mut self.progress {
+			let percent = (done.min(total) * 100).checked_div(total).unwrap_or(100) as u8;
+			if self.last_percent != Some(percent) {
+				self.last_percent = Some(percent);
+				progress.on_progress(self.phase, percent);
+			}
+		}
+	}
+
+	pub(crate) fn finish(&mut self) {
+		self.report(1, 1);
+	}
+
+	pub(crate) fn is_listened(&self) -> bool {
+		self.progress.is_some()
+	}
+}
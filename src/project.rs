@@ -0,0 +1,497 @@
+//! Projects: a manifest (`xxbf.toml`) lists programs with the targets they are compiled to, their
+//! options and their test cases, for `xxbf build` to compile all of them and `xxbf test` to check
+//! all the test cases. For example
+//!
+//! ```toml
+//! # Where the compiled programs are written (`build` by default).
+//! out_dir = "build"
+//!
+//! [[program]]
+//! name = "rev"
+//! src = "rev.bf"
+//! targets = ["c", "lua"]
+//! optimize = true
+//! extensions = ["comments"]
+//! dialect = "brainfuck"
+//!
+//! [[program.test]]
+//! name = "letters"
+//! input = "abc"
+//! output = "cba"
+//!
+//! [[program.test]]
+//! input_file = "tests/long.in"
+//! output_file = "tests/long.out"
+//! ```
+//!
+//! The manifest is a subset of TOML: top-level keys and `[[program]]` and `[[program.test]]`
+//! tables of keys whose values are strings, booleans or arrays (on one line) of strings. Paths
+//! are relative to the directory of the manifest.
+//!
+//! Targets are the backends of `conformance`, and a program is written to
+//! `<out_dir>/<name>/<file>` where the file is named as the backend expects it (such as
+//! `prog.c` or `Main.java`). Test cases are run by the interpreter, and by each target of the
+//! program whose toolchain is available (the compiled programs getting the input followed by a 0
+//! byte, see `conformance`).
+
+use crate::conformance::{Backend, Verdict, backends, build_and_run};
+use crate::frontend::frontend;
+use crate::prelude::expand_includes;
+use crate::program::Program;
+use crate::tokenizer::{Extensions, host_call_char};
+use std::path::{Path, PathBuf};
+
+/// Name of the manifest file when none is given.
+pub const DEFAULT_MANIFEST: &str = "xxbf.toml";
+
+pub struct Manifest {
+	pub out_dir: PathBuf,
+	pub programs: Vec<ProjectProgram>,
+}
+
+pub struct ProjectProgram {
+	pub name: String,
+	pub src: PathBuf,
+	/// Names of the backends the program is compiled to (C by default).
+	pub targets: Vec<String>,
+	pub optimize: bool,
+	pub extensions: Extensions,
+	pub tests: Vec<TestCase>,
+}
+
+pub struct TestCase {
+	pub name: String,
+	pub input: Vec<u8>,
+	pub expected_output: Vec<u8>,
+}
+
+#[derive(Debug)]
+enum Value {
+	String(String),
+	Boolean(bool),
+	Array(Vec<Value>),
+}
+
+/// Where the keys being read go.
+enum Section {
+	Root,
+	Program,
+	Test,
+}
+
+/// A test case being read, its input and expected output being given directly or by files.
+#[derive(Default)]
+struct TestCaseEntry {
+	name: Option<String>,
+	input: Option<Vec<u8>>,
+	expected_output: Option<Vec<u8>>,
+}
+
+impl Manifest {
+	/// Reads the manifest at the given path, the files it refers to must exist.
+	pub fn load(path: &Path) -> Result<Manifest, String> {
+		let text = std::fs::read_to_string(path)
+			.map_err(|error| format!("cannot read `{}`: {}", path.display(), error))?;
+		let dir = path.parent().unwrap_or(Path::new("."));
+		Manifest::parse(&text, dir).map_err(|error| format!("{}: {}", path.display(), error))
+	}
+
+	/// Parses the text of a manifest, paths being relative to the given directory.
+	pub fn parse(text: &str, dir: &Path) -> Result<Manifest, String> {
+		let mut manifest = Manifest {
+			out_dir: dir.join("build"),
+			programs: Vec::new(),
+		};
+		// Test cases of the last program, that are completed when the next section starts.
+		let mut tests: Vec<TestCaseEntry> = Vec::new();
+		let mut section = Section::Root;
+		for (line_index, line) in text.lines().enumerate() {
+			let line_number = line_index + 1;
+			let error = |message: String| format!("line {}: {}", line_number, message);
+			let line = strip_comment(line).trim();
+			if line.is_empty() {
+				continue;
+			}
+			if let Some(header) = line
+				.strip_prefix("[[")
+				.and_then(|rest| rest.strip_suffix("]]"))
+			{
+				section = match header.trim() {
+					"program" => {
+						complete_tests(&mut manifest, &mut tests).map_err(error)?;
+						manifest.programs.push(ProjectProgram {
+							name: String::new(),
+							src: PathBuf::new(),
+							targets: vec!["c".to_owned()],
+							optimize: true,
+							extensions: Extensions::default(),
+							tests: Vec::new(),
+						});
+						Section::Program
+					}
+					"program.test" if !manifest.programs.is_empty() => {
+						tests.push(TestCaseEntry::default());
+						Section::Test
+					}
+					"program.test" => {
+						return Err(error("a test must follow a program".to_owned()));
+					}
+					header => return Err(error(format!("unknown table `{}`", header))),
+				};
+				continue;
+			}
+			let (key, value) = line
+				.split_once('=')
+				.ok_or_else(|| error("expected `key = value`".to_owned()))?;
+			let key = key.trim();
+			let value = parse_value(value.trim()).map_err(error)?;
+			match section {
+				Section::Root => match key {
+					"out_dir" => {
+						manifest.out_dir = dir.join(expect_string(key, value).map_err(error)?)
+					}
+					_ => return Err(error(format!("unknown key `{}`", key))),
+				},
+				Section::Program => {
+					let program = manifest.programs.last_mut().unwrap();
+					set_program_key(program, key, value, dir).map_err(error)?;
+				}
+				Section::Test => {
+					let test = tests.last_mut().unwrap();
+					set_test_key(test, key, value, dir).map_err(error)?;
+				}
+			}
+		}
+		complete_tests(&mut manifest, &mut tests)?;
+		for program in &manifest.programs {
+			if program.name.is_empty() || program.src.as_os_str().is_empty() {
+				return Err("every program must have a `name` and a `src`".to_owned());
+			}
+		}
+		Ok(manifest)
+	}
+}
+
+/// Removes the comment (that starts with a `#` outside of strings) of the line.
+fn strip_comment(line: &str) -> &str {
+	let mut quote = None;
+	let mut escaped = false;
+	for (index, c) in line.char_indices() {
+		match (quote, c) {
+			(Some('"'), '\\') if !escaped => {
+				escaped = true;
+				continue;
+			}
+			(Some(q), c) if c == q && !escaped => quote = None,
+			(None, '"' | '\'') => quote = Some(c),
+			(None, '#') => return &line[..index],
+			_ => {}
+		}
+		escaped = false;
+	}
+	line
+}
+
+fn parse_value(text: &str) -> Result<Value, String> {
+	let (value, rest) = parse_value_prefix(text)?;
+	if !rest.trim().is_empty() {
+		return Err(format!("unexpected `{}` after a value", rest.trim()));
+	}
+	Ok(value)
+}
+
+/// Parses the value at the start of the text, returning it with what follows.
+fn parse_value_prefix(text: &str) -> Result<(Value, &str), String> {
+	if let Some(rest) = text.strip_prefix('"') {
+		let mut string = String::new();
+		let mut chars = rest.char_indices();
+		while let Some((index, c)) = chars.next() {
+			match c {
+				'"' => return Ok((Value::String(string), &rest[index + 1..])),
+				'\\' => string.push(match chars.next().map(|(_, c)| c) {
+					Some('n') => '\n',
+					Some('t') => '\t',
+					Some('r') => '\r',
+					Some('"') => '"',
+					Some('\\') => '\\',
+					Some('u') => {
+						let hex: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+						u32::from_str_radix(&hex, 16)
+							.ok()
+							.and_then(char::from_u32)
+							.ok_or_else(|| format!("invalid escape `\\u{}`", hex))?
+					}
+					Some(c) => return Err(format!("invalid escape `\\{}`", c)),
+					None => break,
+				}),
+				c => string.push(c),
+			}
+		}
+		Err("unterminated string".to_owned())
+	} else if let Some(rest) = text.strip_prefix('\'') {
+		let end = rest
+			.find('\'')
+			.ok_or_else(|| "unterminated string".to_owned())?;
+		Ok((Value::String(rest[..end].to_owned()), &rest[end + 1..]))
+	} else if let Some(mut rest) = text.strip_prefix('[') {
+		let mut values = Vec::new();
+		loop {
+			rest = rest.trim_start();
+			if let Some(after) = rest.strip_prefix(']') {
+				return Ok((Value::Array(values), after));
+			}
+			let (value, after) = parse_value_prefix(rest)?;
+			values.push(value);
+			rest = after.trim_start();
+			if let Some(after) = rest.strip_prefix(',') {
+				rest = after;
+			} else if !rest.starts_with(']') {
+				return Err("expected `,` or `]` in an array".to_owned());
+			}
+		}
+	} else if let Some(rest) = text.strip_prefix("true") {
+		Ok((Value::Boolean(true), rest))
+	} else if let Some(rest) = text.strip_prefix("false") {
+		Ok((Value::Boolean(false), rest))
+	} else {
+		Err(format!("unsupported value `{}`", text))
+	}
+}
+
+fn expect_string(key: &str, value: Value) -> Result<String, String> {
+	match value {
+		Value::String(string) => Ok(string),
+		_ => Err(format!("`{}` expects a string", key)),
+	}
+}
+
+fn expect_strings(key: &str, value: Value) -> Result<Vec<String>, String> {
+	match value {
+		Value::Array(values) => values
+			.into_iter()
+			.map(|value| expect_string(key, value))
+			.collect::<Result<_, _>>()
+			.map_err(|_| format!("`{}` expects an array of strings", key)),
+		_ => Err(format!("`{}` expects an array of strings", key)),
+	}
+}
+
+fn read_file(dir: &Path, path: &str) -> Result<Vec<u8>, String> {
+	std::fs::read(dir.join(path)).map_err(|error| format!("cannot read `{}`: {}", path, error))
+}
+
+fn set_program_key(
+	program: &mut ProjectProgram,
+	key: &str,
+	value: Value,
+	dir: &Path,
+) -> Result<(), String> {
+	match key {
+		"name" => program.name = expect_string(key, value)?,
+		"src" => program.src = dir.join(expect_string(key, value)?),
+		"targets" => {
+			let names = expect_strings(key, value)?;
+			let backends = backends();
+			if let Some(name) = names
+				.iter()
+				.find(|name| !backends.iter().any(|backend| backend.name == name.as_str()))
+			{
+				return Err(format!("unknown target `{}`", name));
+			}
+			program.targets = names;
+		}
+		"optimize" => match value {
+			Value::Boolean(optimize) => program.optimize = optimize,
+			_ => return Err("`optimize` expects a boolean".to_owned()),
+		},
+		"extensions" => {
+			for name in expect_strings(key, value)? {
+				match name.as_str() {
+					"comments" => program.extensions.comments = true,
+					"clock" => program.extensions.clock = true,
+					"random" => program.extensions.random = true,
+					"extended-type-1" => program.extensions.extended_type_1 = true,
+					"include" => program.extensions.include = true,
+					"loop-attributes" => program.extensions.loop_attributes = true,
+					name => match host_call_char(name) {
+						Some(c) => program.extensions.host_call = Some(c),
+						None => return Err(format!("unknown extension `{}`", name)),
+					},
+				}
+			}
+			program.extensions.check()?;
+		}
+		"dialect" => {
+			let name = expect_string(key, value)?;
+			program.extensions.frontend =
+				frontend(&name).ok_or_else(|| format!("unknown dialect `{}`", name))?;
+			program.extensions.check()?;
+		}
+		_ => return Err(format!("unknown key `{}` (for a program)", key)),
+	}
+	Ok(())
+}
+
+fn set_test_key(
+	test: &mut TestCaseEntry,
+	key: &str,
+	value: Value,
+	dir: &Path,
+) -> Result<(), String> {
+	match key {
+		"name" => test.name = Some(expect_string(key, value)?),
+		"input" => test.input = Some(expect_string(key, value)?.into_bytes()),
+		"input_file" => test.input = Some(read_file(dir, &expect_string(key, value)?)?),
+		"output" => test.expected_output = Some(expect_string(key, value)?.into_bytes()),
+		"output_file" => {
+			test.expected_output = Some(read_file(dir, &expect_string(key, value)?)?);
+		}
+		_ => return Err(format!("unknown key `{}` (for a test)", key)),
+	}
+	Ok(())
+}
+
+/// Gives the test cases read to the last program (the input being empty by default).
+fn complete_tests(manifest: &mut Manifest, tests: &mut Vec<TestCaseEntry>) -> Result<(), String> {
+	let Some(program) = manifest.programs.last_mut() else {
+		return Ok(());
+	};
+	for entry in tests.drain(..) {
+		let name = entry
+			.name
+			.unwrap_or_else(|| format!("test {}", program.tests.len() + 1));
+		let expected_output = entry.expected_output.ok_or_else(|| {
+			format!(
+				"the test `{}` of `{}` has no `output` nor `output_file`",
+				name, program.name
+			)
+		})?;
+		program.tests.push(TestCase {
+			name,
+			input: entry.input.unwrap_or_default(),
+			expected_output,
+		});
+	}
+	Ok(())
+}
+
+impl ProjectProgram {
+	/// Parses (and optimizes if asked to) the program.
+	pub fn load(&self) -> Result<Program, String> {
+		let src_code = std::fs::read_to_string(&self.src)
+			.map_err(|error| format!("cannot read `{}`: {}", self.src.display(), error))?;
+		let src_code = if self.extensions.include {
+			expand_includes(&src_code)
+				.map_err(|error| format!("in `{}`, {}", self.src.display(), error))?
+		} else {
+			src_code
+		};
+		let mut program = Program::parse(&src_code, &self.extensions)
+			.map_err(|_| format!("cannot parse `{}`", self.src.display()))?;
+		if self.optimize {
+			program.optimize(1);
+		}
+		Ok(program)
+	}
+
+	fn backends(&self) -> impl Iterator<Item = Backend> + '_ {
+		backends()
+			.into_iter()
+			.filter(move |backend| self.targets.iter().any(|name| name == backend.name))
+	}
+}
+
+/// Compiles every program of the project to every of its targets, the given callback being
+/// called with the path of each file written.
+pub fn build_project(
+	manifest: &Manifest,
+	mut on_built: impl FnMut(&ProjectProgram, &str, &Path),
+) -> Result<(), String> {
+	for project_program in &manifest.programs {
+		let program = project_program.load()?;
+		let dir = manifest.out_dir.join(&project_program.name);
+		std::fs::create_dir_all(&dir)
+			.map_err(|error| format!("cannot create `{}`: {}", dir.display(), error))?;
+		for backend in project_program.backends() {
+			let path = dir.join(backend.file_name);
+			std::fs::write(&path, (backend.emit)(&program))
+				.map_err(|error| format!("cannot write `{}`: {}", path.display(), error))?;
+			on_built(project_program, backend.name, &path);
+		}
+	}
+	Ok(())
+}
+
+#[derive(Debug)]
+pub struct TestResult {
+	pub program_name: String,
+	pub test_name: String,
+	/// `interpreter` or the name of the backend that ran the test.
+	pub runner: &'static str,
+	/// `Skipped` if the toolchain of the backend is not available.
+	pub verdict: Verdict,
+}
+
+/// Runs every test case of the project, the given callback being called with the result of each
+/// of them as soon as it is known.
+pub fn test_project(
+	manifest: &Manifest,
+	mut on_result: impl FnMut(&TestResult),
+) -> Result<Vec<TestResult>, String> {
+	let work_dir = std::env::temp_dir().join(format!("xxbf-test-{}", std::process::id()));
+	let mut results = Vec::new();
+	for project_program in &manifest.programs {
+		if project_program.tests.is_empty() {
+			continue;
+		}
+		let program = project_program.load()?;
+		let mut report = |test: &TestCase, runner, verdict| {
+			let result = TestResult {
+				program_name: project_program.name.clone(),
+				test_name: test.name.clone(),
+				runner,
+				verdict,
+			};
+			on_result(&result);
+			results.push(result);
+		};
+		for test in &project_program.tests {
+			let actual = program.run(Some(test.input.clone()));
+			report(
+				test,
+				"interpreter",
+				verdict(&test.expected_output, Ok(actual)),
+			);
+		}
+		for backend in project_program.backends() {
+			let code = (backend.emit)(&program);
+			for (test_index, test) in project_program.tests.iter().enumerate() {
+				if !backend.is_available() {
+					report(test, backend.name, Verdict::Skipped);
+					continue;
+				}
+				let dir = work_dir.join(format!(
+					"{}-{}-{}",
+					project_program.name, backend.name, test_index
+				));
+				let mut input = test.input.clone();
+				input.push(0);
+				let actual = build_and_run(&backend, &code, &dir, &input);
+				report(test, backend.name, verdict(&test.expected_output, actual));
+			}
+		}
+	}
+	std::fs::remove_dir_all(&work_dir).ok();
+	Ok(results)
+}
+
+fn verdict(expected: &[u8], actual: Result<Vec<u8>, String>) -> Verdict {
+	match actual {
+		Ok(actual) if actual == expected => Verdict::Pass,
+		Ok(actual) => Verdict::Fail {
+			expected: expected.to_vec(),
+			actual,
+		},
+		Err(error) => Verdict::Error(error),
+	}
+}
@@ -0,0 +1,669 @@
+//! Structured control flow recovered from a control flow graph (see `graph`), so that passes that
+//! work on the graph, and may restructure its control flow, can still feed the backends that only
+//! have structured control flow (the soup IR and the backends it feeds, without gotos).
+//!
+//! The graph is structured as in "Beyond Relooper" (Ramsey, 2022): following the dominator tree,
+//! a block that is the target of several forward branches (a merge block) is placed after a
+//! `Block` that the branches exit, and a loop header starts a `Loop` that the back edges continue.
+//! The exits of a loop are placed after it the same way (rather than inside it), so that the
+//! loops of a sequence stay in a sequence. Irreducible control flow (a cycle that can be entered
+//! at several blocks) is first made reducible by copying the blocks of the cycle, once for each
+//! additional entry.
+//!
+//! The structured control flow is turned back into a soup program when its branches are tests of
+//! loops (as in the graphs made by `graphify`, see `ungraphify`).
+
+use crate::astraw::LoopAttributes;
+use crate::astsoup::SoupInstr;
+use crate::extended::ExtInstr;
+use crate::graph::{BlockId, Graph, Terminator};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// Structured control flow, where control never falls off the end of a sequence of structured
+/// instructions (it always ends with a branch, a halt or a construct that does not fall through).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Structured {
+	/// The instructions of the block (without its terminator).
+	Code(BlockId),
+	/// The body runs, a branch to the block continuing after it.
+	Block(Vec<Structured>),
+	/// The body runs, a branch to the loop running it again.
+	Loop(Vec<Structured>),
+	/// Branches on the value of the cell under the head.
+	If {
+		if_zero: Vec<Structured>,
+		if_non_zero: Vec<Structured>,
+	},
+	/// Branches to the enclosing block or loop of the given depth, 0 being the innermost (ifs not
+	/// counting).
+	Br(usize),
+	/// End of the program.
+	Halt,
+}
+
+/// Structures the control flow of the graph, making it reducible first if it is not (which adds
+/// copies of blocks to the graph). Blocks that cannot be reached are left out.
+pub fn structure(graph: &mut Graph) -> Vec<Structured> {
+	let mut analysis = Analysis::new(graph);
+	if !analysis.is_reducible() {
+		let mut reachable: HashSet<BlockId> = analysis.order.iter().copied().collect();
+		let blocks = reachable.iter().copied().collect();
+		make_reducible(graph, &blocks, &mut reachable);
+		analysis = Analysis::new(graph);
+	}
+	let mut structured = Vec::new();
+	analysis.tree(0, &mut Vec::new(), &mut structured);
+	structured
+}
+
+/// The soup program of the graph, if its control flow can be structured with loops only (see
+/// `to_soup`).
+pub fn ungraphify(graph: &Graph) -> Result<Vec<SoupInstr>, String> {
+	let mut graph = graph.clone();
+	let structured = structure(&mut graph);
+	to_soup(&graph, &structured)
+}
+
+/// What control falling off the end of a soup instruction sequence does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Continuation {
+	/// The program halts.
+	End,
+	/// Continues after a block or continues a loop, identified by a number.
+	Label(usize),
+}
+
+/// The soup program of the structured control flow of the graph, that fails if the control flow
+/// needs more than loops: a branch that is not the test at the start of a loop (such as an if or
+/// a break out of a loop), or a halt that is not at the end of the program.
+///
+/// A loop that runs instructions before its test becomes these instructions followed by a loop
+/// that runs them again at the end of its body.
+pub fn to_soup(graph: &Graph, structured: &[Structured]) -> Result<Vec<SoupInstr>, String> {
+	let mut soup_prog = Vec::new();
+	SoupBuilder {
+		graph,
+		labels: Vec::new(),
+		next_label: 0,
+	}
+	.seq(structured, Continuation::End, &mut soup_prog)?;
+	Ok(soup_prog)
+}
+
+struct SoupBuilder<'a> {
+	graph: &'a Graph,
+	/// Continuation of a branch to each enclosing block or loop, the innermost being the last.
+	labels: Vec<Continuation>,
+	next_label: usize,
+}
+
+impl SoupBuilder<'_> {
+	fn new_label(&mut self) -> Continuation {
+		self.next_label += 1;
+		Continuation::Label(self.next_label - 1)
+	}
+
+	fn code(&self, id: BlockId, soup_prog: &mut Vec<SoupInstr>) {
+		soup_prog.extend(
+			self.graph.blocks[&id]
+				.soup_instrs
+				.iter()
+				.map(|(_, instr)| instr.clone()),
+		);
+	}
+
+	/// Adds the soup instructions of the sequence, falling off its end doing the continuation.
+	fn seq(
+		&mut self,
+		structured: &[Structured],
+		continuation: Continuation,
+		soup_prog: &mut Vec<SoupInstr>,
+	) -> Result<(), String> {
+		for (index, instr) in structured.iter().enumerate() {
+			let rest = &structured[index + 1..];
+			match instr {
+				Structured::Code(id) => self.code(*id, soup_prog),
+				Structured::Halt => {
+					let ends_program = matches!(
+						soup_prog.last(),
+						Some(SoupInstr::Extended {
+							instr: ExtInstr::End,
+							..
+						})
+					);
+					if continuation != Continuation::End && !ends_program {
+						return Err("the program halts before its end".to_owned());
+					}
+					return Ok(());
+				}
+				Structured::Br(depth) => {
+					if self.labels[self.labels.len() - 1 - depth] != continuation {
+						return Err(
+							"a branch does not go where falling through goes (such as a break \
+							out of a loop)"
+								.to_owned(),
+						);
+					}
+					return Ok(());
+				}
+				Structured::Block(body) => {
+					let after = if rest.is_empty() {
+						continuation
+					} else {
+						self.new_label()
+					};
+					self.labels.push(after);
+					self.seq(body, after, soup_prog)?;
+					self.labels.pop();
+				}
+				Structured::Loop(body) => {
+					let Some((
+						Structured::If {
+							if_zero,
+							if_non_zero,
+						},
+						header,
+					)) = body.split_last()
+					else {
+						return Err("a loop does not end with its test".to_owned());
+					};
+					let mut header_prog = Vec::new();
+					for instr in header {
+						match instr {
+							Structured::Code(id) => self.code(*id, &mut header_prog),
+							_ => return Err("a loop has control flow before its test".to_owned()),
+						}
+					}
+					// The code after the loop is in its exit branch, which must not continue it.
+					let again = self.new_label();
+					self.labels.push(again);
+					let mut loop_body = Vec::new();
+					self.seq(if_non_zero, again, &mut loop_body)?;
+					soup_prog.extend(header_prog.iter().cloned());
+					loop_body.extend(header_prog);
+					// Control flow graphs do not keep the attributes of the loops.
+					soup_prog.push(SoupInstr::Loop(loop_body, LoopAttributes::default()));
+					self.seq(if_zero, continuation, soup_prog)?;
+					self.labels.pop();
+					return Ok(());
+				}
+				Structured::If { .. } => {
+					return Err("a branch is not the test of a loop".to_owned());
+				}
+			}
+		}
+		Err("control falls off the end of a sequence".to_owned())
+	}
+}
+
+/// What a branch to an enclosing construct of the structured control flow does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Label {
+	/// Continues after a block, at the given block of the graph (by its index in `order`).
+	Block(usize),
+	/// Continues a loop, at the given loop header.
+	Loop(usize),
+}
+
+/// Adds the structured control flow placed within blocks (see `Analysis::within`), and returns
+/// the block that continues the sequence if any.
+type Inner<'a> = dyn FnMut(&Analysis, &mut Vec<Label>, &mut Vec<Structured>) -> Option<usize> + 'a;
+
+/// The dominator tree and the loops of the blocks that can be reached, blocks being identified by
+/// their index in reverse postorder.
+struct Analysis<'a> {
+	graph: &'a Graph,
+	/// Blocks in reverse postorder.
+	order: Vec<BlockId>,
+	successors: Vec<Vec<usize>>,
+	predecessors: Vec<Vec<usize>>,
+	/// Immediate dominator of each block (the entry being its own).
+	idoms: Vec<usize>,
+	/// Blocks immediately dominated by each block, in reverse postorder.
+	children: Vec<Vec<usize>>,
+	/// Blocks of the loop of each loop header (the header included).
+	loops: HashMap<usize, HashSet<usize>>,
+}
+
+impl Analysis<'_> {
+	fn new(graph: &Graph) -> Analysis<'_> {
+		// Postorder of a depth-first search from the entry.
+		let mut postorder = Vec::new();
+		let mut visited = HashSet::from([graph.entry]);
+		let mut stack = vec![(graph.entry, graph.successors(graph.entry), 0)];
+		while let Some((id, successors, next)) = stack.last_mut() {
+			if let Some(&successor) = successors.get(*next) {
+				*next += 1;
+				if visited.insert(successor) {
+					stack.push((successor, graph.successors(successor), 0));
+				}
+			} else {
+				postorder.push(*id);
+				stack.pop();
+			}
+		}
+		let order: Vec<BlockId> = postorder.into_iter().rev().collect();
+		let index_of: HashMap<BlockId, usize> = order
+			.iter()
+			.enumerate()
+			.map(|(index, &id)| (id, index))
+			.collect();
+		let successors: Vec<Vec<usize>> = order
+			.iter()
+			.map(|&id| {
+				graph
+					.successors(id)
+					.iter()
+					.map(|successor| index_of[successor])
+					.collect()
+			})
+			.collect();
+		let mut predecessors = vec![Vec::new(); order.len()];
+		for (index, block_successors) in successors.iter().enumerate() {
+			for &successor in block_successors {
+				predecessors[successor].push(index);
+			}
+		}
+
+		// Dominators as in "A Simple, Fast Dominance Algorithm" (Cooper, Harvey and Kennedy).
+		let mut idoms: Vec<Option<usize>> = vec![None; order.len()];
+		idoms[0] = Some(0);
+		let mut changed = true;
+		while changed {
+			changed = false;
+			for index in 1..order.len() {
+				let mut new_idom = None;
+				for &predecessor in predecessors[index].iter() {
+					if idoms[predecessor].is_none() {
+						continue;
+					}
+					new_idom = Some(match new_idom {
+						None => predecessor,
+						Some(mut other) => {
+							let mut finger = predecessor;
+							while finger != other {
+								while finger > other {
+									finger = idoms[finger].unwrap();
+								}
+								while other > finger {
+									other = idoms[other].unwrap();
+								}
+							}
+							finger
+						}
+					});
+				}
+				if idoms[index] != new_idom {
+					idoms[index] = new_idom;
+					changed = true;
+				}
+			}
+		}
+		let idoms: Vec<usize> = idoms.into_iter().map(Option::unwrap).collect();
+		let mut children = vec![Vec::new(); order.len()];
+		for (index, &idom) in idoms.iter().enumerate().skip(1) {
+			children[idom].push(index);
+		}
+
+		// The loop of a header is made of the blocks that reach a back edge to it without going
+		// through it.
+		let mut loops: HashMap<usize, HashSet<usize>> = HashMap::new();
+		for (index, block_predecessors) in predecessors.iter().enumerate() {
+			let back_edge_sources: Vec<usize> = block_predecessors
+				.iter()
+				.copied()
+				.filter(|&predecessor| predecessor >= index)
+				.collect();
+			if back_edge_sources.is_empty() {
+				continue;
+			}
+			let mut body = HashSet::from([index]);
+			let mut work = back_edge_sources;
+			while let Some(block) = work.pop() {
+				if body.insert(block) {
+					work.extend(predecessors[block].iter().copied());
+				}
+			}
+			loops.insert(index, body);
+		}
+
+		Analysis {
+			graph,
+			order,
+			successors,
+			predecessors,
+			idoms,
+			children,
+			loops,
+		}
+	}
+
+	fn dominates(&self, dominator: usize, mut block: usize) -> bool {
+		while block > dominator {
+			block = self.idoms[block];
+		}
+		block == dominator
+	}
+
+	/// The graph is reducible if every edge that goes backward in reverse postorder goes to a
+	/// block that dominates its source (a loop header).
+	fn is_reducible(&self) -> bool {
+		self.successors
+			.iter()
+			.enumerate()
+			.all(|(index, successors)| {
+				successors
+					.iter()
+					.all(|&successor| successor > index || self.dominates(successor, index))
+			})
+	}
+
+	fn is_loop_header(&self, block: usize) -> bool {
+		self.loops.contains_key(&block)
+	}
+
+	/// The block is not placed where it is branched to, but after a `Block` that is exited to go
+	/// to it: it is a merge block (the target of several forward branches), or an exit of the
+	/// loop of its immediate dominator.
+	fn is_placed_after(&self, block: usize) -> bool {
+		let forward_predecessors = self.predecessors[block]
+			.iter()
+			.filter(|&&predecessor| predecessor < block)
+			.count();
+		let idom = self.idoms[block];
+		forward_predecessors >= 2
+			|| self
+				.loops
+				.get(&idom)
+				.is_some_and(|body| idom != block && !body.contains(&block))
+	}
+
+	/// Adds the structured control flow of the block and of the blocks it dominates.
+	fn tree(&self, mut block: usize, labels: &mut Vec<Label>, structured: &mut Vec<Structured>) {
+		loop {
+			let placed_after: Vec<usize> = self.children[block]
+				.iter()
+				.copied()
+				.filter(|&child| self.is_placed_after(child))
+				.collect();
+			let next = if self.is_loop_header(block) {
+				let (exits, inner): (Vec<usize>, Vec<usize>) = placed_after
+					.into_iter()
+					.partition(|child| !self.loops[&block].contains(child));
+				self.within(
+					&exits,
+					labels,
+					structured,
+					&mut |analysis, labels, structured| {
+						labels.push(Label::Loop(block));
+						let mut body = Vec::new();
+						let next = analysis.within(
+							&inner,
+							labels,
+							&mut body,
+							&mut |analysis, labels, body| analysis.node(block, labels, body),
+						);
+						if let Some(next) = next {
+							analysis.tree(next, labels, &mut body);
+						}
+						labels.pop();
+						structured.push(Structured::Loop(body));
+						None
+					},
+				)
+			} else {
+				self.within(
+					&placed_after,
+					labels,
+					structured,
+					&mut |analysis, labels, structured| analysis.node(block, labels, structured),
+				)
+			};
+			match next {
+				Some(next) => block = next,
+				None => return,
+			}
+		}
+	}
+
+	/// Adds the structured control flow of `inner` in blocks followed by the blocks to place
+	/// after it (in reverse postorder), and returns the block that continues the sequence if any.
+	fn within(
+		&self,
+		placed_after: &[usize],
+		labels: &mut Vec<Label>,
+		structured: &mut Vec<Structured>,
+		inner: &mut Inner,
+	) -> Option<usize> {
+		let Some((&last, others)) = placed_after.split_last() else {
+			return inner(self, labels, structured);
+		};
+		labels.push(Label::Block(last));
+		let mut body = Vec::new();
+		if let Some(next) = self.within(others, labels, &mut body, inner) {
+			self.tree(next, labels, &mut body);
+		}
+		labels.pop();
+		structured.push(Structured::Block(body));
+		Some(last)
+	}
+
+	/// Adds the code and the terminator of the block, and returns the block that continues the
+	/// sequence if any.
+	fn node(
+		&self,
+		block: usize,
+		labels: &mut Vec<Label>,
+		structured: &mut Vec<Structured>,
+	) -> Option<usize> {
+		let id = self.order[block];
+		structured.push(Structured::Code(id));
+		match self.graph.blocks[&id].terminator {
+			Terminator::Goto(_) => {
+				self.branch(block, self.successors[block][0], labels, structured)
+			}
+			Terminator::Branch { .. } => {
+				let if_zero = self.branch_tree(block, self.successors[block][0], labels);
+				let if_non_zero = self.branch_tree(block, self.successors[block][1], labels);
+				structured.push(Structured::If {
+					if_zero,
+					if_non_zero,
+				});
+				None
+			}
+			Terminator::Halt => {
+				structured.push(Structured::Halt);
+				None
+			}
+		}
+	}
+
+	/// The structured control flow of a branch of an if.
+	fn branch_tree(
+		&self,
+		source: usize,
+		target: usize,
+		labels: &mut Vec<Label>,
+	) -> Vec<Structured> {
+		let mut structured = Vec::new();
+		if let Some(next) = self.branch(source, target, labels, &mut structured) {
+			self.tree(next, labels, &mut structured);
+		}
+		structured
+	}
+
+	/// Adds a branch to the target if it is not placed right there, in which case it is returned.
+	fn branch(
+		&self,
+		source: usize,
+		target: usize,
+		labels: &[Label],
+		structured: &mut Vec<Structured>,
+	) -> Option<usize> {
+		let label = if target <= source {
+			Label::Loop(target)
+		} else if self.is_placed_after(target) {
+			Label::Block(target)
+		} else {
+			return Some(target);
+		};
+		let depth = labels
+			.iter()
+			.rev()
+			.position(|&enclosing| enclosing == label)
+			.expect("branch to a block that is not placed after an enclosing construct");
+		structured.push(Structured::Br(depth));
+		None
+	}
+}
+
+/// Makes the control flow of the given blocks reducible, by copying each cycle that can be entered
+/// at several blocks once for each additional entry (the copy being entered only at this entry),
+/// and then doing the same in each cycle without its entry. Only the branches of the blocks that
+/// can be reached are considered.
+fn make_reducible(
+	graph: &mut Graph,
+	blocks: &BTreeSet<BlockId>,
+	reachable: &mut HashSet<BlockId>,
+) {
+	for component in strongly_connected_components(graph, blocks) {
+		let is_cycle = component.len() > 1
+			|| component
+				.iter()
+				.any(|&id| graph.successors(id).contains(&id));
+		if !is_cycle {
+			continue;
+		}
+		let outside_predecessors =
+			|graph: &Graph, reachable: &HashSet<BlockId>, entry: BlockId| -> Vec<BlockId> {
+				let mut predecessors: Vec<BlockId> = reachable
+					.iter()
+					.copied()
+					.filter(|id| !component.contains(id) && graph.successors(*id).contains(&entry))
+					.collect();
+				predecessors.sort_unstable();
+				predecessors
+			};
+		// The entry of the graph stays in the original cycle, as it cannot be redirected.
+		let mut entries: Vec<BlockId> = component
+			.iter()
+			.copied()
+			.filter(|&id| {
+				id == graph.entry || !outside_predecessors(graph, reachable, id).is_empty()
+			})
+			.collect();
+		entries.sort_by_key(|&id| id != graph.entry);
+		let mut cycles = Vec::new();
+		for (entry_index, &entry) in entries.iter().enumerate() {
+			if entry_index == 0 {
+				cycles.push((entry, component.clone()));
+				continue;
+			}
+			let copies: HashMap<BlockId, BlockId> = component
+				.iter()
+				.map(|&id| (id, graph.add_block(graph.blocks[&id].clone())))
+				.collect();
+			reachable.extend(copies.values().copied());
+			for &copy in copies.values() {
+				redirect(graph, copy, |target| {
+					copies.get(&target).copied().unwrap_or(target)
+				});
+			}
+			for predecessor in outside_predecessors(graph, reachable, entry) {
+				redirect(graph, predecessor, |target| {
+					if target == entry {
+						copies[&entry]
+					} else {
+						target
+					}
+				});
+			}
+			cycles.push((copies[&entry], copies.values().copied().collect()));
+		}
+		for (entry, mut cycle) in cycles {
+			cycle.remove(&entry);
+			make_reducible(graph, &cycle, reachable);
+		}
+	}
+}
+
+/// Changes the targets of the terminator of the block.
+fn redirect(graph: &mut Graph, id: BlockId, new_target: impl Fn(BlockId) -> BlockId) {
+	let terminator = &mut graph.blocks.get_mut(&id).unwrap().terminator;
+	match terminator {
+		Terminator::Goto(target) => *target = new_target(*target),
+		Terminator::Branch {
+			if_zero,
+			if_non_zero,
+		} => {
+			*if_zero = new_target(*if_zero);
+			*if_non_zero = new_target(*if_non_zero);
+		}
+		Terminator::Halt => (),
+	}
+}
+
+/// Strongly connected components of the graph restricted to the given blocks (Tarjan's algorithm,
+/// without recursion).
+fn strongly_connected_components(
+	graph: &Graph,
+	blocks: &BTreeSet<BlockId>,
+) -> Vec<BTreeSet<BlockId>> {
+	let successors = |id: BlockId| -> Vec<BlockId> {
+		graph
+			.successors(id)
+			.into_iter()
+			.filter(|successor| blocks.contains(successor))
+			.collect()
+	};
+	let mut components = Vec::new();
+	// Index and lowest reachable index of each visited block.
+	let mut indices: HashMap<BlockId, (usize, usize)> = HashMap::new();
+	let mut component_stack: Vec<BlockId> = Vec::new();
+	let mut on_stack: HashSet<BlockId> = HashSet::new();
+	for &root in blocks {
+		if indices.contains_key(&root) {
+			continue;
+		}
+		let mut stack = vec![(root, successors(root), 0)];
+		indices.insert(root, (indices.len(), indices.len()));
+		component_stack.push(root);
+		on_stack.insert(root);
+		while let Some((id, block_successors, next)) = stack.last_mut() {
+			let id = *id;
+			if let Some(&successor) = block_successors.get(*next) {
+				*next += 1;
+				if !indices.contains_key(&successor) {
+					indices.insert(successor, (indices.len(), indices.len()));
+					component_stack.push(successor);
+					on_stack.insert(successor);
+					stack.push((successor, successors(successor), 0));
+				} else if on_stack.contains(&successor) {
+					let successor_index = indices[&successor].0;
+					let low = &mut indices.get_mut(&id).unwrap().1;
+					*low = (*low).min(successor_index);
+				}
+				continue;
+			}
+			stack.pop();
+			let (index, low) = indices[&id];
+			if let Some((parent, _, _)) = stack.last() {
+				let parent_low = &mut indices.get_mut(parent).unwrap().1;
+				*parent_low = (*parent_low).min(low);
+			}
+			if index == low {
+				let mut component = BTreeSet::new();
+				loop {
+					let member = component_stack.pop().unwrap();
+					on_stack.remove(&member);
+					component.insert(member);
+					if member == id {
+						break;
+					}
+				}
+				components.push(component);
+			}
+		}
+	}
+	components
+}
@@ -0,0 +1,174 @@
+//! Machine-readable report of a compilation, written as JSON: the optimization passes applied
+//! and how each of them changed the IR, the kinds of the loops of the compiled program, the
+//! warnings, the size of the output and the time taken by each phase.
+
+use crate::astraw::RawInstr;
+use crate::astsoup::SoupInstr;
+use crate::json::json_string;
+use crate::parser::line_and_column;
+use crate::passes::PassStats;
+use crate::program::Stage;
+use crate::warnings::Warning;
+use std::time::Duration;
+
+/// Kinds of loops, named as in the textual IR (see `irtext`).
+const LOOP_KINDS: [&str; 8] = [
+	"loop",
+	"mult_loop",
+	"fixed_loop",
+	"moving_loop",
+	"block_move",
+	"print_until_zero",
+	"read_block",
+	"read_until_zero",
+];
+
+pub struct CompileReport {
+	pub src_file: Option<String>,
+	pub target: String,
+	pub optimized: bool,
+	/// Number of instructions of the program before optimizations.
+	pub raw_instrs: usize,
+	/// The passes in the order they ran (none if the program is not optimized).
+	pub passes: Vec<PassStats>,
+	/// Number of loops of each kind in the compiled program, in the order of `LOOP_KINDS`.
+	pub loops: [usize; LOOP_KINDS.len()],
+	pub warnings: Vec<Warning>,
+	pub output_bytes: usize,
+	pub parse_time: Duration,
+	pub optimize_time: Duration,
+	pub codegen_time: Duration,
+}
+
+/// Counts the loops of each kind of the program (in the order of `LOOP_KINDS`).
+pub fn count_loops(stage: &Stage) -> [usize; LOOP_KINDS.len()] {
+	let mut counts = [0; LOOP_KINDS.len()];
+	match stage {
+		Stage::Raw(raw_prog) => {
+			counts[0] = raw_prog
+				.iter()
+				.filter(|instr| matches!(instr, RawInstr::BracketLoop { .. }))
+				.count();
+		}
+		Stage::Soup(soup_prog) => count_soup_loops(soup_prog, &mut counts),
+	}
+	counts
+}
+
+fn count_soup_loops(instr_seq: &[SoupInstr], counts: &mut [usize; LOOP_KINDS.len()]) {
+	for instr in instr_seq {
+		let kind = match instr {
+			SoupInstr::Soup { .. }
+			| SoupInstr::Seek { .. }
+			| SoupInstr::Output { .. }
+			| SoupInstr::Input { .. }
+			| SoupInstr::Clock { .. }
+			| SoupInstr::Random { .. }
+			| SoupInstr::Extended { .. }
+			| SoupInstr::HostCall { .. } => continue,
+			SoupInstr::Loop(body, _) => {
+				count_soup_loops(body, counts);
+				0
+			}
+			SoupInstr::MultFixedLoop { .. } => 1,
+			SoupInstr::SoupFixedLoop { .. } => 2,
+			SoupInstr::SoupMovingLoop { .. } => 3,
+			SoupInstr::BlockMove { .. } => 4,
+			SoupInstr::PrintUntilZero { .. } => 5,
+			SoupInstr::ReadBlock { .. } => 6,
+			SoupInstr::ReadUntilZero { .. } => 7,
+		};
+		counts[kind] += 1;
+	}
+}
+
+fn milliseconds(duration: Duration) -> String {
+	format!("{:.3}", duration.as_secs_f64() * 1000.0)
+}
+
+impl CompileReport {
+	/// The source code is used to give the positions of the warnings (as lines and columns).
+	pub fn to_json(&self, src_code: &str) -> String {
+		let mut instrs_before = self.raw_instrs;
+		let passes: Vec<String> = self
+			.passes
+			.iter()
+			.map(|pass| {
+				let json = format!(
+					"\t\t{{\"name\": {}, \"instrs_before\": {}, \"instrs_after\": {}, \
+					\"ir_bytes\": {}, \"transformations\": {}, \"time_ms\": {}}}",
+					json_string(&pass.name),
+					instrs_before,
+					pass.instrs,
+					pass.ir_bytes,
+					pass.transformations,
+					milliseconds(pass.duration)
+				);
+				instrs_before = pass.instrs;
+				json
+			})
+			.collect();
+		let loops: Vec<String> = LOOP_KINDS
+			.iter()
+			.zip(self.loops)
+			.map(|(kind, count)| format!("\t\t{}: {}", json_string(kind), count))
+			.collect();
+		let warnings: Vec<String> = self
+			.warnings
+			.iter()
+			.map(|warning| {
+				let Warning::UnreachableCode {
+					loop_span,
+					unreachable_span,
+				} = warning;
+				let (line, column) = line_and_column(src_code, unreachable_span.start);
+				let (loop_line, loop_column) = line_and_column(src_code, loop_span.start);
+				format!(
+					"\t\t{{\"kind\": \"unreachable_code\", \"line\": {}, \"column\": {}, \
+					\"loop_line\": {}, \"loop_column\": {}}}",
+					line, column, loop_line, loop_column
+				)
+			})
+			.collect();
+		let total_time = self.parse_time + self.optimize_time + self.codegen_time;
+		let fields = [
+			(
+				"src_file",
+				self.src_file
+					.as_deref()
+					.map_or("null".to_owned(), json_string),
+			),
+			("target", json_string(&self.target)),
+			("optimized", self.optimized.to_string()),
+			("raw_instrs", self.raw_instrs.to_string()),
+			("passes", json_list(&passes)),
+			("loops", format!("{{\n{}\n\t}}", loops.join(",\n"))),
+			("warnings", json_list(&warnings)),
+			("output_bytes", self.output_bytes.to_string()),
+			(
+				"timing_ms",
+				format!(
+					"{{\"parse\": {}, \"optimize\": {}, \"codegen\": {}, \"total\": {}}}",
+					milliseconds(self.parse_time),
+					milliseconds(self.optimize_time),
+					milliseconds(self.codegen_time),
+					milliseconds(total_time)
+				),
+			),
+		];
+		let fields: Vec<String> = fields
+			.iter()
+			.map(|(name, value)| format!("\t\"{}\": {}", name, value))
+			.collect();
+		format!("{{\n{}\n}}\n", fields.join(",\n"))
+	}
+}
+
+/// A JSON array of the given (already indented) values, one per line.
+fn json_list(values: &[String]) -> String {
+	if values.is_empty() {
+		"[]".to_owned()
+	} else {
+		format!("[\n{}\n\t]", values.join(",\n"))
+	}
+}
@@ -0,0 +1,63 @@
+//! Progress of long runs of programs by the VM, reported periodically (on stderr) so that a long
+//! computation can be told apart from a program that is stuck waiting for input or hung.
+
+use crate::vm::{Observer, PROGRESS_INTERVAL};
+use std::time::{Duration, Instant};
+
+/// When the progress is reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressPeriod {
+	/// Every second (of wall-clock time).
+	EverySecond,
+	/// Every given number of millions of steps (at least 1).
+	EveryMillionSteps(u64),
+}
+
+/// Observer of the VM that prints a line of status (steps, head and output so far) periodically.
+pub struct ProgressReporter {
+	period: ProgressPeriod,
+	start: Instant,
+	last_report: Instant,
+	output_len: usize,
+}
+
+impl ProgressReporter {
+	pub fn new(period: ProgressPeriod) -> ProgressReporter {
+		let now = Instant::now();
+		ProgressReporter {
+			period,
+			start: now,
+			last_report: now,
+			output_len: 0,
+		}
+	}
+}
+
+impl Observer for ProgressReporter {
+	fn on_output(&mut self, char_values: &[u8]) {
+		self.output_len += char_values.len();
+	}
+
+	fn counts_steps(&self) -> bool {
+		true
+	}
+
+	fn on_progress(&mut self, steps: u64, head: usize) {
+		let due = match self.period {
+			ProgressPeriod::EverySecond => self.last_report.elapsed() >= Duration::from_secs(1),
+			ProgressPeriod::EveryMillionSteps(millions) => {
+				(steps / PROGRESS_INTERVAL).is_multiple_of(millions)
+			}
+		};
+		if due {
+			self.last_report = Instant::now();
+			eprintln!(
+				"[progress] {:.1}s: {} steps, head at cell {}, {} output bytes",
+				self.start.elapsed().as_secs_f64(),
+				steps,
+				head,
+				self.output_len
+			);
+		}
+	}
+}
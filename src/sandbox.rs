@@ -0,0 +1,148 @@
+//! Sandbox for running untrusted programs (`--sandbox`), such as programs submitted by the users
+//! of a service: the run is limited in steps, cells, output and input, nothing is read from stdin
+//! nor printed, so a sandboxed run cannot block and always ends, with an error if a limit is
+//! exceeded (see `Program::run_sandboxed`).
+//!
+//! Steps are counted by the raw interpreter or by the bytecode interpreter without the JIT, the
+//! other engines running whole loops in a single step. The VM stops on an error by panicking, the
+//! panic being caught by `Program::run_sandboxed`, but reported by the panic hook first (on stderr
+//! by default, a service can set its own hook with `std::panic::set_hook`).
+
+use crate::vm::{Observer, OutputLimitExceeded, RunLimits};
+use std::any::Any;
+
+/// Limits of a sandboxed run, the default being the preset of `--sandbox`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sandbox {
+	/// Number of steps (see `Observer::on_progress`).
+	pub max_steps: u64,
+	/// Number of cells of the tape (and of the storage of the extended-type-1 extension).
+	pub max_cells: usize,
+	pub max_output_len: usize,
+	pub max_input_len: usize,
+}
+
+impl Default for Sandbox {
+	fn default() -> Sandbox {
+		Sandbox {
+			max_steps: 100_000_000,
+			max_cells: 1 << 20,
+			max_output_len: 1 << 20,
+			max_input_len: 1 << 20,
+		}
+	}
+}
+
+impl Sandbox {
+	pub fn new() -> Sandbox {
+		Sandbox::default()
+	}
+
+	/// Fails if the input is too long to be given to a sandboxed run.
+	pub fn check_input(&self, input: &[u8]) -> Result<(), String> {
+		if input.len() > self.max_input_len {
+			Err(format!(
+				"the input is longer than {} bytes",
+				self.max_input_len
+			))
+		} else {
+			Ok(())
+		}
+	}
+}
+
+/// Observer of the VM that only sets the limits of the run.
+impl Observer for Sandbox {
+	fn on_output(&mut self, _char_values: &[u8]) {}
+
+	fn limits(&self) -> RunLimits {
+		RunLimits {
+			max_steps: Some(self.max_steps),
+			max_cells: Some(self.max_cells),
+			max_output_len: Some(self.max_output_len),
+		}
+	}
+}
+
+/// What a sandboxed run did, whether it halted or it exceeded a limit.
+#[derive(Debug)]
+pub struct SandboxOutcome {
+	/// All the output if the program halted, what it output before the error otherwise (up to
+	/// the limit if it output too much).
+	pub output: Vec<u8>,
+	/// Error that stopped the program (such as a limit being exceeded), if any.
+	pub error: Option<String>,
+	/// The error is that the program output more than its limit.
+	pub output_limit_exceeded: bool,
+	pub steps: u64,
+}
+
+/// Observer of a sandboxed run, that keeps its output (as the output returned by the VM is lost if
+/// the run stops on an error) and counts its steps.
+pub(crate) struct SandboxRun {
+	sandbox: Sandbox,
+	output: Vec<u8>,
+	steps: u64,
+}
+
+impl SandboxRun {
+	pub(crate) fn new(sandbox: &Sandbox) -> SandboxRun {
+		SandboxRun {
+			sandbox: *sandbox,
+			output: Vec::new(),
+			steps: 0,
+		}
+	}
+
+	/// The outcome of the run, given the result of the VM (an error if it panicked).
+	pub(crate) fn into_outcome(
+		self,
+		result: Result<Vec<u8>, Box<dyn Any + Send>>,
+	) -> SandboxOutcome {
+		let error = result.err();
+		SandboxOutcome {
+			output: self.output,
+			output_limit_exceeded: error
+				.as_ref()
+				.is_some_and(|payload| payload.is::<OutputLimitExceeded>()),
+			error: error.map(|payload| panic_message(&*payload)),
+			steps: self.steps,
+		}
+	}
+}
+
+impl Observer for SandboxRun {
+	fn on_output(&mut self, char_values: &[u8]) {
+		self.output.extend_from_slice(char_values);
+	}
+
+	fn counts_steps(&self) -> bool {
+		true
+	}
+
+	fn on_progress(&mut self, steps: u64, _head: usize) {
+		self.steps = steps;
+	}
+
+	fn limits(&self) -> RunLimits {
+		self.sandbox.limits()
+	}
+}
+
+/// Message of a panic of the VM.
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+	payload
+		.downcast_ref::<String>()
+		.cloned()
+		.or_else(|| {
+			payload
+				.downcast_ref::<OutputLimitExceeded>()
+				.map(OutputLimitExceeded::to_string)
+		})
+		.or_else(|| {
+			payload
+				.downcast_ref::<&str>()
+				.map(|&error| error.to_owned())
+		})
+		.unwrap_or_else(|| "unknown error".to_owned())
+}
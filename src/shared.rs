@@ -0,0 +1,136 @@
+//! Programs shared between threads: a `SharedProgram` is made once from a `Program` (its
+//! bytecode being generated at that time if it is optimized) and can then be cloned cheaply and
+//! run concurrently on many inputs, each run having its own memory.
+
+use crate::astraw::RawInstr;
+use crate::astsoup::soupify;
+use crate::bytecode::{Op, flatten};
+use crate::cancel::{CancelToken, RunOutcome};
+use crate::config::VmConfig;
+use crate::program::{Program, Stage, TapeMismatch, check_tape};
+use crate::tapeconfig::TapeConfig;
+use crate::tapedump::used_cells;
+use crate::vm::{Observer, run_bytecode, run_raw};
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
+
+#[derive(Debug)]
+struct Shared {
+	/// Run as it is if the program is not optimized (see `Program::run`).
+	raw_prog: Option<Vec<RawInstr>>,
+	/// Generated when the shared program is made if the program is optimized, or else the first
+	/// time it is run with the JIT.
+	bytecode: OnceLock<Vec<Op>>,
+	tape_config: TapeConfig,
+	/// See `Program::optimized_for`.
+	optimized_for: Option<TapeConfig>,
+}
+
+/// A program ready to be run, that is `Send` and `Sync` and that clones share.
+#[derive(Debug, Clone)]
+pub struct SharedProgram {
+	shared: Arc<Shared>,
+}
+
+impl SharedProgram {
+	/// The program as it is now, with its tape configuration.
+	pub fn new(program: &Program) -> SharedProgram {
+		let (raw_prog, bytecode) = match program.stage() {
+			Stage::Raw(raw_prog) => (Some(raw_prog.clone()), OnceLock::new()),
+			Stage::Soup(_) => (None, OnceLock::from(program.to_bytecode())),
+		};
+		SharedProgram {
+			shared: Arc::new(Shared {
+				raw_prog,
+				bytecode,
+				tape_config: program.tape_config().clone(),
+				optimized_for: program.optimized_for().cloned(),
+			}),
+		}
+	}
+
+	/// Same as `Program::run`.
+	pub fn run(&self, input: Option<Vec<u8>>) -> Vec<u8> {
+		self.run_impl(input, &self.shared.tape_config, None, None, None)
+	}
+
+	/// Same as `Program::run_with`.
+	pub fn run_with(
+		&self,
+		input: Option<Vec<u8>>,
+		config: &VmConfig,
+	) -> Result<Vec<u8>, TapeMismatch> {
+		check_tape(self.shared.optimized_for.as_ref(), &config.tape_config)?;
+		Ok(self.run_impl(input, &config.tape_config, config.jit_threshold, None, None))
+	}
+
+	/// Same as `Program::run_cancellable`.
+	pub fn run_cancellable(
+		&self,
+		input: Option<Vec<u8>>,
+		config: &VmConfig,
+		cancel: &CancelToken,
+	) -> Result<RunOutcome, TapeMismatch> {
+		check_tape(self.shared.optimized_for.as_ref(), &config.tape_config)?;
+		let start = Instant::now();
+		let mut halt_state = HaltState::default();
+		let output = self.run_impl(
+			input,
+			&config.tape_config,
+			None,
+			Some(&mut halt_state),
+			Some(cancel),
+		);
+		Ok(RunOutcome {
+			output,
+			cancelled: cancel.is_cancelled(),
+			head: halt_state.head,
+			used_cells: halt_state.used_cells,
+			duration: start.elapsed(),
+		})
+	}
+
+	fn run_impl(
+		&self,
+		input: Option<Vec<u8>>,
+		tape_config: &TapeConfig,
+		jit_threshold: Option<u64>,
+		observer: Option<&mut dyn Observer>,
+		cancel: Option<&CancelToken>,
+	) -> Vec<u8> {
+		match &self.shared.raw_prog {
+			Some(raw_prog) if jit_threshold.is_none() => {
+				run_raw(raw_prog, input, tape_config, observer, None, cancel)
+			}
+			_ => {
+				let ops = self
+					.shared
+					.bytecode
+					.get_or_init(|| flatten(&soupify(self.shared.raw_prog.as_ref().unwrap())));
+				run_bytecode(ops, input, tape_config, jit_threshold, observer, cancel)
+			}
+		}
+	}
+}
+
+/// Observer of where the program stopped.
+#[derive(Default)]
+pub(crate) struct HaltState {
+	pub(crate) head: usize,
+	pub(crate) used_cells: usize,
+}
+
+impl Observer for HaltState {
+	fn on_output(&mut self, _char_values: &[u8]) {}
+
+	fn on_halt(&mut self, tape: &[u8], head: usize) {
+		self.head = head;
+		self.used_cells = used_cells(tape, head).len();
+	}
+}
+
+// Fails to compile if a shared program cannot be sent to or shared with other threads.
+const _: () = {
+	const fn assert_send_and_sync<T: Send + Sync>() {}
+	assert_send_and_sync::<SharedProgram>();
+};
@@ -0,0 +1,112 @@
+//! Transpilation of the linear intermediate representation (see `lowering`) to a POSIX shell
+//! script.
+//!
+//! The shell has no arrays, so each cell is a variable (`m` followed by its index) accessed with
+//! `eval`, unset variables reading as 0. Cell arithmetic uses `$(( ))`, output uses `printf` with
+//! an octal escape, and input reads one byte at a time with `dd` (converted to a number by `od`).
+//! This is slow, but only needs the POSIX utilities. The head must not go to the left of the
+//! first cell, and as with the C backend, reading at the end of the input gives 255.
+
+use crate::lowering::{LinearInstr, LinearProgram};
+
+struct TranspiledSh {
+	code: String,
+	indent_level: u32,
+	/// Length of the code at the beginning of the body of each loop being emitted.
+	loop_body_starts: Vec<usize>,
+}
+
+impl TranspiledSh {
+	fn emit_line(&mut self, line_content: &str) {
+		self.code
+			.extend(std::iter::repeat_n("\t", self.indent_level as usize));
+		self.code.push_str(line_content);
+		self.code.push('\n');
+	}
+
+	fn emit_indent(&mut self) {
+		self.indent_level += 1;
+	}
+	fn emit_unindent(&mut self) {
+		self.indent_level -= 1;
+	}
+
+	fn emit_header(&mut self, program: &LinearProgram) {
+		self.emit_line("#!/bin/sh");
+		// Each helper takes the index of a cell, the value of the cell being read in `v`.
+		self.emit_line("bf_get() { eval \"v=\\${m$1:-0}\"; }");
+		self.emit_line("bf_add() { bf_get \"$1\"; eval \"m$1=$(( (v + $2) & 255 ))\"; }");
+		if program.uses_output {
+			self.emit_line("bf_out() { bf_get \"$1\"; printf \"\\\\$(printf %o \"$v\")\"; }");
+		}
+		if program.uses_input {
+			self.emit_line("bf_in() {");
+			self.emit_indent();
+			self.emit_line("c=$(dd bs=1 count=1 2>/dev/null | od -An -tu1)");
+			self.emit_line("eval \"m$1=$(( ${c:-255} ))\"");
+			self.emit_unindent();
+			self.emit_line("}");
+		}
+		self.emit_line("h=0");
+	}
+
+	fn emit_instr(&mut self, instr: &LinearInstr) {
+		match *instr {
+			LinearInstr::Add { offset, value } => {
+				self.emit_line(&format!("bf_add {} {}", h(offset), value))
+			}
+			LinearInstr::Set { offset, value } => {
+				self.emit_line(&format!("eval \"m{}={}\"", h(offset), value))
+			}
+			LinearInstr::MulAdd {
+				source,
+				target,
+				factor,
+			} => {
+				self.emit_line(&format!("bf_get {}", h(source)));
+				self.emit_line(&format!("bf_add {} $((v * {}))", h(target), factor));
+			}
+			LinearInstr::Move(delta) => self.emit_line(&format!("h=$((h + {}))", delta)),
+			LinearInstr::Output { offset } => self.emit_line(&format!("bf_out {}", h(offset))),
+			LinearInstr::Input { offset } => self.emit_line(&format!("bf_in {}", h(offset))),
+			// Every byte is written by its own `printf`.
+			LinearInstr::Flush => (),
+			LinearInstr::LoopStart(_) => {
+				self.emit_line("while bf_get $h; [ \"$v\" -ne 0 ]; do");
+				self.emit_indent();
+				self.loop_body_starts.push(self.code.len());
+			}
+			LinearInstr::LoopEnd(_) => {
+				if self.loop_body_starts.pop() == Some(self.code.len()) {
+					// The body of a loop cannot be empty.
+					self.emit_line(":");
+				}
+				self.emit_unindent();
+				self.emit_line("done");
+			}
+		}
+	}
+}
+
+pub fn transpile_linear_to_sh(program: &LinearProgram) -> String {
+	let mut transpiled = TranspiledSh {
+		code: String::new(),
+		indent_level: 0,
+		loop_body_starts: Vec::new(),
+	};
+	transpiled.emit_header(program);
+	for instr in &program.instrs {
+		transpiled.emit_instr(instr);
+	}
+	assert!(transpiled.indent_level == 0);
+	transpiled.code
+}
+
+/// Index of the cell at the given offset from the head.
+fn h(relative_head: isize) -> String {
+	if relative_head == 0 {
+		"$h".to_owned()
+	} else {
+		format!("$((h + {}))", relative_head)
+	}
+}
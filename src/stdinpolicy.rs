@@ -0,0 +1,83 @@
+//! What the interpreted program may do with stdin (`--stdin-policy`), independently of whether an
+//! input is given with `-i`: by default, a program without `-i` reads stdin with a prompt (its
+//! input being colored on the terminal) and a program with `-i` never touches stdin (reading 0
+//! once the input is exhausted), but a given input can also be followed by stdin, and stdin can
+//! be read without a prompt (as when it is piped) or not at all.
+
+use crate::vm::Observer;
+use std::io::{Read, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StdinPolicy {
+	/// Stdin is read when the input is exhausted, the bytes read being colored on the terminal.
+	Prompt,
+	/// Stdin is read when the input is exhausted, as it is.
+	Silent,
+	/// Stdin is never read, the program reads 0 once the input is exhausted.
+	Closed,
+}
+
+impl StdinPolicy {
+	pub fn from_name(name: &str) -> Option<StdinPolicy> {
+		match name {
+			"prompt" => Some(StdinPolicy::Prompt),
+			"silent" => Some(StdinPolicy::Silent),
+			"closed" => Some(StdinPolicy::Closed),
+			_ => None,
+		}
+	}
+
+	/// The policy when none is given, depending on whether an input is given.
+	pub fn default_for(input_given: bool) -> StdinPolicy {
+		if input_given {
+			StdinPolicy::Closed
+		} else {
+			StdinPolicy::Prompt
+		}
+	}
+}
+
+/// Observer of the VM (run in interactive mode, without input) that gives the program the given
+/// input and then reads stdin according to the policy, one byte at a time.
+pub struct StdinFallback {
+	input: Vec<u8>,
+	policy: StdinPolicy,
+}
+
+impl StdinFallback {
+	pub fn new(input: Vec<u8>, policy: StdinPolicy) -> StdinFallback {
+		StdinFallback { input, policy }
+	}
+}
+
+impl Observer for StdinFallback {
+	fn on_output(&mut self, _char_values: &[u8]) {}
+
+	fn read_interactive_input(&mut self) -> Option<Vec<u8>> {
+		if !self.input.is_empty() {
+			return Some(std::mem::take(&mut self.input));
+		}
+		Some(match self.policy {
+			StdinPolicy::Prompt => {
+				print!("\x1b[36m");
+				std::io::stdout().flush().ok();
+				let char_values = read_byte();
+				print!("\x1b[39m");
+				char_values
+			}
+			StdinPolicy::Silent => read_byte(),
+			StdinPolicy::Closed => Vec::new(),
+		})
+	}
+}
+
+/// The next byte of stdin, if any (no bytes meaning the end of the input).
+fn read_byte() -> Vec<u8> {
+	std::io::stdin()
+		.lock()
+		.bytes()
+		.next()
+		.and_then(Result::ok)
+		.into_iter()
+		.collect()
+}
@@ -0,0 +1,107 @@
+//! Configuration of the tape before the program starts: values of the first cells, position of
+//! the head, and named regions of cells (one after the other from the first cell) that can be
+//! read-only. Changing the value of a cell of a read-only region is an error that stops the
+//! program. Only the VM and the C backend support it.
+//!
+//! The seed of the generator of the random extension and the model of the machine (see
+//! `machine`) are given here too, as part of the state of the machine when the program starts.
+//!
+//! Optimizations can remove writes that cancel each other out (such as `+-`), so an optimized
+//! program may not stop where the program that was not optimized would.
+
+use crate::machine::MachineModel;
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protection {
+	ReadWrite,
+	ReadOnly,
+}
+
+#[derive(Debug, Clone)]
+pub struct Region {
+	pub name: String,
+	pub cells: Range<usize>,
+	pub protection: Protection,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TapeConfig {
+	/// Values of the first cells, the others being zeros.
+	pub initial_tape: Vec<u8>,
+	/// Index of the cell under the head when the program starts.
+	pub head_start: usize,
+	/// Regions, in the order of their cells (the cells after the last region are in none).
+	pub regions: Vec<Region>,
+	/// Seed of the generator of the random extension (see `Extensions::random`), seeded by the
+	/// time if absent.
+	pub seed: Option<u64>,
+	pub machine: MachineModel,
+}
+
+impl TapeConfig {
+	/// The tape starts with zeros and the head on the first cell, with no protected cells (the
+	/// seed and the machine model do not matter, as every target that does not support the tape
+	/// configuration does not support the extensions that depend on them either).
+	pub fn is_default(&self) -> bool {
+		self.initial_tape.is_empty() && self.head_start == 0 && self.regions.is_empty()
+	}
+
+	/// The programs start on the same cells (trailing zeros aside) with the head on the same cell,
+	/// which is what the optimizations that evaluate the start of a program rely on.
+	pub fn same_start(&self, other: &TapeConfig) -> bool {
+		let used = |tape: &[u8]| {
+			tape.iter()
+				.rposition(|&value| value != 0)
+				.map_or(0, |i| i + 1)
+		};
+		self.initial_tape[..used(&self.initial_tape)]
+			== other.initial_tape[..used(&other.initial_tape)]
+			&& self.head_start == other.head_start
+	}
+
+	/// Adds a region of the given size right after the last region.
+	pub fn add_region(&mut self, name: String, size: usize, protection: Protection) {
+		let start = self.regions.last().map_or(0, |region| region.cells.end);
+		self.regions.push(Region {
+			name,
+			cells: start..start + size,
+			protection,
+		});
+	}
+
+	pub fn region(&self, name: &str) -> Option<&Region> {
+		self.regions.iter().find(|region| region.name == name)
+	}
+
+	pub fn read_only_regions(&self) -> impl Iterator<Item = &Region> {
+		self.regions
+			.iter()
+			.filter(|region| region.protection == Protection::ReadOnly)
+	}
+
+	/// Number of cells that must be in the tape for the configuration to fit.
+	pub fn min_tape_len(&self) -> usize {
+		let regions_end = self.regions.last().map_or(0, |region| region.cells.end);
+		self.initial_tape
+			.len()
+			.max(regions_end)
+			.max(self.head_start + 1)
+	}
+}
+
+/// Parses a region given as `name:size` or `name:size:ro` (or `:rw`, the default).
+pub fn parse_region(text: &str) -> Option<(String, usize, Protection)> {
+	let mut parts = text.split(':');
+	let name = parts.next().filter(|name| !name.is_empty())?;
+	let size = parts.next()?.parse().ok()?;
+	let protection = match parts.next() {
+		None | Some("rw") => Protection::ReadWrite,
+		Some("ro") => Protection::ReadOnly,
+		Some(_) => return None,
+	};
+	if parts.next().is_some() {
+		return None;
+	}
+	Some((name.to_owned(), size, protection))
+}
@@ -0,0 +1,93 @@
+//! Hexadecimal dump of the tape of a program run by the VM, printed when it halts (or when the
+//! VM stops on an error) to inspect the final state of the memory.
+
+use crate::vm::Observer;
+use std::ops::Range;
+
+const CELLS_PER_ROW: usize = 16;
+
+/// Observer of the VM that prints (to stderr) a dump of the tape when the program halts.
+pub struct TapeDumper {
+	/// Indices of the dumped cells, by default from the first cell to the last non-zero cell or
+	/// the head.
+	range: Option<Range<usize>>,
+}
+
+impl TapeDumper {
+	pub fn new(range: Option<Range<usize>>) -> TapeDumper {
+		TapeDumper { range }
+	}
+}
+
+impl Observer for TapeDumper {
+	fn on_output(&mut self, _char_values: &[u8]) {}
+
+	fn on_halt(&mut self, tape: &[u8], head: usize) {
+		let range = self.range.clone().unwrap_or_else(|| used_cells(tape, head));
+		eprint!("{}", format_tape_dump(tape, head, range));
+	}
+}
+
+/// Indices of the cells from the first cell to the last non-zero cell or the head.
+pub fn used_cells(tape: &[u8], head: usize) -> Range<usize> {
+	let used_len = tape
+		.iter()
+		.rposition(|&cell| cell != 0)
+		.map_or(0, |index| index + 1);
+	// A head before the start of the tape is a huge index.
+	let head_len = if head <= tape.len() { head + 1 } else { 0 };
+	0..used_len.max(head_len).max(1)
+}
+
+/// Parses a range of cell indices such as `16..48` (the end being excluded).
+pub fn parse_range(text: &str) -> Option<Range<usize>> {
+	let (start, end) = text.split_once("..")?;
+	let range = start.parse().ok()?..end.parse().ok()?;
+	(range.start < range.end).then_some(range)
+}
+
+/// Rows of 16 cells in hexadecimal and as ASCII characters (like `hexdump -C`), the first row
+/// starting at a multiple of 16, the cells out of the range being left blank. The head is marked
+/// under its cell if it is in the range, and told after the rows otherwise.
+pub fn format_tape_dump(tape: &[u8], head: usize, range: Range<usize>) -> String {
+	let mut dump = String::new();
+	let first_row_start = range.start - range.start % CELLS_PER_ROW;
+	for row_start in (first_row_start..range.end).step_by(CELLS_PER_ROW) {
+		let mut hex = String::new();
+		let mut ascii = String::new();
+		let mut head_column = None;
+		for index in row_start..row_start + CELLS_PER_ROW {
+			if index == row_start + CELLS_PER_ROW / 2 {
+				hex.push(' ');
+			}
+			if index == head && range.contains(&index) {
+				head_column = Some(hex.len());
+			}
+			if range.contains(&index) {
+				let cell = tape.get(index).copied().unwrap_or(0);
+				hex.push_str(&format!("{:02x} ", cell));
+				ascii.push(if cell.is_ascii_graphic() || cell == b' ' {
+					cell as char
+				} else {
+					'.'
+				});
+			} else {
+				hex.push_str("   ");
+				ascii.push(' ');
+			}
+		}
+		dump.push_str(&format!("{:08x}  {} |{}|\n", row_start, hex, ascii));
+		if let Some(head_column) = head_column {
+			dump.push_str(&format!(
+				"{}^^ head (cell {})\n",
+				" ".repeat(10 + head_column),
+				head
+			));
+		}
+	}
+	if !range.contains(&head) {
+		// A head before the start of the tape is a huge index.
+		dump.push_str(&format!("head at cell {}\n", head as isize));
+	}
+	dump
+}
@@ -0,0 +1,146 @@
+//! Precomputation of the start of a program (`--emit tape-init`): the instructions before the
+//! first one that does I/O (or uses an extension) only compute the tape from its initial state,
+//! so they are run once, at compile time, and the compiled program starts on the tape they leave
+//! with only the rest of the program to run. This is for programs that spend a long time building
+//! tables before reading their input or printing anything.
+//!
+//! A loop that runs for too long (or forever) is not precomputed, nor is anything after it.
+
+use crate::astsoup::{SoupInstr, instr_count};
+use crate::sandbox::panic_message;
+use crate::tapeconfig::TapeConfig;
+use crate::vm::{Observer, RunLimits, run_soup};
+use std::panic::AssertUnwindSafe;
+
+/// Number of steps of the VM that the precomputation can take.
+pub const DEFAULT_MAX_STEPS: u64 = 100_000_000;
+
+#[derive(Debug, Clone)]
+pub struct TapeInit {
+	/// The tape configuration the residual program starts on, with the precomputed cells (without
+	/// the trailing zeros) and head.
+	pub tape_config: TapeConfig,
+	/// The instructions that were not precomputed.
+	pub residual: Vec<SoupInstr>,
+	/// Number of instructions that were precomputed (top-level instructions, a loop counting as
+	/// one).
+	pub precomputed_instrs: usize,
+}
+
+/// Runs the longest prefix of the program that only computes the tape, within the given number
+/// of steps, starting on the given tape configuration.
+pub fn precompute_tape(
+	soup_prog: &[SoupInstr],
+	tape_config: &TapeConfig,
+	max_steps: u64,
+) -> TapeInit {
+	let mut prefix_len = soup_prog.iter().take_while(|instr| is_pure(instr)).count();
+	loop {
+		let mut capture = Capture::new(&soup_prog[..prefix_len]);
+		let mut observers: Vec<Box<dyn Observer + '_>> = vec![
+			Box::new(&mut capture),
+			Box::new(RunLimits {
+				max_steps: Some(max_steps),
+				..RunLimits::default()
+			}),
+		];
+		let run = std::panic::catch_unwind(AssertUnwindSafe(|| {
+			run_soup(
+				&soup_prog[..prefix_len],
+				Some(Vec::new()),
+				tape_config,
+				Some(&mut observers),
+			)
+		}));
+		drop(observers);
+		match run {
+			Ok(_) => {
+				let mut tape = capture.tape;
+				let used_len = tape
+					.iter()
+					.rposition(|&value| value != 0)
+					.map_or(0, |i| i + 1);
+				tape.truncate(used_len);
+				return TapeInit {
+					tape_config: TapeConfig {
+						initial_tape: tape,
+						head_start: capture.head,
+						..tape_config.clone()
+					},
+					residual: soup_prog[prefix_len..].to_vec(),
+					precomputed_instrs: prefix_len,
+				};
+			}
+			Err(payload) => {
+				// The instruction that ran out of steps (or that made the head go before the
+				// first cell) is not precomputed, the instructions before it run to completion.
+				assert!(
+					capture.last_started < prefix_len,
+					"{}",
+					panic_message(&*payload)
+				);
+				prefix_len = capture.last_started;
+			}
+		}
+	}
+}
+
+/// The instruction only computes the tape.
+fn is_pure(instr: &SoupInstr) -> bool {
+	match instr {
+		SoupInstr::Soup { .. }
+		| SoupInstr::Seek { .. }
+		| SoupInstr::MultFixedLoop { .. }
+		| SoupInstr::SoupFixedLoop { .. }
+		| SoupInstr::SoupMovingLoop { .. }
+		| SoupInstr::BlockMove { .. } => true,
+		SoupInstr::Loop(body, _) => body.iter().all(is_pure),
+		_ => false,
+	}
+}
+
+/// Observer of the VM that keeps the tape when the program halts and the last top-level
+/// instruction that started.
+struct Capture {
+	/// Index of each top-level instruction (in the order of `Observer::on_instr`), in order.
+	top_level_indices: Vec<usize>,
+	last_started: usize,
+	tape: Vec<u8>,
+	head: usize,
+}
+
+impl Capture {
+	fn new(instr_seq: &[SoupInstr]) -> Capture {
+		let mut top_level_indices = Vec::with_capacity(instr_seq.len());
+		let mut index = 0;
+		for instr in instr_seq {
+			top_level_indices.push(index);
+			index += instr_count(std::slice::from_ref(instr));
+		}
+		Capture {
+			top_level_indices,
+			last_started: 0,
+			tape: Vec::new(),
+			head: 0,
+		}
+	}
+}
+
+impl Observer for Capture {
+	fn on_output(&mut self, _char_values: &[u8]) {}
+
+	fn traces_instrs(&self) -> bool {
+		true
+	}
+
+	fn on_instr(&mut self, instr_index: usize, _head: usize, _cell_value: u8) {
+		if let Ok(top_level) = self.top_level_indices.binary_search(&instr_index) {
+			self.last_started = top_level;
+		}
+	}
+
+	fn on_halt(&mut self, tape: &[u8], head: usize) {
+		self.tape = tape.to_vec();
+		self.head = head;
+	}
+}
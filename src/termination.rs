@@ -0,0 +1,173 @@
+use crate::analysis::{analyze, modified_cells};
+use crate::astraw::{RawInstr, top_level};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+	Terminates,
+	DoesNotTerminate,
+	Unknown,
+}
+
+impl std::fmt::Display for Verdict {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Verdict::Terminates => write!(f, "terminates"),
+			Verdict::DoesNotTerminate => write!(f, "does not terminate"),
+			Verdict::Unknown => write!(f, "unknown"),
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct LoopVerdict {
+	/// Index of the loop instruction in the raw program.
+	pub loop_index: usize,
+	pub verdict: Verdict,
+	/// Why the verdict was given.
+	pub reason: &'static str,
+}
+
+#[derive(Debug, Clone)]
+pub struct TerminationReport {
+	/// One verdict per loop, in the order of the raw program.
+	pub loops: Vec<LoopVerdict>,
+	pub program: Verdict,
+}
+
+/// Tries to prove the termination (or non-termination) of each loop of the program.
+///
+/// A loop terminates when all its inner loops terminate and either its decision cell changes by
+/// an odd amount at each iteration and nothing else modifies it (it then reaches zero in at most
+/// 256 iterations, this covers `[-]` and `[->+<]` style loops), or it scans to the right without
+/// writing to the cells it is going to check (it then reaches untouched zero cells).
+pub fn check_termination(raw_prog: &[RawInstr]) -> TerminationReport {
+	let analysis = analyze(raw_prog);
+	// Loops are handled from the last to the first so that inner loops come before outer loops.
+	let mut verdicts: Vec<Option<LoopVerdict>> = vec![None; raw_prog.len()];
+	for (loop_index, instr) in raw_prog.iter().enumerate().rev() {
+		let body_len = match instr {
+			RawInstr::BracketLoop { body_len, .. } => *body_len,
+			_ => continue,
+		};
+		let body = &raw_prog[loop_index + 1..loop_index + 1 + body_len];
+		let inner_terminates = |inner_loop_index: usize| {
+			matches!(
+				verdicts[inner_loop_index],
+				Some(LoopVerdict {
+					verdict: Verdict::Terminates,
+					..
+				})
+			)
+		};
+		let never_exiting = analysis
+			.never_exiting_loops
+			.iter()
+			.find(|never_exiting| never_exiting.loop_index == loop_index);
+		let (verdict, reason) = if let Some(never_exiting) = never_exiting {
+			if never_exiting.entered {
+				(
+					Verdict::DoesNotTerminate,
+					"it is entered and no iteration can make its decision cell zero",
+				)
+			} else {
+				(
+					Verdict::Unknown,
+					"it never exits once entered, but it may not be entered",
+				)
+			}
+		} else if let Some(reason) = proven_termination(body, loop_index + 1, inner_terminates) {
+			(Verdict::Terminates, reason)
+		} else {
+			(
+				Verdict::Unknown,
+				"it is not a counter or scan loop with terminating inner loops",
+			)
+		};
+		verdicts[loop_index] = Some(LoopVerdict {
+			loop_index,
+			verdict,
+			reason,
+		});
+	}
+
+	let loops: Vec<LoopVerdict> = verdicts.into_iter().flatten().collect();
+	let program = if !analysis.finishes {
+		Verdict::DoesNotTerminate
+	} else if loops
+		.iter()
+		.all(|loop_verdict| loop_verdict.verdict == Verdict::Terminates)
+	{
+		Verdict::Terminates
+	} else {
+		Verdict::Unknown
+	};
+	TerminationReport { loops, program }
+}
+
+/// Returns the reason why a loop with the given body (that starts at `start_index` in the raw
+/// program) terminates, if it can be proven.
+fn proven_termination(
+	body: &[RawInstr],
+	start_index: usize,
+	inner_terminates: impl Fn(usize) -> bool,
+) -> Option<&'static str> {
+	// Offsets of the cells that an iteration modifies, relative to the head at its start.
+	let mut modified = Vec::new();
+	let mut decision_delta: u8 = 0;
+	// Something other than the top-level `+` and `-` modifies the decision cell.
+	let mut decision_cell_clobbered = false;
+	let mut head: isize = 0;
+	let mut index = start_index;
+	for (instr, inner_body) in top_level(body) {
+		let instr_index = index;
+		index += 1 + inner_body.len();
+		match instr {
+			RawInstr::Plus(n) => {
+				modified.push(head);
+				if head == 0 {
+					decision_delta = decision_delta.wrapping_add(*n as u8);
+				}
+			}
+			RawInstr::Minus(n) => {
+				modified.push(head);
+				if head == 0 {
+					decision_delta = decision_delta.wrapping_sub(*n as u8);
+				}
+			}
+			RawInstr::Left(n) => head -= *n as isize,
+			RawInstr::Right(n) => head += *n as isize,
+			RawInstr::Dot => (),
+			RawInstr::Comma | RawInstr::Clock | RawInstr::Random | RawInstr::HostCall => {
+				modified.push(head);
+				decision_cell_clobbered |= head == 0;
+			}
+			RawInstr::Extended(instr) => {
+				if instr.writes_cell() {
+					modified.push(head);
+					decision_cell_clobbered |= head == 0;
+				}
+			}
+			RawInstr::BracketLoop { .. } => {
+				if !inner_terminates(instr_index) {
+					return None;
+				}
+				// The head position after an inner loop must not depend on its iteration count.
+				let inner_modified = modified_cells(inner_body)?;
+				// An inner loop exits on a zero cell, so it clobbers its own decision cell.
+				decision_cell_clobbered |= head == 0 || inner_modified.contains(&-head);
+				modified.extend(inner_modified.into_iter().map(|cell| head + cell));
+			}
+		}
+	}
+
+	if head == 0 {
+		(!decision_cell_clobbered && decision_delta % 2 == 1)
+			.then_some("its decision cell changes by an odd amount at each iteration")
+	} else if head > 0 {
+		let writes_ahead = modified.iter().any(|&cell| cell > 0 && cell % head == 0);
+		(!writes_ahead).then_some("it scans to the right without writing to the cells it checks")
+	} else {
+		// Scanning to the left can run off the left end of the tape.
+		None
+	}
+}
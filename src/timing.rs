@@ -0,0 +1,19 @@
+use std::time::Duration;
+
+/// Prints (to stderr) how long a compilation phase took and the size of what it produced, this
+/// is the format of the `--time-passes` report.
+pub fn print_phase_time(phase: &str, duration: Duration, size: usize, size_unit: &str) {
+	eprintln!(
+		"time-passes: {:<20} {:>10.3} ms {:>10} {}",
+		phase,
+		duration.as_secs_f64() * 1000.0,
+		size,
+		size_unit
+	);
+}
+
+/// Prints (to stderr) the peak memory taken by the IR during optimization, in the format of the
+/// `--time-passes` report.
+pub fn print_peak_ir_memory(bytes: usize) {
+	eprintln!("time-passes: {:<20} {:>24} bytes", "peak IR memory", bytes);
+}
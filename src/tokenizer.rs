@@ -0,0 +1,370 @@
+use crate::astraw::LoopAttributes;
+use crate::extended::ExtInstr;
+use crate::frontend::{Brainfuck, Frontend};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+	Plus,
+	Minus,
+	Left,
+	Right,
+	Dot,
+	Comma,
+	OpeningBracket,
+	ClosingBracket,
+	/// `~`, only produced with `Extensions::clock`.
+	Clock,
+	/// `?`, only produced with `Extensions::random`.
+	Random,
+	/// Only produced with `Extensions::extended_type_1`.
+	Extended(ExtInstr),
+	/// The character of `Extensions::host_call`, only produced with it.
+	HostCall,
+	/// Comment and whitespace characters, only produced by `tokenize_with_trivia`.
+	Trivia,
+	/// A `{` block comment (see `Extensions::comments`) that runs to the end of the source code,
+	/// it is always produced so that the parser can report it.
+	UnterminatedBlockComment,
+	/// A `[@name]` comment (see `Extensions::loop_attributes`) giving the attribute to the loop
+	/// that follows it, only produced with the extension.
+	LoopAttribute(LoopAttributes),
+	/// A `[@name]` comment whose name is not one of an attribute, it is always produced (with
+	/// the extension) so that the parser can report it.
+	UnknownLoopAttribute,
+}
+
+impl TokenKind {
+	fn from_char(c: char) -> TokenKind {
+		match c {
+			'+' => TokenKind::Plus,
+			'-' => TokenKind::Minus,
+			'<' => TokenKind::Left,
+			'>' => TokenKind::Right,
+			'.' => TokenKind::Dot,
+			',' => TokenKind::Comma,
+			'[' => TokenKind::OpeningBracket,
+			']' => TokenKind::ClosingBracket,
+			_ => TokenKind::Trivia,
+		}
+	}
+
+	/// Runs of these instructions can be collapsed into one counted token without changing the
+	/// meaning of the program (comments between the instructions of a run are skipped).
+	fn is_collapsible(self) -> bool {
+		matches!(
+			self,
+			TokenKind::Plus | TokenKind::Minus | TokenKind::Left | TokenKind::Right
+		)
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct Token {
+	pub kind: TokenKind,
+	/// Number of consecutive instruction characters represented by this token,
+	/// always 1 for non-collapsible tokens (and for trivia).
+	pub count: usize,
+	/// Position of the first character of the token in the source code.
+	pub pos: usize,
+	/// Length in bytes of the source code span covered by the token.
+	pub len: usize,
+}
+
+impl Token {
+	pub fn text<'a>(&self, src_code: &'a str) -> &'a str {
+		&src_code[self.pos..self.pos + self.len]
+	}
+}
+
+/// Opt-in syntax extensions (none of them are enabled by default), and the source language.
+#[derive(Debug, Clone)]
+pub struct Extensions {
+	/// Source language of the program (brainfuck by default, see `frontend`). The other
+	/// extensions only apply to the frontends that support them.
+	pub frontend: &'static dyn Frontend,
+	/// `;` and `//` start comments that end at the end of the line, and `{`/`}` delimit block
+	/// comments, the content of these comments is ignored even if it contains instructions.
+	pub comments: bool,
+	/// `~` sets the current cell to the tick counter, that counts (modulo 256) the ticks of a
+	/// clock since the program started. The interpreter has a deterministic virtual clock that
+	/// ticks each time it is read, and compiled programs (only in C) tick every 10 milliseconds.
+	pub clock: bool,
+	/// `?` sets the current cell to a pseudo-random byte, from a generator seeded by
+	/// `TapeConfig::seed` (or by the time if there is no seed).
+	pub random: bool,
+	/// The instructions of Extended Brainfuck Type I (see `extended`): `@` ends the program, `$`
+	/// and `!` copy the cell under the head to and from a storage cell, `}` and `{` shift the
+	/// cell right and left by one bit, `~` inverts its bits, and `^`, `&` and `|` combine it with
+	/// the storage cell (xor, and, or).
+	pub extended_type_1: bool,
+	/// Lines of the form `@include <std/name>` are replaced by routines of the standard prelude
+	/// (see `prelude::expand_includes`, that is to be called on the source code before parsing).
+	pub include: bool,
+	/// The given character calls the host with the value of the current cell, which is set to
+	/// the value returned by the host (see `hostcall`).
+	pub host_call: Option<char>,
+	/// `[@name]` (with a name of lowercase letters and dashes) is not a loop but a comment that
+	/// gives an attribute to the loop right after it, such as `[@no-unroll]` (see
+	/// `astraw::LoopAttributes`).
+	pub loop_attributes: bool,
+}
+
+/// Character of the `host-call` extension when none is given.
+pub const DEFAULT_HOST_CALL_CHAR: char = '%';
+
+/// Character of the `host-call` extension given by its name in a list of extensions (`host-call`
+/// or `host-call=<character>`), `None` if the name is not one of the `host-call` extension.
+pub fn host_call_char(extension_name: &str) -> Option<char> {
+	if extension_name == "host-call" {
+		return Some(DEFAULT_HOST_CALL_CHAR);
+	}
+	let mut chars = extension_name.strip_prefix("host-call=")?.chars();
+	match (chars.next(), chars.next()) {
+		(Some(c), None) => Some(c),
+		_ => None,
+	}
+}
+
+impl Default for Extensions {
+	fn default() -> Extensions {
+		Extensions {
+			frontend: &Brainfuck,
+			comments: false,
+			clock: false,
+			random: false,
+			extended_type_1: false,
+			include: false,
+			host_call: None,
+			loop_attributes: false,
+		}
+	}
+}
+
+impl Extensions {
+	/// Error if extensions that give a meaning to the same characters are enabled together, or
+	/// if extensions are enabled with a frontend that does not support them.
+	pub fn check(&self) -> Result<(), String> {
+		let any_enabled = self.comments
+			|| self.clock
+			|| self.random
+			|| self.extended_type_1
+			|| self.include
+			|| self.host_call.is_some()
+			|| self.loop_attributes;
+		if any_enabled && !self.frontend.supports_extensions() {
+			return Err(format!(
+				"the `{}` dialect does not support extensions",
+				self.frontend.name()
+			));
+		}
+		for (conflicting, name) in [
+			(self.comments, "comments"),
+			(self.clock, "clock"),
+			(self.loop_attributes, "loop-attributes"),
+		] {
+			if self.extended_type_1 && conflicting {
+				return Err(format!(
+					"the `extended-type-1` extension cannot be combined with the `{}` extension",
+					name
+				));
+			}
+		}
+		if let Some(c) = self.host_call {
+			if let Some(name) = self.meaning_of(c) {
+				return Err(format!(
+					"the character `{}` of the `host-call` extension already is {}",
+					c, name
+				));
+			}
+		}
+		Ok(())
+	}
+
+	/// What the character already means (other than for the `host-call` extension), if anything.
+	fn meaning_of(&self, c: char) -> Option<&'static str> {
+		if TokenKind::from_char(c) != TokenKind::Trivia {
+			Some("a brainfuck instruction")
+		} else if self.comments && ";/{}".contains(c) {
+			Some("used by the `comments` extension")
+		} else if self.clock && c == '~' {
+			Some("used by the `clock` extension")
+		} else if self.random && c == '?' {
+			Some("used by the `random` extension")
+		} else if self.extended_type_1 && ExtInstr::from_char(c).is_some() {
+			Some("used by the `extended-type-1` extension")
+		} else if self.include && c == '@' {
+			Some("used by the `include` extension")
+		} else {
+			None
+		}
+	}
+}
+
+/// Splits the source code into instruction tokens (with the frontend of the extensions),
+/// collapsing runs of identical `+`, `-`, `<` and `>` into single counted tokens.
+pub fn tokenize(src_code: &str, extensions: &Extensions) -> Vec<Token> {
+	tokenize_source(src_code, extensions, false)
+}
+
+/// Same as `tokenize` but comment and whitespace spans are kept as `Trivia` tokens, so that the
+/// tokens cover the whole source code (see `untokenize`). Runs are not collapsed across trivia.
+pub fn tokenize_with_trivia(src_code: &str, extensions: &Extensions) -> Vec<Token> {
+	tokenize_source(src_code, extensions, true)
+}
+
+/// Tokenizes the whole source code of a program with the frontend, its shebang line (see
+/// `shebang_len`) being a comment. Parts of the source code (such as a loop body) are to be
+/// tokenized by the frontend itself, as they have no shebang line.
+fn tokenize_source(src_code: &str, extensions: &Extensions, keep_trivia: bool) -> Vec<Token> {
+	let shebang_len = shebang_len(src_code);
+	let mut tokens =
+		extensions
+			.frontend
+			.tokenize(&src_code[shebang_len..], extensions, keep_trivia);
+	for token in tokens.iter_mut() {
+		token.pos += shebang_len;
+	}
+	if shebang_len > 0 && keep_trivia {
+		match tokens.first_mut() {
+			Some(first) if first.kind == TokenKind::Trivia => {
+				first.pos = 0;
+				first.len += shebang_len;
+			}
+			_ => tokens.insert(
+				0,
+				Token {
+					kind: TokenKind::Trivia,
+					count: 1,
+					pos: 0,
+					len: shebang_len,
+				},
+			),
+		}
+	}
+	tokens
+}
+
+/// Tokenization of brainfuck source code (see `frontend::Brainfuck`).
+pub(crate) fn tokenize_brainfuck(
+	src_code: &str,
+	extensions: &Extensions,
+	keep_trivia: bool,
+) -> Vec<Token> {
+	let mut tokens: Vec<Token> = Vec::new();
+	let mut skip_until = 0;
+	for (pos, c) in src_code.char_indices() {
+		if pos < skip_until {
+			continue;
+		}
+		let comment = extended_comment(src_code, pos, extensions)
+			.or_else(|| loop_attribute(src_code, pos, extensions));
+		let (kind, len) = match comment {
+			Some((kind, len)) => (kind, len),
+			None if c == '~' && extensions.clock => (TokenKind::Clock, 1),
+			None if c == '?' && extensions.random => (TokenKind::Random, 1),
+			None if Some(c) == extensions.host_call => (TokenKind::HostCall, c.len_utf8()),
+			None if extensions.extended_type_1 && ExtInstr::from_char(c).is_some() => {
+				(TokenKind::Extended(ExtInstr::from_char(c).unwrap()), 1)
+			}
+			None => (TokenKind::from_char(c), c.len_utf8()),
+		};
+		skip_until = pos + len;
+		push_token(&mut tokens, kind, pos, len, keep_trivia);
+	}
+	tokens
+}
+
+/// Length of the shebang line (such as `#!/usr/bin/env xxbf`) that starts the source code, if
+/// any, so that brainfuck scripts can be made executable. It is a comment (without its newline)
+/// even if it contains instructions.
+pub fn shebang_len(src_code: &str) -> usize {
+	if src_code.starts_with("#!") {
+		src_code.find('\n').unwrap_or(src_code.len())
+	} else {
+		0
+	}
+}
+
+/// Adds the token of the given kind and span to the tokens (that come before it), merging it
+/// into the last one if it continues a run of trivia or of a collapsible instruction.
+pub(crate) fn push_token(
+	tokens: &mut Vec<Token>,
+	kind: TokenKind,
+	pos: usize,
+	len: usize,
+	keep_trivia: bool,
+) {
+	if kind == TokenKind::Trivia && !keep_trivia {
+		return;
+	}
+	match tokens.last_mut() {
+		Some(last) if last.kind == kind && kind == TokenKind::Trivia => {
+			last.len = pos + len - last.pos;
+		}
+		Some(last) if last.kind == kind && kind.is_collapsible() => {
+			last.count += 1;
+			last.len = pos + len - last.pos;
+		}
+		_ => tokens.push(Token {
+			kind,
+			count: 1,
+			pos,
+			len,
+		}),
+	}
+}
+
+/// If an extended comment starts at `pos`, returns its token kind and length.
+fn extended_comment(
+	src_code: &str,
+	pos: usize,
+	extensions: &Extensions,
+) -> Option<(TokenKind, usize)> {
+	if !extensions.comments {
+		return None;
+	}
+	let rest = &src_code[pos..];
+	if rest.starts_with(';') || rest.starts_with("//") {
+		// The newline is not part of the comment.
+		Some((TokenKind::Trivia, rest.find('\n').unwrap_or(rest.len())))
+	} else if rest.starts_with('{') {
+		match rest.find('}') {
+			Some(index) => Some((TokenKind::Trivia, index + 1)),
+			None => Some((TokenKind::UnterminatedBlockComment, rest.len())),
+		}
+	} else {
+		None
+	}
+}
+
+/// If a loop attribute comment starts at `pos`, returns its token kind and length.
+fn loop_attribute(
+	src_code: &str,
+	pos: usize,
+	extensions: &Extensions,
+) -> Option<(TokenKind, usize)> {
+	if !extensions.loop_attributes {
+		return None;
+	}
+	let rest = &src_code[pos..];
+	let name_len = rest
+		.strip_prefix("[@")?
+		.find(|c: char| !(c.is_ascii_lowercase() || c == '-'))?;
+	let name = &rest[2..2 + name_len];
+	if name.is_empty() || !rest[2 + name_len..].starts_with(']') {
+		return None;
+	}
+	let len = 2 + name_len + 1;
+	let mut attributes = LoopAttributes::default();
+	if attributes.set(name) {
+		Some((TokenKind::LoopAttribute(attributes), len))
+	} else {
+		Some((TokenKind::UnknownLoopAttribute, len))
+	}
+}
+
+/// Gets back the source code from tokens that cover all of it (as given by
+/// `tokenize_with_trivia`), possibly after some tokens were edited or moved around.
+pub fn untokenize(src_code: &str, tokens: &[Token]) -> String {
+	tokens.iter().map(|token| token.text(src_code)).collect()
+}
@@ -0,0 +1,106 @@
+//! Verification of a compilation backend on a single program: the compiled program is built once
+//! with the toolchain of the backend (see `conformance`) and run on each input of a directory,
+//! its output having to match the output of the interpreter on the same input.
+//!
+//! As in conformance testing, the compiled program gets the input followed by a 0 byte (the
+//! interpreter giving 0 at the end of the input).
+
+use crate::conformance::{Verdict, backends, build, run_built};
+use crate::ctranspiler::COptions;
+use crate::program::Program;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub struct VerifyOptions {
+	/// Name of the backend, as given to `--target` (see `conformance::backends`).
+	pub backend_name: String,
+	/// Directory of the inputs (every file in it), the program being run on an empty input only
+	/// if absent.
+	pub inputs_dir: Option<PathBuf>,
+	/// Options of the C backend, the other backends having none.
+	pub c_options: COptions,
+}
+
+#[derive(Debug)]
+pub struct InputResult {
+	/// File name of the input.
+	pub input_name: String,
+	pub verdict: Verdict,
+}
+
+/// Compiles and builds the program with the backend and compares its output to the output of the
+/// interpreter on each input, the given callback being called with the result of each input as
+/// soon as it is known. Fails if the backend is unknown, its toolchain is not available, or the
+/// compiled program does not build.
+pub fn verify_target(
+	program: &Program,
+	options: &VerifyOptions,
+	mut on_result: impl FnMut(&InputResult),
+) -> Result<Vec<InputResult>, String> {
+	let backend = backends()
+		.into_iter()
+		.find(|backend| backend.name == options.backend_name)
+		.ok_or_else(|| format!("unknown backend `{}`", options.backend_name))?;
+	if !backend.is_available() {
+		return Err(format!(
+			"the toolchain of backend `{}` is not available",
+			backend.name
+		));
+	}
+	let inputs = match &options.inputs_dir {
+		Some(inputs_dir) => input_files(inputs_dir)?,
+		None => vec![("(empty input)".to_owned(), Vec::new())],
+	};
+
+	let code = if backend.name == "c" {
+		program.to_c(&options.c_options).into_bytes()
+	} else {
+		(backend.emit)(program)
+	};
+	let dir = std::env::temp_dir().join(format!("xxbf-verify-{}", std::process::id()));
+	if let Err(error) = build(&backend, &code, &dir) {
+		std::fs::remove_dir_all(&dir).ok();
+		return Err(error);
+	}
+	let mut results = Vec::new();
+	for (input_name, input) in inputs {
+		let expected = program.run(Some(input.clone()));
+		let mut compiled_input = input;
+		compiled_input.push(0);
+		let verdict = match run_built(&backend, &dir, &compiled_input) {
+			Ok(actual) if actual == expected => Verdict::Pass,
+			Ok(actual) => Verdict::Fail { expected, actual },
+			Err(error) => Verdict::Error(error),
+		};
+		let result = InputResult {
+			input_name,
+			verdict,
+		};
+		on_result(&result);
+		results.push(result);
+	}
+	std::fs::remove_dir_all(&dir).ok();
+	Ok(results)
+}
+
+/// Names and contents of the files of the directory, in alphabetical order.
+fn input_files(inputs_dir: &Path) -> Result<Vec<(String, Vec<u8>)>, String> {
+	let entries = std::fs::read_dir(inputs_dir)
+		.map_err(|error| format!("cannot read `{}`: {}", inputs_dir.display(), error))?;
+	let mut paths: Vec<PathBuf> = entries
+		.filter_map(|entry| Some(entry.ok()?.path()))
+		.filter(|path| path.is_file())
+		.collect();
+	paths.sort();
+	paths
+		.into_iter()
+		.map(|path| {
+			let input = std::fs::read(&path)
+				.map_err(|error| format!("cannot read `{}`: {}", path.display(), error))?;
+			Ok((
+				path.file_name().unwrap().to_string_lossy().into_owned(),
+				input,
+			))
+		})
+		.collect()
+}
@@ -1,57 +1,654 @@
 use crate::astraw::RawInstr;
 use crate::astsoup::SoupInstr;
+use crate::bytecode::Op;
+use crate::cancel::CancelToken;
+use crate::extended::ExtInstr;
+use crate::graph::{Block, Graph, Terminator};
+use crate::jit::Jit;
+use crate::machine::MachineModel;
+use crate::tapeconfig::{Region, TapeConfig};
+use crate::watch::Watchpoints;
+use std::collections::{BTreeMap, HashMap};
 use std::io::{Read, Write};
 
-struct VmMem {
+/// Execution engine that runs a program (`--run-stage`), whatever its optimization.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunStage {
+	/// Interpreter of the raw program, that must not be optimized.
+	Raw,
+	/// Interpreter of the soup program (see `run_soup`).
+	Soup,
+	/// Interpreter of the control flow graph of the soup program (see `run_graph`).
+	Graph,
+	/// Interpreter of the bytecode (see `run_bytecode`), without the JIT.
+	Bytecode,
+	/// Bytecode interpreter with the JIT.
+	Jit,
+}
+
+impl RunStage {
+	pub fn from_name(name: &str) -> Option<RunStage> {
+		match name {
+			"raw" => Some(RunStage::Raw),
+			"soup" => Some(RunStage::Soup),
+			"graph" => Some(RunStage::Graph),
+			"bytecode" => Some(RunStage::Bytecode),
+			"jit" => Some(RunStage::Jit),
+			_ => None,
+		}
+	}
+}
+
+/// Number of cells after the head that are allocated before entering compiled code.
+const JIT_TAPE_MARGIN: usize = 1 << 12;
+
+/// Number of steps between two calls to `Observer::on_progress`.
+pub const PROGRESS_INTERVAL: u64 = 1_000_000;
+
+/// Limits on a run of the VM, that stops with an error (as on any other error) when one is
+/// exceeded (see `sandbox`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RunLimits {
+	/// Number of steps (see `Observer::on_progress`).
+	pub max_steps: Option<u64>,
+	/// Number of cells of the tape (and of the storage of the extended-type-1 extension).
+	pub max_cells: Option<usize>,
+	/// Number of bytes of output.
+	pub max_output_len: Option<usize>,
+}
+
+impl RunLimits {
+	/// The tighter of the two limits, for each of them.
+	pub fn min(self, other: RunLimits) -> RunLimits {
+		fn min<T: Ord>(a: Option<T>, b: Option<T>) -> Option<T> {
+			match (a, b) {
+				(Some(a), Some(b)) => Some(a.min(b)),
+				(a, b) => a.or(b),
+			}
+		}
+		RunLimits {
+			max_steps: min(self.max_steps, other.max_steps),
+			max_cells: min(self.max_cells, other.max_cells),
+			max_output_len: min(self.max_output_len, other.max_output_len),
+		}
+	}
+}
+
+/// Observer of the VM that only sets the limits of the run.
+impl Observer for RunLimits {
+	fn on_output(&mut self, _char_values: &[u8]) {}
+
+	fn limits(&self) -> RunLimits {
+		*self
+	}
+}
+
+/// Payload of the panic of the VM when the program exceeds its limit of output, the output being
+/// truncated to the limit, so that it can be told apart from the other errors (that have the
+/// message of the error as payload).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputLimitExceeded {
+	pub max_output_len: usize,
+}
+
+/// Exit status of the command line interpreter when the program exceeds its limit of output
+/// (`--max-output`), telling it apart from halting (0) and from stopping on an error (101).
+pub const OUTPUT_LIMIT_EXIT_CODE: i32 = 3;
+
+impl std::fmt::Display for OutputLimitExceeded {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(
+			f,
+			"output limit exceeded (the program output more than {} bytes)",
+			self.max_output_len
+		)
+	}
+}
+
+/// Gets notified of what a program run by the VM does, for example to react to its output.
+pub trait Observer {
+	/// The program output the given bytes (that were already printed in interactive mode).
+	fn on_output(&mut self, char_values: &[u8]);
+
+	/// The program read the given bytes.
+	fn on_input(&mut self, _char_values: &[u8]) {}
+
+	/// Gives the next bytes of input when the program reads and there is no input left in
+	/// interactive mode, or `None` for the VM to read a byte from stdin. No bytes means the end of
+	/// the input.
+	fn read_interactive_input(&mut self) -> Option<Vec<u8>> {
+		None
+	}
+
+	/// The program called the host (see `hostcall`) with the given value of a cell, returns the
+	/// new value of the cell (`None` leaves it unchanged).
+	fn on_host_call(&mut self, _cell_value: u8) -> Option<u8> {
+		None
+	}
+
+	/// `on_instr` is only called if this is `true`, as it slows the VM down.
+	fn traces_instrs(&self) -> bool {
+		false
+	}
+
+	/// The instruction of the given index is about to run, with the given head and value of the
+	/// cell under it. Only the raw engine (with the index of the raw instruction) and the soup
+	/// engine (with the index of the soup instruction in the order of `astsoup::instr_count`, a
+	/// loop coming before its body) trace instructions.
+	fn on_instr(&mut self, _instr_index: usize, _head: usize, _cell_value: u8) {}
+
+	/// `on_output_tape` is only called if this is `true`.
+	fn watches_output_tape(&self) -> bool {
+		false
+	}
+
+	/// The program is about to output the given bytes (then given to `on_output`), with the given
+	/// tape. The head is not given, as optimized programs output cells at an offset from it.
+	fn on_output_tape(&mut self, _tape: &[u8], _char_values: &[u8]) {}
+
+	/// `on_progress` is only called if this is `true`, as counting steps slows the VM down (and
+	/// disables the JIT, as compiled code does not count them).
+	fn counts_steps(&self) -> bool {
+		false
+	}
+
+	/// The program ran the given number of steps, a step being an instruction of the engine (raw
+	/// instruction, soup instruction or bytecode op, a soup loop being one step as is an iteration
+	/// of an empty raw loop), and the head is at the given index. Called every
+	/// `PROGRESS_INTERVAL` steps, and with the total number of steps when the program halts (or
+	/// the VM stops on an error), before `on_halt`.
+	fn on_progress(&mut self, _steps: u64, _head: usize) {}
+
+	/// Limits on the run, none by default. Any limit makes the VM count steps (so the JIT is
+	/// disabled, as compiled code does not check them).
+	fn limits(&self) -> RunLimits {
+		RunLimits::default()
+	}
+
+	/// The program halted (or the VM stopped on an error), with the given tape and head.
+	fn on_halt(&mut self, _tape: &[u8], _head: usize) {}
+}
+
+impl<T: Observer + ?Sized> Observer for &mut T {
+	fn on_output(&mut self, char_values: &[u8]) {
+		(**self).on_output(char_values);
+	}
+
+	fn on_input(&mut self, char_values: &[u8]) {
+		(**self).on_input(char_values);
+	}
+
+	fn read_interactive_input(&mut self) -> Option<Vec<u8>> {
+		(**self).read_interactive_input()
+	}
+
+	fn on_host_call(&mut self, cell_value: u8) -> Option<u8> {
+		(**self).on_host_call(cell_value)
+	}
+
+	fn traces_instrs(&self) -> bool {
+		(**self).traces_instrs()
+	}
+
+	fn on_instr(&mut self, instr_index: usize, head: usize, cell_value: u8) {
+		(**self).on_instr(instr_index, head, cell_value);
+	}
+
+	fn watches_output_tape(&self) -> bool {
+		(**self).watches_output_tape()
+	}
+
+	fn on_output_tape(&mut self, tape: &[u8], char_values: &[u8]) {
+		(**self).on_output_tape(tape, char_values);
+	}
+
+	fn counts_steps(&self) -> bool {
+		(**self).counts_steps()
+	}
+
+	fn on_progress(&mut self, steps: u64, head: usize) {
+		(**self).on_progress(steps, head);
+	}
+
+	fn limits(&self) -> RunLimits {
+		(**self).limits()
+	}
+
+	fn on_halt(&mut self, tape: &[u8], head: usize) {
+		(**self).on_halt(tape, head);
+	}
+}
+
+/// Observers that are all notified, in order.
+impl Observer for Vec<Box<dyn Observer + '_>> {
+	fn on_output(&mut self, char_values: &[u8]) {
+		for observer in self.iter_mut() {
+			observer.on_output(char_values);
+		}
+	}
+
+	fn on_input(&mut self, char_values: &[u8]) {
+		for observer in self.iter_mut() {
+			observer.on_input(char_values);
+		}
+	}
+
+	/// The input given by the first observer that gives some.
+	fn read_interactive_input(&mut self) -> Option<Vec<u8>> {
+		self.iter_mut()
+			.find_map(|observer| observer.read_interactive_input())
+	}
+
+	/// The value given by the last observer that gives one, each observer getting the value given
+	/// by those before it.
+	fn on_host_call(&mut self, cell_value: u8) -> Option<u8> {
+		let mut new_value = None;
+		for observer in self.iter_mut() {
+			new_value = observer
+				.on_host_call(new_value.unwrap_or(cell_value))
+				.or(new_value);
+		}
+		new_value
+	}
+
+	fn traces_instrs(&self) -> bool {
+		self.iter().any(|observer| observer.traces_instrs())
+	}
+
+	fn on_instr(&mut self, instr_index: usize, head: usize, cell_value: u8) {
+		for observer in self.iter_mut() {
+			if observer.traces_instrs() {
+				observer.on_instr(instr_index, head, cell_value);
+			}
+		}
+	}
+
+	fn watches_output_tape(&self) -> bool {
+		self.iter().any(|observer| observer.watches_output_tape())
+	}
+
+	fn on_output_tape(&mut self, tape: &[u8], char_values: &[u8]) {
+		for observer in self.iter_mut() {
+			if observer.watches_output_tape() {
+				observer.on_output_tape(tape, char_values);
+			}
+		}
+	}
+
+	fn counts_steps(&self) -> bool {
+		self.iter().any(|observer| observer.counts_steps())
+	}
+
+	fn on_progress(&mut self, steps: u64, head: usize) {
+		for observer in self.iter_mut() {
+			if observer.counts_steps() {
+				observer.on_progress(steps, head);
+			}
+		}
+	}
+
+	/// The tightest limits of all the observers.
+	fn limits(&self) -> RunLimits {
+		self.iter().fold(RunLimits::default(), |limits, observer| {
+			limits.min(observer.limits())
+		})
+	}
+
+	fn on_halt(&mut self, tape: &[u8], head: usize) {
+		for observer in self.iter_mut() {
+			observer.on_halt(tape, head);
+		}
+	}
+}
+
+struct VmMem<'a> {
 	cell_vec: Vec<u8>,
 	head: usize,
 	interact_with_user: bool,
 	input_stack: Vec<u8>,
 	output_stack: Vec<u8>,
+	observer: Option<&'a mut dyn Observer>,
+	/// The observer counts steps (see `Observer::counts_steps`).
+	counts_steps: bool,
+	/// Number of steps run so far, if they are counted.
+	steps: u64,
+	limits: RunLimits,
+	/// The observer watches the tape at outputs (see `Observer::watches_output_tape`).
+	watches_output_tape: bool,
+	read_only_regions: Vec<Region>,
+	/// End of the last read-only region, the cells after it can be written.
+	read_only_end: usize,
+	/// Virtual clock of the clock extension, that ticks each time it is read.
+	clock: u64,
+	/// State of the generator of the random extension.
+	random_state: u64,
+	machine: MachineModel,
+	/// Storage of the extended-type-1 extension (see `machine`), that grows as it is used.
+	storage: Vec<u8>,
 }
 
-impl VmMem {
-	fn new(input: Option<Vec<u8>>) -> VmMem {
+/// Dropping the memory is when the program halts, or when the VM panics on an error.
+impl Drop for VmMem<'_> {
+	fn drop(&mut self) {
+		if let Some(observer) = &mut self.observer {
+			if self.counts_steps && !self.steps.is_multiple_of(PROGRESS_INTERVAL) {
+				observer.on_progress(self.steps, self.head);
+			}
+			observer.on_halt(&self.cell_vec, self.head);
+		}
+	}
+}
+
+impl<'a> VmMem<'a> {
+	fn new(
+		input: Option<Vec<u8>>,
+		tape_config: &TapeConfig,
+		observer: Option<&'a mut dyn Observer>,
+	) -> VmMem<'a> {
+		let read_only_regions: Vec<Region> = tape_config.read_only_regions().cloned().collect();
+		let limits = observer
+			.as_ref()
+			.map_or(RunLimits::default(), |observer| observer.limits());
 		VmMem {
-			cell_vec: Vec::new(),
-			head: 0,
+			cell_vec: tape_config.initial_tape.clone(),
+			head: tape_config.head_start,
 			interact_with_user: input.is_none(),
 			input_stack: input.map_or(Vec::new(), |v| {
 				v.into_iter().chain(std::iter::once(0)).rev().collect()
 			}),
 			output_stack: Vec::new(),
+			counts_steps: observer
+				.as_ref()
+				.is_some_and(|observer| observer.counts_steps())
+				|| limits != RunLimits::default(),
+			steps: 0,
+			limits,
+			watches_output_tape: observer
+				.as_ref()
+				.is_some_and(|observer| observer.watches_output_tape()),
+			observer,
+			read_only_end: read_only_regions
+				.last()
+				.map_or(0, |region| region.cells.end),
+			read_only_regions,
+			clock: 0,
+			random_state: tape_config.seed.unwrap_or_else(seed_from_time),
+			machine: tape_config.machine.clone(),
+			storage: Vec::new(),
 		}
 	}
 
+	/// Counts a step (only called if steps are counted).
+	fn step(&mut self) {
+		if let Some(max_steps) = self.limits.max_steps {
+			if self.steps == max_steps {
+				panic!("the program ran more than {} steps", max_steps);
+			}
+		}
+		self.steps += 1;
+		if self.steps.is_multiple_of(PROGRESS_INTERVAL) {
+			if let Some(observer) = &mut self.observer {
+				observer.on_progress(self.steps, self.head);
+			}
+		}
+	}
+
+	/// Reads the virtual clock (modulo 256), so that timed programs run deterministically.
+	fn read_clock(&mut self) -> u8 {
+		let ticks = self.clock as u8;
+		self.clock += 1;
+		ticks
+	}
+
+	/// Next byte of the generator (SplitMix64, as in the C backend, so that a seed gives the same
+	/// bytes in both).
+	fn random_byte(&mut self) -> u8 {
+		self.random_state = self.random_state.wrapping_add(0x9e3779b97f4a7c15);
+		let mut z = self.random_state;
+		z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+		((z ^ (z >> 31)) >> 56) as u8
+	}
+
+	/// Calls the host with the cell of the given index (see `Observer::on_host_call`).
+	fn host_call(&mut self, index: usize) {
+		let cell_value = self.get(index);
+		let new_value = self
+			.observer
+			.as_mut()
+			.and_then(|observer| observer.on_host_call(cell_value));
+		if let Some(new_value) = new_value {
+			self.set(index, new_value);
+		}
+	}
+
+	/// Does the instruction (that must not be `End`) on the cell of the given index.
+	fn extended(&mut self, instr: ExtInstr, index: usize) {
+		let storage_index = self.machine.storage_index(index);
+		if self.storage.len() <= storage_index {
+			self.check_cells(storage_index);
+			self.storage.resize(storage_index + 1, 0);
+		}
+		let (cell, storage) = instr.apply(self.get(index), self.storage[storage_index]);
+		self.storage[storage_index] = storage;
+		if instr.writes_cell() {
+			self.set(index, cell);
+		}
+	}
+
+	/// Stops the program if the cell of the given index is past the cells it can use.
+	fn check_cells(&self, index: usize) {
+		if let Some(max_cells) = self.limits.max_cells {
+			if index >= max_cells {
+				panic!("the program used more than {} cells", max_cells);
+			}
+		}
+	}
+
+	/// Grows the tape up to the given length (that must be greater than its length).
+	#[cold]
+	fn grow_tape(&mut self, len: usize) {
+		self.check_cells(len - 1);
+		self.cell_vec.resize(len, 0);
+	}
+
 	fn get(&self, index: usize) -> u8 {
 		self.cell_vec.get(index).copied().unwrap_or(0)
 	}
 
+	/// Stops the program if the value would change a cell of a read-only region.
+	fn check_write(&self, index: usize, value: u8) {
+		if index < self.read_only_end && self.get(index) != value {
+			if let Some(region) = self
+				.read_only_regions
+				.iter()
+				.find(|region| region.cells.contains(&index))
+			{
+				panic!(
+					"the program changed cell {} of the read-only region `{}`",
+					index, region.name
+				);
+			}
+		}
+	}
+
+	// Always inlined, as the engines are compiled for both values of `COUNTS_STEPS` and would
+	// otherwise call it.
+	#[inline(always)]
 	fn set(&mut self, index: usize, value: u8) {
-		let len = self.cell_vec.len();
-		if len <= index {
-			self.cell_vec
-				.extend(std::iter::repeat(0).take(index + 1 - len))
+		self.check_write(index, value);
+		if self.cell_vec.len() <= index {
+			self.grow_tape(index + 1);
 		}
 		self.cell_vec[index] = value;
 	}
 
+	/// Adds the deltas times the factor (wrapping) to the cells at their offsets from the head,
+	/// dense deltas being added to the slice of their cells in a single pass (the tape being
+	/// grown at most once).
+	fn add_cell_deltas(&mut self, cell_deltas: &CellDeltas, factor: u8) {
+		match cell_deltas {
+			CellDeltas::Dense {
+				first_offset,
+				deltas,
+			} if self.head as isize + first_offset >= self.read_only_end as isize => {
+				let start = (self.head as isize + first_offset) as usize;
+				let end = start + deltas.len();
+				if self.cell_vec.len() < end {
+					self.grow_tape(end);
+				}
+				for (cell, delta) in self.cell_vec[start..end].iter_mut().zip(deltas) {
+					*cell = cell.wrapping_add(delta.wrapping_mul(factor));
+				}
+			}
+			CellDeltas::Dense {
+				first_offset,
+				deltas,
+			} => {
+				for (offset, &delta) in (*first_offset..).zip(deltas) {
+					self.add_cell_delta(offset, delta.wrapping_mul(factor));
+				}
+			}
+			CellDeltas::Sparse(deltas) => {
+				for &(offset, delta) in deltas {
+					self.add_cell_delta(offset, delta.wrapping_mul(factor));
+				}
+			}
+		}
+	}
+
+	/// Adds the deltas times the factor (wrapping) to the cells at their offsets from the head,
+	/// the tape being grown once before (up to the cell of the greatest offset) so that the cells
+	/// are then written without `set` checking if the tape has to grow.
+	fn add_cell_delta_map(&mut self, cell_deltas: &BTreeMap<isize, isize>, factor: isize) {
+		let (Some((&first, _)), Some((&last, _))) =
+			(cell_deltas.first_key_value(), cell_deltas.last_key_value())
+		else {
+			return;
+		};
+		if self.head as isize + first < 0 {
+			// The head is before the start of the tape, `set` stops the program.
+			for (&offset, &delta) in cell_deltas.iter() {
+				self.add_cell_delta(offset, (delta * factor).rem_euclid(256) as u8);
+			}
+			return;
+		}
+		let end = (self.head as isize + last + 1) as usize;
+		if self.cell_vec.len() < end {
+			self.grow_tape(end);
+		}
+		for (&offset, &delta) in cell_deltas.iter() {
+			let index = (self.head as isize + offset) as usize;
+			let new_value =
+				self.cell_vec[index].wrapping_add((delta * factor).rem_euclid(256) as u8);
+			self.check_write(index, new_value);
+			self.cell_vec[index] = new_value;
+		}
+	}
+
+	fn add_cell_delta(&mut self, offset: isize, delta: u8) {
+		let index = (self.head as isize + offset) as usize;
+		self.set(index, self.get(index).wrapping_add(delta));
+	}
+
+	/// Number of bytes the program can still output.
+	fn output_room(&self) -> usize {
+		self.limits
+			.max_output_len
+			.map_or(usize::MAX, |max_output_len| {
+				max_output_len.saturating_sub(self.output_stack.len())
+			})
+	}
+
+	/// Stops the program as it outputs more than its limit (see `OutputLimitExceeded`).
+	#[cold]
+	fn exceed_output_limit(&self) -> ! {
+		std::panic::panic_any(OutputLimitExceeded {
+			max_output_len: self.limits.max_output_len.unwrap(),
+		})
+	}
+
 	fn output_char_value(&mut self, char_value: u8) {
+		if self.output_room() == 0 {
+			self.exceed_output_limit();
+		}
+		self.notify_output_tape(&[char_value]);
 		if self.interact_with_user {
-			print!("{}", char_value as char);
+			std::io::stdout().write_all(&[char_value]).ok();
 		}
 		self.output_stack.push(char_value);
+		if let Some(observer) = &mut self.observer {
+			observer.on_output(&[char_value]);
+		}
+	}
+
+	fn notify_output_tape(&mut self, char_values: &[u8]) {
+		if self.watches_output_tape {
+			if let Some(observer) = &mut self.observer {
+				observer.on_output_tape(&self.cell_vec, char_values);
+			}
+		}
+	}
+
+	/// Outputs the bytes, or as many of them as the limit allows before stopping the program.
+	fn output_char_values(&mut self, char_values: &[u8]) {
+		let room = self.output_room();
+		if char_values.len() > room {
+			self.output_char_values(&char_values[..room]);
+			self.exceed_output_limit();
+		}
+		self.notify_output_tape(char_values);
+		if self.interact_with_user {
+			std::io::stdout().write_all(char_values).ok();
+		}
+		self.output_stack.extend_from_slice(char_values);
+		if let Some(observer) = &mut self.observer {
+			observer.on_output(char_values);
+		}
+	}
+
+	/// Reads the given number of input bytes at once (as far as the input stack goes).
+	fn input_char_values(&mut self, count: usize) -> Vec<u8> {
+		let from_stack = count.min(self.input_stack.len());
+		let stack_len = self.input_stack.len();
+		let mut char_values: Vec<u8> = self
+			.input_stack
+			.drain(stack_len - from_stack..)
+			.rev()
+			.collect();
+		if let Some(observer) = &mut self.observer {
+			observer.on_input(&char_values);
+		}
+		char_values.extend((from_stack..count).map(|_| self.input_char_value()));
+		char_values
 	}
 
 	fn input_char_value(&mut self) -> u8 {
+		let char_value = self.pop_input_char_value();
+		if let Some(observer) = &mut self.observer {
+			observer.on_input(&[char_value]);
+		}
+		char_value
+	}
+
+	fn pop_input_char_value(&mut self) -> u8 {
 		match self.input_stack.pop() {
 			Some(value) => value,
 			None => {
 				if self.interact_with_user {
+					let observer_input = self
+						.observer
+						.as_mut()
+						.and_then(|observer| observer.read_interactive_input());
+					if let Some(char_values) = observer_input {
+						self.input_stack.extend(char_values.into_iter().rev());
+						return self.input_stack.pop().unwrap_or(0);
+					}
 					print!("\x1b[36m");
 					std::io::stdout().flush().ok();
 					self.input_stack.push(
 						std::io::stdin()
+							.lock()
 							.bytes()
 							.next()
 							.transpose()
@@ -67,20 +664,173 @@ impl VmMem {
 			}
 		}
 	}
+
+	/// Makes sure that the cells around the head are in `cell_vec`, as compiled code only
+	/// accesses the cells in there (see `jit`).
+	fn grow_tape_around_head(&mut self) {
+		let wanted_len = self.head.saturating_add(JIT_TAPE_MARGIN);
+		// A head before the start of the tape would be a huge index.
+		if self.head <= self.cell_vec.len() && self.cell_vec.len() < wanted_len {
+			self.cell_vec.resize(wanted_len, 0);
+		}
+	}
+
+	/// See `SoupInstr::BlockMove`.
+	fn block_move(&mut self, offset: isize) {
+		let mut len = 0;
+		while self.get((self.head as isize - len as isize * offset) as usize) != 0 {
+			len += 1;
+		}
+		if len != 0 {
+			let destination = (self.head as isize + offset) as usize;
+			self.set(
+				destination,
+				self.get(destination).wrapping_add(self.get(self.head)),
+			);
+			// The other cells of the run are shifted, the cell at its far end becoming zero (the
+			// cells of the run are non-zero so they are all in `cell_vec`).
+			let (start, end) = if offset == 1 {
+				(self.head + 1 - len, self.head)
+			} else {
+				(self.head + 1, self.head + len)
+			};
+			for index in start..end {
+				self.check_write((index as isize + offset) as usize, self.cell_vec[index]);
+			}
+			self.cell_vec
+				.copy_within(start..end, (start as isize + offset) as usize);
+			let last = (self.head as isize - (len as isize - 1) * offset) as usize;
+			self.set(last, 0);
+		}
+		self.head = (self.head as isize - len as isize * offset) as usize;
+	}
+
+	fn print_until_zero(&mut self, stride: isize) {
+		let mut char_values = Vec::new();
+		while self.get(self.head) != 0 {
+			char_values.push(self.get(self.head));
+			self.head = (self.head as isize + stride) as usize;
+		}
+		self.output_char_values(&char_values);
+	}
+
+	fn read_block(&mut self, stride: isize) {
+		let mut indices = Vec::new();
+		let mut index = self.head;
+		while self.get(index) != 0 {
+			indices.push(index);
+			index = (index as isize + stride) as usize;
+		}
+		let char_values = self.input_char_values(indices.len());
+		for (index, char_value) in indices.into_iter().zip(char_values) {
+			self.set(index, char_value);
+		}
+		self.head = index;
+	}
+
+	fn read_until_zero(&mut self, stride: isize) {
+		while self.get(self.head) != 0 {
+			// Read the bytes of the input stack up to the next zero at once.
+			let count = self
+				.input_stack
+				.iter()
+				.rev()
+				.position(|&value| value == 0)
+				.map_or(1, |position| position + 1);
+			for char_value in self.input_char_values(count) {
+				self.head = (self.head as isize + stride) as usize;
+				self.set(self.head, char_value);
+			}
+		}
+	}
 }
 
-pub fn run_raw(instr_seq: Vec<RawInstr>, input: Option<Vec<u8>>) -> Vec<u8> {
-	let mut m = VmMem::new(input);
-	let mut instr_stack: Vec<RawInstr> = instr_seq.into_iter().rev().collect();
-	while let Some(instr) = instr_stack.pop() {
-		match &instr {
-			RawInstr::Plus => m.set(m.head, m.get(m.head).wrapping_add(1)),
-			RawInstr::Minus => m.set(m.head, m.get(m.head).wrapping_sub(1)),
-			RawInstr::Left => {
-				assert!(m.head >= 1);
-				m.head -= 1;
-			}
-			RawInstr::Right => m.head += 1,
+/// Seed of the generator of the random extension when none is given.
+fn seed_from_time() -> u64 {
+	std::time::SystemTime::now()
+		.duration_since(std::time::UNIX_EPOCH)
+		.map_or(0, |duration| duration.as_nanos() as u64)
+}
+
+/// The program stops early if one of the watchpoints is hit (see `watch`) or if it is cancelled
+/// (see `cancel`).
+pub fn run_raw(
+	instr_seq: &[RawInstr],
+	input: Option<Vec<u8>>,
+	tape_config: &TapeConfig,
+	observer: Option<&mut dyn Observer>,
+	watchpoints: Option<&mut Watchpoints>,
+	cancel: Option<&CancelToken>,
+) -> Vec<u8> {
+	let m = VmMem::new(input, tape_config, observer);
+	if m.counts_steps {
+		run_raw_with::<true>(instr_seq, m, watchpoints, cancel)
+	} else {
+		run_raw_with::<false>(instr_seq, m, watchpoints, cancel)
+	}
+}
+
+/// Same as `run_raw`, the code that counts steps being compiled only if `COUNTS_STEPS`.
+fn run_raw_with<const COUNTS_STEPS: bool>(
+	instr_seq: &[RawInstr],
+	mut m: VmMem,
+	mut watchpoints: Option<&mut Watchpoints>,
+	cancel: Option<&CancelToken>,
+) -> Vec<u8> {
+	let trace_instrs = m
+		.observer
+		.as_ref()
+		.is_some_and(|observer| observer.traces_instrs());
+	if let Some(watchpoints) = &mut watchpoints {
+		watchpoints.start(|index| m.get(index), m.head);
+	}
+	// Index of the next instruction to run, and indices of the loops it is in.
+	let mut pc = 0;
+	let mut loop_stack: Vec<usize> = Vec::new();
+	'run: loop {
+		// Reaching the end of a loop body jumps back at its beginning or exits the loop.
+		while let Some(&loop_index) = loop_stack.last() {
+			let body_end = match instr_seq[loop_index] {
+				RawInstr::BracketLoop { body_len, .. } => loop_index + 1 + body_len,
+				_ => unreachable!(),
+			};
+			if pc < body_end {
+				break;
+			} else if m.get(m.head) != 0 {
+				if cancel.is_some_and(|cancel| cancel.is_cancelled()) {
+					break 'run;
+				}
+				// An iteration of an empty loop is a step, as it runs no instruction.
+				if COUNTS_STEPS && pc == loop_index + 1 {
+					m.step();
+				}
+				pc = loop_index + 1;
+			} else {
+				loop_stack.pop();
+			}
+		}
+		let instr = match instr_seq.get(pc) {
+			Some(instr) => instr,
+			None => break,
+		};
+		if trace_instrs {
+			let cell_value = m.get(m.head);
+			if let Some(observer) = &mut m.observer {
+				observer.on_instr(pc, m.head, cell_value);
+			}
+		}
+		if COUNTS_STEPS {
+			m.step();
+		}
+		pc += 1;
+		match instr {
+			RawInstr::Plus(n) => m.set(m.head, m.get(m.head).wrapping_add(*n as u8)),
+			RawInstr::Minus(n) => m.set(m.head, m.get(m.head).wrapping_sub(*n as u8)),
+			RawInstr::Left(n) => {
+				assert!(m.head >= *n);
+				m.head -= n;
+			}
+			RawInstr::Right(n) => m.head += n,
 			RawInstr::Dot => {
 				let char_value = m.get(m.head);
 				m.output_char_value(char_value);
@@ -89,94 +839,459 @@ pub fn run_raw(instr_seq: Vec<RawInstr>, input: Option<Vec<u8>>) -> Vec<u8> {
 				let char_value = m.input_char_value();
 				m.set(m.head, char_value);
 			}
-			RawInstr::BracketLoop(body) => {
+			RawInstr::Clock => {
+				let ticks = m.read_clock();
+				m.set(m.head, ticks);
+			}
+			RawInstr::Random => {
+				let byte = m.random_byte();
+				m.set(m.head, byte);
+			}
+			RawInstr::Extended(ExtInstr::End) => break,
+			RawInstr::Extended(instr) => m.extended(*instr, m.head),
+			RawInstr::HostCall => m.host_call(m.head),
+			RawInstr::BracketLoop { body_len, .. } => {
 				if m.get(m.head) != 0 {
-					// The loop itself must be under its content.
-					instr_stack.push(instr.clone());
-					instr_stack.extend(body.iter().rev().cloned());
+					loop_stack.push(pc - 1);
+				} else {
+					pc += body_len;
 				}
 			}
 		}
+		if let Some(watchpoints) = &mut watchpoints {
+			if watchpoints.check(|index| m.get(index), m.head, pc - 1) {
+				break;
+			}
+		}
 	}
-	if m.interact_with_user && m.output_stack.last().map_or(false, |&v| v != 10) {
-		println!("");
+	if m.interact_with_user && m.output_stack.last().is_some_and(|&v| v != 10) {
+		println!();
 	}
-	m.output_stack
+	std::mem::take(&mut m.output_stack)
 }
 
-pub fn run_soup(instr_seq: Vec<SoupInstr>, input: Option<Vec<u8>>) -> Vec<u8> {
-	let mut m = VmMem::new(input);
-	let mut instr_stack: Vec<SoupInstr> = instr_seq.into_iter().rev().collect();
+pub fn run_soup(
+	instr_seq: &[SoupInstr],
+	input: Option<Vec<u8>>,
+	tape_config: &TapeConfig,
+	observer: Option<&mut dyn Observer>,
+) -> Vec<u8> {
+	let m = VmMem::new(input, tape_config, observer);
+	if m.counts_steps {
+		run_soup_with::<true>(instr_seq, m)
+	} else {
+		run_soup_with::<false>(instr_seq, m)
+	}
+}
+
+/// Same as `run_soup`, the code that counts steps being compiled only if `COUNTS_STEPS`.
+fn run_soup_with<const COUNTS_STEPS: bool>(instr_seq: &[SoupInstr], mut m: VmMem) -> Vec<u8> {
+	// Indices of the instructions given to `Observer::on_instr`, by address.
+	let instr_indices: Option<HashMap<*const SoupInstr, usize>> = m
+		.observer
+		.as_ref()
+		.is_some_and(|observer| observer.traces_instrs())
+		.then(|| {
+			let mut instr_indices = HashMap::new();
+			index_soup_instrs(instr_seq, &mut instr_indices);
+			instr_indices
+		});
+	let mut instr_stack: Vec<&SoupInstr> = instr_seq.iter().rev().collect();
 	while let Some(instr) = instr_stack.pop() {
-		match &instr {
+		if let Some(instr_indices) = &instr_indices {
+			let cell_value = m.get(m.head);
+			if let Some(observer) = &mut m.observer {
+				observer.on_instr(
+					instr_indices[&(instr as *const SoupInstr)],
+					m.head,
+					cell_value,
+				);
+			}
+		}
+		if COUNTS_STEPS {
+			m.step();
+		}
+		match instr {
 			SoupInstr::Soup {
 				cell_deltas,
 				head_delta,
 			} => {
-				for (relative_head, delta) in cell_deltas.iter() {
-					let index = (m.head as isize + relative_head) as usize;
-					let old_value: isize = m.get(index) as isize;
-					let new_value = ((old_value + delta) as usize % 256) as u8;
-					m.set(index, new_value);
-				}
+				m.add_cell_delta_map(cell_deltas, 1);
 				m.head = (m.head as isize + head_delta) as usize;
 			}
-			SoupInstr::Output => {
-				let char_value = m.get(m.head);
+			SoupInstr::Seek { delta } => m.head = (m.head as isize + delta) as usize,
+			SoupInstr::Output { offset } => {
+				let char_value = m.get((m.head as isize + offset) as usize);
 				m.output_char_value(char_value);
 			}
-			SoupInstr::Input => {
+			SoupInstr::Input { offset } => {
 				let char_value = m.input_char_value();
-				m.set(m.head, char_value);
+				m.set((m.head as isize + offset) as usize, char_value);
+			}
+			SoupInstr::Clock { offset } => {
+				let ticks = m.read_clock();
+				m.set((m.head as isize + offset) as usize, ticks);
 			}
+			SoupInstr::Random { offset } => {
+				let byte = m.random_byte();
+				m.set((m.head as isize + offset) as usize, byte);
+			}
+			SoupInstr::Extended {
+				instr: ExtInstr::End,
+				..
+			} => break,
+			SoupInstr::Extended { instr, offset } => {
+				m.extended(*instr, (m.head as isize + offset) as usize)
+			}
+			SoupInstr::HostCall { offset } => m.host_call((m.head as isize + offset) as usize),
 			SoupInstr::MultFixedLoop { cell_deltas } => {
 				assert!(matches!(cell_deltas.get(&0), Some(-1)));
 				let n = m.get(m.head) as isize;
-				for (relative_head, delta) in cell_deltas.iter() {
-					let index = (m.head as isize + relative_head) as usize;
-					let old_value: isize = m.get(index) as isize;
-					let new_value = ((old_value + delta * n) as usize % 256) as u8;
-					m.set(index, new_value);
-				}
+				m.add_cell_delta_map(cell_deltas, n);
 				m.set(m.head, 0);
 			}
 			SoupInstr::SoupFixedLoop { cell_deltas } => {
-				for (relative_head, delta) in cell_deltas.iter() {
-					let index = (m.head as isize + relative_head) as usize;
-					let old_value: isize = m.get(index) as isize;
-					let new_value = ((old_value + delta) as usize % 256) as u8;
-					m.set(index, new_value);
-				}
-				if m.get(m.head) != 0 {
-					instr_stack.push(instr.clone());
+				let mut decision = m.get(m.head);
+				let decision_delta = decision_delta(cell_deltas);
+				while decision != 0 {
+					// Each iteration is a step, so that the loops that never end are stopped.
+					if COUNTS_STEPS {
+						m.step();
+					}
+					m.add_cell_delta_map(cell_deltas, 1);
+					decision = decision.wrapping_add(decision_delta);
 				}
 			}
 			SoupInstr::SoupMovingLoop {
 				cell_deltas,
 				head_delta,
 			} => {
-				for (relative_head, delta) in cell_deltas.iter() {
-					let index = (m.head as isize + relative_head) as usize;
-					let old_value: isize = m.get(index) as isize;
-					let new_value = ((old_value + delta) as usize % 256) as u8;
-					m.set(index, new_value);
-				}
-				m.head = (m.head as isize + head_delta) as usize;
-				if m.get(m.head) != 0 {
-					instr_stack.push(instr.clone());
+				while m.get(m.head) != 0 {
+					if COUNTS_STEPS {
+						m.step();
+					}
+					m.add_cell_delta_map(cell_deltas, 1);
+					m.head = (m.head as isize + head_delta) as usize;
 				}
 			}
-			SoupInstr::Loop(body) => {
+			SoupInstr::BlockMove { offset } => m.block_move(*offset),
+			SoupInstr::PrintUntilZero { stride } => m.print_until_zero(*stride),
+			SoupInstr::ReadBlock { stride } => m.read_block(*stride),
+			SoupInstr::ReadUntilZero { stride } => m.read_until_zero(*stride),
+			SoupInstr::Loop(body, _) => {
 				if m.get(m.head) != 0 {
 					// The loop itself must be under its content.
-					instr_stack.push(instr.clone());
-					instr_stack.extend(body.iter().rev().cloned());
+					instr_stack.push(instr);
+					instr_stack.extend(body.iter().rev());
+				}
+			}
+		}
+	}
+	if m.interact_with_user && m.output_stack.last().is_some_and(|&v| v != 10) {
+		println!();
+	}
+	std::mem::take(&mut m.output_stack)
+}
+
+/// Numbers the instructions in the order of `astsoup::instr_count` (a loop before its body).
+fn index_soup_instrs(
+	instr_seq: &[SoupInstr],
+	instr_indices: &mut HashMap<*const SoupInstr, usize>,
+) {
+	for instr in instr_seq {
+		instr_indices.insert(instr as *const SoupInstr, instr_indices.len());
+		if let SoupInstr::Loop(body, _) = instr {
+			index_soup_instrs(body, instr_indices);
+		}
+	}
+}
+
+/// Delta (modulo 256) of the decision cell of a loop whose head does not move. No other delta
+/// writes that cell, so its value can be followed in a local variable across iterations instead
+/// of being read back from the tape after each of them (the tape is still written).
+fn decision_delta(cell_deltas: &BTreeMap<isize, isize>) -> u8 {
+	cell_deltas
+		.get(&0)
+		.map_or(0, |&delta| delta.rem_euclid(256) as u8)
+}
+
+/// Cell deltas prepared to be added to the tape (see `VmMem::add_cell_deltas`).
+enum CellDeltas {
+	/// Deltas (modulo 256) of the cells from the first offset on, with no gaps, which is the most
+	/// common case.
+	Dense {
+		first_offset: isize,
+		deltas: Vec<u8>,
+	},
+	Sparse(Vec<(isize, u8)>),
+}
+
+impl CellDeltas {
+	fn new(cell_deltas: &BTreeMap<isize, isize>) -> CellDeltas {
+		let deltas = cell_deltas
+			.iter()
+			.map(|(&offset, &delta)| (offset, delta.rem_euclid(256) as u8));
+		match (cell_deltas.first_key_value(), cell_deltas.last_key_value()) {
+			(Some((&first, _)), Some((&last, _)))
+				if (last - first + 1) as usize == cell_deltas.len() =>
+			{
+				CellDeltas::Dense {
+					first_offset: first,
+					deltas: deltas.map(|(_, delta)| delta).collect(),
+				}
+			}
+			_ => CellDeltas::Sparse(deltas.collect()),
+		}
+	}
+}
+
+/// Runs the control flow graph of a soup program (see `graph`), block after block. The cell
+/// deltas of the instructions are prepared before running.
+pub fn run_graph(
+	graph: &Graph,
+	input: Option<Vec<u8>>,
+	tape_config: &TapeConfig,
+	observer: Option<&mut dyn Observer>,
+) -> Vec<u8> {
+	let m = VmMem::new(input, tape_config, observer);
+	if m.counts_steps {
+		run_graph_with::<true>(graph, m)
+	} else {
+		run_graph_with::<false>(graph, m)
+	}
+}
+
+/// Same as `run_graph`, the code that counts steps being compiled only if `COUNTS_STEPS`.
+fn run_graph_with<const COUNTS_STEPS: bool>(graph: &Graph, mut m: VmMem) -> Vec<u8> {
+	// The blocks by id, with the prepared cell deltas of their instructions (if they have some).
+	type PreparedBlock<'a> = (&'a Block, Vec<Option<CellDeltas>>);
+	let mut blocks: Vec<Option<PreparedBlock>> = Vec::new();
+	for (&id, block) in graph.blocks.iter() {
+		if blocks.len() <= id as usize {
+			blocks.resize_with(id as usize + 1, || None);
+		}
+		let prepared = block
+			.soup_instrs
+			.iter()
+			.map(|(_, instr)| {
+				let cell_deltas = match instr {
+					SoupInstr::Soup { cell_deltas, .. }
+					| SoupInstr::MultFixedLoop { cell_deltas }
+					| SoupInstr::SoupFixedLoop { cell_deltas }
+					| SoupInstr::SoupMovingLoop { cell_deltas, .. } => cell_deltas,
+					_ => return None,
+				};
+				Some(CellDeltas::new(cell_deltas))
+			})
+			.collect();
+		blocks[id as usize] = Some((block, prepared));
+	}
+	let mut block_id = graph.entry;
+	'blocks: loop {
+		let (block, prepared) = blocks[block_id as usize].as_ref().unwrap();
+		// A block without instructions (such as the body of `[]`) is a step, so that the empty
+		// loops that never end are stopped.
+		if COUNTS_STEPS && block.soup_instrs.is_empty() {
+			m.step();
+		}
+		for ((_, instr), cell_deltas) in block.soup_instrs.iter().zip(prepared) {
+			let cell_index = |m: &VmMem, offset: isize| (m.head as isize + offset) as usize;
+			if COUNTS_STEPS {
+				m.step();
+			}
+			match instr {
+				SoupInstr::Soup { head_delta, .. } => {
+					m.add_cell_deltas(cell_deltas.as_ref().unwrap(), 1);
+					m.head = (m.head as isize + head_delta) as usize;
+				}
+				SoupInstr::Seek { delta } => m.head = cell_index(&m, *delta),
+				SoupInstr::Output { offset } => {
+					let char_value = m.get(cell_index(&m, *offset));
+					m.output_char_value(char_value);
+				}
+				SoupInstr::Input { offset } => {
+					let char_value = m.input_char_value();
+					m.set(cell_index(&m, *offset), char_value);
+				}
+				SoupInstr::Clock { offset } => {
+					let ticks = m.read_clock();
+					m.set(cell_index(&m, *offset), ticks);
+				}
+				SoupInstr::Random { offset } => {
+					let byte = m.random_byte();
+					m.set(cell_index(&m, *offset), byte);
+				}
+				SoupInstr::Extended {
+					instr: ExtInstr::End,
+					..
+				} => break 'blocks,
+				SoupInstr::Extended { instr, offset } => {
+					let index = cell_index(&m, *offset);
+					m.extended(*instr, index);
+				}
+				SoupInstr::HostCall { offset } => {
+					let index = cell_index(&m, *offset);
+					m.host_call(index);
+				}
+				SoupInstr::MultFixedLoop { .. } => {
+					let n = m.get(m.head);
+					m.add_cell_deltas(cell_deltas.as_ref().unwrap(), n);
+					m.set(m.head, 0);
+				}
+				SoupInstr::SoupFixedLoop {
+					cell_deltas: cell_delta_map,
+				} => {
+					let mut decision = m.get(m.head);
+					let decision_delta = decision_delta(cell_delta_map);
+					while decision != 0 {
+						// Each iteration is a step, so that the loops that never end are stopped.
+						if COUNTS_STEPS {
+							m.step();
+						}
+						m.add_cell_deltas(cell_deltas.as_ref().unwrap(), 1);
+						decision = decision.wrapping_add(decision_delta);
+					}
+				}
+				SoupInstr::SoupMovingLoop { head_delta, .. } => {
+					while m.get(m.head) != 0 {
+						if COUNTS_STEPS {
+							m.step();
+						}
+						m.add_cell_deltas(cell_deltas.as_ref().unwrap(), 1);
+						m.head = (m.head as isize + head_delta) as usize;
+					}
+				}
+				SoupInstr::BlockMove { offset } => m.block_move(*offset),
+				SoupInstr::PrintUntilZero { stride } => m.print_until_zero(*stride),
+				SoupInstr::ReadBlock { stride } => m.read_block(*stride),
+				SoupInstr::ReadUntilZero { stride } => m.read_until_zero(*stride),
+				SoupInstr::Loop(..) => unreachable!(),
+			}
+		}
+		block_id = match block.terminator {
+			Terminator::Goto(target) => target,
+			Terminator::Branch {
+				if_zero,
+				if_non_zero,
+			} => {
+				if m.get(m.head) == 0 {
+					if_zero
+				} else {
+					if_non_zero
+				}
+			}
+			Terminator::Halt => break,
+		};
+	}
+	if m.interact_with_user && m.output_stack.last().is_some_and(|&v| v != 10) {
+		println!();
+	}
+	std::mem::take(&mut m.output_stack)
+}
+
+/// Runs the bytecode, with hot loops being compiled (see `jit`) if a JIT threshold is given and
+/// there are no read-only cells (that compiled code does not protect), cancellation token
+/// (compiled code cannot be cancelled) nor counting of steps.
+pub fn run_bytecode(
+	ops: &[Op],
+	input: Option<Vec<u8>>,
+	tape_config: &TapeConfig,
+	jit_threshold: Option<u64>,
+	observer: Option<&mut dyn Observer>,
+	cancel: Option<&CancelToken>,
+) -> Vec<u8> {
+	let m = VmMem::new(input, tape_config, observer);
+	if m.counts_steps {
+		run_bytecode_with::<true>(ops, m, None, cancel)
+	} else {
+		run_bytecode_with::<false>(ops, m, jit_threshold, cancel)
+	}
+}
+
+/// Same as `run_bytecode`, the code that counts steps being compiled only if `COUNTS_STEPS`.
+fn run_bytecode_with<const COUNTS_STEPS: bool>(
+	ops: &[Op],
+	mut m: VmMem,
+	jit_threshold: Option<u64>,
+	cancel: Option<&CancelToken>,
+) -> Vec<u8> {
+	let mut jit = jit_threshold
+		.filter(|_| m.read_only_end == 0 && cancel.is_none())
+		.map(|threshold| Jit::new(ops, threshold));
+	let mut op_index = 0;
+	// The op where compiled code returned is interpreted before entering compiled code again.
+	let mut interpret_next = false;
+	while let Some(op) = ops.get(op_index) {
+		if let Some(compiled_fn) = jit.as_ref().and_then(|jit| jit.entry(op_index)) {
+			if !interpret_next {
+				m.grow_tape_around_head();
+				op_index = Jit::call(compiled_fn, &mut m.cell_vec, &mut m.head, op_index);
+				interpret_next = true;
+				continue;
+			}
+		}
+		interpret_next = false;
+		if COUNTS_STEPS {
+			m.step();
+		}
+		let jump_index = op_index;
+		op_index += 1;
+		let cell_index = |m: &VmMem, offset: i32| (m.head as isize + offset as isize) as usize;
+		match *op {
+			Op::Add { offset, value } => {
+				let index = cell_index(&m, offset);
+				m.set(index, m.get(index).wrapping_add(value));
+			}
+			Op::Set { offset, value } => m.set(cell_index(&m, offset), value),
+			Op::MulAdd { offset, factor } => {
+				let index = cell_index(&m, offset);
+				let product = m.get(m.head).wrapping_mul(factor);
+				m.set(index, m.get(index).wrapping_add(product));
+			}
+			Op::Move(delta) => m.head = cell_index(&m, delta),
+			Op::Output { offset } => {
+				let char_value = m.get(cell_index(&m, offset));
+				m.output_char_value(char_value);
+			}
+			Op::Input { offset } => {
+				let char_value = m.input_char_value();
+				m.set(cell_index(&m, offset), char_value);
+			}
+			Op::Clock { offset } => {
+				let ticks = m.read_clock();
+				m.set(cell_index(&m, offset), ticks);
+			}
+			Op::Random { offset } => {
+				let byte = m.random_byte();
+				m.set(cell_index(&m, offset), byte);
+			}
+			Op::Extended {
+				instr: ExtInstr::End,
+				..
+			} => break,
+			Op::Extended { instr, offset } => m.extended(instr, cell_index(&m, offset)),
+			Op::HostCall { offset } => m.host_call(cell_index(&m, offset)),
+			Op::BlockMove { offset } => m.block_move(offset as isize),
+			Op::PrintUntilZero { stride } => m.print_until_zero(stride as isize),
+			Op::ReadBlock { stride } => m.read_block(stride as isize),
+			Op::ReadUntilZero { stride } => m.read_until_zero(stride as isize),
+			Op::JumpIfZero(target) | Op::JumpIfNonZero(target) => {
+				let target = target as usize;
+				let cell_is_zero = m.get(m.head) == 0;
+				if cell_is_zero == matches!(op, Op::JumpIfZero(_)) {
+					if target <= jump_index && cancel.is_some_and(|cancel| cancel.is_cancelled()) {
+						break;
+					}
+					op_index = target;
+					if let Some(jit) = jit.as_mut().filter(|_| target <= jump_index) {
+						jit.on_back_edge(ops, jump_index, target);
+					}
 				}
 			}
 		}
 	}
-	if m.interact_with_user && m.output_stack.last().map_or(false, |&v| v != 10) {
-		println!("");
+	if m.interact_with_user && m.output_stack.last().is_some_and(|&v| v != 10) {
+		println!();
 	}
-	m.output_stack
+	std::mem::take(&mut m.output_stack)
 }
@@ -0,0 +1,134 @@
+use crate::analysis::analyze;
+use crate::astraw::RawInstr;
+use crate::parser::{instr_spans, line_and_column};
+use crate::tokenizer::Extensions;
+
+/// Something suspicious about a program that is still valid.
+#[derive(Debug, Clone)]
+pub enum Warning {
+	/// The code in `unreachable_span` can never run because the loop in `loop_span` is entered
+	/// and never exits (see `analysis::analyze`). Spans are byte ranges of the source.
+	UnreachableCode {
+		loop_span: std::ops::Range<usize>,
+		unreachable_span: std::ops::Range<usize>,
+	},
+}
+
+/// Maximum number of source code lines printed for one warning.
+const MAX_PRINTED_LINES: usize = 6;
+
+/// Maximum number of columns printed for each line of source code.
+const MAX_PRINTED_COLUMNS: usize = 100;
+
+/// Number of columns printed before the loop when its line is long.
+const LOOP_CONTEXT_COLUMNS: usize = 20;
+
+/// Runs the analyses that produce warnings on the raw program parsed from the given source code.
+pub fn check(src_code: &str, raw_prog: &[RawInstr], extensions: &Extensions) -> Vec<Warning> {
+	let spans = instr_spans(src_code, extensions);
+	analyze(raw_prog)
+		.unreachable_code
+		.into_iter()
+		.map(|finding| Warning::UnreachableCode {
+			loop_span: spans[finding.loop_index].clone(),
+			unreachable_span: spans[finding.unreachable.start].start
+				..spans[finding.unreachable.end - 1].end,
+		})
+		.collect()
+}
+
+impl Warning {
+	/// Prints (to stderr) the warning with the involved lines of code, the spans being
+	/// highlighted and underlined.
+	pub fn print(&self, src_code: &str, src_code_name: Option<&str>, ansi_escape_codes: bool) {
+		let bold_on = if ansi_escape_codes { "\x1b[1m" } else { "" };
+		let bold_off = if ansi_escape_codes { "\x1b[22m" } else { "" };
+		let color_yellow = if ansi_escape_codes { "\x1b[33m" } else { "" };
+		let color_cyan = if ansi_escape_codes { "\x1b[36m" } else { "" };
+		let color_off = if ansi_escape_codes { "\x1b[39m" } else { "" };
+
+		let Warning::UnreachableCode {
+			loop_span,
+			unreachable_span,
+		} = self;
+		let (line_number, column) = line_and_column(src_code, loop_span.start);
+		eprintln!(
+			"{}{}Warning{} on line {} column {}{}: Unreachable code after a loop that never \
+			exits{}",
+			bold_on,
+			color_yellow,
+			color_off,
+			line_number,
+			column,
+			match src_code_name {
+				Some(name) => format!(" of {}", name),
+				None => "".to_owned(),
+			},
+			bold_off
+		);
+
+		// Print the lines from the loop to the end of the unreachable code, each followed by a
+		// line that marks the loop with `^` and the unreachable code with `~`.
+		let first_line_start = src_code[..loop_span.start]
+			.rfind('\n')
+			.map_or(0, |index| index + 1);
+		let last_line_end = src_code[unreachable_span.end..]
+			.find('\n')
+			.map_or(src_code.len(), |index| unreachable_span.end + index);
+		let lines = src_code[first_line_start..last_line_end].split('\n');
+		let mut line_start = first_line_start;
+		for (line_index, line) in lines.enumerate() {
+			if line_index == MAX_PRINTED_LINES {
+				eprintln!("...");
+				break;
+			}
+			let mut printed_line = String::new();
+			let mut marks = String::new();
+			let mut current_color = color_off;
+			let mut column = 0;
+			for (inline_index, c) in line.char_indices() {
+				let index = line_start + inline_index;
+				// Long lines (such as in generated code) are cut, starting a bit before the loop.
+				if index + LOOP_CONTEXT_COLUMNS < loop_span.start {
+					continue;
+				}
+				if column >= MAX_PRINTED_COLUMNS {
+					printed_line.push_str(color_off);
+					printed_line.push_str("...");
+					current_color = color_off;
+					break;
+				}
+				// Make sure that tabs are manually extended to a fixed number of columns.
+				let width = if c == '\t' { 4 } else { 1 };
+				let (mark, color) = if loop_span.contains(&index) {
+					('^', color_yellow)
+				} else if unreachable_span.contains(&index) {
+					('~', color_cyan)
+				} else {
+					(' ', color_off)
+				};
+				if color != current_color {
+					printed_line.push_str(color);
+					marks.push_str(color);
+					current_color = color;
+				}
+				if c == '\t' {
+					printed_line.push_str("    ");
+				} else {
+					printed_line.push(c);
+				}
+				marks.extend(std::iter::repeat_n(mark, width));
+				column += width;
+			}
+			if current_color != color_off {
+				printed_line.push_str(color_off);
+			}
+			eprintln!("{}", printed_line);
+			let marks = marks.trim_end();
+			if marks.contains(['^', '~']) {
+				eprintln!("{}{}{}{}", bold_on, marks, color_off, bold_off);
+			}
+			line_start += line.len() + 1;
+		}
+	}
+}
@@ -0,0 +1,136 @@
+//! Watchpoints: conditions on a cell or on the head (such as `cell[5] == 0` or `head > 100`)
+//! that the VM checks after every instruction of a program that is not optimized, stopping the
+//! program when one of them becomes true (to find which instruction zeroed a counter, for
+//! example). A condition that holds when the program starts stops it when it becomes true again.
+
+#[derive(Debug, Clone, Copy)]
+enum Watched {
+	Cell(usize),
+	Head,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Comparison {
+	Equal,
+	NotEqual,
+	Less,
+	LessOrEqual,
+	Greater,
+	GreaterOrEqual,
+}
+
+#[derive(Debug, Clone)]
+pub struct Watch {
+	/// The condition as it was given.
+	pub text: String,
+	watched: Watched,
+	comparison: Comparison,
+	value: usize,
+}
+
+impl Watch {
+	/// Parses `cell[N] OP VALUE` or `head OP VALUE`, the operator being one of `==`, `!=`, `<`,
+	/// `<=`, `>` and `>=`.
+	pub fn parse(text: &str) -> Option<Watch> {
+		// Operators that are prefixes of others come after them.
+		let operators = [
+			("==", Comparison::Equal),
+			("!=", Comparison::NotEqual),
+			("<=", Comparison::LessOrEqual),
+			(">=", Comparison::GreaterOrEqual),
+			("<", Comparison::Less),
+			(">", Comparison::Greater),
+		];
+		let (watched, comparison, value) =
+			operators.iter().find_map(|&(operator, comparison)| {
+				let (watched, value) = text.split_once(operator)?;
+				Some((watched.trim(), comparison, value.trim()))
+			})?;
+		let watched = if watched == "head" {
+			Watched::Head
+		} else {
+			let index = watched.strip_prefix("cell[")?.strip_suffix(']')?;
+			Watched::Cell(index.trim().parse().ok()?)
+		};
+		Some(Watch {
+			text: text.trim().to_owned(),
+			watched,
+			comparison,
+			value: value.parse().ok()?,
+		})
+	}
+
+	fn holds(&self, cell: impl Fn(usize) -> u8, head: usize) -> bool {
+		let watched = match self.watched {
+			Watched::Cell(index) => cell(index) as usize,
+			Watched::Head => head,
+		};
+		match self.comparison {
+			Comparison::Equal => watched == self.value,
+			Comparison::NotEqual => watched != self.value,
+			Comparison::Less => watched < self.value,
+			Comparison::LessOrEqual => watched <= self.value,
+			Comparison::Greater => watched > self.value,
+			Comparison::GreaterOrEqual => watched >= self.value,
+		}
+	}
+}
+
+/// The watch that stopped the program.
+#[derive(Debug)]
+pub struct WatchHit {
+	pub watch_index: usize,
+	/// Index of the raw instruction after which the condition became true.
+	pub instr_index: usize,
+}
+
+pub struct Watchpoints {
+	watches: Vec<Watch>,
+	/// Whether each condition held after the last instruction.
+	held: Vec<bool>,
+	pub hit: Option<WatchHit>,
+}
+
+impl Watchpoints {
+	pub fn new(watches: Vec<Watch>) -> Watchpoints {
+		Watchpoints {
+			held: vec![false; watches.len()],
+			watches,
+			hit: None,
+		}
+	}
+
+	pub fn watches(&self) -> &[Watch] {
+		&self.watches
+	}
+
+	/// To be called when the program starts.
+	pub(crate) fn start(&mut self, cell: impl Fn(usize) -> u8, head: usize) {
+		for (held, watch) in self.held.iter_mut().zip(&self.watches) {
+			*held = watch.holds(&cell, head);
+		}
+	}
+
+	/// To be called after each instruction, returns `true` if a condition became true (the
+	/// program then has to stop).
+	pub(crate) fn check(
+		&mut self,
+		cell: impl Fn(usize) -> u8,
+		head: usize,
+		instr_index: usize,
+	) -> bool {
+		for (watch_index, watch) in self.watches.iter().enumerate() {
+			let holds = watch.holds(&cell, head);
+			let became_true = holds && !self.held[watch_index];
+			self.held[watch_index] = holds;
+			if became_true {
+				self.hit = Some(WatchHit {
+					watch_index,
+					instr_index,
+				});
+				return true;
+			}
+		}
+		false
+	}
+}
@@ -0,0 +1,217 @@
+//! Transpilation of the linear intermediate representation (see `lowering`) to the WebAssembly
+//! text format, written to be read (for example to learn WebAssembly): instructions are folded,
+//! and every loop is a `block` containing a `loop` that starts with a `br_if` out of the block,
+//! preceded by a comment telling where the loop comes from in the source code (if the program
+//! was not optimized).
+//!
+//! The module uses WASI for input and output (`fd_read` and `fd_write`), so it runs with WASI
+//! runtimes such as `wasmtime`. As with the C backend, the tape has a fixed size and reading at
+//! the end of the input gives 255.
+
+use crate::lowering::{LinearInstr, LinearProgram};
+use crate::parser::line_and_column;
+
+/// Number of cells of the tape array of the generated program.
+pub const TAPE_SIZE: usize = 30000;
+
+/// Address of the tape in the linear memory, what comes before is used for input and output.
+const TAPE_ADDRESS: usize = 32;
+
+/// Where the loops of the program come from in the source code.
+pub struct LoopSources<'a> {
+	pub src_code: &'a str,
+	/// Positions of the brackets of each loop, in the order in which the loops appear in the
+	/// program (see `parser::bracket_pairs`).
+	pub bracket_pairs: &'a [(usize, usize)],
+}
+
+struct TranspiledWat<'a> {
+	code: String,
+	indent_level: u32,
+	/// Absent for optimized programs, where loops do not correspond to source code loops.
+	loop_sources: Option<LoopSources<'a>>,
+}
+
+impl TranspiledWat<'_> {
+	fn emit_line(&mut self, line_content: &str) {
+		self.code
+			.extend(std::iter::repeat_n("  ", self.indent_level as usize));
+		self.code.push_str(line_content);
+		self.code.push('\n');
+	}
+
+	fn emit_indent(&mut self) {
+		self.indent_level += 1;
+	}
+	fn emit_unindent(&mut self) {
+		self.indent_level -= 1;
+	}
+
+	fn emit_header(&mut self, program: &LinearProgram) {
+		self.emit_line("(module");
+		self.emit_indent();
+		if program.uses_output {
+			self.emit_line(
+				"(import \"wasi_snapshot_preview1\" \"fd_write\" \
+				(func $fd_write (param i32 i32 i32 i32) (result i32)))",
+			);
+		}
+		if program.uses_input {
+			self.emit_line(
+				"(import \"wasi_snapshot_preview1\" \"fd_read\" \
+				(func $fd_read (param i32 i32 i32 i32) (result i32)))",
+			);
+		}
+		self.emit_line(&format!(
+			";; Bytes 0 to 7 hold an I/O vector (address and length of the byte to read or \
+			write), bytes 8 to 11 the number of bytes read or written, byte 16 the byte, and the \
+			tape starts at byte {}.",
+			TAPE_ADDRESS
+		));
+		self.emit_line("(memory (export \"memory\") 1)");
+		self.emit_line("(data (i32.const 0) \"\\10\\00\\00\\00\\01\\00\\00\\00\")");
+		if program.uses_output {
+			self.emit_line("");
+			self.emit_line("(func $putchar (param $byte i32)");
+			self.emit_indent();
+			self.emit_line("(i32.store8 (i32.const 16) (local.get $byte))");
+			self.emit_line(
+				"(drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8))))",
+			);
+			self.emit_unindent();
+		}
+		if program.uses_input {
+			self.emit_line("");
+			self.emit_line("(func $getchar (result i32)");
+			self.emit_indent();
+			self.emit_line(
+				"(drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))",
+			);
+			self.emit_line(";; Nothing read means the end of the input.");
+			self.emit_line("(if (result i32) (i32.load (i32.const 8))");
+			self.emit_indent();
+			self.emit_line("(then (i32.load8_u (i32.const 16)))");
+			self.emit_line("(else (i32.const 255))))");
+			self.emit_unindent();
+			self.emit_unindent();
+		}
+		self.emit_line("");
+		self.emit_line("(func (export \"_start\")");
+		self.emit_indent();
+		self.emit_line(";; Address of the cell under the head.");
+		self.emit_line("(local $h i32)");
+		self.emit_line(&format!("(local.set $h (i32.const {}))", TAPE_ADDRESS));
+	}
+
+	fn emit_footer(&mut self) {
+		self.emit_unindent();
+		self.emit_line(")");
+		self.emit_unindent();
+		self.emit_line(")");
+		assert!(self.indent_level == 0);
+	}
+
+	fn emit_add(&mut self, relative_head: isize, added: &str) {
+		let load = load(relative_head);
+		self.emit_line(&format!(
+			"{} (i32.add {} {}))",
+			store(relative_head),
+			load,
+			added
+		));
+	}
+
+	fn emit_instr(&mut self, instr: &LinearInstr) {
+		match *instr {
+			LinearInstr::Add { offset, value } => {
+				self.emit_add(offset, &format!("(i32.const {})", value))
+			}
+			LinearInstr::Set { offset, value } => {
+				self.emit_line(&format!("{} (i32.const {}))", store(offset), value))
+			}
+			LinearInstr::MulAdd {
+				source,
+				target,
+				factor,
+			} => {
+				let added = format!("(i32.mul {} (i32.const {}))", load(source), factor);
+				self.emit_add(target, &added);
+			}
+			LinearInstr::Move(delta) => self.emit_line(&format!(
+				"(local.set $h (i32.add (local.get $h) (i32.const {})))",
+				delta
+			)),
+			LinearInstr::Output { offset } => {
+				self.emit_line(&format!("(call $putchar {})", load(offset)))
+			}
+			LinearInstr::Input { offset } => {
+				self.emit_line(&format!("{} (call $getchar))", store(offset)))
+			}
+			// Every byte is written by its own `fd_write`.
+			LinearInstr::Flush => (),
+			LinearInstr::LoopStart(id) => {
+				if let Some(loop_sources) = &self.loop_sources {
+					let (open, close) = loop_sources.bracket_pairs[id];
+					let (open_line, open_column) = line_and_column(loop_sources.src_code, open);
+					let (close_line, close_column) = line_and_column(loop_sources.src_code, close);
+					self.emit_line(&format!(
+						";; Loop from `[` at {}:{} to `]` at {}:{}",
+						open_line, open_column, close_line, close_column
+					));
+				}
+				self.emit_line(&format!("(block $break_{}", id));
+				self.emit_indent();
+				self.emit_line(&format!("(loop $continue_{}", id));
+				self.emit_indent();
+				self.emit_line(&format!("(br_if $break_{} (i32.eqz {}))", id, load(0)));
+			}
+			LinearInstr::LoopEnd(id) => {
+				self.emit_line(&format!("(br $continue_{})))", id));
+				self.emit_unindent();
+				self.emit_unindent();
+			}
+		}
+	}
+}
+
+/// The loop sources are given for programs that were not optimized, their loops then being the
+/// loops of the source code.
+pub fn transpile_linear_to_wat(
+	program: &LinearProgram,
+	loop_sources: Option<LoopSources>,
+) -> String {
+	let mut transpiled = TranspiledWat {
+		code: String::new(),
+		indent_level: 0,
+		loop_sources,
+	};
+	transpiled.emit_header(program);
+	for instr in &program.instrs {
+		transpiled.emit_instr(instr);
+	}
+	transpiled.emit_footer();
+	transpiled.code
+}
+
+/// Address and memory argument of the cell at the given offset from the head.
+fn address(relative_head: isize) -> String {
+	if relative_head >= 0 {
+		let offset = if relative_head == 0 {
+			String::new()
+		} else {
+			format!(" offset={}", relative_head)
+		};
+		format!("{} (local.get $h)", offset)
+	} else {
+		format!(" (i32.sub (local.get $h) (i32.const {}))", -relative_head)
+	}
+}
+
+fn load(relative_head: isize) -> String {
+	format!("(i32.load8_u{})", address(relative_head))
+}
+
+/// Beginning of a store to the cell, the value and the closing parenthesis are to be added.
+fn store(relative_head: isize) -> String {
+	format!("(i32.store8{}", address(relative_head))
+}
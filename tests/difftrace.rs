@@ -0,0 +1,56 @@
+//! `difftrace --max-steps` stops the engines on programs that never end.
+
+use std::sync::mpsc;
+use std::time::Duration;
+use xxbf::difftrace::{DiffTrace, DiffTraceOptions, diff_trace};
+use xxbf::passes::PassManager;
+use xxbf::program::{Program, Stage};
+use xxbf::tokenizer::Extensions;
+use xxbf::vm::RunStage;
+
+#[test]
+fn max_steps_stops_infinite_loops() {
+	let stage_pairs = [
+		[RunStage::Raw, RunStage::Soup],
+		[RunStage::Raw, RunStage::Graph],
+		[RunStage::Soup, RunStage::Graph],
+		[RunStage::Graph, RunStage::Bytecode],
+	];
+	// An empty loop, a fixed loop and a moving loop.
+	for src_code in ["+[]", "+[>+<]", "+[>+]"] {
+		for stages in stage_pairs {
+			let program = Program::parse(src_code, &Extensions::default()).unwrap();
+			let Stage::Raw(raw_prog) = program.stage() else {
+				unreachable!()
+			};
+			let raw_prog = raw_prog.clone();
+			let options = DiffTraceOptions {
+				stages,
+				max_steps: 1000,
+				..DiffTraceOptions::default()
+			};
+			// A run that does not stop would hang the test instead of failing it.
+			let (sender, receiver) = mpsc::channel();
+			std::thread::spawn(move || {
+				let diff_trace = diff_trace(&raw_prog, &PassManager::default(), &options);
+				sender.send(diff_trace).ok();
+			});
+			let diff_trace = receiver
+				.recv_timeout(Duration::from_secs(60))
+				.unwrap_or_else(|_| panic!("`{}` on {:?} does not stop", src_code, stages));
+			assert!(
+				matches!(
+					diff_trace,
+					DiffTrace::Agree {
+						outputs: 0,
+						complete: false
+					}
+				),
+				"`{}` on {:?}: {:?}",
+				src_code,
+				stages,
+				diff_trace
+			);
+		}
+	}
+}
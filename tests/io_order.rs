@@ -0,0 +1,116 @@
+//! The optimized engines read and output the same bytes in the same order as the raw interpreter,
+//! in particular when a block of cell updates and head moves is split by inputs and outputs.
+
+use xxbf::bytecode::{flatten, peephole};
+use xxbf::graph::graphify;
+use xxbf::passes::PassManager;
+use xxbf::program::{Program, Stage};
+use xxbf::tapeconfig::TapeConfig;
+use xxbf::tokenizer::Extensions;
+use xxbf::vm::{Observer, run_bytecode, run_graph, run_raw, run_soup};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Io {
+	Input(u8),
+	Output(u8),
+}
+
+/// Observer of the VM that logs the bytes read and output, in order.
+#[derive(Default)]
+struct IoLog(Vec<Io>);
+
+impl Observer for IoLog {
+	fn on_output(&mut self, char_values: &[u8]) {
+		self.0
+			.extend(char_values.iter().map(|&value| Io::Output(value)));
+	}
+
+	fn on_input(&mut self, char_values: &[u8]) {
+		self.0
+			.extend(char_values.iter().map(|&value| Io::Input(value)));
+	}
+}
+
+fn io_log(run: impl FnOnce(&mut dyn Observer) -> Vec<u8>) -> Vec<Io> {
+	let mut log = IoLog::default();
+	run(&mut log);
+	log.0
+}
+
+#[test]
+fn io_order_across_split_blocks() {
+	let src_codes = [
+		std::fs::read_to_string("bf_programs/optimizer/soups_across_io.b").unwrap(),
+		">+>,<+.>>,<<-.>.<<.".to_owned(),
+		"+>,>+++<<->.<.>>.".to_owned(),
+		",[>+>,.<<-]>>.<.".to_owned(),
+		",.>++>,<<[->>+<<]>>.<.>>,>+<<.>.".to_owned(),
+	];
+	let input = b"\x03abcdef";
+	let tape_config = &TapeConfig::default();
+	for src_code in &src_codes {
+		let program = Program::parse(src_code, &Extensions::default()).unwrap();
+		let Stage::Raw(raw_prog) = program.stage() else {
+			unreachable!()
+		};
+		let expected = io_log(|observer| {
+			run_raw(
+				raw_prog,
+				Some(input.to_vec()),
+				tape_config,
+				Some(observer),
+				None,
+				None,
+			)
+		});
+		for strict_io in [false, true] {
+			let pass_manager = PassManager {
+				strict_io,
+				..PassManager::default()
+			};
+			let soup_prog = pass_manager.optimize(raw_prog).unwrap();
+			let graph = graphify(&soup_prog);
+			let mut ops = flatten(&soup_prog);
+			peephole(&mut ops);
+			let logs = [
+				(
+					"soup",
+					io_log(|observer| {
+						run_soup(
+							&soup_prog,
+							Some(input.to_vec()),
+							tape_config,
+							Some(observer),
+						)
+					}),
+				),
+				(
+					"graph",
+					io_log(|observer| {
+						run_graph(&graph, Some(input.to_vec()), tape_config, Some(observer))
+					}),
+				),
+				(
+					"bytecode",
+					io_log(|observer| {
+						run_bytecode(
+							&ops,
+							Some(input.to_vec()),
+							tape_config,
+							None,
+							Some(observer),
+							None,
+						)
+					}),
+				),
+			];
+			for (engine, log) in logs {
+				assert_eq!(
+					log, expected,
+					"`{}` on the {} engine (strict I/O: {})",
+					src_code, engine, strict_io
+				);
+			}
+		}
+	}
+}